@@ -1,183 +1,4927 @@
-use crate::cursor::Error as RespError;
+use std::str::FromStr;
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::json::path::{Path, PathError};
+use crate::json::{Format, Json, ParseError};
+#[cfg(test)]
 use crate::resp::parse;
-use crate::resp::RespValue;
+use crate::resp::{Protocol, RespValue};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("unknown subcommand '{1}'. Try {0} HELP.")]
+    UnknownSubcommand(String, String),
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+    #[error("syntax error")]
+    Syntax,
+    #[error("value is not an integer or out of range")]
+    NotInteger,
+    #[error("value is not a valid float")]
+    NotFloat,
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
+    #[error("Unsupported option {0}")]
+    UnsupportedOption(String),
+    #[error("NX and XX, GT or LT options at the same time are not compatible")]
+    NxNotCompatible,
+    #[error("GT and LT options at the same time are not compatible")]
+    GtLtNotCompatible,
+    #[error("DB index is out of range")]
+    DbIndexOutOfRange,
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("value is out of range, must be positive")]
+    NotPositive,
+    #[error("{0} can't be negative")]
+    Negative(String),
+    #[error("RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list")]
+    ZeroRank,
+    #[error("timeout is not a float or out of range")]
+    InvalidTimeout,
+    #[error("timeout is negative")]
+    NegativeTimeout,
+    #[error("numkeys should be greater than 0")]
+    InvalidNumKeys,
+    #[error("count should be greater than 0")]
+    InvalidCount,
+    #[error("Protocol version is not an integer or out of range")]
+    InvalidProtocolVersion,
+    #[error("unsupported protocol version")]
+    NoProto,
+    #[error("Mandatory argument FIELDS is missing or not at the right position")]
+    MissingFields,
+    #[error("Parameter `numFields` should be greater than 0")]
+    InvalidNumFields,
+    #[error("The `numfields` parameter must match the number of arguments")]
+    NumFieldsMismatch,
+    #[error("Number of keys can't be greater than number of args")]
+    TooManyKeys,
+    #[error("min or max is not a float")]
+    InvalidScoreRange,
+    #[error("min or max not valid string range item")]
+    InvalidLexRange,
+    #[error("syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")]
+    LimitWithoutBy,
+    #[error("syntax error, WITHSCORES not supported in combination with BYLEX")]
+    WithScoresByLex,
+    #[error("XX and NX options at the same time are not compatible")]
+    XxNxNotCompatible,
+    #[error("GT, LT, and/or NX options at the same time are not compatible")]
+    GtLtNxNotCompatible,
+    #[error("INCR option supports a single increment-element pair")]
+    IncrSinglePair,
+    #[error("at least 1 input key is needed for '{0}' command")]
+    NoInputKeys(String),
+    #[error("weight value is not a float")]
+    InvalidWeight,
+    #[error("Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error("invalid start ID for the interval")]
+    InvalidStartId,
+    #[error("invalid end ID for the interval")]
+    InvalidEndId,
+    #[error("The MAXLEN argument must be >= 0.")]
+    NegativeMaxLen,
+    #[error("The LIMIT argument must be >= 0.")]
+    NegativeLimit,
+    #[error("syntax error, LIMIT cannot be used without the special ~ option")]
+    LimitWithoutApprox,
+    #[error("timeout is not an integer or out of range")]
+    TimeoutNotInteger,
+    #[error(
+        "Unbalanced '{0}' list of streams: for each stream key an ID or '{1}' must be specified."
+    )]
+    UnbalancedStreams(String, &'static str),
+    #[error("Missing GROUP option for XREADGROUP")]
+    MissingGroup,
+    #[error("The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.")]
+    LastIdInGroupRead,
+    #[error("value for ENTRIESREAD must be positive or -1")]
+    InvalidEntriesRead,
+    #[error("Invalid min-idle-time argument for {0}")]
+    InvalidMinIdle(String),
+    #[error("Invalid {0} option argument for XCLAIM")]
+    InvalidClaimOption(String),
+    #[error("Unrecognized XCLAIM option '{0}'")]
+    UnrecognizedClaimOption(String),
+    #[error("COUNT must be > 0")]
+    CountNotPositive,
+    #[error("decrement would overflow")]
+    DecrementOverflow,
+    #[error("offset is out of range")]
+    OffsetOutOfRange,
+    #[error("value is out of range")]
+    ValueOutOfRange,
+    #[error("If you want both the length and indexes, please just use IDX.")]
+    LcsLenAndIdx,
+    #[error("bit offset is not an integer or out of range")]
+    BitOffsetOutOfRange,
+    #[error("bit is not an integer or out of range")]
+    BitNotInteger,
+    #[error("The bit argument must be 1 or 0.")]
+    BitArgument,
+    #[error("BITOP NOT must be called with a single source key.")]
+    BitopNotSingleKey,
+    #[error("Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.")]
+    InvalidBitfieldType,
+    #[error("Invalid OVERFLOW type specified")]
+    InvalidOverflowType,
+    #[error("BITFIELD_RO only supports the GET subcommand")]
+    BitfieldRoGetOnly,
+    #[error("invalid longitude,latitude pair {0}")]
+    InvalidLonLat(String),
+    #[error("unsupported unit provided. please use M, KM, FT, MI")]
+    UnsupportedUnit,
+    #[error("radius cannot be negative")]
+    NegativeRadius,
+    #[error("height or width cannot be negative")]
+    NegativeBox,
+    #[error("exactly one of FROMMEMBER or FROMLONLAT can be specified for {0}")]
+    GeoOrigin(String),
+    #[error("exactly one of BYRADIUS and BYBOX can be specified for {0}")]
+    GeoShape(String),
+    #[error("{0} is not compatible with WITHDIST, WITHHASH and WITHCOORD options")]
+    GeoStoreWith(String),
+    #[error(transparent)]
+    InvalidJson(#[from] ParseError),
+    #[error(transparent)]
+    InvalidJsonPath(#[from] PathError),
+    #[error("bad error rate")]
+    BadErrorRate,
+    #[error("(0 < error rate range < 1)")]
+    BloomErrorRateRange,
+    #[error("bad capacity")]
+    BadBloomCapacity,
+    #[error("(capacity should be larger than 0)")]
+    BloomCapacityRange,
+    #[error("bad expansion")]
+    BadBloomExpansion,
+    #[error("expansion should be greater or equal to 1")]
+    BloomExpansionRange,
+    #[error("Nonscaling filters cannot expand")]
+    NonScalingExpansion,
+    #[error("Invalid information value")]
+    InvalidBloomInfo,
+    #[error("Bad capacity")]
+    BadCuckooCapacity,
+    #[error("Bad bucket size")]
+    BadBucketSize,
+    #[error("Bad maxIterations")]
+    BadMaxIterations,
+    #[error("Bad expansion")]
+    BadCuckooExpansion,
+    #[error("Capacity must be at least (BucketSize * 2)")]
+    CuckooCapacityTooSmall,
+}
+
+impl Error {
+    /// Code the error reply starts with.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NoProto => "NOPROTO",
+            _ => "ERR",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Append(Bytes, Bytes),
+    /// BLMOVE and BRPOPLPUSH, with the timeout in milliseconds (0 blocks forever).
+    BLMove(Bytes, Bytes, End, End, u64),
+    /// BLMPOP, with the timeout in milliseconds (0 blocks forever).
+    BLMPop(Vec<Bytes>, End, usize, u64),
+    /// BLPOP and BRPOP, with the timeout in milliseconds (0 blocks forever).
+    BPop(Vec<Bytes>, End, u64),
+    /// BF.ADD and BF.MADD, with whether to reply with an array.
+    BfAdd(Bytes, Vec<Bytes>, bool),
+    /// BF.EXISTS and BF.MEXISTS, with whether to reply with an array.
+    BfExists(Bytes, Vec<Bytes>, bool),
+    BfInfo(Bytes, Option<BloomInfoField>),
+    BfReserve(Bytes, BloomOptions),
+    /// BITCOUNT, with the range to count in if one was given.
+    BitCount(Bytes, Option<BitRange>),
+    /// BITFIELD, and BITFIELD_RO with GET operations alone.
+    BitField(Bytes, Vec<BitFieldOp>),
+    /// BITOP, with the destination first.
+    BitOp(BitOperation, Bytes, Vec<Bytes>),
+    /// BITPOS, with the bit to look for and the range to look in if one was
+    /// given.
+    BitPos(Bytes, bool, Option<BitRange>),
+    CfCount(Bytes, Bytes),
+    CfDel(Bytes, Bytes),
+    /// CF.EXISTS and CF.MEXISTS, with whether to reply with an array.
+    CfExists(Bytes, Vec<Bytes>, bool),
+    CfInfo(Bytes),
+    /// CF.ADD, CF.ADDNX, CF.INSERT and CF.INSERTNX: the options to create
+    /// the filter with unless `NOCREATE` was given, the items, whether to
+    /// skip items already there, and whether to reply with an array.
+    CfInsert(Bytes, Option<CuckooOptions>, Vec<Bytes>, bool, bool),
+    CfReserve(Bytes, CuckooOptions),
+    ConfigGet(String),
+    Copy(Bytes, Bytes, bool),
+    Del(Vec<Bytes>),
+    Echo(Bytes),
+    Exists(Vec<Bytes>),
+    Expire(Bytes, Expiry, ExpireCondition),
+    ExpireTime(Bytes, TimeUnit),
+    /// GEOADD, with its NX, XX and CH flags and the longitude, latitude and
+    /// name of each member.
+    GeoAdd(Bytes, ZAddOptions, Vec<(f64, f64, Bytes)>),
+    GeoDist(Bytes, Bytes, Bytes, DistanceUnit),
+    GeoHash(Bytes, Vec<Bytes>),
+    GeoPos(Bytes, Vec<Bytes>),
+    GeoSearch(Bytes, GeoSearch),
+    /// GEOSEARCHSTORE, with the destination first and whether STOREDIST was
+    /// given.
+    GeoSearchStore(Bytes, Bytes, GeoSearch, bool),
+    Get(Bytes),
+    GetBit(Bytes, usize),
+    GetDel(Bytes),
+    /// GETEX, with the TTL to set if an option was given: the expiry, or
+    /// `None` for PERSIST.
+    GetEx(Bytes, Option<Option<Expiry>>),
+    GetRange(Bytes, i64, i64),
+    HDel(Bytes, Vec<Bytes>),
+    HExists(Bytes, Bytes),
+    /// HEXPIRE, HPEXPIRE, HEXPIREAT and HPEXPIREAT.
+    HExpire(Bytes, Expiry, ExpireCondition, Vec<Bytes>),
+    HExpireTime(Bytes, TimeUnit, Vec<Bytes>),
+    HGet(Bytes, Bytes),
+    HGetAll(Bytes),
+    HIncrBy(Bytes, Bytes, i64),
+    HIncrByFloat(Bytes, Bytes, f64),
+    HKeys(Bytes),
+    HLen(Bytes),
+    HMGet(Bytes, Vec<Bytes>),
+    HMSet(Bytes, Vec<(Bytes, Bytes)>),
+    HPersist(Bytes, Vec<Bytes>),
+    /// HRANDFIELD, with the count and whether to include values if a count was given.
+    HRandField(Bytes, Option<(i64, bool)>),
+    HScan(Bytes, u64, ScanOptions),
+    HSet(Bytes, Vec<(Bytes, Bytes)>),
+    HSetNx(Bytes, Bytes, Bytes),
+    HTtl(Bytes, TimeUnit, Vec<Bytes>),
+    HVals(Bytes),
+    /// HELLO, with the protocol version to switch to if one was given.
+    Hello(Option<Protocol>),
+    /// INCR, DECR, INCRBY and DECRBY, with decrements negated.
+    IncrBy(Bytes, i64),
+    IncrByFloat(Bytes, f64),
+    JsonArrAppend(Bytes, Path, Vec<Json>),
+    /// JSON.DEL and JSON.FORGET.
+    JsonDel(Bytes, Path),
+    JsonGet(Bytes, Format, Vec<Path>),
+    JsonNumIncrBy(Bytes, Path, Json),
+    JsonSet(Bytes, Path, Json, Option<SetCondition>),
+    JsonType(Bytes, Path),
+    Keys(Bytes),
+    LIndex(Bytes, i64),
+    LInsert(Bytes, InsertPosition, Bytes, Bytes),
+    LLen(Bytes),
+    LMPop(Vec<Bytes>, End, usize),
+    LMove(Bytes, Bytes, End, End),
+    LPos(Bytes, Bytes, LPosOptions),
+    LRange(Bytes, i64, i64),
+    LRem(Bytes, i64, Bytes),
+    LSet(Bytes, i64, Bytes),
+    LTrim(Bytes, i64, i64),
+    Lcs(Bytes, Bytes, LcsOptions),
+    MGet(Vec<Bytes>),
+    MSet(Vec<(Bytes, Bytes)>),
+    MSetNx(Vec<(Bytes, Bytes)>),
+    ObjectEncoding(Bytes),
+    ObjectIdleTime(Bytes),
+    Persist(Bytes),
+    PfAdd(Bytes, Vec<Bytes>),
+    PfCount(Vec<Bytes>),
+    /// PFMERGE, with the destination first.
+    PfMerge(Bytes, Vec<Bytes>),
+    Ping,
+    /// LPOP and RPOP, with the count if one was given.
+    Pop(Bytes, End, Option<usize>),
+    /// LPUSH, RPUSH and, when the flag is set, LPUSHX and RPUSHX.
+    Push(Bytes, Vec<Bytes>, End, bool),
+    Rename(Bytes, Bytes),
+    RenameNx(Bytes, Bytes),
+    SAdd(Bytes, Vec<Bytes>),
+    SCard(Bytes),
+    /// SINTER, SUNION and SDIFF.
+    SCombine(SetOp, Vec<Bytes>),
+    /// SINTERSTORE, SUNIONSTORE and SDIFFSTORE, with the destination first.
+    SCombineStore(SetOp, Bytes, Vec<Bytes>),
+    /// SINTERCARD, with the limit (0 for none).
+    SInterCard(Vec<Bytes>, usize),
+    SIsMember(Bytes, Bytes),
+    SMIsMember(Bytes, Vec<Bytes>),
+    SMembers(Bytes),
+    SMove(Bytes, Bytes, Bytes),
+    /// SPOP, with the count if one was given.
+    SPop(Bytes, Option<usize>),
+    /// SRANDMEMBER, with the count if one was given.
+    SRandMember(Bytes, Option<i64>),
+    SRem(Bytes, Vec<Bytes>),
+    SScan(Bytes, u64, ScanOptions),
+    Scan(u64, ScanOptions),
+    /// SET, along with GETSET, SETEX and PSETEX.
+    Set(Bytes, Bytes, SetOptions),
+    SetBit(Bytes, usize, bool),
+    SetNx(Bytes, Bytes),
+    SetRange(Bytes, usize, Bytes),
+    StrLen(Bytes),
+    Touch(Vec<Bytes>),
+    Ttl(Bytes, TimeUnit),
+    Type(Bytes),
+    Unlink(Vec<Bytes>),
+    XAck(Bytes, Bytes, Vec<StreamId>),
+    /// XADD, with whether NOMKSTREAM was given and how to trim the stream
+    /// after adding.
+    XAdd(Bytes, bool, Option<StreamTrim>, XAddId, Vec<(Bytes, Bytes)>),
+    /// XAUTOCLAIM, with the ID to scan pending entries from, how many to
+    /// claim at most and whether JUSTID was given.
+    XAutoClaim(Bytes, Claim, StreamId, usize, bool),
+    XClaim(Bytes, Claim, Vec<StreamId>, ClaimOptions),
+    XDel(Bytes, Vec<StreamId>),
+    /// XGROUP CREATE, with the ID to deliver entries after (`None` for `$`),
+    /// whether MKSTREAM was given and the ENTRIESREAD value.
+    XGroupCreate(Bytes, Bytes, Option<StreamId>, bool, Option<u64>),
+    XGroupCreateConsumer(Bytes, Bytes, Bytes),
+    XGroupDelConsumer(Bytes, Bytes, Bytes),
+    XGroupDestroy(Bytes, Bytes),
+    /// XGROUP SETID, with the ID and ENTRIESREAD value as for XGROUP CREATE.
+    XGroupSetId(Bytes, Bytes, Option<StreamId>, Option<u64>),
+    XInfoConsumers(Bytes, Bytes),
+    XInfoGroups(Bytes),
+    XInfoStream(Bytes),
+    XLen(Bytes),
+    /// XPENDING, with the entries to list for the extended form.
+    XPending(Bytes, Bytes, Option<PendingRange>),
+    /// XRANGE and XREVRANGE, with the inclusive bounds, the count if one was
+    /// given and whether to reverse the order.
+    XRange(Bytes, StreamId, StreamId, Option<usize>, bool),
+    /// XREAD, with the streams and where to read each from, the most entries
+    /// to read from each and, if it blocks, the timeout in milliseconds (0
+    /// blocks forever).
+    XRead(Vec<Bytes>, Vec<ReadId>, Option<usize>, Option<u64>),
+    /// XREADGROUP, with the same streams, IDs, count and timeout as XREAD.
+    XReadGroup(
+        ReadGroup,
+        Vec<Bytes>,
+        Vec<ReadId>,
+        Option<usize>,
+        Option<u64>,
+    ),
+    XTrim(Bytes, StreamTrim),
+    ZAdd(Bytes, ZAddOptions, Vec<(f64, Bytes)>),
+    ZCard(Bytes),
+    /// ZINTER, ZUNION and ZDIFF.
+    ZCombine(SetOp, Vec<Bytes>, ZCombineOptions),
+    /// ZINTERSTORE, ZUNIONSTORE and ZDIFFSTORE, with the destination first.
+    ZCombineStore(SetOp, Bytes, Vec<Bytes>, ZCombineOptions),
+    ZCount(Bytes, ScoreRange),
+    ZIncrBy(Bytes, f64, Bytes),
+    ZLexCount(Bytes, LexRange),
+    ZMScore(Bytes, Vec<Bytes>),
+    /// ZPOPMIN and ZPOPMAX, with the count if one was given.
+    ZPop(Bytes, ScoreEnd, Option<usize>),
+    /// ZRANGE and its older BYSCORE, BYLEX and REV forms.
+    ZRange(Bytes, ZRangeOptions),
+    /// ZRANK and ZREVRANK, with whether to reverse the order and to include
+    /// the score.
+    ZRank(Bytes, Bytes, bool, bool),
+    ZRem(Bytes, Vec<Bytes>),
+    ZScore(Bytes, Bytes),
+}
+
+/// Unit a command takes or replies with times in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+}
+
+impl TimeUnit {
+    fn as_millis(self) -> u64 {
+        match self {
+            TimeUnit::Seconds => 1000,
+            TimeUnit::Milliseconds => 1,
+        }
+    }
+}
+
+/// When a key should expire, as requested by a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// Milliseconds from now.
+    After(u64),
+    /// Milliseconds since the Unix epoch.
+    At(u64),
+    /// Leave the current TTL untouched.
+    KeepTtl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    /// Only set the key if it does not exist (`NX`).
+    IfAbsent,
+    /// Only set the key if it already exists (`XX`).
+    IfPresent,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SetOptions {
+    pub expiry: Option<Expiry>,
+    pub condition: Option<SetCondition>,
+    /// Reply with the previous value instead of `OK` (`GET`).
+    pub get: bool,
+}
+
+/// Options of LCS.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LcsOptions {
+    /// Reply with the length of the subsequence alone (`LEN`).
+    pub len: bool,
+    /// Reply with the ranges matched in each string (`IDX`).
+    pub idx: bool,
+    /// Leave out matches shorter than this (`MINMATCHLEN`).
+    pub min_match_len: usize,
+    /// Include the length of each match (`WITHMATCHLEN`).
+    pub with_match_len: bool,
+}
+
+/// Unit the range of BITCOUNT and BITPOS is given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeUnit {
+    Byte,
+    Bit,
+}
+
+/// Range of BITCOUNT and BITPOS, where negative offsets count from the end
+/// of the string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitRange {
+    pub start: i64,
+    /// Inclusive end, or `None` for the end of the string.
+    pub end: Option<i64>,
+    pub unit: RangeUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// Integer type of a BITFIELD field, such as `i16` or `u8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitField {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+/// How BITFIELD handles writes that don't fit their field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// Operation of BITFIELD, with field offsets in bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitFieldOp {
+    Get(BitField, usize),
+    Set(BitField, usize, i64),
+    IncrBy(BitField, usize, i64),
+    /// Sets how the writes following it handle overflow.
+    Overflow(Overflow),
+}
+
+/// Options of SCAN and of the commands scanning a single key, such as HSCAN.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Glob pattern keys or elements must match (`MATCH`).
+    pub pattern: Option<Bytes>,
+    /// Number of items to visit per call (`COUNT`).
+    pub count: usize,
+    /// Only return keys holding this type (`TYPE`, SCAN only).
+    pub type_name: Option<String>,
+    /// Return hash fields without their values (`NOVALUES`, HSCAN only).
+    pub no_values: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            count: 10,
+            type_name: None,
+            no_values: false,
+        }
+    }
+}
+
+/// End of a list an element is pushed to or popped from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+/// How the set algebra commands combine their inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPosition {
+    Before,
+    After,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LPosOptions {
+    /// Which match to start from; negative ranks search from the tail (`RANK`).
+    pub rank: i64,
+    /// Number of matches to return, 0 meaning all of them (`COUNT`). Without
+    /// it a single position is returned rather than an array.
+    pub count: Option<usize>,
+    /// Maximum number of elements to compare, 0 meaning the whole list (`MAXLEN`).
+    pub maxlen: usize,
+}
+
+impl Default for LPosOptions {
+    fn default() -> Self {
+        Self {
+            rank: 1,
+            count: None,
+            maxlen: 0,
+        }
+    }
+}
+
+/// Flags of ZADD. Incompatible combinations are rejected while parsing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ZAddOptions {
+    /// Only add new members.
+    pub nx: bool,
+    /// Only update existing members.
+    pub xx: bool,
+    /// Only update scores to higher values.
+    pub gt: bool,
+    /// Only update scores to lower values.
+    pub lt: bool,
+    /// Count changed members along with the added ones.
+    pub ch: bool,
+    /// Increment the score of the single member given, like ZINCRBY.
+    pub incr: bool,
+}
+
+/// Longitudes and latitudes the GEO commands accept, the area Web Mercator
+/// covers.
+pub const MAX_LONGITUDE: f64 = 180.0;
+pub const MAX_LATITUDE: f64 = 85.051_128_78;
+
+/// Unit the GEO commands take and reply with distances in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceUnit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl DistanceUnit {
+    pub fn as_meters(self) -> f64 {
+        match self {
+            DistanceUnit::Meters => 1.0,
+            DistanceUnit::Kilometers => 1000.0,
+            DistanceUnit::Feet => 0.3048,
+            DistanceUnit::Miles => 1609.34,
+        }
+    }
+}
+
+/// Center of a GEOSEARCH.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+    /// The position of a member (`FROMMEMBER`).
+    Member(Bytes),
+    /// A longitude and latitude (`FROMLONLAT`).
+    LonLat(f64, f64),
+}
+
+/// Area of a GEOSEARCH around its center, in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    /// A circle of this radius (`BYRADIUS`).
+    Radius(f64),
+    /// A box of this width and height (`BYBOX`).
+    Box(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Options of GEOSEARCH and GEOSEARCHSTORE.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoSearch {
+    pub origin: GeoOrigin,
+    pub shape: GeoShape,
+    /// Unit the shape was given in, which distances are replied in.
+    pub unit: DistanceUnit,
+    /// Sort matches by distance (`ASC` or `DESC`).
+    pub order: Option<SortOrder>,
+    /// Return at most this many matches (`COUNT`), and whether any will do
+    /// rather than the closest (`ANY`).
+    pub count: Option<(usize, bool)>,
+    /// Include the position of each match (`WITHCOORD`).
+    pub with_coord: bool,
+    /// Include the distance of each match (`WITHDIST`).
+    pub with_dist: bool,
+    /// Include the geohash score of each match (`WITHHASH`).
+    pub with_hash: bool,
+}
+
+/// Options of BF.RESERVE, and of filters BF.ADD creates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomOptions {
+    /// Chance of reporting an item that was never added.
+    pub error_rate: f64,
+    /// Items the first sub-filter holds before the next one is started.
+    pub capacity: u64,
+    /// How many times larger each sub-filter is than the last (`EXPANSION`),
+    /// or `None` for a filter that doesn't scale (`NONSCALING`).
+    pub expansion: Option<u32>,
+}
+
+impl Default for BloomOptions {
+    fn default() -> Self {
+        Self {
+            error_rate: 0.01,
+            capacity: 100,
+            expansion: Some(2),
+        }
+    }
+}
+
+/// A single field of BF.INFO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BloomInfoField {
+    Capacity,
+    Size,
+    Filters,
+    Items,
+    Expansion,
+}
+
+/// Options of CF.RESERVE, and of filters CF.ADD and CF.INSERT create.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuckooOptions {
+    pub capacity: u64,
+    /// Fingerprints in each bucket (`BUCKETSIZE`).
+    pub bucket_size: u8,
+    /// Evictions tried before starting a new sub-filter (`MAXITERATIONS`).
+    pub max_iterations: u16,
+    /// How many times more buckets each sub-filter has than the last
+    /// (`EXPANSION`), with 0 for a filter that doesn't scale.
+    pub expansion: u16,
+}
+
+impl Default for CuckooOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            bucket_size: 2,
+            max_iterations: 20,
+            expansion: 1,
+        }
+    }
+}
+
+/// End of a sorted set members are popped from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreEnd {
+    Min,
+    Max,
+}
+
+/// A score bound such as `1.5`, or `(1.5` to leave the score itself out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: ScoreBound,
+    pub max: ScoreBound,
+}
+
+/// A member bound: `-` and `+` for the lowest and highest member, `[member`
+/// to include the member itself and `(member` to leave it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+/// What ZRANGE selects members by.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeBy {
+    /// Inclusive range of ranks, negative ones counting from the end.
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZRangeOptions {
+    pub by: RangeBy,
+    /// Order from the highest score down.
+    pub rev: bool,
+    /// Members to skip and how many to return at most, negative for all.
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+}
+
+/// How the sorted set algebra commands combine the scores a member has in
+/// each input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ZCombineOptions {
+    /// Factor the scores of each input are multiplied by, 1 if not given.
+    pub weights: Option<Vec<f64>>,
+    pub aggregate: Aggregate,
+    pub with_scores: bool,
+}
+
+/// ID of a stream entry: the unix time in milliseconds it was added at, and a
+/// sequence number telling apart entries added in the same millisecond.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// The greatest ID smaller than this one.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// ID given to XADD for the new entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XAddId {
+    /// `*`: generated from the current time.
+    Auto,
+    /// `<ms>-*`: the next sequence number within the given millisecond.
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// Where XREAD reads a stream from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadId {
+    /// `$`: only entries added after XREAD was called.
+    New,
+    /// `+`: the last entry of the stream.
+    Last,
+    /// Entries with an ID greater than the given one.
+    After(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// Keep at most this many entries.
+    MaxLen(usize),
+    /// Evict entries with an ID smaller than this one.
+    MinId(StreamId),
+}
+
+/// Trimming requested by XADD and XTRIM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamTrim {
+    pub strategy: TrimStrategy,
+    /// `~`: only evict whole nodes, possibly leaving extra entries behind.
+    pub approximate: bool,
+    /// Most entries to evict, if LIMIT was given.
+    pub limit: Option<usize>,
+}
+
+/// Who XREADGROUP reads for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadGroup {
+    pub group: Bytes,
+    pub consumer: Bytes,
+    /// Don't add the entries read to the pending entries of the group.
+    pub noack: bool,
+}
+
+/// Who XCLAIM and XAUTOCLAIM claim pending entries for, and how long in
+/// milliseconds the entries must have been idle for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claim {
+    pub group: Bytes,
+    pub consumer: Bytes,
+    pub min_idle: u64,
+}
+
+/// When XCLAIM records claimed entries as last delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryTime {
+    /// IDLE: this many milliseconds ago.
+    Idle(u64),
+    /// TIME: at this unix time in milliseconds.
+    At(u64),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClaimOptions {
+    /// When claimed entries were delivered, now if not given.
+    pub delivery_time: Option<DeliveryTime>,
+    /// Delivery count to give claimed entries instead of incrementing it.
+    pub retry_count: Option<u64>,
+    /// Claim entries of the stream that aren't pending yet.
+    pub force: bool,
+    /// Reply with the IDs alone, without counting a delivery.
+    pub just_id: bool,
+    /// Move the last delivered ID of the group forward to this one.
+    pub last_id: Option<StreamId>,
+}
+
+/// Pending entries listed by the extended form of XPENDING.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingRange {
+    /// Only list entries idle for at least this many milliseconds.
+    pub min_idle: Option<u64>,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: usize,
+    /// Only list entries pending for this consumer.
+    pub consumer: Option<Bytes>,
+}
+
+/// Flags of the EXPIRE family. `xx` may be combined with `gt` or `lt`, every
+/// other combination is rejected while parsing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExpireCondition {
+    /// Only set a TTL if the key has none.
+    pub nx: bool,
+    /// Only set a TTL if the key already has one.
+    pub xx: bool,
+    /// Only set a TTL later than the current one.
+    pub gt: bool,
+    /// Only set a TTL earlier than the current one.
+    pub lt: bool,
+}
+
+/// Arguments of a command, consumed front to back while parsing.
+struct Args {
+    name: String,
+    args: std::vec::IntoIter<Bytes>,
+}
+
+impl Args {
+    fn next_arg(&mut self) -> Result<Bytes, Error> {
+        self.args
+            .next()
+            .ok_or_else(|| Error::WrongArity(self.name.clone()))
+    }
+
+    /// Returns all remaining arguments, of which there must be at least one.
+    fn keys(&mut self) -> Result<Vec<Bytes>, Error> {
+        if self.args.len() == 0 {
+            return Err(Error::WrongArity(self.name.clone()));
+        }
+        Ok(self.args.by_ref().collect())
+    }
+
+    /// Returns the value following an option keyword; a missing value is a
+    /// syntax error rather than an arity error.
+    fn next_option_value(&mut self) -> Result<Bytes, Error> {
+        self.args.next().ok_or(Error::Syntax)
+    }
+
+    /// Returns the next argument upper-cased, for matching option keywords.
+    fn next_token(&mut self) -> Option<String> {
+        self.args
+            .next()
+            .map(|arg| String::from_utf8_lossy(&arg).to_ascii_uppercase())
+    }
+
+    /// Returns all remaining arguments as pairs, of which there must be at
+    /// least one.
+    fn pairs(&mut self) -> Result<Vec<(Bytes, Bytes)>, Error> {
+        let args: Vec<Bytes> = self.args.by_ref().collect();
+        let pairs = args.chunks_exact(2);
+        if args.is_empty() || !pairs.remainder().is_empty() {
+            return Err(Error::WrongArity(self.name.clone()));
+        }
+        Ok(pairs
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect())
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if self.args.len() > 0 {
+            return Err(Error::WrongArity(self.name));
+        }
+        Ok(())
+    }
+}
+
+fn parse_float(arg: &[u8]) -> Result<f64, Error> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|arg| !arg.is_nan())
+        .ok_or(Error::NotFloat)
+}
+
+fn parse_int<T: FromStr>(arg: &[u8]) -> Result<T, Error> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<T>().ok())
+        .ok_or(Error::NotInteger)
+}
+
+impl Command {
+    #[cfg(test)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let value = parse(bytes).map_err(|error| Error::Protocol(error.to_string()))?;
+        Self::from_value(value)
+    }
+
+    pub fn from_value(value: RespValue) -> Result<Self, Error> {
+        let RespValue::Array(arr) = value else {
+            return Err(Error::Protocol("expected an array".to_string()));
+        };
+
+        let mut args = arr
+            .into_iter()
+            .map(|arg| match arg {
+                RespValue::BulkString(arg) => Ok(arg),
+                _ => Err(Error::Protocol("expected a bulk string".to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+
+        let name = args
+            .next()
+            .ok_or_else(|| Error::Protocol("empty command".to_string()))?;
+        let name = String::from_utf8_lossy(&name).to_ascii_lowercase();
+        let mut args = Args { name, args };
+
+        let command = match args.name.as_str() {
+            "append" => Command::Append(args.next_arg()?, args.next_arg()?),
+            "bf.add" => Command::BfAdd(args.next_arg()?, vec![args.next_arg()?], false),
+            "bf.exists" => Command::BfExists(args.next_arg()?, vec![args.next_arg()?], false),
+            "bf.info" => {
+                let key = args.next_arg()?;
+                let field = match args.next_token().as_deref() {
+                    Some("CAPACITY") => Some(BloomInfoField::Capacity),
+                    Some("SIZE") => Some(BloomInfoField::Size),
+                    Some("FILTERS") => Some(BloomInfoField::Filters),
+                    Some("ITEMS") => Some(BloomInfoField::Items),
+                    Some("EXPANSION") => Some(BloomInfoField::Expansion),
+                    Some(_) => return Err(Error::InvalidBloomInfo),
+                    None => None,
+                };
+                Command::BfInfo(key, field)
+            }
+            "bf.madd" => Command::BfAdd(args.next_arg()?, args.keys()?, true),
+            "bf.mexists" => Command::BfExists(args.next_arg()?, args.keys()?, true),
+            "bf.reserve" => parse_bf_reserve(&mut args)?,
+            "bitcount" => {
+                let key = args.next_arg()?;
+                Command::BitCount(key, parse_bit_range(&mut args, true)?)
+            }
+            "bitfield" => parse_bitfield(&mut args, false)?,
+            "bitfield_ro" => parse_bitfield(&mut args, true)?,
+            "bitop" => parse_bitop(&mut args)?,
+            "bitpos" => {
+                let key = args.next_arg()?;
+                let bit = match &args.next_arg()?[..] {
+                    b"0" => false,
+                    b"1" => true,
+                    arg => {
+                        parse_int::<i64>(arg)?;
+                        return Err(Error::BitArgument);
+                    }
+                };
+                Command::BitPos(key, bit, parse_bit_range(&mut args, false)?)
+            }
+            "blmove" => Command::BLMove(
+                args.next_arg()?,
+                args.next_arg()?,
+                parse_end(&args.next_arg()?)?,
+                parse_end(&args.next_arg()?)?,
+                parse_timeout(&args.next_arg()?)?,
+            ),
+            "blmpop" => parse_mpop(&mut args, true)?,
+            "blpop" => parse_bpop(&mut args, End::Left)?,
+            "brpop" => parse_bpop(&mut args, End::Right)?,
+            "brpoplpush" => Command::BLMove(
+                args.next_arg()?,
+                args.next_arg()?,
+                End::Right,
+                End::Left,
+                parse_timeout(&args.next_arg()?)?,
+            ),
+            "cf.add" | "cf.addnx" => {
+                let (key, item) = (args.next_arg()?, args.next_arg()?);
+                let nx = args.name == "cf.addnx";
+                Command::CfInsert(key, Some(CuckooOptions::default()), vec![item], nx, false)
+            }
+            "cf.count" => Command::CfCount(args.next_arg()?, args.next_arg()?),
+            "cf.del" => Command::CfDel(args.next_arg()?, args.next_arg()?),
+            "cf.exists" => Command::CfExists(args.next_arg()?, vec![args.next_arg()?], false),
+            "cf.info" => Command::CfInfo(args.next_arg()?),
+            "cf.insert" => parse_cf_insert(&mut args, false)?,
+            "cf.insertnx" => parse_cf_insert(&mut args, true)?,
+            "cf.mexists" => Command::CfExists(args.next_arg()?, args.keys()?, true),
+            "cf.reserve" => parse_cf_reserve(&mut args)?,
+            "config" => {
+                let subcommand = args
+                    .next_token()
+                    .ok_or_else(|| Error::WrongArity("config".to_string()))?;
+                match subcommand.as_str() {
+                    "GET" => {
+                        args.name = "config|get".to_string();
+                        let key = args.next_arg()?;
+                        Command::ConfigGet(String::from_utf8_lossy(&key).into_owned())
+                    }
+                    _ => return Err(Error::UnknownSubcommand("CONFIG".to_string(), subcommand)),
+                }
+            }
+            "copy" => parse_copy(&mut args)?,
+            "decr" => Command::IncrBy(args.next_arg()?, -1),
+            "decrby" => {
+                let key = args.next_arg()?;
+                let decrement = parse_int::<i64>(&args.next_arg()?)?;
+                let increment = decrement.checked_neg().ok_or(Error::DecrementOverflow)?;
+                Command::IncrBy(key, increment)
+            }
+            "del" => Command::Del(args.keys()?),
+            "echo" => Command::Echo(args.next_arg()?),
+            "exists" => Command::Exists(args.keys()?),
+            "expire" => parse_expire(&mut args, TimeUnit::Seconds, false)?,
+            "expireat" => parse_expire(&mut args, TimeUnit::Seconds, true)?,
+            "expiretime" => Command::ExpireTime(args.next_arg()?, TimeUnit::Seconds),
+            "geoadd" => parse_geoadd(&mut args)?,
+            "geodist" => {
+                let key = args.next_arg()?;
+                let (member_a, member_b) = (args.next_arg()?, args.next_arg()?);
+                let unit = match args.args.next() {
+                    Some(unit) => parse_distance_unit(&unit)?,
+                    None => DistanceUnit::Meters,
+                };
+                if args.args.len() > 0 {
+                    return Err(Error::Syntax);
+                }
+                Command::GeoDist(key, member_a, member_b, unit)
+            }
+            "geohash" => Command::GeoHash(args.next_arg()?, args.args.by_ref().collect()),
+            "geopos" => Command::GeoPos(args.next_arg()?, args.args.by_ref().collect()),
+            "geosearch" => {
+                let key = args.next_arg()?;
+                let (search, _) = parse_geosearch(&mut args, false)?;
+                Command::GeoSearch(key, search)
+            }
+            "geosearchstore" => {
+                let destination = args.next_arg()?;
+                let source = args.next_arg()?;
+                let (search, store_dist) = parse_geosearch(&mut args, true)?;
+                Command::GeoSearchStore(destination, source, search, store_dist)
+            }
+            "get" => Command::Get(args.next_arg()?),
+            "getbit" => Command::GetBit(
+                args.next_arg()?,
+                parse_bit_offset(&args.next_arg()?, 1, false)?,
+            ),
+            "getdel" => Command::GetDel(args.next_arg()?),
+            "getex" => parse_getex(&mut args)?,
+            "getrange" => Command::GetRange(
+                args.next_arg()?,
+                parse_int(&args.next_arg()?)?,
+                parse_int(&args.next_arg()?)?,
+            ),
+            "getset" => {
+                let options = SetOptions {
+                    get: true,
+                    ..SetOptions::default()
+                };
+                Command::Set(args.next_arg()?, args.next_arg()?, options)
+            }
+            "hdel" => Command::HDel(args.next_arg()?, args.keys()?),
+            "hello" => parse_hello(&mut args)?,
+            "hexists" => Command::HExists(args.next_arg()?, args.next_arg()?),
+            "hexpire" => parse_hexpire(&mut args, TimeUnit::Seconds, false)?,
+            "hexpireat" => parse_hexpire(&mut args, TimeUnit::Seconds, true)?,
+            "hexpiretime" => {
+                let key = args.next_arg()?;
+                let token = args.next_token();
+                Command::HExpireTime(key, TimeUnit::Seconds, parse_fields(&mut args, token)?)
+            }
+            "hget" => Command::HGet(args.next_arg()?, args.next_arg()?),
+            "hgetall" => Command::HGetAll(args.next_arg()?),
+            "hincrby" => Command::HIncrBy(
+                args.next_arg()?,
+                args.next_arg()?,
+                parse_int(&args.next_arg()?)?,
+            ),
+            "hincrbyfloat" => Command::HIncrByFloat(
+                args.next_arg()?,
+                args.next_arg()?,
+                parse_float(&args.next_arg()?)?,
+            ),
+            "hkeys" => Command::HKeys(args.next_arg()?),
+            "hlen" => Command::HLen(args.next_arg()?),
+            "hmget" => Command::HMGet(args.next_arg()?, args.keys()?),
+            "hmset" => Command::HMSet(args.next_arg()?, args.pairs()?),
+            "hpersist" => {
+                let key = args.next_arg()?;
+                let token = args.next_token();
+                Command::HPersist(key, parse_fields(&mut args, token)?)
+            }
+            "hpexpire" => parse_hexpire(&mut args, TimeUnit::Milliseconds, false)?,
+            "hpexpireat" => parse_hexpire(&mut args, TimeUnit::Milliseconds, true)?,
+            "hpexpiretime" => {
+                let key = args.next_arg()?;
+                let token = args.next_token();
+                Command::HExpireTime(key, TimeUnit::Milliseconds, parse_fields(&mut args, token)?)
+            }
+            "hpttl" => {
+                let key = args.next_arg()?;
+                let token = args.next_token();
+                Command::HTtl(key, TimeUnit::Milliseconds, parse_fields(&mut args, token)?)
+            }
+            "hrandfield" => parse_hrandfield(&mut args)?,
+            "hscan" => {
+                let key = args.next_arg()?;
+                let cursor = parse_cursor(&args.next_arg()?)?;
+                Command::HScan(key, cursor, parse_scan_options(&mut args)?)
+            }
+            "hset" => Command::HSet(args.next_arg()?, args.pairs()?),
+            "hsetnx" => Command::HSetNx(args.next_arg()?, args.next_arg()?, args.next_arg()?),
+            "httl" => {
+                let key = args.next_arg()?;
+                let token = args.next_token();
+                Command::HTtl(key, TimeUnit::Seconds, parse_fields(&mut args, token)?)
+            }
+            "hvals" => Command::HVals(args.next_arg()?),
+            "incr" => Command::IncrBy(args.next_arg()?, 1),
+            "incrby" => Command::IncrBy(args.next_arg()?, parse_int(&args.next_arg()?)?),
+            "incrbyfloat" => {
+                Command::IncrByFloat(args.next_arg()?, parse_float(&args.next_arg()?)?)
+            }
+            "json.arrappend" => {
+                let (key, path) = (args.next_arg()?, parse_json_path(&args.next_arg()?)?);
+                let values = args.keys()?.into_iter().map(|value| parse_json(&value));
+                let values = values.collect::<Result<_, _>>()?;
+                Command::JsonArrAppend(key, path, values)
+            }
+            "json.del" | "json.forget" => {
+                let key = args.next_arg()?;
+                Command::JsonDel(key, parse_optional_json_path(&mut args)?)
+            }
+            "json.get" => parse_json_get(&mut args)?,
+            "json.numincrby" => {
+                let (key, path) = (args.next_arg()?, parse_json_path(&args.next_arg()?)?);
+                let increment = parse_json(&args.next_arg()?)?;
+                if !matches!(increment, Json::Integer(_) | Json::Float(_)) {
+                    return Err(Error::NotFloat);
+                }
+                Command::JsonNumIncrBy(key, path, increment)
+            }
+            "json.set" => {
+                let (key, path) = (args.next_arg()?, parse_json_path(&args.next_arg()?)?);
+                let value = parse_json(&args.next_arg()?)?;
+                let condition = match args.next_token().as_deref() {
+                    Some("NX") => Some(SetCondition::IfAbsent),
+                    Some("XX") => Some(SetCondition::IfPresent),
+                    Some(_) => return Err(Error::Syntax),
+                    None => None,
+                };
+                Command::JsonSet(key, path, value, condition)
+            }
+            "json.type" => {
+                let key = args.next_arg()?;
+                Command::JsonType(key, parse_optional_json_path(&mut args)?)
+            }
+            "keys" => Command::Keys(args.next_arg()?),
+            "lindex" => Command::LIndex(args.next_arg()?, parse_int(&args.next_arg()?)?),
+            "linsert" => parse_linsert(&mut args)?,
+            "llen" => Command::LLen(args.next_arg()?),
+            "lmpop" => parse_mpop(&mut args, false)?,
+            "lmove" => Command::LMove(
+                args.next_arg()?,
+                args.next_arg()?,
+                parse_end(&args.next_arg()?)?,
+                parse_end(&args.next_arg()?)?,
+            ),
+            "lpop" => parse_pop(&mut args, End::Left)?,
+            "lpos" => parse_lpos(&mut args)?,
+            "lpush" => Command::Push(args.next_arg()?, args.keys()?, End::Left, false),
+            "lpushx" => Command::Push(args.next_arg()?, args.keys()?, End::Left, true),
+            "lrange" => Command::LRange(
+                args.next_arg()?,
+                parse_int(&args.next_arg()?)?,
+                parse_int(&args.next_arg()?)?,
+            ),
+            "lrem" => Command::LRem(
+                args.next_arg()?,
+                parse_int(&args.next_arg()?)?,
+                args.next_arg()?,
+            ),
+            "lset" => Command::LSet(
+                args.next_arg()?,
+                parse_int(&args.next_arg()?)?,
+                args.next_arg()?,
+            ),
+            "ltrim" => Command::LTrim(
+                args.next_arg()?,
+                parse_int(&args.next_arg()?)?,
+                parse_int(&args.next_arg()?)?,
+            ),
+            "lcs" => parse_lcs(&mut args)?,
+            "mget" => Command::MGet(args.keys()?),
+            "mset" => Command::MSet(args.pairs()?),
+            "msetnx" => Command::MSetNx(args.pairs()?),
+            "object" => {
+                let subcommand = args
+                    .next_token()
+                    .ok_or_else(|| Error::WrongArity("object".to_string()))?;
+                match subcommand.as_str() {
+                    "ENCODING" => {
+                        args.name = "object|encoding".to_string();
+                        Command::ObjectEncoding(args.next_arg()?)
+                    }
+                    "IDLETIME" => {
+                        args.name = "object|idletime".to_string();
+                        Command::ObjectIdleTime(args.next_arg()?)
+                    }
+                    _ => return Err(Error::UnknownSubcommand("OBJECT".to_string(), subcommand)),
+                }
+            }
+            "persist" => Command::Persist(args.next_arg()?),
+            "pexpire" => parse_expire(&mut args, TimeUnit::Milliseconds, false)?,
+            "pexpireat" => parse_expire(&mut args, TimeUnit::Milliseconds, true)?,
+            "pexpiretime" => Command::ExpireTime(args.next_arg()?, TimeUnit::Milliseconds),
+            "pfadd" => Command::PfAdd(args.next_arg()?, args.args.by_ref().collect()),
+            "pfcount" => Command::PfCount(args.keys()?),
+            "pfmerge" => Command::PfMerge(args.next_arg()?, args.args.by_ref().collect()),
+            "ping" => Command::Ping,
+            "psetex" => parse_setex(&mut args, 1)?,
+            "pttl" => Command::Ttl(args.next_arg()?, TimeUnit::Milliseconds),
+            "rename" => Command::Rename(args.next_arg()?, args.next_arg()?),
+            "renamenx" => Command::RenameNx(args.next_arg()?, args.next_arg()?),
+            "rpop" => parse_pop(&mut args, End::Right)?,
+            "rpoplpush" => {
+                Command::LMove(args.next_arg()?, args.next_arg()?, End::Right, End::Left)
+            }
+            "rpush" => Command::Push(args.next_arg()?, args.keys()?, End::Right, false),
+            "rpushx" => Command::Push(args.next_arg()?, args.keys()?, End::Right, true),
+            "sadd" => Command::SAdd(args.next_arg()?, args.keys()?),
+            "scan" => {
+                let cursor = parse_cursor(&args.next_arg()?)?;
+                Command::Scan(cursor, parse_scan_options(&mut args)?)
+            }
+            "scard" => Command::SCard(args.next_arg()?),
+            "sdiff" => Command::SCombine(SetOp::Diff, args.keys()?),
+            "sdiffstore" => Command::SCombineStore(SetOp::Diff, args.next_arg()?, args.keys()?),
+            "set" => parse_set(&mut args)?,
+            "setbit" => {
+                let key = args.next_arg()?;
+                let offset = parse_bit_offset(&args.next_arg()?, 1, false)?;
+                let bit = match &args.next_arg()?[..] {
+                    b"0" => false,
+                    b"1" => true,
+                    _ => return Err(Error::BitNotInteger),
+                };
+                Command::SetBit(key, offset, bit)
+            }
+            "setex" => parse_setex(&mut args, 1000)?,
+            "setnx" => Command::SetNx(args.next_arg()?, args.next_arg()?),
+            "setrange" => {
+                let key = args.next_arg()?;
+                let offset = parse_int::<i64>(&args.next_arg()?)?;
+                let offset = usize::try_from(offset).map_err(|_| Error::OffsetOutOfRange)?;
+                Command::SetRange(key, offset, args.next_arg()?)
+            }
+            "sinter" => Command::SCombine(SetOp::Inter, args.keys()?),
+            "sintercard" => parse_sintercard(&mut args)?,
+            "sinterstore" => Command::SCombineStore(SetOp::Inter, args.next_arg()?, args.keys()?),
+            "sismember" => Command::SIsMember(args.next_arg()?, args.next_arg()?),
+            "smembers" => Command::SMembers(args.next_arg()?),
+            "smismember" => Command::SMIsMember(args.next_arg()?, args.keys()?),
+            "smove" => Command::SMove(args.next_arg()?, args.next_arg()?, args.next_arg()?),
+            "spop" => {
+                let key = args.next_arg()?;
+                let count = args.args.next().map(|count| parse_count(&count));
+                Command::SPop(key, count.transpose()?)
+            }
+            "srandmember" => {
+                let key = args.next_arg()?;
+                let count = args.args.next().map(|count| parse_random_count(&count));
+                Command::SRandMember(key, count.transpose()?)
+            }
+            "srem" => Command::SRem(args.next_arg()?, args.keys()?),
+            "sscan" => {
+                let key = args.next_arg()?;
+                let cursor = parse_cursor(&args.next_arg()?)?;
+                Command::SScan(key, cursor, parse_scan_options(&mut args)?)
+            }
+            "strlen" => Command::StrLen(args.next_arg()?),
+            "sunion" => Command::SCombine(SetOp::Union, args.keys()?),
+            "sunionstore" => Command::SCombineStore(SetOp::Union, args.next_arg()?, args.keys()?),
+            "touch" => Command::Touch(args.keys()?),
+            "ttl" => Command::Ttl(args.next_arg()?, TimeUnit::Seconds),
+            "type" => Command::Type(args.next_arg()?),
+            "unlink" => Command::Unlink(args.keys()?),
+            "xack" => {
+                let key = args.next_arg()?;
+                let group = args.next_arg()?;
+                Command::XAck(key, group, parse_stream_ids(args.keys()?)?)
+            }
+            "xadd" => parse_xadd(&mut args)?,
+            "xautoclaim" => parse_xautoclaim(&mut args)?,
+            "xclaim" => parse_xclaim(&mut args)?,
+            "xdel" => Command::XDel(args.next_arg()?, parse_stream_ids(args.keys()?)?),
+            "xgroup" => parse_xgroup(&mut args)?,
+            "xinfo" => {
+                let subcommand = args
+                    .next_token()
+                    .ok_or_else(|| Error::WrongArity("xinfo".to_string()))?;
+                args.name = format!("xinfo|{}", subcommand.to_ascii_lowercase());
+                match subcommand.as_str() {
+                    "CONSUMERS" => Command::XInfoConsumers(args.next_arg()?, args.next_arg()?),
+                    "GROUPS" => Command::XInfoGroups(args.next_arg()?),
+                    "STREAM" => {
+                        let key = args.next_arg()?;
+                        if let Some(option) = args.next_token() {
+                            return Err(Error::UnsupportedOption(option));
+                        }
+                        Command::XInfoStream(key)
+                    }
+                    _ => return Err(Error::UnknownSubcommand("XINFO".to_string(), subcommand)),
+                }
+            }
+            "xlen" => Command::XLen(args.next_arg()?),
+            "xpending" => parse_xpending(&mut args)?,
+            "xrange" => parse_xrange(&mut args, false)?,
+            "xread" => parse_xread(&mut args, false)?,
+            "xreadgroup" => parse_xread(&mut args, true)?,
+            "xrevrange" => parse_xrange(&mut args, true)?,
+            "xtrim" => {
+                let key = args.next_arg()?;
+                let strategy = args.next_arg()?;
+                let trim = parse_stream_trim(&mut args, &strategy)?;
+                if args.args.len() > 0 {
+                    return Err(Error::Syntax);
+                }
+                Command::XTrim(key, trim)
+            }
+            "zadd" => parse_zadd(&mut args)?,
+            "zcard" => Command::ZCard(args.next_arg()?),
+            "zcount" => Command::ZCount(
+                args.next_arg()?,
+                ScoreRange {
+                    min: parse_score_bound(&args.next_arg()?)?,
+                    max: parse_score_bound(&args.next_arg()?)?,
+                },
+            ),
+            "zdiff" => parse_zcombine(&mut args, SetOp::Diff, false)?,
+            "zdiffstore" => parse_zcombine(&mut args, SetOp::Diff, true)?,
+            "zincrby" => Command::ZIncrBy(
+                args.next_arg()?,
+                parse_float(&args.next_arg()?)?,
+                args.next_arg()?,
+            ),
+            "zinter" => parse_zcombine(&mut args, SetOp::Inter, false)?,
+            "zinterstore" => parse_zcombine(&mut args, SetOp::Inter, true)?,
+            "zlexcount" => Command::ZLexCount(
+                args.next_arg()?,
+                LexRange {
+                    min: parse_lex_bound(args.next_arg()?)?,
+                    max: parse_lex_bound(args.next_arg()?)?,
+                },
+            ),
+            "zmscore" => Command::ZMScore(args.next_arg()?, args.keys()?),
+            "zpopmax" => {
+                let key = args.next_arg()?;
+                let count = args.args.next().map(|count| parse_count(&count));
+                Command::ZPop(key, ScoreEnd::Max, count.transpose()?)
+            }
+            "zpopmin" => {
+                let key = args.next_arg()?;
+                let count = args.args.next().map(|count| parse_count(&count));
+                Command::ZPop(key, ScoreEnd::Min, count.transpose()?)
+            }
+            "zrange" => parse_zrange(&mut args, RangeKind::Rank, false)?,
+            "zrangebylex" => parse_zrange(&mut args, RangeKind::Lex, false)?,
+            "zrangebyscore" => parse_zrange(&mut args, RangeKind::Score, false)?,
+            "zrank" => parse_zrank(&mut args, false)?,
+            "zrem" => Command::ZRem(args.next_arg()?, args.keys()?),
+            "zrevrange" => parse_zrange(&mut args, RangeKind::Rank, true)?,
+            "zrevrangebylex" => parse_zrange(&mut args, RangeKind::Lex, true)?,
+            "zrevrangebyscore" => parse_zrange(&mut args, RangeKind::Score, true)?,
+            "zrevrank" => parse_zrank(&mut args, true)?,
+            "zscore" => Command::ZScore(args.next_arg()?, args.next_arg()?),
+            "zunion" => parse_zcombine(&mut args, SetOp::Union, false)?,
+            "zunionstore" => parse_zcombine(&mut args, SetOp::Union, true)?,
+            _ => return Err(Error::UnknownCommand(args.name)),
+        };
+
+        args.finish()?;
+        Ok(command)
+    }
+}
+
+/// Parses a relative TTL given in `unit_ms` milliseconds, rejecting
+/// non-positive values and values that overflow once converted.
+fn parse_ttl(arg: &[u8], unit_ms: u64, command: &str) -> Result<u64, Error> {
+    let ttl = parse_int::<i64>(arg)?;
+    u64::try_from(ttl)
+        .ok()
+        .filter(|ttl| *ttl > 0)
+        .and_then(|ttl| ttl.checked_mul(unit_ms))
+        .ok_or_else(|| Error::InvalidExpireTime(command.to_string()))
+}
+
+fn parse_copy(args: &mut Args) -> Result<Command, Error> {
+    let source = args.next_arg()?;
+    let destination = args.next_arg()?;
+    let mut replace = false;
+
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "REPLACE" => replace = true,
+            // There is a single database, so only index 0 can be addressed.
+            "DB" => {
+                let db = parse_int::<i64>(&args.next_option_value()?)?;
+                if db != 0 {
+                    return Err(Error::DbIndexOutOfRange);
+                }
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(Command::Copy(source, destination, replace))
+}
+
+/// Parses the EXPIRE family. Unlike SET, a TTL that is zero, negative or an
+/// absolute time in the past is accepted and means the key is deleted, so such
+/// values become `Expiry::At(0)`.
+fn parse_expire(args: &mut Args, unit: TimeUnit, absolute: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let expiry = parse_expiry(args, unit, absolute)?;
+
+    let mut condition = ExpireCondition::default();
+    while let Some(token) = args.next_token() {
+        if !parse_expire_flag(&mut condition, &token) {
+            return Err(Error::UnsupportedOption(token));
+        }
+    }
+
+    if condition.nx && (condition.xx || condition.gt || condition.lt) {
+        return Err(Error::NxNotCompatible);
+    }
+    if condition.gt && condition.lt {
+        return Err(Error::GtLtNotCompatible);
+    }
+
+    Ok(Command::Expire(key, expiry, condition))
+}
+
+/// Parses HEXPIRE and its variants, which take at most one flag before the
+/// fields, so no incompatible flags can be combined.
+fn parse_hexpire(args: &mut Args, unit: TimeUnit, absolute: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let expiry = parse_expiry(args, unit, absolute)?;
+
+    let mut condition = ExpireCondition::default();
+    let mut token = args.next_token();
+    if token
+        .as_deref()
+        .is_some_and(|token| parse_expire_flag(&mut condition, token))
+    {
+        token = args.next_token();
+    }
+
+    Ok(Command::HExpire(
+        key,
+        expiry,
+        condition,
+        parse_fields(args, token)?,
+    ))
+}
+
+/// Parses the TTL argument shared by the EXPIRE and HEXPIRE families.
+fn parse_expiry(args: &mut Args, unit: TimeUnit, absolute: bool) -> Result<Expiry, Error> {
+    let ttl = parse_int::<i64>(&args.next_arg()?)?;
+    let ttl = ttl
+        .checked_mul(unit.as_millis() as i64)
+        .ok_or_else(|| Error::InvalidExpireTime(args.name.clone()))?;
+    Ok(match u64::try_from(ttl) {
+        Ok(ttl) if ttl > 0 && absolute => Expiry::At(ttl),
+        Ok(ttl) if ttl > 0 => Expiry::After(ttl),
+        _ => Expiry::At(0),
+    })
+}
+
+/// Sets the flag named by `token`, returning false if it names none.
+fn parse_expire_flag(condition: &mut ExpireCondition, token: &str) -> bool {
+    match token {
+        "NX" => condition.nx = true,
+        "XX" => condition.xx = true,
+        "GT" => condition.gt = true,
+        "LT" => condition.lt = true,
+        _ => return false,
+    }
+    true
+}
+
+/// Parses `FIELDS numfields field [field ...]`, the keyword being `token`,
+/// which the caller already consumed.
+fn parse_fields(args: &mut Args, token: Option<String>) -> Result<Vec<Bytes>, Error> {
+    match token.as_deref() {
+        Some("FIELDS") => {}
+        Some(_) => return Err(Error::MissingFields),
+        None => return Err(Error::WrongArity(args.name.clone())),
+    }
+    let numfields = parse_int::<usize>(&args.next_arg()?)
+        .ok()
+        .filter(|numfields| *numfields > 0)
+        .ok_or(Error::InvalidNumFields)?;
+    let fields: Vec<Bytes> = args.args.by_ref().collect();
+    if fields.is_empty() {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+    if fields.len() != numfields {
+        return Err(Error::NumFieldsMismatch);
+    }
+    Ok(fields)
+}
+
+/// Parses a blocking timeout given in seconds, possibly fractional, into
+/// milliseconds.
+fn parse_timeout(arg: &[u8]) -> Result<u64, Error> {
+    let timeout = std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|timeout| timeout.is_finite())
+        .ok_or(Error::InvalidTimeout)?;
+    if timeout < 0.0 {
+        return Err(Error::NegativeTimeout);
+    }
+    let timeout = (timeout * 1000.0).ceil();
+    if timeout > u64::MAX as f64 {
+        return Err(Error::InvalidTimeout);
+    }
+    Ok(timeout as u64)
+}
+
+fn parse_bpop(args: &mut Args, end: End) -> Result<Command, Error> {
+    let mut keys = args.keys()?;
+    let timeout = keys.pop().expect("at least one argument");
+    if keys.is_empty() {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+
+    Ok(Command::BPop(keys, end, parse_timeout(&timeout)?))
+}
+
+/// Parses LMPOP and, with the leading timeout, BLMPOP.
+fn parse_mpop(args: &mut Args, blocking: bool) -> Result<Command, Error> {
+    let timeout = match blocking {
+        true => Some(parse_timeout(&args.next_arg()?)?),
+        false => None,
+    };
+    let numkeys = parse_int::<usize>(&args.next_arg()?)
+        .ok()
+        .filter(|numkeys| *numkeys > 0)
+        .ok_or(Error::InvalidNumKeys)?;
+    if numkeys >= args.args.len() {
+        return Err(Error::Syntax);
+    }
+    let keys = args.args.by_ref().take(numkeys).collect();
+    let end = parse_end(&args.next_arg()?)?;
+
+    let mut count = 1;
+    match args.next_token().as_deref() {
+        Some("COUNT") => {
+            count = parse_int::<usize>(&args.next_option_value()?)
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or(Error::InvalidCount)?;
+        }
+        Some(_) => return Err(Error::Syntax),
+        None => {}
+    }
+    if args.args.len() > 0 {
+        return Err(Error::Syntax);
+    }
+
+    Ok(match timeout {
+        Some(timeout) => Command::BLMPop(keys, end, count, timeout),
+        None => Command::LMPop(keys, end, count),
+    })
+}
+
+fn parse_end(arg: &[u8]) -> Result<End, Error> {
+    if arg.eq_ignore_ascii_case(b"LEFT") {
+        Ok(End::Left)
+    } else if arg.eq_ignore_ascii_case(b"RIGHT") {
+        Ok(End::Right)
+    } else {
+        Err(Error::Syntax)
+    }
+}
+
+fn parse_linsert(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let position = match args.next_arg()? {
+        arg if arg.eq_ignore_ascii_case(b"BEFORE") => InsertPosition::Before,
+        arg if arg.eq_ignore_ascii_case(b"AFTER") => InsertPosition::After,
+        _ => return Err(Error::Syntax),
+    };
+
+    Ok(Command::LInsert(
+        key,
+        position,
+        args.next_arg()?,
+        args.next_arg()?,
+    ))
+}
+
+fn parse_lpos(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let element = args.next_arg()?;
+    let mut options = LPosOptions::default();
+
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "RANK" => {
+                options.rank = parse_int(&args.next_option_value()?)?;
+                if options.rank == 0 {
+                    return Err(Error::ZeroRank);
+                }
+            }
+            "COUNT" | "MAXLEN" => {
+                let value = parse_int::<i64>(&args.next_option_value()?)?;
+                let value = usize::try_from(value).map_err(|_| Error::Negative(token.clone()))?;
+                if token == "COUNT" {
+                    options.count = Some(value);
+                } else {
+                    options.maxlen = value;
+                }
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(Command::LPos(key, element, options))
+}
+
+fn parse_pop(args: &mut Args, end: End) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let count = args.args.next().map(|count| parse_count(&count));
+    Ok(Command::Pop(key, end, count.transpose()?))
+}
+
+/// Parses the optional count of LPOP, RPOP and SPOP, which can't be negative.
+fn parse_count(arg: &[u8]) -> Result<usize, Error> {
+    let count = parse_int::<i64>(arg).map_err(|_| Error::NotPositive)?;
+    usize::try_from(count).map_err(|_| Error::NotPositive)
+}
+
+fn parse_zadd(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    if args.args.len() < 2 {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+
+    let mut options = ZAddOptions::default();
+    while let Some(arg) = args.args.as_slice().first() {
+        match String::from_utf8_lossy(arg).to_ascii_uppercase().as_str() {
+            "NX" => options.nx = true,
+            "XX" => options.xx = true,
+            "GT" => options.gt = true,
+            "LT" => options.lt = true,
+            "CH" => options.ch = true,
+            "INCR" => options.incr = true,
+            _ => break,
+        }
+        args.args.next();
+    }
+
+    let args: Vec<Bytes> = args.args.by_ref().collect();
+    let pairs = args.chunks_exact(2);
+    if args.is_empty() || !pairs.remainder().is_empty() {
+        return Err(Error::Syntax);
+    }
+    if options.nx && options.xx {
+        return Err(Error::XxNxNotCompatible);
+    }
+    if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+        return Err(Error::GtLtNxNotCompatible);
+    }
+    if options.incr && pairs.len() > 1 {
+        return Err(Error::IncrSinglePair);
+    }
+
+    let pairs = pairs
+        .map(|pair| Ok((parse_float(&pair[0])?, pair[1].clone())))
+        .collect::<Result<_, Error>>()?;
+    Ok(Command::ZAdd(key, options, pairs))
+}
+
+/// What the form of ZRANGE being parsed selects by until told otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// Parses ZRANGE and the older commands it subsumes. Only ZRANGE itself
+/// takes BYSCORE, BYLEX and REV; the others imply them.
+fn parse_zrange(args: &mut Args, kind: RangeKind, rev: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let start = args.next_arg()?;
+    let stop = args.next_arg()?;
+
+    let unified = args.name == "zrange";
+    let (mut kind, mut rev) = (kind, rev);
+    let mut limit = None;
+    let mut with_scores = false;
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "BYSCORE" if unified => kind = RangeKind::Score,
+            "BYLEX" if unified => kind = RangeKind::Lex,
+            "REV" if unified => rev = true,
+            "LIMIT" => {
+                let offset = parse_int(&args.next_option_value()?)?;
+                let count = parse_int(&args.next_option_value()?)?;
+                limit = Some((offset, count));
+            }
+            "WITHSCORES" => with_scores = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    if limit.is_some() && kind == RangeKind::Rank {
+        return Err(Error::LimitWithoutBy);
+    }
+    if with_scores && kind == RangeKind::Lex {
+        return Err(Error::WithScoresByLex);
+    }
+
+    // Reversed score and member ranges are given from the highest bound down.
+    let (min, max) = match rev && kind != RangeKind::Rank {
+        true => (stop, start),
+        false => (start, stop),
+    };
+    let by = match kind {
+        RangeKind::Rank => RangeBy::Rank(parse_int(&min)?, parse_int(&max)?),
+        RangeKind::Score => RangeBy::Score(ScoreRange {
+            min: parse_score_bound(&min)?,
+            max: parse_score_bound(&max)?,
+        }),
+        RangeKind::Lex => RangeBy::Lex(LexRange {
+            min: parse_lex_bound(min)?,
+            max: parse_lex_bound(max)?,
+        }),
+    };
+
+    Ok(Command::ZRange(
+        key,
+        ZRangeOptions {
+            by,
+            rev,
+            limit,
+            with_scores,
+        },
+    ))
+}
+
+fn parse_zrank(args: &mut Args, rev: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let member = args.next_arg()?;
+    let with_score = match args.next_token().as_deref() {
+        Some("WITHSCORE") => true,
+        Some(_) => return Err(Error::Syntax),
+        None => false,
+    };
+    Ok(Command::ZRank(key, member, rev, with_score))
+}
+
+/// Parses ZINTER, ZUNION and ZDIFF, and with the leading destination their
+/// STORE forms. ZDIFF takes neither weights nor an aggregate, and only the
+/// forms that reply with the result take WITHSCORES.
+fn parse_zcombine(args: &mut Args, op: SetOp, store: bool) -> Result<Command, Error> {
+    let destination = match store {
+        true => Some(args.next_arg()?),
+        false => None,
+    };
+    let numkeys = parse_int::<i64>(&args.next_arg()?)?;
+    if numkeys < 1 {
+        return Err(Error::NoInputKeys(args.name.clone()));
+    }
+    if args.args.as_slice().is_empty() {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > args.args.len() {
+        return Err(Error::Syntax);
+    }
+    let keys = args.args.by_ref().take(numkeys).collect();
+
+    let mut options = ZCombineOptions::default();
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "WEIGHTS" if op != SetOp::Diff => {
+                if args.args.len() < numkeys {
+                    return Err(Error::Syntax);
+                }
+                let weights = args
+                    .args
+                    .by_ref()
+                    .take(numkeys)
+                    .map(|weight| parse_float(&weight).map_err(|_| Error::InvalidWeight))
+                    .collect::<Result<_, _>>()?;
+                options.weights = Some(weights);
+            }
+            "AGGREGATE" if op != SetOp::Diff => {
+                options.aggregate = match args.next_token().as_deref() {
+                    Some("SUM") => Aggregate::Sum,
+                    Some("MIN") => Aggregate::Min,
+                    Some("MAX") => Aggregate::Max,
+                    _ => return Err(Error::Syntax),
+                };
+            }
+            "WITHSCORES" if !store => options.with_scores = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(match destination {
+        Some(destination) => Command::ZCombineStore(op, destination, keys, options),
+        None => Command::ZCombine(op, keys, options),
+    })
+}
+
+/// Parses a stream ID such as `1526919030474-55`, taking `1526919030474`
+/// alone to mean `1526919030474-<missing_seq>`.
+fn parse_stream_id(arg: &[u8], missing_seq: u64) -> Result<StreamId, Error> {
+    let arg = std::str::from_utf8(arg).map_err(|_| Error::InvalidStreamId)?;
+    let (ms, seq) = match arg.split_once('-') {
+        Some((ms, seq)) => (ms, Some(seq)),
+        None => (arg, None),
+    };
+    let ms = ms.parse().map_err(|_| Error::InvalidStreamId)?;
+    let seq = match seq {
+        Some(seq) => seq.parse().map_err(|_| Error::InvalidStreamId)?,
+        None => missing_seq,
+    };
+    Ok(StreamId { ms, seq })
+}
+
+/// Parses an XRANGE bound into an inclusive one: `-` and `+` stand for the
+/// smallest and greatest IDs, a `(` prefix leaves the ID itself out, and an ID
+/// without a sequence number covers the whole millisecond.
+fn parse_stream_bound(arg: &[u8], start: bool) -> Result<StreamId, Error> {
+    match (arg, start) {
+        (b"-", _) => return Ok(StreamId::MIN),
+        (b"+", _) => return Ok(StreamId::MAX),
+        _ => {}
+    }
+    let missing_seq = match start {
+        true => 0,
+        false => u64::MAX,
+    };
+    match (arg.strip_prefix(b"("), start) {
+        (Some(id), true) => parse_stream_id(id, missing_seq)?
+            .next()
+            .ok_or(Error::InvalidStartId),
+        (Some(id), false) => parse_stream_id(id, missing_seq)?
+            .prev()
+            .ok_or(Error::InvalidEndId),
+        (None, _) => parse_stream_id(arg, missing_seq),
+    }
+}
+
+/// Parses the threshold of a MAXLEN or MINID trimming `strategy`, optionally
+/// preceded by `=` or `~` and followed by LIMIT.
+fn parse_stream_trim(args: &mut Args, strategy: &[u8]) -> Result<StreamTrim, Error> {
+    let strategy = String::from_utf8_lossy(strategy).to_ascii_uppercase();
+    let mut threshold = args.next_option_value()?;
+    let approximate = threshold == "~";
+    if approximate || threshold == "=" {
+        threshold = args.next_option_value()?;
+    }
+    let strategy = match strategy.as_str() {
+        "MAXLEN" => {
+            let maxlen = parse_int::<i64>(&threshold)?;
+            TrimStrategy::MaxLen(usize::try_from(maxlen).map_err(|_| Error::NegativeMaxLen)?)
+        }
+        "MINID" => TrimStrategy::MinId(parse_stream_id(&threshold, 0)?),
+        _ => return Err(Error::Syntax),
+    };
+
+    let mut limit = None;
+    if let Some(arg) = args.args.as_slice().first() {
+        if arg.eq_ignore_ascii_case(b"LIMIT") {
+            args.args.next();
+            let count = parse_int::<i64>(&args.next_option_value()?)?;
+            let count = usize::try_from(count).map_err(|_| Error::NegativeLimit)?;
+            if !approximate {
+                return Err(Error::LimitWithoutApprox);
+            }
+            limit = Some(count);
+        }
+    }
+    Ok(StreamTrim {
+        strategy,
+        approximate,
+        limit,
+    })
+}
+
+/// Parses XADD, whose NOMKSTREAM and trimming options come before the ID.
+fn parse_xadd(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let mut nomkstream = false;
+    let mut trim = None;
+    let id = loop {
+        let arg = args.next_arg()?;
+        match String::from_utf8_lossy(&arg).to_ascii_uppercase().as_str() {
+            "NOMKSTREAM" => nomkstream = true,
+            "MAXLEN" | "MINID" => trim = Some(parse_stream_trim(args, &arg)?),
+            "*" => break XAddId::Auto,
+            _ => match arg.strip_suffix(b"-*") {
+                Some(ms) => {
+                    let ms = parse_int(ms).map_err(|_| Error::InvalidStreamId)?;
+                    break XAddId::AutoSeq(ms);
+                }
+                None => break XAddId::Explicit(parse_stream_id(&arg, 0)?),
+            },
+        }
+    };
+    let fields = args.pairs()?;
+    Ok(Command::XAdd(key, nomkstream, trim, id, fields))
+}
+
+fn parse_xrange(args: &mut Args, rev: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let (start, end) = match rev {
+        true => {
+            let end = args.next_arg()?;
+            (args.next_arg()?, end)
+        }
+        false => (args.next_arg()?, args.next_arg()?),
+    };
+    let start = parse_stream_bound(&start, true)?;
+    let end = parse_stream_bound(&end, false)?;
+
+    let mut count = None;
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "COUNT" => {
+                let value = parse_int::<i64>(&args.next_option_value()?)?;
+                count = Some(value.max(0) as usize);
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok(Command::XRange(key, start, end, count, rev))
+}
+
+fn parse_stream_ids(args: Vec<Bytes>) -> Result<Vec<StreamId>, Error> {
+    args.iter().map(|id| parse_stream_id(id, 0)).collect()
+}
+
+/// Parses XREAD and XREADGROUP, whose STREAMS option comes last and is
+/// followed by the keys and then an ID for each of them.
+fn parse_xread(args: &mut Args, group: bool) -> Result<Command, Error> {
+    if args.args.len() < 3 {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+
+    let mut count = None;
+    let mut block = None;
+    let mut read_group = None;
+    let mut noack = false;
+    loop {
+        match args.next_token().as_deref() {
+            Some("COUNT") => {
+                let value = parse_int::<i64>(&args.next_option_value()?)?;
+                count = (value > 0).then_some(value as usize);
+            }
+            Some("BLOCK") => {
+                let timeout = parse_int::<i64>(&args.next_option_value()?)
+                    .map_err(|_| Error::TimeoutNotInteger)?;
+                let timeout = u64::try_from(timeout).map_err(|_| Error::NegativeTimeout)?;
+                block = Some(timeout);
+            }
+            Some("GROUP") if group => {
+                let group = args.next_option_value()?;
+                read_group = Some((group, args.next_option_value()?));
+            }
+            Some("NOACK") if group => noack = true,
+            Some("STREAMS") => break,
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    let rest: Vec<Bytes> = args.args.by_ref().collect();
+    let (keys, ids) = rest.split_at(rest.len() / 2);
+    if keys.is_empty() || keys.len() != ids.len() {
+        let new = if group { ">" } else { "$" };
+        return Err(Error::UnbalancedStreams(args.name.clone(), new));
+    }
+    if group && read_group.is_none() {
+        return Err(Error::MissingGroup);
+    }
+    let ids = ids
+        .iter()
+        .map(|id| match (&id[..], group) {
+            (b"$", false) | (b">", true) => Ok(ReadId::New),
+            (b"+", false) => Ok(ReadId::Last),
+            (b"$", true) => Err(Error::LastIdInGroupRead),
+            (id, _) => Ok(ReadId::After(parse_stream_id(id, 0)?)),
+        })
+        .collect::<Result<_, Error>>()?;
+
+    let keys = keys.to_vec();
+    Ok(match read_group {
+        Some((group, consumer)) => {
+            let read_group = ReadGroup {
+                group,
+                consumer,
+                noack,
+            };
+            Command::XReadGroup(read_group, keys, ids, count, block)
+        }
+        None => Command::XRead(keys, ids, count, block),
+    })
+}
+
+/// Parses the ID of XGROUP CREATE and SETID, `$` standing for the last ID of
+/// the stream, followed by their options.
+fn parse_group_id(
+    args: &mut Args,
+    mkstream: Option<&mut bool>,
+) -> Result<(Option<StreamId>, Option<u64>), Error> {
+    let id = match &args.next_arg()?[..] {
+        b"$" => None,
+        id => Some(parse_stream_id(id, 0)?),
+    };
+
+    let mut entries_read = None;
+    let mut mkstream = mkstream;
+    while let Some(token) = args.next_token() {
+        match (token.as_str(), mkstream.as_deref_mut()) {
+            ("MKSTREAM", Some(mkstream)) => *mkstream = true,
+            ("ENTRIESREAD", _) => {
+                let value = parse_int::<i64>(&args.next_option_value()?)?;
+                entries_read = match value {
+                    -1 => None,
+                    value => Some(u64::try_from(value).map_err(|_| Error::InvalidEntriesRead)?),
+                };
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok((id, entries_read))
+}
+
+fn parse_xgroup(args: &mut Args) -> Result<Command, Error> {
+    let subcommand = args
+        .next_token()
+        .ok_or_else(|| Error::WrongArity("xgroup".to_string()))?;
+    args.name = format!("xgroup|{}", subcommand.to_ascii_lowercase());
+    if ![
+        "CREATE",
+        "CREATECONSUMER",
+        "DELCONSUMER",
+        "DESTROY",
+        "SETID",
+    ]
+    .contains(&subcommand.as_str())
+    {
+        return Err(Error::UnknownSubcommand("XGROUP".to_string(), subcommand));
+    }
+
+    let key = args.next_arg()?;
+    let group = args.next_arg()?;
+    Ok(match subcommand.as_str() {
+        "CREATE" => {
+            let mut mkstream = false;
+            let (id, entries_read) = parse_group_id(args, Some(&mut mkstream))?;
+            Command::XGroupCreate(key, group, id, mkstream, entries_read)
+        }
+        "CREATECONSUMER" => Command::XGroupCreateConsumer(key, group, args.next_arg()?),
+        "DELCONSUMER" => Command::XGroupDelConsumer(key, group, args.next_arg()?),
+        "DESTROY" => Command::XGroupDestroy(key, group),
+        _ => {
+            let (id, entries_read) = parse_group_id(args, None)?;
+            Command::XGroupSetId(key, group, id, entries_read)
+        }
+    })
+}
+
+fn parse_xpending(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let group = args.next_arg()?;
+    if args.args.len() == 0 {
+        return Ok(Command::XPending(key, group, None));
+    }
+
+    let mut start = args.next_arg()?;
+    let mut min_idle = None;
+    if start.eq_ignore_ascii_case(b"IDLE") {
+        let idle = parse_int::<i64>(&args.next_option_value()?)?;
+        min_idle = Some(idle.max(0) as u64);
+        start = args.next_option_value()?;
+    }
+    let start = parse_stream_bound(&start, true)?;
+    let end = parse_stream_bound(&args.next_option_value()?, false)?;
+    let count = parse_int::<i64>(&args.next_option_value()?)?;
+    let consumer = args.args.next();
+    if args.args.len() > 0 {
+        return Err(Error::Syntax);
+    }
+
+    let range = PendingRange {
+        min_idle,
+        start,
+        end,
+        count: count.max(0) as usize,
+        consumer,
+    };
+    Ok(Command::XPending(key, group, Some(range)))
+}
+
+/// Parses the group, consumer and minimum idle time XCLAIM and XAUTOCLAIM
+/// start with.
+fn parse_claim(args: &mut Args) -> Result<Claim, Error> {
+    let group = args.next_arg()?;
+    let consumer = args.next_arg()?;
+    let min_idle = parse_int::<i64>(&args.next_arg()?)
+        .map_err(|_| Error::InvalidMinIdle(args.name.to_ascii_uppercase()))?;
+    Ok(Claim {
+        group,
+        consumer,
+        min_idle: min_idle.max(0) as u64,
+    })
+}
+
+/// Parses XCLAIM, whose IDs run up to the first argument that isn't one.
+fn parse_xclaim(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let claim = parse_claim(args)?;
+
+    let mut ids = Vec::new();
+    while let Some(id) = args.args.as_slice().first() {
+        match parse_stream_id(id, 0) {
+            Ok(id) => ids.push(id),
+            Err(_) => break,
+        }
+        args.args.next();
+    }
+    if ids.is_empty() {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+
+    let mut options = ClaimOptions::default();
+    while let Some(token) = args.next_token() {
+        let mut value = || {
+            let value = args.next_option_value()?;
+            parse_int::<i64>(&value).map_err(|_| Error::InvalidClaimOption(token.clone()))
+        };
+        match token.as_str() {
+            "IDLE" => options.delivery_time = Some(DeliveryTime::Idle(value()?.max(0) as u64)),
+            "TIME" => options.delivery_time = Some(DeliveryTime::At(value()?.max(0) as u64)),
+            "RETRYCOUNT" => options.retry_count = Some(value()?.max(0) as u64),
+            "FORCE" => options.force = true,
+            "JUSTID" => options.just_id = true,
+            "LASTID" => {
+                let id = args.next_option_value()?;
+                options.last_id = Some(parse_stream_id(&id, 0)?);
+            }
+            _ => return Err(Error::UnrecognizedClaimOption(token)),
+        }
+    }
+    Ok(Command::XClaim(key, claim, ids, options))
+}
+
+fn parse_xautoclaim(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let claim = parse_claim(args)?;
+    let start = parse_stream_bound(&args.next_arg()?, true)?;
+
+    let mut count = 100;
+    let mut just_id = false;
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "COUNT" => {
+                let value = parse_int::<i64>(&args.next_option_value()?)?;
+                count = usize::try_from(value)
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or(Error::CountNotPositive)?;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok(Command::XAutoClaim(key, claim, start, count, just_id))
+}
+
+fn parse_score_bound(arg: &[u8]) -> Result<ScoreBound, Error> {
+    let (value, exclusive) = match arg.strip_prefix(b"(") {
+        Some(value) => (value, true),
+        None => (arg, false),
+    };
+    let value = parse_float(value).map_err(|_| Error::InvalidScoreRange)?;
+    Ok(ScoreBound { value, exclusive })
+}
+
+fn parse_lex_bound(arg: Bytes) -> Result<LexBound, Error> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(arg.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(arg.slice(1..))),
+        _ => Err(Error::InvalidLexRange),
+    }
+}
+
+fn parse_sintercard(args: &mut Args) -> Result<Command, Error> {
+    let numkeys = parse_int::<usize>(&args.next_arg()?)
+        .ok()
+        .filter(|numkeys| *numkeys > 0)
+        .ok_or(Error::InvalidNumKeys)?;
+    if numkeys > args.args.len() {
+        return Err(Error::TooManyKeys);
+    }
+    let keys = args.args.by_ref().take(numkeys).collect();
+
+    let mut limit = 0;
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "LIMIT" => {
+                let value = parse_int::<i64>(&args.next_option_value()?)?;
+                limit = usize::try_from(value).map_err(|_| Error::Negative("LIMIT".to_string()))?;
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(Command::SInterCard(keys, limit))
+}
+
+fn parse_cursor(arg: &[u8]) -> Result<u64, Error> {
+    parse_int::<u64>(arg).map_err(|_| Error::InvalidCursor)
+}
+
+/// Parses the options following the cursor of SCAN and HSCAN.
+fn parse_scan_options(args: &mut Args) -> Result<ScanOptions, Error> {
+    let mut options = ScanOptions::default();
+
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "MATCH" => options.pattern = Some(args.next_option_value()?),
+            "COUNT" => {
+                options.count = parse_int::<usize>(&args.next_option_value()?)?;
+                if options.count == 0 {
+                    return Err(Error::Syntax);
+                }
+            }
+            "TYPE" if args.name == "scan" => {
+                let type_name = args.next_option_value()?;
+                options.type_name = Some(String::from_utf8_lossy(&type_name).to_ascii_lowercase());
+            }
+            "NOVALUES" if args.name == "hscan" => options.no_values = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_hello(args: &mut Args) -> Result<Command, Error> {
+    let Some(version) = args.args.next() else {
+        return Ok(Command::Hello(None));
+    };
+    let protocol = match parse_int::<i64>(&version).map_err(|_| Error::InvalidProtocolVersion)? {
+        2 => Protocol::Resp2,
+        3 => Protocol::Resp3,
+        _ => return Err(Error::NoProto),
+    };
+
+    // There are no users or client names to speak of, so AUTH and SETNAME
+    // are accepted and ignored.
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "AUTH" => {
+                args.next_option_value()?;
+                args.next_option_value()?;
+            }
+            "SETNAME" => {
+                args.next_option_value()?;
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(Command::Hello(Some(protocol)))
+}
+
+/// Most items a negative HRANDFIELD or SRANDMEMBER count may ask for. Redis
+/// streams such replies, but here they are built whole before being sent.
+const MAX_RANDOM_REPEATS: u64 = 1024 * 1024;
+
+fn parse_random_count(arg: &[u8]) -> Result<i64, Error> {
+    let count = parse_int::<i64>(arg)?;
+    if count < 0 && count.unsigned_abs() > MAX_RANDOM_REPEATS {
+        return Err(Error::ValueOutOfRange);
+    }
+    Ok(count)
+}
+
+fn parse_hrandfield(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let Some(count) = args.args.next() else {
+        return Ok(Command::HRandField(key, None));
+    };
+    let count = parse_random_count(&count)?;
+    let with_values = match args.next_token().as_deref() {
+        Some("WITHVALUES") => true,
+        Some(_) => return Err(Error::Syntax),
+        None => false,
+    };
+
+    Ok(Command::HRandField(key, Some((count, with_values))))
+}
+
+fn parse_set(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let value = args.next_arg()?;
+    let mut options = SetOptions::default();
+
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "NX" | "XX" => {
+                let condition = if token == "NX" {
+                    SetCondition::IfAbsent
+                } else {
+                    SetCondition::IfPresent
+                };
+                if options
+                    .condition
+                    .is_some_and(|current| current != condition)
+                {
+                    return Err(Error::Syntax);
+                }
+                options.condition = Some(condition);
+            }
+            "GET" => options.get = true,
+            "KEEPTTL" | "EX" | "PX" | "EXAT" | "PXAT" => {
+                if options.expiry.is_some() {
+                    return Err(Error::Syntax);
+                }
+                if token == "KEEPTTL" {
+                    options.expiry = Some(Expiry::KeepTtl);
+                    continue;
+                }
+
+                let (unit_ms, absolute) = match token.as_str() {
+                    "EX" => (1000, false),
+                    "PX" => (1, false),
+                    "EXAT" => (1000, true),
+                    _ => (1, true),
+                };
+                let ttl = parse_ttl(&args.next_option_value()?, unit_ms, "set")?;
+                options.expiry = Some(if absolute {
+                    Expiry::At(ttl)
+                } else {
+                    Expiry::After(ttl)
+                });
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    Ok(Command::Set(key, value, options))
+}
+
+/// Parses SETEX and PSETEX, whose TTL is in `unit_ms` milliseconds.
+fn parse_setex(args: &mut Args, unit_ms: u64) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let ttl = parse_ttl(&args.next_arg()?, unit_ms, &args.name)?;
+    let options = SetOptions {
+        expiry: Some(Expiry::After(ttl)),
+        ..SetOptions::default()
+    };
+    Ok(Command::Set(key, args.next_arg()?, options))
+}
+
+fn parse_getex(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let expiry = match args.next_token().as_deref() {
+        None => None,
+        Some("PERSIST") => Some(None),
+        Some(token @ ("EX" | "PX" | "EXAT" | "PXAT")) => {
+            let unit_ms = match token {
+                "EX" | "EXAT" => 1000,
+                _ => 1,
+            };
+            let ttl = parse_ttl(&args.next_option_value()?, unit_ms, "getex")?;
+            Some(Some(match token {
+                "EXAT" | "PXAT" => Expiry::At(ttl),
+                _ => Expiry::After(ttl),
+            }))
+        }
+        Some(_) => return Err(Error::Syntax),
+    };
+    if args.args.len() > 0 {
+        return Err(Error::Syntax);
+    }
+    Ok(Command::GetEx(key, expiry))
+}
+
+fn parse_lcs(args: &mut Args) -> Result<Command, Error> {
+    let key_a = args.next_arg()?;
+    let key_b = args.next_arg()?;
+    let mut options = LcsOptions::default();
+
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "LEN" => options.len = true,
+            "IDX" => options.idx = true,
+            "WITHMATCHLEN" => options.with_match_len = true,
+            "MINMATCHLEN" => {
+                let len = parse_int::<i64>(&args.next_option_value()?)?;
+                options.min_match_len = len.max(0) as usize;
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    if options.len && options.idx {
+        return Err(Error::LcsLenAndIdx);
+    }
+    Ok(Command::Lcs(key_a, key_b, options))
+}
+
+fn parse_lon_lat(lon: &[u8], lat: &[u8]) -> Result<(f64, f64), Error> {
+    let (lon, lat) = (parse_float(lon)?, parse_float(lat)?);
+    if lon.abs() > MAX_LONGITUDE || lat.abs() > MAX_LATITUDE {
+        return Err(Error::InvalidLonLat(format!("{lon:.6},{lat:.6}")));
+    }
+    Ok((lon, lat))
+}
+
+fn parse_distance_unit(arg: &[u8]) -> Result<DistanceUnit, Error> {
+    match String::from_utf8_lossy(arg).to_ascii_lowercase().as_str() {
+        "m" => Ok(DistanceUnit::Meters),
+        "km" => Ok(DistanceUnit::Kilometers),
+        "ft" => Ok(DistanceUnit::Feet),
+        "mi" => Ok(DistanceUnit::Miles),
+        _ => Err(Error::UnsupportedUnit),
+    }
+}
+
+fn parse_geoadd(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    if args.args.len() < 3 {
+        return Err(Error::WrongArity(args.name.clone()));
+    }
+
+    let mut options = ZAddOptions::default();
+    while let Some(arg) = args.args.as_slice().first() {
+        match String::from_utf8_lossy(arg).to_ascii_uppercase().as_str() {
+            "NX" => options.nx = true,
+            "XX" => options.xx = true,
+            "CH" => options.ch = true,
+            _ => break,
+        }
+        args.args.next();
+    }
+
+    let args: Vec<Bytes> = args.args.by_ref().collect();
+    let members = args.chunks_exact(3);
+    if args.is_empty() || !members.remainder().is_empty() || (options.nx && options.xx) {
+        return Err(Error::Syntax);
+    }
+    let members = members
+        .map(|member| {
+            let (lon, lat) = parse_lon_lat(&member[0], &member[1])?;
+            Ok((lon, lat, member[2].clone()))
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Command::GeoAdd(key, options, members))
+}
+
+fn parse_bf_reserve(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let error_rate = parse_float(&args.next_arg()?).map_err(|_| Error::BadErrorRate)?;
+    if error_rate <= 0.0 || error_rate >= 1.0 {
+        return Err(Error::BloomErrorRateRange);
+    }
+    let capacity = parse_int::<i64>(&args.next_arg()?).map_err(|_| Error::BadBloomCapacity)?;
+    let capacity = u64::try_from(capacity)
+        .ok()
+        .filter(|capacity| *capacity > 0)
+        .ok_or(Error::BloomCapacityRange)?;
+
+    let mut options = BloomOptions {
+        error_rate,
+        capacity,
+        ..BloomOptions::default()
+    };
+    let (mut expansion, mut non_scaling) = (None, false);
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "EXPANSION" => {
+                let value = parse_int::<i64>(&args.next_option_value()?)
+                    .map_err(|_| Error::BadBloomExpansion)?;
+                let value = u32::try_from(value)
+                    .ok()
+                    .filter(|value| *value > 0)
+                    .ok_or(Error::BloomExpansionRange)?;
+                expansion = Some(value);
+            }
+            "NONSCALING" => non_scaling = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    options.expansion = match (expansion, non_scaling) {
+        (Some(_), true) => return Err(Error::NonScalingExpansion),
+        (_, true) => None,
+        (Some(expansion), false) => Some(expansion),
+        (None, false) => options.expansion,
+    };
+    Ok(Command::BfReserve(key, options))
+}
+
+/// Parses an option of CF.RESERVE that must be an integer in `range`.
+fn parse_cuckoo_option<T: TryFrom<i64>>(
+    arg: &[u8],
+    range: std::ops::RangeInclusive<i64>,
+    error: Error,
+) -> Result<T, Error> {
+    parse_int::<i64>(arg)
+        .ok()
+        .filter(|value| range.contains(value))
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or(error)
+}
+
+fn parse_cf_reserve(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let mut options = CuckooOptions {
+        capacity: parse_cuckoo_option(&args.next_arg()?, 1..=i64::MAX, Error::BadCuckooCapacity)?,
+        ..CuckooOptions::default()
+    };
+    while let Some(token) = args.next_token() {
+        let value = args.next_option_value()?;
+        match token.as_str() {
+            "BUCKETSIZE" => {
+                options.bucket_size = parse_cuckoo_option(&value, 1..=255, Error::BadBucketSize)?;
+            }
+            "MAXITERATIONS" => {
+                options.max_iterations =
+                    parse_cuckoo_option(&value, 1..=65535, Error::BadMaxIterations)?;
+            }
+            "EXPANSION" => {
+                options.expansion =
+                    parse_cuckoo_option(&value, 0..=32768, Error::BadCuckooExpansion)?;
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    if options.capacity < options.bucket_size as u64 * 2 {
+        return Err(Error::CuckooCapacityTooSmall);
+    }
+    Ok(Command::CfReserve(key, options))
+}
+
+/// Parses CF.INSERT, or CF.INSERTNX if `nx`.
+fn parse_cf_insert(args: &mut Args, nx: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let mut create = Some(CuckooOptions::default());
+    loop {
+        let token = args.next_token().ok_or(Error::Syntax)?;
+        match token.as_str() {
+            "CAPACITY" => {
+                let value = args.next_option_value()?;
+                let capacity = parse_cuckoo_option(&value, 1..=i64::MAX, Error::BadCuckooCapacity)?;
+                if let Some(options) = &mut create {
+                    options.capacity = capacity;
+                }
+            }
+            "NOCREATE" => create = None,
+            "ITEMS" => break,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    Ok(Command::CfInsert(key, create, args.keys()?, nx, true))
+}
+
+fn parse_json(arg: &[u8]) -> Result<Json, Error> {
+    Ok(crate::json::parse(arg)?)
+}
+
+fn parse_json_path(arg: &[u8]) -> Result<Path, Error> {
+    Ok(Path::parse(&String::from_utf8_lossy(arg))?)
+}
+
+/// Parses the path argument commands default to the root without.
+fn parse_optional_json_path(args: &mut Args) -> Result<Path, Error> {
+    match args.args.next() {
+        Some(path) => parse_json_path(&path),
+        None => Ok(Path::root()),
+    }
+}
+
+/// Parses JSON.GET, whose formatting options and paths may come in any
+/// order.
+fn parse_json_get(args: &mut Args) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let mut format = Format::default();
+    let mut paths = Vec::new();
+    while let Some(arg) = args.args.next() {
+        let value = match String::from_utf8_lossy(&arg).to_ascii_uppercase().as_str() {
+            "INDENT" => &mut format.indent,
+            "NEWLINE" => &mut format.newline,
+            "SPACE" => &mut format.space,
+            _ => {
+                paths.push(parse_json_path(&arg)?);
+                continue;
+            }
+        };
+        *value = String::from_utf8_lossy(&args.next_option_value()?).into_owned();
+    }
+    Ok(Command::JsonGet(key, format, paths))
+}
+
+/// Parses the options of GEOSEARCH, or of GEOSEARCHSTORE if `store`, which
+/// also takes STOREDIST. Returns whether STOREDIST was given along with them.
+fn parse_geosearch(args: &mut Args, store: bool) -> Result<(GeoSearch, bool), Error> {
+    let name = args.name.to_ascii_uppercase();
+    let mut origin = None;
+    let mut shape = None;
+    let mut order = None;
+    let mut count = None;
+    let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+        (false, false, false, false);
+
+    while let Some(token) = args.next_token() {
+        match token.as_str() {
+            "FROMMEMBER" | "FROMLONLAT" if origin.is_some() => {
+                return Err(Error::GeoOrigin(name));
+            }
+            "FROMMEMBER" => origin = Some(GeoOrigin::Member(args.next_option_value()?)),
+            "FROMLONLAT" => {
+                let lon = args.next_option_value()?;
+                let (lon, lat) = parse_lon_lat(&lon, &args.next_option_value()?)?;
+                origin = Some(GeoOrigin::LonLat(lon, lat));
+            }
+            "BYRADIUS" | "BYBOX" if shape.is_some() => return Err(Error::GeoShape(name)),
+            "BYRADIUS" => {
+                let radius = parse_float(&args.next_option_value()?)?;
+                if radius < 0.0 {
+                    return Err(Error::NegativeRadius);
+                }
+                let unit = parse_distance_unit(&args.next_option_value()?)?;
+                shape = Some((GeoShape::Radius(radius * unit.as_meters()), unit));
+            }
+            "BYBOX" => {
+                let width = parse_float(&args.next_option_value()?)?;
+                let height = parse_float(&args.next_option_value()?)?;
+                if width < 0.0 || height < 0.0 {
+                    return Err(Error::NegativeBox);
+                }
+                let unit = parse_distance_unit(&args.next_option_value()?)?;
+                let meters = unit.as_meters();
+                shape = Some((GeoShape::Box(width * meters, height * meters), unit));
+            }
+            "ASC" => order = Some(SortOrder::Asc),
+            "DESC" => order = Some(SortOrder::Desc),
+            "COUNT" => {
+                let limit = parse_int::<i64>(&args.next_option_value()?)?;
+                let limit = usize::try_from(limit)
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or(Error::CountNotPositive)?;
+                let any = args
+                    .args
+                    .as_slice()
+                    .first()
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case(b"ANY"));
+                if any {
+                    args.args.next();
+                }
+                count = Some((limit, any));
+            }
+            "WITHCOORD" => with_coord = true,
+            "WITHDIST" => with_dist = true,
+            "WITHHASH" => with_hash = true,
+            "STOREDIST" if store => store_dist = true,
+            _ => return Err(Error::Syntax),
+        }
+    }
+
+    if store && (with_coord || with_dist || with_hash) {
+        return Err(Error::GeoStoreWith(name));
+    }
+    let origin = origin.ok_or_else(|| Error::GeoOrigin(name.clone()))?;
+    let (shape, unit) = shape.ok_or(Error::GeoShape(name))?;
+    let search = GeoSearch {
+        origin,
+        shape,
+        unit,
+        order,
+        count,
+        with_coord,
+        with_dist,
+        with_hash,
+    };
+    Ok((search, store_dist))
+}
+
+/// Most bits a string holds, at its largest length of 512MB.
+const MAX_BITS: u64 = 512 * 1024 * 1024 * 8;
+
+/// Parses the offset of a field `bits` wide, which BITFIELD also takes as
+/// `#N` for the `N`th field of its width if `indexed`.
+fn parse_bit_offset(arg: &[u8], bits: u32, indexed: bool) -> Result<usize, Error> {
+    let (arg, width) = match arg.strip_prefix(b"#") {
+        Some(arg) if indexed => (arg, bits as u64),
+        _ => (arg, 1),
+    };
+    parse_int::<u64>(arg)
+        .ok()
+        .and_then(|offset| offset.checked_mul(width))
+        .filter(|offset| offset + bits as u64 <= MAX_BITS)
+        .map(|offset| offset as usize)
+        .ok_or(Error::BitOffsetOutOfRange)
+}
+
+/// Parses the optional range of BITCOUNT and BITPOS; BITCOUNT takes a start
+/// alone as a syntax error rather than a range to the end of the string.
+fn parse_bit_range(args: &mut Args, end_required: bool) -> Result<Option<BitRange>, Error> {
+    let Some(start) = args.args.next() else {
+        return Ok(None);
+    };
+    let start = parse_int(&start)?;
+    let end = args.args.next().map(|end| parse_int(&end)).transpose()?;
+    if end.is_none() && end_required {
+        return Err(Error::Syntax);
+    }
+    let unit = match args.next_token().as_deref() {
+        None | Some("BYTE") => RangeUnit::Byte,
+        Some("BIT") => RangeUnit::Bit,
+        Some(_) => return Err(Error::Syntax),
+    };
+    if args.args.len() > 0 {
+        return Err(Error::Syntax);
+    }
+    Ok(Some(BitRange { start, end, unit }))
+}
+
+fn parse_bitop(args: &mut Args) -> Result<Command, Error> {
+    let op = match args.next_token().as_deref() {
+        Some("AND") => BitOperation::And,
+        Some("OR") => BitOperation::Or,
+        Some("XOR") => BitOperation::Xor,
+        Some("NOT") => BitOperation::Not,
+        Some(_) => return Err(Error::Syntax),
+        None => return Err(Error::WrongArity(args.name.clone())),
+    };
+    let destination = args.next_arg()?;
+    let keys = args.keys()?;
+    if op == BitOperation::Not && keys.len() != 1 {
+        return Err(Error::BitopNotSingleKey);
+    }
+    Ok(Command::BitOp(op, destination, keys))
+}
+
+fn parse_bitfield_type(arg: &[u8]) -> Result<BitField, Error> {
+    let (signed, bits) = match arg.split_first() {
+        Some((b'i' | b'I', bits)) => (true, bits),
+        Some((b'u' | b'U', bits)) => (false, bits),
+        _ => return Err(Error::InvalidBitfieldType),
+    };
+    let max = if signed { 64 } else { 63 };
+    parse_int::<u32>(bits)
+        .ok()
+        .filter(|bits| (1..=max).contains(bits))
+        .map(|bits| BitField { signed, bits })
+        .ok_or(Error::InvalidBitfieldType)
+}
+
+/// Parses BITFIELD, or BITFIELD_RO if `read_only`.
+fn parse_bitfield(args: &mut Args, read_only: bool) -> Result<Command, Error> {
+    let key = args.next_arg()?;
+    let mut ops = Vec::new();
+    while let Some(token) = args.next_token() {
+        if read_only && token != "GET" {
+            return Err(Error::BitfieldRoGetOnly);
+        }
+        if token == "OVERFLOW" {
+            let overflow = match args.next_token().as_deref() {
+                Some("WRAP") => Overflow::Wrap,
+                Some("SAT") => Overflow::Sat,
+                Some("FAIL") => Overflow::Fail,
+                Some(_) => return Err(Error::InvalidOverflowType),
+                None => return Err(Error::Syntax),
+            };
+            ops.push(BitFieldOp::Overflow(overflow));
+            continue;
+        }
+
+        let (field, offset) = match token.as_str() {
+            "GET" | "SET" | "INCRBY" => (args.next_option_value()?, args.next_option_value()?),
+            _ => return Err(Error::Syntax),
+        };
+        let field = parse_bitfield_type(&field)?;
+        let offset = parse_bit_offset(&offset, field.bits, true)?;
+        ops.push(match token.as_str() {
+            "GET" => BitFieldOp::Get(field, offset),
+            "SET" => BitFieldOp::Set(field, offset, parse_int(&args.next_option_value()?)?),
+            _ => BitFieldOp::IncrBy(field, offset, parse_int(&args.next_option_value()?)?),
+        });
+    }
+    Ok(Command::BitField(key, ops))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_command() {
+        let input = b"*1\r\n$4\r\nPING\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Ping);
+    }
+
+    #[test]
+    fn test_echo_command() {
+        let input = b"*2\r\n$4\r\nECHO\r\n$5\r\nHello\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Echo(Bytes::from("Hello")));
+    }
+
+    #[test]
+    fn test_set_command_with_expiry() {
+        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nPX\r\n$2\r\n10\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from("value"),
+                SetOptions {
+                    expiry: Some(Expiry::After(10)),
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_command_without_expiry() {
+        let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from("value"),
+                SetOptions::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_command_with_binary_value() {
+        let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$3\r\n\xff\x00\xfe\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from_static(b"\xff\x00\xfe"),
+                SetOptions::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_command_with_all_options() {
+        let input = b"*7\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nnx\r\n$2\r\nEX\r\n$2\r\n30\r\n$3\r\nGET\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from("value"),
+                SetOptions {
+                    expiry: Some(Expiry::After(30_000)),
+                    condition: Some(SetCondition::IfAbsent),
+                    get: true,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_command_with_absolute_expiry() {
+        let input = b"*6\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nXX\r\n$4\r\nEXAT\r\n$10\r\n1700000000\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from("value"),
+                SetOptions {
+                    expiry: Some(Expiry::At(1_700_000_000_000)),
+                    condition: Some(SetCondition::IfPresent),
+                    get: false,
+                }
+            )
+        );
+
+        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$4\r\nPXAT\r\n$3\r\n123\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from("value"),
+                SetOptions {
+                    expiry: Some(Expiry::At(123)),
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_command_with_keepttl() {
+        let input = b"*4\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$7\r\nKEEPTTL\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Set(
+                Bytes::from("key"),
+                Bytes::from("value"),
+                SetOptions {
+                    expiry: Some(Expiry::KeepTtl),
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_set_command_with_conflicting_options() {
+        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nNX\r\n$2\r\nXX\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*6\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nEX\r\n$1\r\n1\r\n$7\r\nKEEPTTL\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_set_command_with_malformed_expiry() {
+        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nPX\r\n$3\r\nabc\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotInteger));
+
+        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nEX\r\n$1\r\n0\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidExpireTime("set".to_string()))
+        );
+
+        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nEX\r\n$19\r\n9223372036854775807\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidExpireTime("set".to_string()))
+        );
+
+        let input = b"*4\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nEX\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_set_command_with_unknown_option() {
+        let input = b"*4\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$3\r\nFOO\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_set_command_wrong_arity() {
+        let input = b"*2\r\n$3\r\nSET\r\n$3\r\nkey\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("set".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_command_variants() {
+        let input = b"*3\r\n$6\r\nGETSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = SetOptions {
+            get: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            command,
+            Command::Set(Bytes::from("key"), Bytes::from("value"), options)
+        );
+
+        let input = b"*4\r\n$5\r\nSETEX\r\n$3\r\nkey\r\n$2\r\n10\r\n$5\r\nvalue\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = SetOptions {
+            expiry: Some(Expiry::After(10_000)),
+            ..Default::default()
+        };
+        assert_eq!(
+            command,
+            Command::Set(Bytes::from("key"), Bytes::from("value"), options)
+        );
+
+        let input = b"*4\r\n$6\r\nPSETEX\r\n$3\r\nkey\r\n$2\r\n10\r\n$5\r\nvalue\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = SetOptions {
+            expiry: Some(Expiry::After(10)),
+            ..Default::default()
+        };
+        assert_eq!(
+            command,
+            Command::Set(Bytes::from("key"), Bytes::from("value"), options)
+        );
+
+        let input = b"*4\r\n$5\r\nSETEX\r\n$3\r\nkey\r\n$1\r\n0\r\n$5\r\nvalue\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidExpireTime("setex".to_string()))
+        );
+
+        let input = b"*3\r\n$5\r\nSETNX\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SetNx(Bytes::from("key"), Bytes::from("value"))
+        );
+    }
+
+    #[test]
+    fn test_mset_and_mget_commands() {
+        let input = b"*5\r\n$4\r\nMSET\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let pairs = vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b"), Bytes::from("2")),
+        ];
+        assert_eq!(command, Command::MSet(pairs));
+
+        let input = b"*4\r\n$4\r\nMSET\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("mset".to_string()))
+        );
+        let input = b"*3\r\n$6\r\nMSETNX\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let pairs = vec![(Bytes::from("a"), Bytes::from("1"))];
+        assert_eq!(command, Command::MSetNx(pairs));
+
+        let input = b"*3\r\n$4\r\nMGET\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let keys = vec![Bytes::from("a"), Bytes::from("b")];
+        assert_eq!(command, Command::MGet(keys));
+    }
+
+    #[test]
+    fn test_getex_command() {
+        let input = b"*4\r\n$5\r\nGETEX\r\n$3\r\nkey\r\n$2\r\nPX\r\n$2\r\n10\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let expiry = Some(Some(Expiry::After(10)));
+        assert_eq!(command, Command::GetEx(Bytes::from("key"), expiry));
+        let input = b"*3\r\n$5\r\nGETEX\r\n$3\r\nkey\r\n$7\r\nPERSIST\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::GetEx(Bytes::from("key"), Some(None)));
+
+        let input = b"*5\r\n$5\r\nGETEX\r\n$3\r\nkey\r\n$7\r\nPERSIST\r\n$2\r\nEX\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*4\r\n$5\r\nGETEX\r\n$3\r\nkey\r\n$4\r\nEXAT\r\n$1\r\n0\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidExpireTime("getex".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_string_range_commands() {
+        let input = b"*3\r\n$6\r\nAPPEND\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Append(Bytes::from("key"), Bytes::from("value"))
+        );
+        let input = b"*2\r\n$6\r\nSTRLEN\r\n$3\r\nkey\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::StrLen(Bytes::from("key")));
+        let input = b"*2\r\n$6\r\nGETDEL\r\n$3\r\nkey\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::GetDel(Bytes::from("key")));
+
+        let input = b"*4\r\n$8\r\nGETRANGE\r\n$3\r\nkey\r\n$1\r\n0\r\n$2\r\n-1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::GetRange(Bytes::from("key"), 0, -1));
+        let input = b"*4\r\n$8\r\nSETRANGE\r\n$3\r\nkey\r\n$1\r\n5\r\n$1\r\nx\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SetRange(Bytes::from("key"), 5, Bytes::from("x"))
+        );
+        let input = b"*4\r\n$8\r\nSETRANGE\r\n$3\r\nkey\r\n$2\r\n-1\r\n$1\r\nx\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::OffsetOutOfRange));
+    }
+
+    #[test]
+    fn test_lcs_command() {
+        let input = b"*7\r\n$3\r\nLCS\r\n$1\r\na\r\n$1\r\nb\r\n$3\r\nIDX\r\n$11\r\nMINMATCHLEN\r\n$1\r\n4\r\n$12\r\nWITHMATCHLEN\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = LcsOptions {
+            len: false,
+            idx: true,
+            min_match_len: 4,
+            with_match_len: true,
+        };
+        assert_eq!(
+            command,
+            Command::Lcs(Bytes::from("a"), Bytes::from("b"), options)
+        );
+
+        let input = b"*5\r\n$3\r\nLCS\r\n$1\r\na\r\n$1\r\nb\r\n$3\r\nLEN\r\n$3\r\nIDX\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::LcsLenAndIdx));
+    }
+
+    #[test]
+    fn test_bit_commands() {
+        let input = b"*4\r\n$6\r\nSETBIT\r\n$1\r\nk\r\n$1\r\n7\r\n$1\r\n1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::SetBit(Bytes::from("k"), 7, true));
+
+        let input = b"*4\r\n$6\r\nSETBIT\r\n$1\r\nk\r\n$10\r\n4294967296\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::BitOffsetOutOfRange));
+
+        let input = b"*4\r\n$6\r\nSETBIT\r\n$1\r\nk\r\n$1\r\n7\r\n$1\r\n2\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::BitNotInteger));
+
+        let input = b"*3\r\n$6\r\nGETBIT\r\n$1\r\nk\r\n$10\r\n4294967295\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::GetBit(Bytes::from("k"), 4294967295));
+
+        let input = b"*2\r\n$8\r\nBITCOUNT\r\n$1\r\nk\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::BitCount(Bytes::from("k"), None));
+
+        let input = b"*5\r\n$8\r\nBITCOUNT\r\n$1\r\nk\r\n$1\r\n1\r\n$2\r\n-2\r\n$3\r\nBIT\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let range = BitRange {
+            start: 1,
+            end: Some(-2),
+            unit: RangeUnit::Bit,
+        };
+        assert_eq!(command, Command::BitCount(Bytes::from("k"), Some(range)));
+
+        let input = b"*3\r\n$8\r\nBITCOUNT\r\n$1\r\nk\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*4\r\n$6\r\nBITPOS\r\n$1\r\nk\r\n$1\r\n0\r\n$1\r\n2\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let range = BitRange {
+            start: 2,
+            end: None,
+            unit: RangeUnit::Byte,
+        };
+        assert_eq!(
+            command,
+            Command::BitPos(Bytes::from("k"), false, Some(range))
+        );
+
+        let input = b"*3\r\n$6\r\nBITPOS\r\n$1\r\nk\r\n$1\r\n2\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::BitArgument));
+    }
+
+    #[test]
+    fn test_bitop_command() {
+        let input = b"*5\r\n$5\r\nBITOP\r\n$3\r\nAND\r\n$1\r\nd\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BitOp(
+                BitOperation::And,
+                Bytes::from("d"),
+                vec![Bytes::from("a"), Bytes::from("b")]
+            )
+        );
+
+        let input = b"*5\r\n$5\r\nBITOP\r\n$3\r\nNOT\r\n$1\r\nd\r\n$1\r\na\r\n$1\r\nb\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::BitopNotSingleKey));
+    }
+
+    #[test]
+    fn test_bitfield_command() {
+        let input = b"*15\r\n$8\r\nBITFIELD\r\n$1\r\nk\r\n$6\r\nINCRBY\r\n$2\r\nu2\r\n$2\r\n#3\r\n$1\r\n1\r\n$8\r\nOVERFLOW\r\n$4\r\nFAIL\r\n$3\r\nSET\r\n$2\r\ni8\r\n$3\r\n100\r\n$2\r\n-1\r\n$3\r\nGET\r\n$3\r\ni64\r\n$1\r\n0\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let field = |signed, bits| BitField { signed, bits };
+        let ops = vec![
+            BitFieldOp::IncrBy(field(false, 2), 6, 1),
+            BitFieldOp::Overflow(Overflow::Fail),
+            BitFieldOp::Set(field(true, 8), 100, -1),
+            BitFieldOp::Get(field(true, 64), 0),
+        ];
+        assert_eq!(command, Command::BitField(Bytes::from("k"), ops));
+
+        let input = b"*5\r\n$8\r\nBITFIELD\r\n$1\r\nk\r\n$3\r\nGET\r\n$3\r\nu64\r\n$1\r\n0\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidBitfieldType));
+
+        let input = b"*4\r\n$8\r\nBITFIELD\r\n$1\r\nk\r\n$8\r\nOVERFLOW\r\n$4\r\nNONE\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidOverflowType));
+
+        let input = b"*5\r\n$11\r\nBITFIELD_RO\r\n$1\r\nk\r\n$3\r\nGET\r\n$2\r\nu8\r\n$2\r\n#1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let ops = vec![BitFieldOp::Get(field(false, 8), 8)];
+        assert_eq!(command, Command::BitField(Bytes::from("k"), ops));
+
+        let input = b"*6\r\n$11\r\nBITFIELD_RO\r\n$1\r\nk\r\n$3\r\nSET\r\n$2\r\nu8\r\n$1\r\n0\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::BitfieldRoGetOnly));
+    }
+
+    #[test]
+    fn test_hyperloglog_commands() {
+        let input = b"*2\r\n$5\r\nPFADD\r\n$3\r\nhll\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::PfAdd(Bytes::from("hll"), vec![]));
+
+        let input = b"*4\r\n$5\r\nPFADD\r\n$3\r\nhll\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::PfAdd(Bytes::from("hll"), vec![Bytes::from("a"), Bytes::from("b")])
+        );
+
+        let input = b"*3\r\n$7\r\nPFCOUNT\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::PfCount(vec![Bytes::from("a"), Bytes::from("b")])
+        );
+
+        let input = b"*2\r\n$7\r\nPFMERGE\r\n$4\r\ndest\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::PfMerge(Bytes::from("dest"), vec![]));
+    }
+
+    #[test]
+    fn test_bloom_commands() {
+        let input = b"*6\r\n$10\r\nBF.RESERVE\r\n$2\r\nbf\r\n$5\r\n0.001\r\n$4\r\n1000\r\n$9\r\nEXPANSION\r\n$1\r\n4\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = BloomOptions {
+            error_rate: 0.001,
+            capacity: 1000,
+            expansion: Some(4),
+        };
+        assert_eq!(command, Command::BfReserve(Bytes::from("bf"), options));
+
+        let input = b"*5\r\n$10\r\nBF.RESERVE\r\n$2\r\nbf\r\n$4\r\n0.01\r\n$2\r\n10\r\n$10\r\nNONSCALING\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = BloomOptions {
+            error_rate: 0.01,
+            capacity: 10,
+            expansion: None,
+        };
+        assert_eq!(command, Command::BfReserve(Bytes::from("bf"), options));
+
+        for (error_rate, capacity, error) in [
+            ("x", "10", Error::BadErrorRate),
+            ("1", "10", Error::BloomErrorRateRange),
+            ("0.1", "x", Error::BadBloomCapacity),
+            ("0.1", "0", Error::BloomCapacityRange),
+        ] {
+            let input = format!(
+                "*4\r\n$10\r\nBF.RESERVE\r\n$2\r\nbf\r\n${}\r\n{error_rate}\r\n${}\r\n{capacity}\r\n",
+                error_rate.len(),
+                capacity.len()
+            );
+            assert_eq!(Command::from_bytes(input.as_bytes()), Err(error));
+        }
+        let input = b"*7\r\n$10\r\nBF.RESERVE\r\n$2\r\nbf\r\n$4\r\n0.01\r\n$2\r\n10\r\n$10\r\nNONSCALING\r\n$9\r\nEXPANSION\r\n$1\r\n2\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NonScalingExpansion));
+
+        let input = b"*4\r\n$7\r\nBF.MADD\r\n$2\r\nbf\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BfAdd(
+                Bytes::from("bf"),
+                vec![Bytes::from("a"), Bytes::from("b")],
+                true
+            )
+        );
+
+        let input = b"*3\r\n$9\r\nBF.EXISTS\r\n$2\r\nbf\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BfExists(Bytes::from("bf"), vec![Bytes::from("a")], false)
+        );
+
+        let input = b"*3\r\n$7\r\nBF.INFO\r\n$2\r\nbf\r\n$5\r\nitems\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BfInfo(Bytes::from("bf"), Some(BloomInfoField::Items))
+        );
+    }
+
+    #[test]
+    fn test_cuckoo_commands() {
+        let input = b"*9\r\n$10\r\nCF.RESERVE\r\n$2\r\ncf\r\n$4\r\n1000\r\n$10\r\nBUCKETSIZE\r\n$1\r\n4\r\n$13\r\nMAXITERATIONS\r\n$2\r\n50\r\n$9\r\nEXPANSION\r\n$1\r\n0\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = CuckooOptions {
+            capacity: 1000,
+            bucket_size: 4,
+            max_iterations: 50,
+            expansion: 0,
+        };
+        assert_eq!(command, Command::CfReserve(Bytes::from("cf"), options));
+
+        let input =
+            b"*5\r\n$10\r\nCF.RESERVE\r\n$2\r\ncf\r\n$1\r\n4\r\n$10\r\nBUCKETSIZE\r\n$3\r\n256\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::BadBucketSize));
+        let input =
+            b"*5\r\n$10\r\nCF.RESERVE\r\n$2\r\ncf\r\n$1\r\n4\r\n$10\r\nBUCKETSIZE\r\n$1\r\n4\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::CuckooCapacityTooSmall)
+        );
+
+        let input = b"*3\r\n$8\r\nCF.ADDNX\r\n$2\r\ncf\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::CfInsert(
+                Bytes::from("cf"),
+                Some(CuckooOptions::default()),
+                vec![Bytes::from("a")],
+                true,
+                false
+            )
+        );
+
+        let input = b"*7\r\n$9\r\nCF.INSERT\r\n$2\r\ncf\r\n$8\r\nCAPACITY\r\n$2\r\n10\r\n$5\r\nITEMS\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = CuckooOptions {
+            capacity: 10,
+            ..CuckooOptions::default()
+        };
+        assert_eq!(
+            command,
+            Command::CfInsert(
+                Bytes::from("cf"),
+                Some(options),
+                vec![Bytes::from("a"), Bytes::from("b")],
+                false,
+                true
+            )
+        );
+
+        let input =
+            b"*5\r\n$11\r\nCF.INSERTNX\r\n$2\r\ncf\r\n$8\r\nNOCREATE\r\n$5\r\nITEMS\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::CfInsert(Bytes::from("cf"), None, vec![Bytes::from("a")], true, true)
+        );
+
+        let input = b"*3\r\n$9\r\nCF.INSERT\r\n$2\r\ncf\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*3\r\n$9\r\nCF.INSERT\r\n$2\r\ncf\r\n$5\r\nITEMS\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("cf.insert".to_string()))
+        );
+
+        let input = b"*3\r\n$6\r\nCF.DEL\r\n$2\r\ncf\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::CfDel(Bytes::from("cf"), Bytes::from("a")));
+    }
+
+    #[test]
+    fn test_json_commands() {
+        let json = |text: &str| crate::json::parse(text.as_bytes()).unwrap();
+        let path = |text: &str| Path::parse(text).unwrap();
+
+        let input =
+            b"*5\r\n$8\r\nJSON.SET\r\n$3\r\ndoc\r\n$1\r\n$\r\n$9\r\n{\"a\":[1]}\r\n$2\r\nnx\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::JsonSet(
+                Bytes::from("doc"),
+                path("$"),
+                json(r#"{"a":[1]}"#),
+                Some(SetCondition::IfAbsent)
+            )
+        );
+
+        let input =
+            b"*5\r\n$8\r\nJSON.SET\r\n$3\r\ndoc\r\n$3\r\n$.a\r\n$1\r\n1\r\n$7\r\nKEEPTTL\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*4\r\n$8\r\nJSON.SET\r\n$3\r\ndoc\r\n$1\r\n$\r\n$1\r\n{\r\n";
+        let error = Command::from_bytes(input).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "EOF while parsing an object at line 1 column 2"
+        );
+
+        let input = b"*8\r\n$8\r\nJSON.GET\r\n$3\r\ndoc\r\n$6\r\nINDENT\r\n$1\r\n\t\r\n$3\r\n$.a\r\n$7\r\nNEWLINE\r\n$1\r\n\n\r\n$2\r\n.b\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let format = Format {
+            indent: "\t".to_string(),
+            newline: "\n".to_string(),
+            space: String::new(),
+        };
+        assert_eq!(
+            command,
+            Command::JsonGet(Bytes::from("doc"), format, vec![path("$.a"), path(".b")])
+        );
+
+        let input = b"*3\r\n$8\r\nJSON.GET\r\n$3\r\ndoc\r\n$2\r\n$[\r\n";
+        let error = Command::from_bytes(input).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid JSONPath '$['");
+
+        let input =
+            b"*5\r\n$14\r\nJSON.ARRAPPEND\r\n$3\r\ndoc\r\n$3\r\n$.a\r\n$1\r\n2\r\n$3\r\n\"x\"\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::JsonArrAppend(
+                Bytes::from("doc"),
+                path("$.a"),
+                vec![json("2"), json("\"x\"")]
+            )
+        );
+
+        let input = b"*4\r\n$14\r\nJSON.NUMINCRBY\r\n$3\r\ndoc\r\n$5\r\n.a[0]\r\n$3\r\n\"x\"\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotFloat));
+
+        let input = b"*2\r\n$8\r\nJSON.DEL\r\n$3\r\ndoc\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::JsonDel(Bytes::from("doc"), Path::root()));
+
+        let input = b"*3\r\n$11\r\nJSON.FORGET\r\n$3\r\ndoc\r\n$4\r\n$..a\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::JsonDel(Bytes::from("doc"), path("$..a")));
+
+        let input = b"*4\r\n$9\r\nJSON.TYPE\r\n$3\r\ndoc\r\n$1\r\nx\r\n$1\r\ny\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("json.type".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_geoadd_and_geodist_commands() {
+        let input = b"*10\r\n$6\r\nGEOADD\r\n$6\r\nSicily\r\n$2\r\nNX\r\n$2\r\nCH\r\n$9\r\n13.361389\r\n$9\r\n38.115556\r\n$7\r\nPalermo\r\n$9\r\n15.087269\r\n$9\r\n37.502669\r\n$7\r\nCatania\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = ZAddOptions {
+            nx: true,
+            ch: true,
+            ..ZAddOptions::default()
+        };
+        let members = vec![
+            (13.361389, 38.115556, Bytes::from("Palermo")),
+            (15.087269, 37.502669, Bytes::from("Catania")),
+        ];
+        assert_eq!(
+            command,
+            Command::GeoAdd(Bytes::from("Sicily"), options, members)
+        );
+
+        let input = b"*5\r\n$6\r\nGEOADD\r\n$6\r\nSicily\r\n$3\r\n200\r\n$2\r\n38\r\n$1\r\nx\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidLonLat("200.000000,38.000000".to_string()))
+        );
+
+        let input = b"*7\r\n$6\r\nGEOADD\r\n$6\r\nSicily\r\n$2\r\nNX\r\n$2\r\nXX\r\n$2\r\n13\r\n$2\r\n38\r\n$1\r\nx\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*5\r\n$7\r\nGEODIST\r\n$6\r\nSicily\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nkm\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::GeoDist(
+                Bytes::from("Sicily"),
+                Bytes::from("a"),
+                Bytes::from("b"),
+                DistanceUnit::Kilometers
+            )
+        );
+
+        let input = b"*5\r\n$7\r\nGEODIST\r\n$6\r\nSicily\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nyd\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::UnsupportedUnit));
+    }
+
+    #[test]
+    fn test_geosearch_commands() {
+        let input = b"*16\r\n$9\r\nGEOSEARCH\r\n$6\r\nSicily\r\n$10\r\nFROMLONLAT\r\n$2\r\n15\r\n$2\r\n37\r\n$5\r\nBYBOX\r\n$3\r\n400\r\n$3\r\n400\r\n$2\r\nkm\r\n$4\r\nDESC\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n$3\r\nANY\r\n$9\r\nWITHCOORD\r\n$8\r\nWITHDIST\r\n$8\r\nWITHHASH\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let search = GeoSearch {
+            origin: GeoOrigin::LonLat(15.0, 37.0),
+            shape: GeoShape::Box(400_000.0, 400_000.0),
+            unit: DistanceUnit::Kilometers,
+            order: Some(SortOrder::Desc),
+            count: Some((2, true)),
+            with_coord: true,
+            with_dist: true,
+            with_hash: true,
+        };
+        assert_eq!(command, Command::GeoSearch(Bytes::from("Sicily"), search));
+
+        let input =
+            b"*5\r\n$9\r\nGEOSEARCH\r\n$6\r\nSicily\r\n$8\r\nBYRADIUS\r\n$1\r\n1\r\n$1\r\nm\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::GeoOrigin("GEOSEARCH".to_string()))
+        );
+
+        let input = b"*10\r\n$9\r\nGEOSEARCH\r\n$6\r\nSicily\r\n$10\r\nFROMMEMBER\r\n$1\r\na\r\n$10\r\nFROMLONLAT\r\n$1\r\n1\r\n$1\r\n2\r\n$8\r\nBYRADIUS\r\n$1\r\n1\r\n$1\r\nm\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::GeoOrigin("GEOSEARCH".to_string()))
+        );
+
+        let input = b"*7\r\n$9\r\nGEOSEARCH\r\n$6\r\nSicily\r\n$10\r\nFROMMEMBER\r\n$1\r\na\r\n$8\r\nBYRADIUS\r\n$2\r\n-1\r\n$1\r\nm\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NegativeRadius));
+
+        let input = b"*8\r\n$9\r\nGEOSEARCH\r\n$6\r\nSicily\r\n$10\r\nFROMMEMBER\r\n$1\r\na\r\n$8\r\nBYRADIUS\r\n$1\r\n1\r\n$1\r\nm\r\n$3\r\nANY\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*9\r\n$14\r\nGEOSEARCHSTORE\r\n$3\r\ndst\r\n$6\r\nSicily\r\n$10\r\nFROMMEMBER\r\n$1\r\na\r\n$8\r\nBYRADIUS\r\n$1\r\n1\r\n$1\r\nm\r\n$9\r\nSTOREDIST\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let search = GeoSearch {
+            origin: GeoOrigin::Member(Bytes::from("a")),
+            shape: GeoShape::Radius(1.0),
+            unit: DistanceUnit::Meters,
+            order: None,
+            count: None,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+        };
+        assert_eq!(
+            command,
+            Command::GeoSearchStore(Bytes::from("dst"), Bytes::from("Sicily"), search, true)
+        );
+
+        let input = b"*9\r\n$14\r\nGEOSEARCHSTORE\r\n$3\r\ndst\r\n$6\r\nSicily\r\n$10\r\nFROMMEMBER\r\n$1\r\na\r\n$8\r\nBYRADIUS\r\n$1\r\n1\r\n$1\r\nm\r\n$8\r\nWITHDIST\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::GeoStoreWith("GEOSEARCHSTORE".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_expire_command() {
+        let input = b"*3\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Expire(
+                Bytes::from("key"),
+                Expiry::After(10_000),
+                ExpireCondition::default()
+            )
+        );
+
+        let input = b"*3\r\n$9\r\nPEXPIREAT\r\n$3\r\nkey\r\n$4\r\n1500\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Expire(
+                Bytes::from("key"),
+                Expiry::At(1500),
+                ExpireCondition::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_expire_command_with_non_positive_ttl() {
+        let input = b"*3\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n-5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Expire(
+                Bytes::from("key"),
+                Expiry::At(0),
+                ExpireCondition::default()
+            )
+        );
+    }
+
+    #[test]
+    fn test_expire_command_with_flags() {
+        let input = b"*5\r\n$7\r\nPEXPIRE\r\n$3\r\nkey\r\n$3\r\n100\r\n$2\r\nxx\r\n$2\r\nGT\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Expire(
+                Bytes::from("key"),
+                Expiry::After(100),
+                ExpireCondition {
+                    xx: true,
+                    gt: true,
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_expire_command_with_incompatible_flags() {
+        let input = b"*5\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n$2\r\nNX\r\n$2\r\nXX\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NxNotCompatible));
+
+        let input = b"*5\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n$2\r\nGT\r\n$2\r\nLT\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::GtLtNotCompatible));
+
+        let input = b"*4\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$2\r\n10\r\n$3\r\nFOO\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnsupportedOption("FOO".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_expire_command_overflow() {
+        let input = b"*3\r\n$6\r\nEXPIRE\r\n$3\r\nkey\r\n$19\r\n9223372036854775807\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidExpireTime("expire".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ttl_commands() {
+        let input = b"*2\r\n$3\r\nTTL\r\n$3\r\nkey\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Ttl(Bytes::from("key"), TimeUnit::Seconds));
+
+        let input = b"*2\r\n$4\r\nPTTL\r\n$3\r\nkey\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Ttl(Bytes::from("key"), TimeUnit::Milliseconds)
+        );
+
+        let input = b"*2\r\n$10\r\nEXPIRETIME\r\n$3\r\nkey\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::ExpireTime(Bytes::from("key"), TimeUnit::Seconds)
+        );
+
+        let input = b"*2\r\n$7\r\nPERSIST\r\n$3\r\nkey\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Persist(Bytes::from("key")));
+    }
+
+    #[test]
+    fn test_del_command() {
+        let input = b"*3\r\n$3\r\nDEL\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Del(vec![Bytes::from("a"), Bytes::from("b")])
+        );
+
+        let input = b"*1\r\n$3\r\nDEL\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("del".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_exists_command() {
+        let input = b"*3\r\n$6\r\nEXISTS\r\n$1\r\na\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Exists(vec![Bytes::from("a"), Bytes::from("a")])
+        );
+    }
+
+    #[test]
+    fn test_rename_commands() {
+        let input = b"*3\r\n$6\r\nRENAME\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Rename(Bytes::from("a"), Bytes::from("b")));
+
+        let input = b"*3\r\n$8\r\nRENAMENX\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::RenameNx(Bytes::from("a"), Bytes::from("b"))
+        );
+
+        let input = b"*4\r\n$6\r\nRENAME\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("rename".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_copy_command() {
+        let input =
+            b"*6\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nDB\r\n$1\r\n0\r\n$7\r\nREPLACE\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Copy(Bytes::from("a"), Bytes::from("b"), true)
+        );
+
+        let input = b"*5\r\n$4\r\nCOPY\r\n$1\r\na\r\n$1\r\nb\r\n$2\r\nDB\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::DbIndexOutOfRange));
+    }
+
+    #[test]
+    fn test_key_commands() {
+        let input = b"*2\r\n$4\r\nTYPE\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Type(Bytes::from("a")));
+
+        let input = b"*3\r\n$6\r\nUNLINK\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Unlink(vec![Bytes::from("a"), Bytes::from("b")])
+        );
+
+        let input = b"*2\r\n$5\r\nTOUCH\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Touch(vec![Bytes::from("a")]));
+    }
+
+    #[test]
+    fn test_object_command() {
+        let input = b"*3\r\n$6\r\nOBJECT\r\n$8\r\nencoding\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::ObjectEncoding(Bytes::from("a")));
+
+        let input = b"*3\r\n$6\r\nOBJECT\r\n$8\r\nIDLETIME\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::ObjectIdleTime(Bytes::from("a")));
+
+        let input = b"*3\r\n$6\r\nOBJECT\r\n$4\r\nFREQ\r\n$1\r\na\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnknownSubcommand(
+                "OBJECT".to_string(),
+                "FREQ".to_string()
+            ))
+        );
+
+        let input = b"*2\r\n$6\r\nOBJECT\r\n$8\r\nENCODING\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("object|encoding".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_keys_command() {
+        let input = b"*2\r\n$4\r\nKEYS\r\n$6\r\nuser:*\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Keys(Bytes::from("user:*")));
+    }
+
+    #[test]
+    fn test_scan_command() {
+        let input = b"*2\r\n$4\r\nSCAN\r\n$1\r\n0\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Scan(0, ScanOptions::default()));
+
+        let input = b"*8\r\n$4\r\nSCAN\r\n$2\r\n42\r\n$5\r\nMATCH\r\n$2\r\na*\r\n$5\r\nCOUNT\r\n$3\r\n100\r\n$4\r\nTYPE\r\n$6\r\nSTRING\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Scan(
+                42,
+                ScanOptions {
+                    pattern: Some(Bytes::from("a*")),
+                    count: 100,
+                    type_name: Some("string".to_string()),
+                    no_values: false,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_scan_command_with_invalid_arguments() {
+        let input = b"*2\r\n$4\r\nSCAN\r\n$2\r\n-1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidCursor));
+
+        let input = b"*4\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*3\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nMATCH\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_push_commands() {
+        let input = b"*4\r\n$5\r\nLPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Push(
+                Bytes::from("list"),
+                vec![Bytes::from("a"), Bytes::from("b")],
+                End::Left,
+                false
+            )
+        );
+
+        let input = b"*3\r\n$6\r\nRPUSHX\r\n$4\r\nlist\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Push(
+                Bytes::from("list"),
+                vec![Bytes::from("a")],
+                End::Right,
+                true
+            )
+        );
+
+        let input = b"*2\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("rpush".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_pop_commands() {
+        let input = b"*2\r\n$4\r\nLPOP\r\n$4\r\nlist\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Pop(Bytes::from("list"), End::Left, None));
+
+        let input = b"*3\r\n$4\r\nRPOP\r\n$4\r\nlist\r\n$1\r\n3\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::Pop(Bytes::from("list"), End::Right, Some(3))
+        );
+
+        let input = b"*3\r\n$4\r\nLPOP\r\n$4\r\nlist\r\n$2\r\n-1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotPositive));
+
+        let input = b"*4\r\n$4\r\nLPOP\r\n$4\r\nlist\r\n$1\r\n1\r\n$1\r\n2\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("lpop".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_list_index_commands() {
+        let input = b"*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\n0\r\n$2\r\n-1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::LRange(Bytes::from("list"), 0, -1));
+
+        let input = b"*3\r\n$6\r\nLINDEX\r\n$4\r\nlist\r\n$2\r\n-2\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::LIndex(Bytes::from("list"), -2));
+
+        let input = b"*4\r\n$4\r\nLSET\r\n$4\r\nlist\r\n$1\r\n1\r\n$1\r\nx\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LSet(Bytes::from("list"), 1, Bytes::from("x"))
+        );
+
+        let input = b"*4\r\n$4\r\nLREM\r\n$4\r\nlist\r\n$2\r\n-2\r\n$1\r\nx\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LRem(Bytes::from("list"), -2, Bytes::from("x"))
+        );
+
+        let input = b"*4\r\n$5\r\nLTRIM\r\n$4\r\nlist\r\n$1\r\n1\r\n$2\r\n-1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::LTrim(Bytes::from("list"), 1, -1));
+
+        let input = b"*2\r\n$4\r\nLLEN\r\n$4\r\nlist\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::LLen(Bytes::from("list")));
+
+        let input = b"*4\r\n$6\r\nLRANGE\r\n$4\r\nlist\r\n$1\r\na\r\n$2\r\n-1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotInteger));
+    }
+
+    #[test]
+    fn test_linsert_command() {
+        let input = b"*5\r\n$7\r\nLINSERT\r\n$4\r\nlist\r\n$6\r\nbefore\r\n$1\r\nb\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LInsert(
+                Bytes::from("list"),
+                InsertPosition::Before,
+                Bytes::from("b"),
+                Bytes::from("a")
+            )
+        );
+
+        let input = b"*5\r\n$7\r\nLINSERT\r\n$4\r\nlist\r\n$6\r\nAROUND\r\n$1\r\nb\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_lpos_command() {
+        let input = b"*3\r\n$4\r\nLPOS\r\n$4\r\nlist\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LPos(
+                Bytes::from("list"),
+                Bytes::from("a"),
+                LPosOptions::default()
+            )
+        );
+
+        let input = b"*9\r\n$4\r\nLPOS\r\n$4\r\nlist\r\n$1\r\na\r\n$4\r\nRANK\r\n$2\r\n-2\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n$6\r\nMAXLEN\r\n$2\r\n10\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LPos(
+                Bytes::from("list"),
+                Bytes::from("a"),
+                LPosOptions {
+                    rank: -2,
+                    count: Some(0),
+                    maxlen: 10,
+                }
+            )
+        );
+
+        let input = b"*5\r\n$4\r\nLPOS\r\n$4\r\nlist\r\n$1\r\na\r\n$4\r\nRANK\r\n$1\r\n0\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::ZeroRank));
+
+        let input = b"*5\r\n$4\r\nLPOS\r\n$4\r\nlist\r\n$1\r\na\r\n$5\r\nCOUNT\r\n$2\r\n-1\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::Negative("COUNT".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_lmove_commands() {
+        let input = b"*5\r\n$5\r\nLMOVE\r\n$3\r\nsrc\r\n$3\r\ndst\r\n$4\r\nleft\r\n$5\r\nRIGHT\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LMove(
+                Bytes::from("src"),
+                Bytes::from("dst"),
+                End::Left,
+                End::Right
+            )
+        );
+
+        let input = b"*3\r\n$9\r\nRPOPLPUSH\r\n$3\r\nsrc\r\n$3\r\ndst\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LMove(
+                Bytes::from("src"),
+                Bytes::from("dst"),
+                End::Right,
+                End::Left
+            )
+        );
+
+        let input = b"*5\r\n$5\r\nLMOVE\r\n$3\r\nsrc\r\n$3\r\ndst\r\n$2\r\nUP\r\n$4\r\nLEFT\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_blocking_pop_commands() {
+        let input = b"*4\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$1\r\nb\r\n$3\r\n0.5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BPop(vec![Bytes::from("a"), Bytes::from("b")], End::Left, 500)
+        );
+
+        let input = b"*3\r\n$5\r\nBRPOP\r\n$1\r\na\r\n$1\r\n0\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BPop(vec![Bytes::from("a")], End::Right, 0)
+        );
+
+        let input = b"*2\r\n$5\r\nBLPOP\r\n$1\r\na\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("blpop".to_string()))
+        );
+
+        let input = b"*3\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$2\r\n-1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NegativeTimeout));
+
+        let input = b"*3\r\n$5\r\nBLPOP\r\n$1\r\na\r\n$4\r\nsoon\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidTimeout));
+    }
+
+    #[test]
+    fn test_blmove_commands() {
+        let input = b"*6\r\n$6\r\nBLMOVE\r\n$3\r\nsrc\r\n$3\r\ndst\r\n$5\r\nRIGHT\r\n$4\r\nLEFT\r\n$3\r\n1.5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BLMove(
+                Bytes::from("src"),
+                Bytes::from("dst"),
+                End::Right,
+                End::Left,
+                1500
+            )
+        );
+
+        let input = b"*4\r\n$10\r\nBRPOPLPUSH\r\n$3\r\nsrc\r\n$3\r\ndst\r\n$1\r\n0\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BLMove(
+                Bytes::from("src"),
+                Bytes::from("dst"),
+                End::Right,
+                End::Left,
+                0
+            )
+        );
+    }
+
+    #[test]
+    fn test_mpop_commands() {
+        let input = b"*5\r\n$5\r\nLMPOP\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$4\r\nLEFT\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::LMPop(vec![Bytes::from("a"), Bytes::from("b")], End::Left, 1)
+        );
+
+        let input = b"*7\r\n$6\r\nBLMPOP\r\n$4\r\n0.01\r\n$1\r\n1\r\n$1\r\na\r\n$5\r\nRIGHT\r\n$5\r\nCOUNT\r\n$1\r\n5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::BLMPop(vec![Bytes::from("a")], End::Right, 5, 10)
+        );
+
+        let input = b"*4\r\n$5\r\nLMPOP\r\n$1\r\n0\r\n$1\r\na\r\n$4\r\nLEFT\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidNumKeys));
+
+        let input = b"*4\r\n$5\r\nLMPOP\r\n$1\r\n2\r\n$1\r\na\r\n$4\r\nLEFT\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input =
+            b"*6\r\n$5\r\nLMPOP\r\n$1\r\n1\r\n$1\r\na\r\n$4\r\nLEFT\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidCount));
+
+        let input = b"*8\r\n$5\r\nLMPOP\r\n$1\r\n1\r\n$1\r\na\r\n$4\r\nLEFT\r\n$5\r\nCOUNT\r\n$1\r\n1\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_hello_command() {
+        let input = b"*1\r\n$5\r\nHELLO\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Hello(None));
+
+        let input = b"*7\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$6\r\nsecret\r\n$7\r\nSETNAME\r\n$3\r\napp\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::Hello(Some(Protocol::Resp3)));
+
+        let input = b"*2\r\n$5\r\nHELLO\r\n$1\r\n4\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NoProto));
+        assert_eq!(Error::NoProto.code(), "NOPROTO");
+
+        let input = b"*2\r\n$5\r\nHELLO\r\n$5\r\nthree\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidProtocolVersion)
+        );
+    }
+
+    #[test]
+    fn test_hset_commands() {
+        let input =
+            b"*6\r\n$4\r\nHSET\r\n$4\r\nhash\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HSet(
+                Bytes::from("hash"),
+                vec![
+                    (Bytes::from("a"), Bytes::from("1")),
+                    (Bytes::from("b"), Bytes::from("2"))
+                ]
+            )
+        );
+
+        let input = b"*5\r\n$4\r\nHSET\r\n$4\r\nhash\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("hset".to_string()))
+        );
+
+        let input = b"*4\r\n$6\r\nHSETNX\r\n$4\r\nhash\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HSetNx(Bytes::from("hash"), Bytes::from("a"), Bytes::from("1"))
+        );
+    }
+
+    #[test]
+    fn test_hash_read_commands() {
+        let input = b"*3\r\n$4\r\nHGET\r\n$4\r\nhash\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HGet(Bytes::from("hash"), Bytes::from("a"))
+        );
+
+        let input = b"*4\r\n$5\r\nHMGET\r\n$4\r\nhash\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HMGet(
+                Bytes::from("hash"),
+                vec![Bytes::from("a"), Bytes::from("b")]
+            )
+        );
+
+        let input = b"*2\r\n$7\r\nHGETALL\r\n$4\r\nhash\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::HGetAll(Bytes::from("hash")));
+
+        let input = b"*3\r\n$7\r\nHGETALL\r\n$4\r\nhash\r\n$1\r\na\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("hgetall".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_hincrby_commands() {
+        let input = b"*4\r\n$7\r\nHINCRBY\r\n$4\r\nhash\r\n$1\r\na\r\n$2\r\n-5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HIncrBy(Bytes::from("hash"), Bytes::from("a"), -5)
+        );
+
+        let input = b"*4\r\n$12\r\nHINCRBYFLOAT\r\n$4\r\nhash\r\n$1\r\na\r\n$5\r\n2.5e3\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HIncrByFloat(Bytes::from("hash"), Bytes::from("a"), 2500.0)
+        );
+
+        let input = b"*4\r\n$12\r\nHINCRBYFLOAT\r\n$4\r\nhash\r\n$1\r\na\r\n$3\r\nabc\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotFloat));
+    }
+
+    #[test]
+    fn test_hrandfield_command() {
+        let input = b"*2\r\n$10\r\nHRANDFIELD\r\n$4\r\nhash\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::HRandField(Bytes::from("hash"), None));
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Command {
-    ConfigGet(String),
-    Echo(String),
-    Get(String),
-    Ping,
-    Set(String, String, Option<u64>),
-}
+        let input = b"*4\r\n$10\r\nHRANDFIELD\r\n$4\r\nhash\r\n$2\r\n-3\r\n$10\r\nwithvalues\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HRandField(Bytes::from("hash"), Some((-3, true)))
+        );
 
-impl Command {
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, RespError> {
-        let RespValue::Array(arr) = parse(bytes)? else {
-            return Err(RespError::InvalidInput(
-                String::from_utf8(bytes.to_vec()).unwrap(),
-            ));
+        let input = b"*4\r\n$10\r\nHRANDFIELD\r\n$4\r\nhash\r\n$1\r\n3\r\n$10\r\nWITHSCORES\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*4\r\n$10\r\nHRANDFIELD\r\n$4\r\nhash\r\n$20\r\n-9223372036854775807\r\n$10\r\nWITHVALUES\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::ValueOutOfRange));
+        let input = b"*3\r\n$10\r\nHRANDFIELD\r\n$4\r\nhash\r\n$11\r\n-1000000000\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::ValueOutOfRange));
+        let input = b"*3\r\n$10\r\nHRANDFIELD\r\n$4\r\nhash\r\n$19\r\n9223372036854775807\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HRandField(Bytes::from("hash"), Some((i64::MAX, false)))
+        );
+    }
+
+    #[test]
+    fn test_hscan_command() {
+        let input = b"*6\r\n$5\r\nHSCAN\r\n$4\r\nhash\r\n$1\r\n0\r\n$5\r\nMATCH\r\n$2\r\na*\r\n$8\r\nNOVALUES\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HScan(
+                Bytes::from("hash"),
+                0,
+                ScanOptions {
+                    pattern: Some(Bytes::from("a*")),
+                    no_values: true,
+                    ..ScanOptions::default()
+                }
+            )
+        );
+
+        let input = b"*5\r\n$5\r\nHSCAN\r\n$4\r\nhash\r\n$1\r\n0\r\n$4\r\nTYPE\r\n$6\r\nstring\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+
+        let input = b"*3\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$8\r\nNOVALUES\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_hexpire_commands() {
+        let input = b"*8\r\n$7\r\nHEXPIRE\r\n$4\r\nhash\r\n$2\r\n10\r\n$2\r\nNX\r\n$6\r\nFIELDS\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HExpire(
+                Bytes::from("hash"),
+                Expiry::After(10_000),
+                ExpireCondition {
+                    nx: true,
+                    ..ExpireCondition::default()
+                },
+                vec![Bytes::from("a"), Bytes::from("b")]
+            )
+        );
+
+        let input = b"*6\r\n$10\r\nHPEXPIREAT\r\n$4\r\nhash\r\n$4\r\n1500\r\n$6\r\nFIELDS\r\n$1\r\n1\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HExpire(
+                Bytes::from("hash"),
+                Expiry::At(1500),
+                ExpireCondition::default(),
+                vec![Bytes::from("a")]
+            )
+        );
+
+        let input = b"*7\r\n$7\r\nHEXPIRE\r\n$4\r\nhash\r\n$2\r\n10\r\n$1\r\na\r\n$6\r\nFIELDS\r\n$1\r\n1\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::MissingFields));
+        let input = b"*8\r\n$7\r\nHEXPIRE\r\n$4\r\nhash\r\n$2\r\n10\r\n$2\r\nNX\r\n$2\r\nXX\r\n$6\r\nFIELDS\r\n$1\r\n1\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::MissingFields));
+        let input = b"*6\r\n$7\r\nHEXPIRE\r\n$4\r\nhash\r\n$2\r\n10\r\n$6\r\nFIELDS\r\n$1\r\n0\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidNumFields));
+        let input = b"*6\r\n$7\r\nHEXPIRE\r\n$4\r\nhash\r\n$2\r\n10\r\n$6\r\nFIELDS\r\n$1\r\n2\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NumFieldsMismatch));
+        let input = b"*5\r\n$7\r\nHEXPIRE\r\n$4\r\nhash\r\n$2\r\n10\r\n$6\r\nFIELDS\r\n$1\r\n1\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("hexpire".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_httl_commands() {
+        let input = b"*5\r\n$5\r\nHPTTL\r\n$4\r\nhash\r\n$6\r\nFIELDS\r\n$1\r\n1\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HTtl(
+                Bytes::from("hash"),
+                TimeUnit::Milliseconds,
+                vec![Bytes::from("a")]
+            )
+        );
+
+        let input =
+            b"*5\r\n$11\r\nHEXPIRETIME\r\n$4\r\nhash\r\n$6\r\nFIELDS\r\n$1\r\n1\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HExpireTime(
+                Bytes::from("hash"),
+                TimeUnit::Seconds,
+                vec![Bytes::from("a")]
+            )
+        );
+
+        let input = b"*6\r\n$8\r\nHPERSIST\r\n$4\r\nhash\r\n$6\r\nFIELDS\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::HPersist(
+                Bytes::from("hash"),
+                vec![Bytes::from("a"), Bytes::from("b")]
+            )
+        );
+
+        let input = b"*2\r\n$4\r\nHTTL\r\n$4\r\nhash\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("httl".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_commands() {
+        let input = b"*4\r\n$4\r\nSADD\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SAdd(Bytes::from("set"), vec![Bytes::from("a"), Bytes::from("b")])
+        );
+
+        let input = b"*3\r\n$4\r\nSPOP\r\n$3\r\nset\r\n$1\r\n2\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::SPop(Bytes::from("set"), Some(2)));
+        let input = b"*3\r\n$4\r\nSPOP\r\n$3\r\nset\r\n$2\r\n-1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotPositive));
+
+        let input = b"*3\r\n$11\r\nSRANDMEMBER\r\n$3\r\nset\r\n$2\r\n-3\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::SRandMember(Bytes::from("set"), Some(-3)));
+        let input = b"*3\r\n$11\r\nSRANDMEMBER\r\n$3\r\nset\r\n$20\r\n-9223372036854775808\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::ValueOutOfRange));
+
+        let input = b"*4\r\n$5\r\nSMOVE\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nx\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SMove(Bytes::from("a"), Bytes::from("b"), Bytes::from("x"))
+        );
+    }
+
+    #[test]
+    fn test_set_algebra_commands() {
+        let input = b"*3\r\n$6\r\nSINTER\r\n$1\r\na\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SCombine(SetOp::Inter, vec![Bytes::from("a"), Bytes::from("b")])
+        );
+
+        let input = b"*3\r\n$10\r\nSDIFFSTORE\r\n$4\r\ndest\r\n$1\r\na\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SCombineStore(SetOp::Diff, Bytes::from("dest"), vec![Bytes::from("a")])
+        );
+
+        let input = b"*6\r\n$10\r\nSINTERCARD\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$5\r\nLIMIT\r\n$1\r\n5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::SInterCard(vec![Bytes::from("a"), Bytes::from("b")], 5)
+        );
+
+        let input = b"*4\r\n$10\r\nSINTERCARD\r\n$1\r\n3\r\n$1\r\na\r\n$1\r\nb\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::TooManyKeys));
+        let input = b"*5\r\n$10\r\nSINTERCARD\r\n$1\r\n1\r\n$1\r\na\r\n$5\r\nLIMIT\r\n$2\r\n-1\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::Negative("LIMIT".to_string()))
+        );
+        let input = b"*2\r\n$11\r\nSUNIONSTORE\r\n$4\r\ndest\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("sunionstore".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_zadd_command() {
+        let input = b"*8\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$2\r\nGT\r\n$2\r\nCH\r\n$3\r\n1.5\r\n$1\r\na\r\n$4\r\n+inf\r\n$1\r\nb\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = ZAddOptions {
+            gt: true,
+            ch: true,
+            ..ZAddOptions::default()
         };
+        assert_eq!(
+            command,
+            Command::ZAdd(
+                Bytes::from("board"),
+                options,
+                vec![(1.5, Bytes::from("a")), (f64::INFINITY, Bytes::from("b"))]
+            )
+        );
 
-        if arr.len() == 0 {
-            return Err(RespError::InvalidInput(
-                String::from_utf8(bytes.to_vec()).unwrap(),
-            ));
-        }
+        let input =
+            b"*6\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$2\r\nNX\r\n$2\r\nXX\r\n$1\r\n1\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::XxNxNotCompatible));
+        let input =
+            b"*6\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$2\r\nNX\r\n$2\r\nGT\r\n$1\r\n1\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::GtLtNxNotCompatible));
+        let input = b"*7\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$4\r\nINCR\r\n$1\r\n1\r\n$1\r\na\r\n$1\r\n2\r\n$1\r\nb\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::IncrSinglePair));
+        let input = b"*5\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$1\r\n1\r\n$1\r\na\r\n$1\r\n2\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*4\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$3\r\nnan\r\n$1\r\na\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotFloat));
+        let input = b"*3\r\n$4\r\nZADD\r\n$5\r\nboard\r\n$1\r\n1\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("zadd".to_string()))
+        );
+    }
 
-        let RespValue::BulkString(cmd) = arr.get(0).unwrap() else {
-            return Err(RespError::InvalidInput(
-                String::from_utf8(bytes.to_vec()).unwrap(),
-            ));
+    #[test]
+    fn test_zrange_commands() {
+        let input = b"*10\r\n$6\r\nZRANGE\r\n$5\r\nboard\r\n$2\r\n(1\r\n$4\r\n+inf\r\n$7\r\nBYSCORE\r\n$3\r\nREV\r\n$5\r\nLIMIT\r\n$1\r\n0\r\n$1\r\n2\r\n$10\r\nWITHSCORES\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let by = RangeBy::Score(ScoreRange {
+            min: ScoreBound {
+                value: f64::INFINITY,
+                exclusive: false,
+            },
+            max: ScoreBound {
+                value: 1.0,
+                exclusive: true,
+            },
+        });
+        let options = ZRangeOptions {
+            by,
+            rev: true,
+            limit: Some((0, 2)),
+            with_scores: true,
         };
+        assert_eq!(command, Command::ZRange(Bytes::from("board"), options));
 
-        match cmd.to_ascii_uppercase().as_str() {
-            "CONFIG" => {
-                if arr.len() < 3 {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                }
+        let input = b"*4\r\n$14\r\nZREVRANGEBYLEX\r\n$5\r\nboard\r\n$1\r\n+\r\n$2\r\n[b\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let by = RangeBy::Lex(LexRange {
+            min: LexBound::Inclusive(Bytes::from("b")),
+            max: LexBound::Max,
+        });
+        let options = ZRangeOptions {
+            by,
+            rev: true,
+            limit: None,
+            with_scores: false,
+        };
+        assert_eq!(command, Command::ZRange(Bytes::from("board"), options));
 
-                let RespValue::BulkString(subcmd) = arr.get(1).unwrap() else {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                };
+        let input = b"*7\r\n$6\r\nZRANGE\r\n$5\r\nboard\r\n$1\r\n0\r\n$2\r\n-1\r\n$5\r\nLIMIT\r\n$1\r\n0\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::LimitWithoutBy));
+        let input = b"*6\r\n$6\r\nZRANGE\r\n$5\r\nboard\r\n$1\r\n-\r\n$1\r\n+\r\n$5\r\nBYLEX\r\n$10\r\nWITHSCORES\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::WithScoresByLex));
+        let input =
+            b"*5\r\n$13\r\nZRANGEBYSCORE\r\n$5\r\nboard\r\n$1\r\n0\r\n$1\r\n1\r\n$3\r\nREV\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*4\r\n$6\r\nZCOUNT\r\n$5\r\nboard\r\n$1\r\na\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidScoreRange));
+        let input = b"*4\r\n$9\r\nZLEXCOUNT\r\n$5\r\nboard\r\n$1\r\na\r\n$1\r\n+\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidLexRange));
+    }
 
-                match subcmd.to_ascii_uppercase().as_str() {
-                    "GET" => {
-                        if arr.len() != 3 {
-                            return Err(RespError::InvalidInput(
-                                String::from_utf8(bytes.to_vec()).unwrap(),
-                            ));
-                        }
+    #[test]
+    fn test_zrank_and_zpop_commands() {
+        let input = b"*4\r\n$8\r\nZREVRANK\r\n$5\r\nboard\r\n$1\r\na\r\n$9\r\nWITHSCORE\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::ZRank(Bytes::from("board"), Bytes::from("a"), true, true)
+        );
+
+        let input = b"*3\r\n$7\r\nZPOPMIN\r\n$5\r\nboard\r\n$1\r\n2\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::ZPop(Bytes::from("board"), ScoreEnd::Min, Some(2))
+        );
+        let input = b"*3\r\n$7\r\nZPOPMAX\r\n$5\r\nboard\r\n$2\r\n-1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotPositive));
+    }
 
-                        let RespValue::BulkString(key) = arr.get(2).unwrap() else {
-                            return Err(RespError::InvalidInput(
-                                String::from_utf8(bytes.to_vec()).unwrap(),
-                            ));
-                        };
+    #[test]
+    fn test_zcombine_commands() {
+        let input = b"*10\r\n$11\r\nZUNIONSTORE\r\n$3\r\nout\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$7\r\nWEIGHTS\r\n$1\r\n2\r\n$3\r\n0.5\r\n$9\r\nAGGREGATE\r\n$3\r\nmax\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = ZCombineOptions {
+            weights: Some(vec![2.0, 0.5]),
+            aggregate: Aggregate::Max,
+            with_scores: false,
+        };
+        assert_eq!(
+            command,
+            Command::ZCombineStore(
+                SetOp::Union,
+                Bytes::from("out"),
+                vec![Bytes::from("a"), Bytes::from("b")],
+                options
+            )
+        );
 
-                        Ok(Command::ConfigGet(key.to_string()))
-                    }
-                    _ => Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    )),
-                }
-            }
-            "ECHO" => {
-                if arr.len() != 2 {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                }
+        let input = b"*4\r\n$6\r\nZINTER\r\n$1\r\n1\r\n$1\r\na\r\n$10\r\nWITHSCORES\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let options = ZCombineOptions {
+            with_scores: true,
+            ..ZCombineOptions::default()
+        };
+        assert_eq!(
+            command,
+            Command::ZCombine(SetOp::Inter, vec![Bytes::from("a")], options)
+        );
 
-                let RespValue::BulkString(msg) = arr.get(1).unwrap() else {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                };
+        let input = b"*3\r\n$6\r\nZUNION\r\n$1\r\n0\r\n$1\r\na\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::NoInputKeys("zunion".to_string()))
+        );
+        let input = b"*4\r\n$6\r\nZUNION\r\n$1\r\n3\r\n$1\r\na\r\n$1\r\nb\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input =
+            b"*6\r\n$6\r\nZINTER\r\n$1\r\n2\r\n$1\r\na\r\n$1\r\nb\r\n$7\r\nWEIGHTS\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*5\r\n$6\r\nZINTER\r\n$1\r\n1\r\n$1\r\na\r\n$7\r\nWEIGHTS\r\n$1\r\nx\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidWeight));
+        let input = b"*5\r\n$5\r\nZDIFF\r\n$1\r\n1\r\n$1\r\na\r\n$9\r\nAGGREGATE\r\n$3\r\nSUM\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input =
+            b"*5\r\n$10\r\nZDIFFSTORE\r\n$3\r\nout\r\n$1\r\n1\r\n$1\r\na\r\n$10\r\nWITHSCORES\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*3\r\n$11\r\nZINTERSTORE\r\n$3\r\nout\r\n$1\r\n1\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("zinterstore".to_string()))
+        );
+    }
 
-                Ok(Command::Echo(msg.to_string()))
-            }
-            "GET" => {
-                if arr.len() != 2 {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                }
+    #[test]
+    fn test_xadd_command() {
+        let input = b"*11\r\n$4\r\nXADD\r\n$1\r\ns\r\n$10\r\nNOMKSTREAM\r\n$6\r\nMAXLEN\r\n$1\r\n~\r\n$4\r\n1000\r\n$5\r\nLIMIT\r\n$2\r\n10\r\n$3\r\n5-*\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let trim = StreamTrim {
+            strategy: TrimStrategy::MaxLen(1000),
+            approximate: true,
+            limit: Some(10),
+        };
+        assert_eq!(
+            command,
+            Command::XAdd(
+                Bytes::from("s"),
+                true,
+                Some(trim),
+                XAddId::AutoSeq(5),
+                vec![(Bytes::from("f"), Bytes::from("v"))]
+            )
+        );
 
-                let RespValue::BulkString(key) = arr.get(1).unwrap() else {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                };
+        let input = b"*5\r\n$4\r\nXADD\r\n$1\r\ns\r\n$3\r\n1-2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let id = XAddId::Explicit(StreamId { ms: 1, seq: 2 });
+        assert!(matches!(command, Command::XAdd(_, false, None, parsed, _) if parsed == id));
 
-                Ok(Command::Get(key.to_string()))
-            }
-            "PING" => Ok(Command::Ping),
-            "SET" => {
-                if arr.len() < 3 {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                }
+        let input = b"*4\r\n$4\r\nXADD\r\n$1\r\ns\r\n$1\r\n*\r\n$1\r\nf\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::WrongArity("xadd".to_string()))
+        );
+        let input = b"*5\r\n$4\r\nXADD\r\n$1\r\ns\r\n$3\r\n1-x\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidStreamId));
+        let input = b"*7\r\n$4\r\nXADD\r\n$1\r\ns\r\n$6\r\nMAXLEN\r\n$2\r\n-1\r\n$1\r\n*\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NegativeMaxLen));
+        let input = b"*9\r\n$4\r\nXADD\r\n$1\r\ns\r\n$6\r\nMAXLEN\r\n$1\r\n5\r\n$5\r\nLIMIT\r\n$1\r\n1\r\n$1\r\n*\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::LimitWithoutApprox));
+    }
 
-                let RespValue::BulkString(key) = arr.get(1).unwrap() else {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                };
+    #[test]
+    fn test_xrange_commands() {
+        let input =
+            b"*6\r\n$6\r\nXRANGE\r\n$1\r\ns\r\n$4\r\n(1-5\r\n$1\r\n2\r\n$5\r\nCOUNT\r\n$1\r\n3\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let start = StreamId { ms: 1, seq: 6 };
+        let end = StreamId {
+            ms: 2,
+            seq: u64::MAX,
+        };
+        assert_eq!(
+            command,
+            Command::XRange(Bytes::from("s"), start, end, Some(3), false)
+        );
 
-                let RespValue::BulkString(value) = arr.get(2).unwrap() else {
-                    return Err(RespError::InvalidInput(
-                        String::from_utf8(bytes.to_vec()).unwrap(),
-                    ));
-                };
+        let input = b"*4\r\n$9\r\nXREVRANGE\r\n$1\r\ns\r\n$1\r\n+\r\n$1\r\n-\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::XRange(Bytes::from("s"), StreamId::MIN, StreamId::MAX, None, true)
+        );
 
-                let mut expiry = None;
+        let input = b"*4\r\n$6\r\nXRANGE\r\n$1\r\ns\r\n$42\r\n(18446744073709551615-18446744073709551615\r\n$1\r\n+\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidStartId));
+        let input = b"*4\r\n$6\r\nXRANGE\r\n$1\r\ns\r\n$1\r\n-\r\n$4\r\n(0-0\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidEndId));
+    }
 
-                for chunk in arr[3..].chunks(2) {
-                    if let [RespValue::BulkString(opt), RespValue::BulkString(val)] = chunk {
-                        if opt.to_ascii_uppercase() == "PX" {
-                            expiry = Some(val.parse::<u64>().unwrap());
-                        }
-                    }
-                }
+    #[test]
+    fn test_xread_command() {
+        let input = b"*10\r\n$5\r\nXREAD\r\n$5\r\nCOUNT\r\n$1\r\n2\r\n$5\r\nBLOCK\r\n$3\r\n100\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n$\r\n$3\r\n0-1\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(
+            command,
+            Command::XRead(
+                vec![Bytes::from("a"), Bytes::from("b")],
+                vec![ReadId::New, ReadId::After(StreamId { ms: 0, seq: 1 })],
+                Some(2),
+                Some(100)
+            )
+        );
 
-                Ok(Command::Set(key.to_string(), value.to_string(), expiry))
-            }
-            _ => Err(RespError::InvalidInput(
-                String::from_utf8(bytes.to_vec()).unwrap(),
-            )),
-        }
+        let input = b"*5\r\n$5\r\nXREAD\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n$\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnbalancedStreams("xread".to_string(), "$"))
+        );
+        let input = b"*6\r\n$5\r\nXREAD\r\n$5\r\nBLOCK\r\n$2\r\n-1\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\n$\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NegativeTimeout));
+        let input = b"*6\r\n$5\r\nXREAD\r\n$5\r\nBLOCK\r\n$3\r\n1.5\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\n$\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::TimeoutNotInteger));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_xreadgroup_command() {
+        let input = b"*12\r\n$10\r\nXREADGROUP\r\n$5\r\nGROUP\r\n$1\r\ng\r\n$1\r\nc\r\n$5\r\nNOACK\r\n$5\r\nCOUNT\r\n$1\r\n1\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n>\r\n$1\r\n0\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let read = ReadGroup {
+            group: Bytes::from("g"),
+            consumer: Bytes::from("c"),
+            noack: true,
+        };
+        assert_eq!(
+            command,
+            Command::XReadGroup(
+                read,
+                vec![Bytes::from("a"), Bytes::from("b")],
+                vec![ReadId::New, ReadId::After(StreamId::MIN)],
+                Some(1),
+                None
+            )
+        );
+
+        let input = b"*4\r\n$10\r\nXREADGROUP\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\n>\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::MissingGroup));
+        let input = b"*7\r\n$10\r\nXREADGROUP\r\n$5\r\nGROUP\r\n$1\r\ng\r\n$1\r\nc\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\n$\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::LastIdInGroupRead));
+        let input = b"*8\r\n$10\r\nXREADGROUP\r\n$5\r\nGROUP\r\n$1\r\ng\r\n$1\r\nc\r\n$7\r\nSTREAMS\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\n>\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnbalancedStreams("xreadgroup".to_string(), ">"))
+        );
+    }
 
     #[test]
-    fn test_ping_command() {
-        let input = b"*1\r\n$4\r\nPING\r\n";
+    fn test_xgroup_command() {
+        let input = b"*8\r\n$6\r\nXGROUP\r\n$6\r\nCREATE\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\n$\r\n$8\r\nMKSTREAM\r\n$11\r\nENTRIESREAD\r\n$1\r\n3\r\n";
         let command = Command::from_bytes(input).unwrap();
-        assert_eq!(command, Command::Ping);
+        assert_eq!(
+            command,
+            Command::XGroupCreate(Bytes::from("s"), Bytes::from("g"), None, true, Some(3))
+        );
+
+        let input = b"*7\r\n$6\r\nXGROUP\r\n$5\r\nSETID\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\n5\r\n$11\r\nENTRIESREAD\r\n$2\r\n-2\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::InvalidEntriesRead));
+        let input = b"*6\r\n$6\r\nXGROUP\r\n$5\r\nSETID\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\n5\r\n$8\r\nMKSTREAM\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*4\r\n$6\r\nXGROUP\r\n$5\r\nHELP2\r\n$1\r\ns\r\n$1\r\ng\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnknownSubcommand(
+                "XGROUP".to_string(),
+                "HELP2".to_string()
+            ))
+        );
     }
 
     #[test]
-    fn test_echo_command() {
-        let input = b"*2\r\n$4\r\nECHO\r\n$5\r\nHello\r\n";
+    fn test_xpending_and_xack_commands() {
+        let input = b"*9\r\n$8\r\nXPENDING\r\n$1\r\ns\r\n$1\r\ng\r\n$4\r\nIDLE\r\n$3\r\n100\r\n$1\r\n-\r\n$2\r\n(5\r\n$2\r\n10\r\n$1\r\nc\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let range = PendingRange {
+            min_idle: Some(100),
+            start: StreamId::MIN,
+            end: StreamId {
+                ms: 5,
+                seq: u64::MAX - 1,
+            },
+            count: 10,
+            consumer: Some(Bytes::from("c")),
+        };
+        assert_eq!(
+            command,
+            Command::XPending(Bytes::from("s"), Bytes::from("g"), Some(range))
+        );
+
+        let input = b"*5\r\n$4\r\nXACK\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\n1\r\n$3\r\n2-3\r\n";
         let command = Command::from_bytes(input).unwrap();
-        assert_eq!(command, Command::Echo("Hello".to_string()));
+        let ids = vec![StreamId { ms: 1, seq: 0 }, StreamId { ms: 2, seq: 3 }];
+        assert_eq!(
+            command,
+            Command::XAck(Bytes::from("s"), Bytes::from("g"), ids)
+        );
     }
 
     #[test]
-    fn test_set_command_with_expiry() {
-        let input = b"*5\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n$2\r\nPX\r\n$2\r\n10\r\n";
+    fn test_xclaim_commands() {
+        let input = b"*15\r\n$6\r\nXCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$2\r\n10\r\n$3\r\n1-0\r\n$1\r\n2\r\n$4\r\nIDLE\r\n$1\r\n5\r\n$10\r\nRETRYCOUNT\r\n$1\r\n3\r\n$5\r\nFORCE\r\n$6\r\nJUSTID\r\n$6\r\nLASTID\r\n$1\r\n9\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let claim = Claim {
+            group: Bytes::from("g"),
+            consumer: Bytes::from("c"),
+            min_idle: 10,
+        };
+        let options = ClaimOptions {
+            delivery_time: Some(DeliveryTime::Idle(5)),
+            retry_count: Some(3),
+            force: true,
+            just_id: true,
+            last_id: Some(StreamId { ms: 9, seq: 0 }),
+        };
+        let ids = vec![StreamId { ms: 1, seq: 0 }, StreamId { ms: 2, seq: 0 }];
+        assert_eq!(
+            command,
+            Command::XClaim(Bytes::from("s"), claim.clone(), ids, options)
+        );
+
+        let input =
+            b"*6\r\n$6\r\nXCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$1\r\nx\r\n$3\r\n1-0\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidMinIdle("XCLAIM".to_string()))
+        );
+        let input = b"*7\r\n$6\r\nXCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$2\r\n10\r\n$3\r\n1-0\r\n$5\r\nBOGUS\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnrecognizedClaimOption("BOGUS".to_string()))
+        );
+        let input = b"*8\r\n$6\r\nXCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$2\r\n10\r\n$3\r\n1-0\r\n$4\r\nTIME\r\n$1\r\nx\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::InvalidClaimOption("TIME".to_string()))
+        );
+
+        let input = b"*9\r\n$10\r\nXAUTOCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$2\r\n10\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n5\r\n$6\r\nJUSTID\r\n";
         let command = Command::from_bytes(input).unwrap();
         assert_eq!(
             command,
-            Command::Set("key".to_string(), "value".to_string(), Some(10))
+            Command::XAutoClaim(Bytes::from("s"), claim, StreamId::MIN, 5, true)
         );
+        let input = b"*8\r\n$10\r\nXAUTOCLAIM\r\n$1\r\ns\r\n$1\r\ng\r\n$1\r\nc\r\n$2\r\n10\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n0\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::CountNotPositive));
     }
 
     #[test]
-    fn test_set_command_without_expiry() {
-        let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+    fn test_xinfo_command() {
+        let input = b"*4\r\n$5\r\nXINFO\r\n$9\r\nCONSUMERS\r\n$1\r\ns\r\n$1\r\ng\r\n";
         let command = Command::from_bytes(input).unwrap();
         assert_eq!(
             command,
-            Command::Set("key".to_string(), "value".to_string(), None)
+            Command::XInfoConsumers(Bytes::from("s"), Bytes::from("g"))
+        );
+        let input = b"*4\r\n$5\r\nXINFO\r\n$6\r\nSTREAM\r\n$1\r\ns\r\n$4\r\nFULL\r\n";
+        assert_eq!(
+            Command::from_bytes(input),
+            Err(Error::UnsupportedOption("FULL".to_string()))
         );
     }
 
+    #[test]
+    fn test_xtrim_command() {
+        let input = b"*5\r\n$5\r\nXTRIM\r\n$1\r\ns\r\n$5\r\nMINID\r\n$1\r\n=\r\n$1\r\n5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        let trim = StreamTrim {
+            strategy: TrimStrategy::MinId(StreamId { ms: 5, seq: 0 }),
+            approximate: false,
+            limit: None,
+        };
+        assert_eq!(command, Command::XTrim(Bytes::from("s"), trim));
+
+        let input = b"*5\r\n$5\r\nXTRIM\r\n$1\r\ns\r\n$6\r\nMAXLEN\r\n$1\r\n1\r\n$4\r\njunk\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+        let input = b"*4\r\n$5\r\nXTRIM\r\n$1\r\ns\r\n$4\r\nSIZE\r\n$1\r\n1\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::Syntax));
+    }
+
+    #[test]
+    fn test_incr_commands() {
+        let input = b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::IncrBy(Bytes::from("n"), 1));
+        let input = b"*3\r\n$6\r\nDECRBY\r\n$1\r\nn\r\n$1\r\n5\r\n";
+        let command = Command::from_bytes(input).unwrap();
+        assert_eq!(command, Command::IncrBy(Bytes::from("n"), -5));
+
+        let input = b"*3\r\n$6\r\nDECRBY\r\n$1\r\nn\r\n$20\r\n-9223372036854775808\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::DecrementOverflow));
+        let input = b"*3\r\n$11\r\nINCRBYFLOAT\r\n$1\r\nn\r\n$1\r\nx\r\n";
+        assert_eq!(Command::from_bytes(input), Err(Error::NotFloat));
+    }
+
     #[test]
     fn test_get_command() {
         let input = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
         let command = Command::from_bytes(input).unwrap();
-        assert_eq!(command, Command::Get("key".to_string()));
+        assert_eq!(command, Command::Get(Bytes::from("key")));
     }
 
     #[test]
@@ -8,6 +8,9 @@ pub enum Error {
     InvalidInput(String),
 }
 
+/// Longest line read without finding its end, Redis' limit on inline requests.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
 pub struct Cursor<'a> {
     input: &'a [u8],
     position: usize,
@@ -18,6 +21,10 @@ impl<'a> Cursor<'a> {
         Self { input, position: 0 }
     }
 
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
     pub fn read(&mut self, n: usize) -> Result<&'a [u8], Error> {
         if self.position + n > self.input.len() {
             return Err(Error::UnexpectedEOF);
@@ -38,7 +45,7 @@ impl<'a> Cursor<'a> {
 
     pub fn read_line(&mut self) -> Result<&'a [u8], Error> {
         let start = self.position;
-        while self.position < self.input.len() - 1 {
+        while self.position + 1 < self.input.len() {
             if self.input[self.position] == b'\r' && self.input[self.position + 1] == b'\n' {
                 let line = &self.input[start..self.position];
                 self.position += 2;
@@ -46,6 +53,9 @@ impl<'a> Cursor<'a> {
             }
             self.position += 1;
         }
+        if self.position - start > MAX_LINE_LEN {
+            return Err(Error::InvalidInput("too big inline request".to_string()));
+        }
         Err(Error::UnexpectedEOF)
     }
 
@@ -125,6 +135,23 @@ mod tests {
         assert_eq!(cursor.position, 4);
     }
 
+    #[test]
+    fn read_line_too_long() {
+        let input = vec![b'a'; MAX_LINE_LEN + 2];
+        let mut cursor = Cursor::new(&input);
+
+        assert!(matches!(cursor.read_line(), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn read_line_empty_input() {
+        let input = b"";
+        let mut cursor = Cursor::new(input);
+
+        assert!(matches!(cursor.read_line(), Err(Error::UnexpectedEOF)));
+        assert_eq!(cursor.position, 0);
+    }
+
     #[test]
     fn read_line_empty() {
         let input = b"\r\n";
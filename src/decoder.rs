@@ -0,0 +1,162 @@
+use bytes::{Buf, BytesMut};
+
+use crate::cursor::Error;
+use crate::resp::{parse_frame, FrameScan, RespValue};
+
+/// Most bytes buffered for a frame that hasn't fully arrived, Redis' default
+/// `client-query-buffer-limit`.
+const MAX_QUERY_BUFFER: usize = 1024 * 1024 * 1024;
+
+/// Buffers bytes read from a connection and splits them into RESP frames.
+///
+/// A frame may arrive split across several reads, and a single read may carry
+/// several pipelined frames, so input is accumulated until `next_frame` can
+/// decode a complete value. Values are only built once the whole frame is
+/// there.
+pub struct Decoder {
+    buffer: BytesMut,
+    scan: FrameScan,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            buffer: BytesMut::with_capacity(4096),
+            scan: FrameScan::default(),
+        }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the next complete frame, or `None` if the buffered input ends
+    /// in the middle of a frame and more bytes are needed.
+    pub fn next_frame(&mut self) -> Result<Option<RespValue>, Error> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(len) = self.scan.advance(&self.buffer)? else {
+            if self.buffer.len() > MAX_QUERY_BUFFER {
+                return Err(Error::InvalidInput(
+                    "query buffer limit exceeded".to_string(),
+                ));
+            }
+            return Ok(None);
+        };
+
+        let (value, _) = parse_frame(&self.buffer[..len])?;
+        self.buffer.advance(len);
+        self.scan = FrameScan::default();
+        Ok(Some(value))
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_single_frame() {
+        let mut decoder = Decoder::new();
+        decoder.extend(b"+OK\r\n");
+
+        let frame = decoder.next_frame().unwrap();
+        assert!(matches!(frame, Some(RespValue::SimpleString(s)) if s == "OK"));
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_empty_buffer() {
+        let mut decoder = Decoder::new();
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_frame_split_across_reads() {
+        let mut decoder = Decoder::new();
+        decoder.extend(b"*2\r\n$4\r\nECHO\r\n$5\r\nhel");
+        assert!(decoder.next_frame().unwrap().is_none());
+
+        decoder.extend(b"lo\r");
+        assert!(decoder.next_frame().unwrap().is_none());
+
+        decoder.extend(b"\n");
+        let Some(RespValue::Array(arr)) = decoder.next_frame().unwrap() else {
+            panic!("Expected Array");
+        };
+        assert_eq!(arr.len(), 2);
+        assert!(matches!(&arr[1], RespValue::BulkString(s) if s == "hello"));
+    }
+
+    #[test]
+    fn decode_pipelined_frames() {
+        let mut decoder = Decoder::new();
+        decoder.extend(b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPI");
+
+        assert!(matches!(
+            decoder.next_frame(),
+            Ok(Some(RespValue::Array(_)))
+        ));
+        assert!(matches!(
+            decoder.next_frame(),
+            Ok(Some(RespValue::Array(_)))
+        ));
+        assert!(decoder.next_frame().unwrap().is_none());
+
+        decoder.extend(b"NG\r\n");
+        assert!(matches!(
+            decoder.next_frame(),
+            Ok(Some(RespValue::Array(_)))
+        ));
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_large_bulk_string() {
+        let value = "x".repeat(100_000);
+        let frame = format!("${}\r\n{}\r\n", value.len(), value);
+        let mut decoder = Decoder::new();
+
+        for chunk in frame.as_bytes().chunks(1024) {
+            assert!(decoder.next_frame().unwrap().is_none());
+            decoder.extend(chunk);
+        }
+
+        let frame = decoder.next_frame().unwrap();
+        assert!(matches!(frame, Some(RespValue::BulkString(s)) if s == value));
+    }
+
+    #[test]
+    fn decode_deeply_nested_input() {
+        let mut decoder = Decoder::new();
+        decoder.extend("*1\r\n".repeat(200_000).as_bytes());
+        assert!(matches!(decoder.next_frame(), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn decode_unterminated_line() {
+        let mut decoder = Decoder::new();
+        decoder.extend(b"+");
+        for _ in 0..16 {
+            decoder.extend(&[b'a'; 4096]);
+        }
+        assert!(decoder.next_frame().unwrap().is_none());
+        decoder.extend(b"aa");
+        assert!(matches!(decoder.next_frame(), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn decode_invalid_input() {
+        let mut decoder = Decoder::new();
+        decoder.extend(b"/hello\r\n");
+        assert!(matches!(decoder.next_frame(), Err(Error::InvalidInput(_))));
+    }
+}
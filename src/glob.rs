@@ -0,0 +1,139 @@
+/// Matches `string` against a Redis glob `pattern`.
+///
+/// Supports `*`, `?`, character classes such as `[abc]`, `[^a]` and `[a-z]`,
+/// and `\` to escape the next byte. A `*` that later fails to match is retried
+/// one byte further along, which is enough since every other token consumes
+/// exactly one byte.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let mut p = 0;
+    let mut s = 0;
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, string[s]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == string[s]).then_some(p + 2),
+            Some(c) => (*c == string[s]).then_some(p + 1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                s += 1;
+            }
+            (None, Some((star, start))) => {
+                p = star + 1;
+                s = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// Matches `c` against the class starting at `pattern[start] == b'['`,
+/// returning the position after the class on success.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern.get(p) {
+            // An unterminated class extends to the end of the pattern.
+            None => break,
+            Some(b']') => {
+                p += 1;
+                break;
+            }
+            Some(b'\\') if p + 1 < pattern.len() => {
+                matched |= pattern[p + 1] == c;
+                p += 2;
+            }
+            Some(&low) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len() => {
+                let high = pattern[p + 2];
+                let (low, high) = if low <= high {
+                    (low, high)
+                } else {
+                    (high, low)
+                };
+                matched |= (low..=high).contains(&c);
+                p += 3;
+            }
+            Some(&other) => {
+                matched |= other == c;
+                p += 1;
+            }
+        }
+    }
+
+    (matched != negate).then_some(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_literal() {
+        assert!(matches(b"hello", b"hello"));
+        assert!(!matches(b"hello", b"hell"));
+        assert!(!matches(b"hell", b"hello"));
+        assert!(matches(b"", b""));
+    }
+
+    #[test]
+    fn match_star() {
+        assert!(matches(b"*", b""));
+        assert!(matches(b"*", b"anything"));
+        assert!(matches(b"h*o", b"hello"));
+        assert!(matches(b"h*llo*", b"hello world"));
+        assert!(matches(b"*o*o*", b"foo bar boo"));
+        assert!(!matches(b"h*z", b"hello"));
+        assert!(matches(b"user:*:name", b"user:1:2:name"));
+    }
+
+    #[test]
+    fn match_question_mark() {
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(matches(b"h?llo", b"hallo"));
+        assert!(!matches(b"h?llo", b"hllo"));
+    }
+
+    #[test]
+    fn match_character_class() {
+        assert!(matches(b"h[ae]llo", b"hello"));
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[ae]llo", b"hillo"));
+        assert!(matches(b"h[^e]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"h[z-a]llo", b"hbllo"));
+        assert!(!matches(b"h[a-b]llo", b"hcllo"));
+    }
+
+    #[test]
+    fn match_escape() {
+        assert!(matches(b"h\\*llo", b"h*llo"));
+        assert!(!matches(b"h\\*llo", b"hello"));
+        assert!(matches(b"h[\\]]llo", b"h]llo"));
+    }
+
+    #[test]
+    fn match_binary() {
+        assert!(matches(b"\xff*", b"\xff\x00\x01"));
+        assert!(!matches(b"\xff*", b"\x00\xff"));
+    }
+}
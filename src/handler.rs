@@ -0,0 +1,1353 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::commands::{BloomInfoField, Command, SetCondition, SetOptions, StreamId, TimeUnit};
+use crate::config;
+use crate::json::{Format, Json};
+use crate::kv;
+use crate::kv::stream::Fields;
+use crate::resp::{Protocol, RespValue};
+
+/// State of a client connection that outlives a single command.
+#[derive(Debug)]
+pub struct Session {
+    id: u64,
+    protocol: Protocol,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::default(),
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the command in `frame` and returns the reply, encoded for the
+/// protocol the client negotiated.
+pub async fn execute(frame: RespValue, session: &mut Session) -> RespValue {
+    let reply = match Command::from_value(frame) {
+        Ok(command) => match run(command, session).await {
+            Ok(reply) => reply,
+            Err(error) => {
+                tracing::warn!(?error, "Error");
+                RespValue::Error(error.to_string())
+            }
+        },
+        Err(error) => {
+            tracing::warn!(?error, "Error");
+            RespValue::Error(format!("{} {}", error.code(), error))
+        }
+    };
+
+    reply.for_protocol(session.protocol)
+}
+
+async fn run(command: Command, session: &mut Session) -> Result<RespValue, kv::Error> {
+    let reply = match command {
+        Command::ConfigGet(key) => {
+            tracing::info!(?key, "Received CONFIG GET");
+            match key.as_str() {
+                "dir" => RespValue::Array(vec![
+                    RespValue::SimpleString("dir".to_string()),
+                    RespValue::BulkString(config::get_dir().into()),
+                ]),
+                "dbfilename" => RespValue::Array(vec![
+                    RespValue::SimpleString("dbfilename".to_string()),
+                    RespValue::BulkString(config::get_dbfilename().into()),
+                ]),
+                _ => RespValue::Array(vec![]),
+            }
+        }
+        Command::Ping => {
+            tracing::info!("Received PING");
+            RespValue::SimpleString("PONG".to_string())
+        }
+        Command::Echo(arg) => {
+            tracing::info!(?arg, "Received ECHO");
+            RespValue::BulkString(arg)
+        }
+        Command::Set(key, value, options) => {
+            tracing::info!(?key, ?value, ?options, "Received SET");
+            let (written, previous) = kv::set(key, value, &options).await?;
+            if options.get {
+                match previous {
+                    Some(previous) => RespValue::BulkString(previous),
+                    None => RespValue::NullBulkString,
+                }
+            } else if written {
+                RespValue::SimpleString("OK".to_string())
+            } else {
+                RespValue::NullBulkString
+            }
+        }
+        Command::Expire(key, expiry, condition) => {
+            tracing::info!(?key, ?expiry, ?condition, "Received EXPIRE");
+            let updated = kv::expire(&key, expiry, condition).await;
+            RespValue::Integer(updated as i64)
+        }
+        Command::ExpireTime(key, unit) => {
+            tracing::info!(?key, ?unit, "Received EXPIRETIME");
+            expire_time_reply(kv::expire_time(&key).await, unit)
+        }
+        Command::Persist(key) => {
+            tracing::info!(?key, "Received PERSIST");
+            RespValue::Integer(kv::persist(&key).await as i64)
+        }
+        Command::Ttl(key, unit) => {
+            tracing::info!(?key, ?unit, "Received TTL");
+            ttl_reply(kv::ttl(&key).await, unit)
+        }
+        Command::Del(keys) => {
+            tracing::info!(?keys, "Received DEL");
+            RespValue::Integer(kv::del(&keys).await as i64)
+        }
+        Command::Unlink(keys) => {
+            tracing::info!(?keys, "Received UNLINK");
+            RespValue::Integer(kv::del(&keys).await as i64)
+        }
+        Command::Exists(keys) => {
+            tracing::info!(?keys, "Received EXISTS");
+            RespValue::Integer(kv::exists(&keys).await as i64)
+        }
+        Command::Touch(keys) => {
+            tracing::info!(?keys, "Received TOUCH");
+            RespValue::Integer(kv::touch(&keys).await as i64)
+        }
+        Command::Type(key) => {
+            tracing::info!(?key, "Received TYPE");
+            RespValue::SimpleString(kv::key_type(&key).await.to_string())
+        }
+        Command::ObjectEncoding(key) => {
+            tracing::info!(?key, "Received OBJECT ENCODING");
+            match kv::encoding(&key).await {
+                Some(encoding) => RespValue::BulkString(encoding.into()),
+                None => RespValue::NullBulkString,
+            }
+        }
+        Command::ObjectIdleTime(key) => {
+            tracing::info!(?key, "Received OBJECT IDLETIME");
+            match kv::idle_time(&key).await {
+                Some(idle) => RespValue::Integer((idle / 1000) as i64),
+                None => RespValue::NullBulkString,
+            }
+        }
+        Command::Rename(key, new_key) => {
+            tracing::info!(?key, ?new_key, "Received RENAME");
+            kv::rename(&key, new_key, false).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::RenameNx(key, new_key) => {
+            tracing::info!(?key, ?new_key, "Received RENAMENX");
+            let renamed = kv::rename(&key, new_key, true).await?;
+            RespValue::Integer(renamed as i64)
+        }
+        Command::Copy(source, destination, replace) => {
+            tracing::info!(?source, ?destination, replace, "Received COPY");
+            let copied = kv::copy(&source, destination, replace).await?;
+            RespValue::Integer(copied as i64)
+        }
+        Command::Keys(pattern) => {
+            tracing::info!(?pattern, "Received KEYS");
+            let keys = kv::keys(&pattern).await;
+            RespValue::Array(keys.into_iter().map(RespValue::BulkString).collect())
+        }
+        Command::Scan(cursor, options) => {
+            tracing::info!(cursor, ?options, "Received SCAN");
+            let (cursor, keys) = kv::scan(
+                cursor,
+                options.count,
+                options.pattern.as_deref(),
+                options.type_name.as_deref(),
+            )
+            .await;
+            RespValue::Array(vec![
+                RespValue::BulkString(cursor.to_string().into()),
+                RespValue::Array(keys.into_iter().map(RespValue::BulkString).collect()),
+            ])
+        }
+        Command::Push(key, elements, end, only_existing) => {
+            tracing::info!(?key, ?elements, ?end, only_existing, "Received PUSH");
+            let len = kv::list::push(key, elements, end, only_existing).await?;
+            RespValue::Integer(len as i64)
+        }
+        Command::Pop(key, end, count) => {
+            tracing::info!(?key, ?end, ?count, "Received POP");
+            let popped = kv::list::pop(&key, end, count.unwrap_or(1)).await?;
+            match (popped, count) {
+                (Some(popped), Some(_)) => bulk_strings(popped),
+                (None, Some(_)) => RespValue::NullArray,
+                (popped, None) => bulk_or_null(popped.and_then(|popped| popped.into_iter().next())),
+            }
+        }
+        Command::LMPop(keys, end, count) => {
+            tracing::info!(?keys, ?end, count, "Received LMPOP");
+            key_and_elements(kv::list::mpop(&keys, end, count).await?)
+        }
+        Command::BPop(keys, end, timeout) => {
+            tracing::info!(?keys, ?end, timeout, "Received BPOP");
+            let op = kv::blocking::Op::Pop(end, 1);
+            match kv::blocking::pop(keys, op, block_for(timeout)).await? {
+                Some((key, elements)) => bulk_strings([key].into_iter().chain(elements).collect()),
+                None => RespValue::NullArray,
+            }
+        }
+        Command::BLMPop(keys, end, count, timeout) => {
+            tracing::info!(?keys, ?end, count, timeout, "Received BLMPOP");
+            let op = kv::blocking::Op::Pop(end, count);
+            key_and_elements(kv::blocking::pop(keys, op, block_for(timeout)).await?)
+        }
+        Command::BLMove(source, destination, from, to, timeout) => {
+            tracing::info!(
+                ?source,
+                ?destination,
+                ?from,
+                ?to,
+                timeout,
+                "Received BLMOVE"
+            );
+            let op = kv::blocking::Op::Move(destination, from, to);
+            match kv::blocking::pop(vec![source], op, block_for(timeout)).await? {
+                Some((_, elements)) => bulk_or_null(elements.into_iter().next()),
+                None => RespValue::NullArray,
+            }
+        }
+        Command::LLen(key) => {
+            tracing::info!(?key, "Received LLEN");
+            RespValue::Integer(kv::list::len(&key).await? as i64)
+        }
+        Command::LRange(key, start, stop) => {
+            tracing::info!(?key, start, stop, "Received LRANGE");
+            bulk_strings(kv::list::range(&key, start, stop).await?)
+        }
+        Command::LIndex(key, index) => {
+            tracing::info!(?key, index, "Received LINDEX");
+            bulk_or_null(kv::list::index(&key, index).await?)
+        }
+        Command::LPos(key, element, options) => {
+            tracing::info!(?key, ?element, ?options, "Received LPOS");
+            let positions = kv::list::position(&key, &element, &options).await?;
+            let mut positions = positions
+                .into_iter()
+                .map(|position| RespValue::Integer(position as i64));
+            match options.count {
+                Some(_) => RespValue::Array(positions.collect()),
+                None => positions.next().unwrap_or(RespValue::NullBulkString),
+            }
+        }
+        Command::LSet(key, index, element) => {
+            tracing::info!(?key, index, ?element, "Received LSET");
+            kv::list::set(&key, index, element).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::LRem(key, count, element) => {
+            tracing::info!(?key, count, ?element, "Received LREM");
+            RespValue::Integer(kv::list::remove(&key, count, &element).await? as i64)
+        }
+        Command::LTrim(key, start, stop) => {
+            tracing::info!(?key, start, stop, "Received LTRIM");
+            kv::list::trim(&key, start, stop).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::LInsert(key, position, pivot, element) => {
+            tracing::info!(?key, ?position, ?pivot, ?element, "Received LINSERT");
+            RespValue::Integer(kv::list::insert(&key, position, &pivot, element).await?)
+        }
+        Command::LMove(source, destination, from, to) => {
+            tracing::info!(?source, ?destination, ?from, ?to, "Received LMOVE");
+            bulk_or_null(kv::list::lmove(&source, destination, from, to).await?)
+        }
+        Command::Hello(protocol) => {
+            tracing::info!(?protocol, "Received HELLO");
+            if let Some(protocol) = protocol {
+                session.protocol = protocol;
+            }
+            let field =
+                |name: &str, value| (RespValue::BulkString(Bytes::from(name.to_string())), value);
+            RespValue::Map(vec![
+                field("server", RespValue::BulkString("redis".into())),
+                field("version", RespValue::BulkString("7.4.0".into())),
+                field(
+                    "proto",
+                    RespValue::Integer(match session.protocol {
+                        Protocol::Resp2 => 2,
+                        Protocol::Resp3 => 3,
+                    }),
+                ),
+                field("id", RespValue::Integer(session.id as i64)),
+                field("mode", RespValue::BulkString("standalone".into())),
+                field("role", RespValue::BulkString("master".into())),
+                field("modules", RespValue::Array(vec![])),
+            ])
+        }
+        Command::HSet(key, pairs) => {
+            tracing::info!(?key, ?pairs, "Received HSET");
+            RespValue::Integer(kv::hash::set(key, pairs).await? as i64)
+        }
+        Command::HMSet(key, pairs) => {
+            tracing::info!(?key, ?pairs, "Received HMSET");
+            kv::hash::set(key, pairs).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::HSetNx(key, field, value) => {
+            tracing::info!(?key, ?field, ?value, "Received HSETNX");
+            RespValue::Integer(kv::hash::set_nx(key, field, value).await? as i64)
+        }
+        Command::HGet(key, field) => {
+            tracing::info!(?key, ?field, "Received HGET");
+            bulk_or_null(kv::hash::get(&key, &field).await?)
+        }
+        Command::HMGet(key, fields) => {
+            tracing::info!(?key, ?fields, "Received HMGET");
+            let values = kv::hash::get_many(&key, &fields).await?;
+            RespValue::Array(values.into_iter().map(bulk_or_null).collect())
+        }
+        Command::HGetAll(key) => {
+            tracing::info!(?key, "Received HGETALL");
+            let pairs = kv::hash::get_all(&key).await?;
+            RespValue::Map(
+                pairs
+                    .into_iter()
+                    .map(|(field, value)| {
+                        (RespValue::BulkString(field), RespValue::BulkString(value))
+                    })
+                    .collect(),
+            )
+        }
+        Command::HDel(key, fields) => {
+            tracing::info!(?key, ?fields, "Received HDEL");
+            RespValue::Integer(kv::hash::delete(&key, &fields).await? as i64)
+        }
+        Command::HExpire(key, expiry, condition, fields) => {
+            tracing::info!(?key, ?expiry, ?condition, ?fields, "Received HEXPIRE");
+            let replies = kv::hash::expire(&key, &fields, expiry, condition).await?;
+            RespValue::Array(replies.into_iter().map(RespValue::Integer).collect())
+        }
+        Command::HTtl(key, unit, fields) => {
+            tracing::info!(?key, ?unit, ?fields, "Received HTTL");
+            let ttls = kv::hash::ttl(&key, &fields).await?;
+            RespValue::Array(ttls.into_iter().map(|ttl| ttl_reply(ttl, unit)).collect())
+        }
+        Command::HExpireTime(key, unit, fields) => {
+            tracing::info!(?key, ?unit, ?fields, "Received HEXPIRETIME");
+            let deadlines = kv::hash::expire_times(&key, &fields).await?;
+            let replies = deadlines
+                .into_iter()
+                .map(|expires_at| expire_time_reply(expires_at, unit))
+                .collect();
+            RespValue::Array(replies)
+        }
+        Command::HPersist(key, fields) => {
+            tracing::info!(?key, ?fields, "Received HPERSIST");
+            let replies = kv::hash::persist(&key, &fields).await?;
+            RespValue::Array(replies.into_iter().map(RespValue::Integer).collect())
+        }
+        Command::HIncrBy(key, field, increment) => {
+            tracing::info!(?key, ?field, increment, "Received HINCRBY");
+            RespValue::Integer(kv::hash::incr_by(key, field, increment).await?)
+        }
+        Command::HIncrByFloat(key, field, increment) => {
+            tracing::info!(?key, ?field, increment, "Received HINCRBYFLOAT");
+            RespValue::BulkString(kv::hash::incr_by_float(key, field, increment).await?)
+        }
+        Command::HKeys(key) => {
+            tracing::info!(?key, "Received HKEYS");
+            bulk_strings(kv::hash::keys(&key).await?)
+        }
+        Command::HVals(key) => {
+            tracing::info!(?key, "Received HVALS");
+            bulk_strings(kv::hash::values(&key).await?)
+        }
+        Command::HLen(key) => {
+            tracing::info!(?key, "Received HLEN");
+            RespValue::Integer(kv::hash::len(&key).await? as i64)
+        }
+        Command::HExists(key, field) => {
+            tracing::info!(?key, ?field, "Received HEXISTS");
+            RespValue::Integer(kv::hash::exists(&key, &field).await? as i64)
+        }
+        Command::HRandField(key, count) => {
+            tracing::info!(?key, ?count, "Received HRANDFIELD");
+            let fields = kv::hash::random_fields(&key, count.map_or(1, |(count, _)| count)).await?;
+            match count {
+                None => bulk_or_null(fields.into_iter().next().map(|(field, _)| field)),
+                Some((_, false)) => {
+                    bulk_strings(fields.into_iter().map(|(field, _)| field).collect())
+                }
+                // RESP3 clients get each field paired with its value.
+                Some((_, true)) if session.protocol == Protocol::Resp3 => RespValue::Array(
+                    fields
+                        .into_iter()
+                        .map(|(field, value)| bulk_strings(vec![field, value]))
+                        .collect(),
+                ),
+                Some((_, true)) => bulk_strings(
+                    fields
+                        .into_iter()
+                        .flat_map(|(field, value)| [field, value])
+                        .collect(),
+                ),
+            }
+        }
+        Command::HScan(key, cursor, options) => {
+            tracing::info!(?key, cursor, ?options, "Received HSCAN");
+            let (cursor, pairs) =
+                kv::hash::scan(&key, cursor, options.count, options.pattern.as_deref()).await?;
+            let items = pairs
+                .into_iter()
+                .flat_map(|(field, value)| match options.no_values {
+                    true => vec![field],
+                    false => vec![field, value],
+                })
+                .collect();
+            RespValue::Array(vec![
+                RespValue::BulkString(cursor.to_string().into()),
+                bulk_strings(items),
+            ])
+        }
+        Command::SAdd(key, members) => {
+            tracing::info!(?key, ?members, "Received SADD");
+            RespValue::Integer(kv::set::add(key, members).await? as i64)
+        }
+        Command::SRem(key, members) => {
+            tracing::info!(?key, ?members, "Received SREM");
+            RespValue::Integer(kv::set::remove(&key, &members).await? as i64)
+        }
+        Command::SMembers(key) => {
+            tracing::info!(?key, "Received SMEMBERS");
+            bulk_set(kv::set::members(&key).await?)
+        }
+        Command::SIsMember(key, member) => {
+            tracing::info!(?key, ?member, "Received SISMEMBER");
+            RespValue::Integer(kv::set::is_member(&key, &member).await? as i64)
+        }
+        Command::SMIsMember(key, members) => {
+            tracing::info!(?key, ?members, "Received SMISMEMBER");
+            let found = kv::set::are_members(&key, &members).await?;
+            RespValue::Array(
+                found
+                    .into_iter()
+                    .map(|found| RespValue::Integer(found as i64))
+                    .collect(),
+            )
+        }
+        Command::SCard(key) => {
+            tracing::info!(?key, "Received SCARD");
+            RespValue::Integer(kv::set::card(&key).await? as i64)
+        }
+        Command::SPop(key, count) => {
+            tracing::info!(?key, ?count, "Received SPOP");
+            let popped = kv::set::pop(&key, count.unwrap_or(1)).await?;
+            match count {
+                Some(_) => bulk_set(popped.unwrap_or_default()),
+                None => bulk_or_null(popped.and_then(|popped| popped.into_iter().next())),
+            }
+        }
+        Command::SRandMember(key, count) => {
+            tracing::info!(?key, ?count, "Received SRANDMEMBER");
+            let members = kv::set::random_members(&key, count.unwrap_or(1)).await?;
+            match count {
+                Some(_) => bulk_strings(members),
+                None => bulk_or_null(members.into_iter().next()),
+            }
+        }
+        Command::SMove(source, destination, member) => {
+            tracing::info!(?source, ?destination, ?member, "Received SMOVE");
+            RespValue::Integer(kv::set::move_member(&source, destination, member).await? as i64)
+        }
+        Command::SCombine(op, keys) => {
+            tracing::info!(?op, ?keys, "Received SINTER, SUNION or SDIFF");
+            bulk_set(kv::set::combine(op, &keys).await?)
+        }
+        Command::SCombineStore(op, destination, keys) => {
+            tracing::info!(
+                ?op,
+                ?destination,
+                ?keys,
+                "Received SINTERSTORE, SUNIONSTORE or SDIFFSTORE"
+            );
+            RespValue::Integer(kv::set::combine_store(op, destination, &keys).await? as i64)
+        }
+        Command::SInterCard(keys, limit) => {
+            tracing::info!(?keys, limit, "Received SINTERCARD");
+            RespValue::Integer(kv::set::inter_card(&keys, limit).await? as i64)
+        }
+        Command::SScan(key, cursor, options) => {
+            tracing::info!(?key, cursor, ?options, "Received SSCAN");
+            let (cursor, members) =
+                kv::set::scan(&key, cursor, options.count, options.pattern.as_deref()).await?;
+            RespValue::Array(vec![
+                RespValue::BulkString(cursor.to_string().into()),
+                bulk_strings(members),
+            ])
+        }
+        Command::ZAdd(key, options, pairs) => {
+            tracing::info!(?key, ?options, ?pairs, "Received ZADD");
+            let (count, score) = kv::zset::add(key, pairs, &options).await?;
+            match options.incr {
+                true => score.map_or(RespValue::NullBulkString, score_reply),
+                false => RespValue::Integer(count as i64),
+            }
+        }
+        Command::ZIncrBy(key, increment, member) => {
+            tracing::info!(?key, increment, ?member, "Received ZINCRBY");
+            score_reply(kv::zset::incr_by(key, increment, member).await?)
+        }
+        Command::ZRem(key, members) => {
+            tracing::info!(?key, ?members, "Received ZREM");
+            RespValue::Integer(kv::zset::remove(&key, &members).await? as i64)
+        }
+        Command::ZCard(key) => {
+            tracing::info!(?key, "Received ZCARD");
+            RespValue::Integer(kv::zset::card(&key).await? as i64)
+        }
+        Command::ZScore(key, member) => {
+            tracing::info!(?key, ?member, "Received ZSCORE");
+            let score = kv::zset::score(&key, &member).await?;
+            score.map_or(RespValue::NullBulkString, score_reply)
+        }
+        Command::ZMScore(key, members) => {
+            tracing::info!(?key, ?members, "Received ZMSCORE");
+            let scores = kv::zset::scores(&key, &members).await?;
+            RespValue::Array(
+                scores
+                    .into_iter()
+                    .map(|score| score.map_or(RespValue::NullBulkString, score_reply))
+                    .collect(),
+            )
+        }
+        Command::ZRank(key, member, rev, with_score) => {
+            tracing::info!(?key, ?member, rev, with_score, "Received ZRANK or ZREVRANK");
+            match kv::zset::rank(&key, &member, rev).await? {
+                Some((rank, score)) if with_score => {
+                    RespValue::Array(vec![RespValue::Integer(rank as i64), score_reply(score)])
+                }
+                Some((rank, _)) => RespValue::Integer(rank as i64),
+                None if with_score => RespValue::NullArray,
+                None => RespValue::NullBulkString,
+            }
+        }
+        Command::ZCount(key, range) => {
+            tracing::info!(?key, ?range, "Received ZCOUNT");
+            RespValue::Integer(kv::zset::count(&key, &range).await? as i64)
+        }
+        Command::ZLexCount(key, range) => {
+            tracing::info!(?key, ?range, "Received ZLEXCOUNT");
+            RespValue::Integer(kv::zset::lex_count(&key, &range).await? as i64)
+        }
+        Command::ZRange(key, options) => {
+            tracing::info!(?key, ?options, "Received ZRANGE");
+            let members = kv::zset::range(&key, &options).await?;
+            match options.with_scores {
+                true => scored_members(members, session.protocol),
+                false => bulk_strings(members.into_iter().map(|(member, _)| member).collect()),
+            }
+        }
+        Command::ZCombine(op, keys, options) => {
+            tracing::info!(?op, ?keys, ?options, "Received ZINTER, ZUNION or ZDIFF");
+            let members = kv::zset::combine(op, &keys, &options).await?;
+            match options.with_scores {
+                true => scored_members(members, session.protocol),
+                false => bulk_strings(members.into_iter().map(|(member, _)| member).collect()),
+            }
+        }
+        Command::ZCombineStore(op, destination, keys, options) => {
+            tracing::info!(
+                ?op,
+                ?destination,
+                ?keys,
+                ?options,
+                "Received ZINTERSTORE, ZUNIONSTORE or ZDIFFSTORE"
+            );
+            let len = kv::zset::combine_store(op, destination, &keys, &options).await?;
+            RespValue::Integer(len as i64)
+        }
+        Command::ZPop(key, end, count) => {
+            tracing::info!(?key, ?end, ?count, "Received ZPOPMIN or ZPOPMAX");
+            let popped = kv::zset::pop(&key, end, count.unwrap_or(1))
+                .await?
+                .unwrap_or_default();
+            match count {
+                Some(_) => scored_members(popped, session.protocol),
+                // Without a count the member and its score are never nested.
+                None => RespValue::Array(
+                    popped
+                        .into_iter()
+                        .flat_map(|(member, score)| {
+                            [RespValue::BulkString(member), score_reply(score)]
+                        })
+                        .collect(),
+                ),
+            }
+        }
+        Command::XAdd(key, nomkstream, trim, id, fields) => {
+            tracing::info!(?key, nomkstream, ?trim, ?id, ?fields, "Received XADD");
+            let id = kv::stream::add(key, nomkstream, trim.as_ref(), id, fields).await?;
+            bulk_or_null(id.map(|id| id.to_string().into()))
+        }
+        Command::XLen(key) => {
+            tracing::info!(?key, "Received XLEN");
+            RespValue::Integer(kv::stream::len(&key).await? as i64)
+        }
+        Command::XRange(key, start, end, count, rev) => {
+            tracing::info!(?key, %start, %end, ?count, rev, "Received XRANGE or XREVRANGE");
+            match count {
+                Some(0) => RespValue::NullArray,
+                count => stream_entries(kv::stream::range(&key, start, end, count, rev).await?),
+            }
+        }
+        Command::XTrim(key, trim) => {
+            tracing::info!(?key, ?trim, "Received XTRIM");
+            RespValue::Integer(kv::stream::trim(&key, &trim).await? as i64)
+        }
+        Command::XDel(key, ids) => {
+            tracing::info!(?key, ?ids, "Received XDEL");
+            RespValue::Integer(kv::stream::delete(&key, &ids).await? as i64)
+        }
+        Command::XRead(keys, ids, count, block) => {
+            tracing::info!(?keys, ?ids, ?count, ?block, "Received XREAD");
+            let block = block.map(block_for);
+            match kv::stream::read(keys, ids, count, block).await? {
+                Some(streams) => {
+                    let streams = streams
+                        .into_iter()
+                        .map(|(key, entries)| (key, stream_entries(entries)));
+                    keyed_streams(streams.collect(), session.protocol)
+                }
+                None => RespValue::NullArray,
+            }
+        }
+        Command::XReadGroup(read, keys, ids, count, block) => {
+            tracing::info!(?read, ?keys, ?ids, ?count, ?block, "Received XREADGROUP");
+            let block = block.map(block_for);
+            match kv::stream::read_group(read, keys, ids, count, block).await? {
+                Some(streams) => {
+                    let streams = streams.into_iter().map(|(key, entries)| {
+                        let entries = entries
+                            .into_iter()
+                            .map(|(id, fields)| stream_entry(id, fields));
+                        (key, RespValue::Array(entries.collect()))
+                    });
+                    keyed_streams(streams.collect(), session.protocol)
+                }
+                None => RespValue::NullArray,
+            }
+        }
+        Command::XGroupCreate(key, group, id, mkstream, entries_read) => {
+            tracing::info!(
+                ?key,
+                ?group,
+                ?id,
+                mkstream,
+                ?entries_read,
+                "Received XGROUP CREATE"
+            );
+            kv::stream::create_group(key, group, id, mkstream, entries_read).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::XGroupSetId(key, group, id, entries_read) => {
+            tracing::info!(?key, ?group, ?id, ?entries_read, "Received XGROUP SETID");
+            kv::stream::set_group_id(&key, &group, id, entries_read).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::XGroupDestroy(key, group) => {
+            tracing::info!(?key, ?group, "Received XGROUP DESTROY");
+            RespValue::Integer(kv::stream::destroy_group(&key, &group).await? as i64)
+        }
+        Command::XGroupCreateConsumer(key, group, consumer) => {
+            tracing::info!(?key, ?group, ?consumer, "Received XGROUP CREATECONSUMER");
+            RespValue::Integer(kv::stream::create_consumer(&key, &group, consumer).await? as i64)
+        }
+        Command::XGroupDelConsumer(key, group, consumer) => {
+            tracing::info!(?key, ?group, ?consumer, "Received XGROUP DELCONSUMER");
+            RespValue::Integer(kv::stream::delete_consumer(&key, &group, &consumer).await? as i64)
+        }
+        Command::XAck(key, group, ids) => {
+            tracing::info!(?key, ?group, ?ids, "Received XACK");
+            RespValue::Integer(kv::stream::acknowledge(&key, &group, &ids).await? as i64)
+        }
+        Command::XPending(key, group, None) => {
+            tracing::info!(?key, ?group, "Received XPENDING");
+            let summary = kv::stream::pending_summary(&key, &group).await?;
+            let (min, max) = match summary.bounds {
+                Some((min, max)) => (stream_id(min), stream_id(max)),
+                None => (RespValue::NullBulkString, RespValue::NullBulkString),
+            };
+            let consumers = match summary.consumers.is_empty() {
+                true => RespValue::NullArray,
+                false => RespValue::Array(
+                    summary
+                        .consumers
+                        .into_iter()
+                        .map(|(consumer, count)| {
+                            bulk_strings(vec![consumer, count.to_string().into()])
+                        })
+                        .collect(),
+                ),
+            };
+            RespValue::Array(vec![
+                RespValue::Integer(summary.count as i64),
+                min,
+                max,
+                consumers,
+            ])
+        }
+        Command::XPending(key, group, Some(range)) => {
+            tracing::info!(?key, ?group, ?range, "Received XPENDING");
+            let entries = kv::stream::pending(&key, &group, &range).await?;
+            RespValue::Array(
+                entries
+                    .into_iter()
+                    .map(|entry| {
+                        RespValue::Array(vec![
+                            stream_id(entry.id),
+                            RespValue::BulkString(entry.consumer),
+                            RespValue::Integer(entry.idle as i64),
+                            RespValue::Integer(entry.deliveries as i64),
+                        ])
+                    })
+                    .collect(),
+            )
+        }
+        Command::XClaim(key, claim, ids, options) => {
+            tracing::info!(?key, ?claim, ?ids, ?options, "Received XCLAIM");
+            let claimed = kv::stream::claim(&key, &claim, &ids, &options).await?;
+            match options.just_id {
+                true => {
+                    RespValue::Array(claimed.into_iter().map(|(id, _)| stream_id(id)).collect())
+                }
+                false => stream_entries(claimed),
+            }
+        }
+        Command::XAutoClaim(key, claim, start, count, just_id) => {
+            tracing::info!(?key, ?claim, %start, count, just_id, "Received XAUTOCLAIM");
+            let (cursor, claimed, deleted) =
+                kv::stream::autoclaim(&key, &claim, start, count, just_id).await?;
+            let claimed = match just_id {
+                true => {
+                    RespValue::Array(claimed.into_iter().map(|(id, _)| stream_id(id)).collect())
+                }
+                false => stream_entries(claimed),
+            };
+            RespValue::Array(vec![
+                stream_id(cursor),
+                claimed,
+                RespValue::Array(deleted.into_iter().map(stream_id).collect()),
+            ])
+        }
+        Command::XInfoStream(key) => {
+            tracing::info!(?key, "Received XINFO STREAM");
+            let info = kv::stream::info(&key).await?;
+            let entry = |entry: Option<(StreamId, Fields)>| match entry {
+                Some((id, fields)) => stream_entry(id, Some(fields)),
+                None => RespValue::NullBulkString,
+            };
+            info_map(vec![
+                ("length", RespValue::Integer(info.length as i64)),
+                ("last-generated-id", stream_id(info.last_generated_id)),
+                ("max-deleted-entry-id", stream_id(info.max_deleted_id)),
+                (
+                    "entries-added",
+                    RespValue::Integer(info.entries_added as i64),
+                ),
+                ("recorded-first-entry-id", stream_id(info.first_recorded_id)),
+                ("groups", RespValue::Integer(info.groups as i64)),
+                ("first-entry", entry(info.first_entry)),
+                ("last-entry", entry(info.last_entry)),
+            ])
+        }
+        Command::XInfoGroups(key) => {
+            tracing::info!(?key, "Received XINFO GROUPS");
+            let groups = kv::stream::group_info(&key).await?;
+            let counter = |counter: Option<u64>| match counter {
+                Some(counter) => RespValue::Integer(counter as i64),
+                None => RespValue::NullBulkString,
+            };
+            RespValue::Array(
+                groups
+                    .into_iter()
+                    .map(|group| {
+                        info_map(vec![
+                            ("name", RespValue::BulkString(group.name)),
+                            ("consumers", RespValue::Integer(group.consumers as i64)),
+                            ("pending", RespValue::Integer(group.pending as i64)),
+                            ("last-delivered-id", stream_id(group.last_delivered_id)),
+                            ("entries-read", counter(group.entries_read)),
+                            ("lag", counter(group.lag)),
+                        ])
+                    })
+                    .collect(),
+            )
+        }
+        Command::XInfoConsumers(key, group) => {
+            tracing::info!(?key, ?group, "Received XINFO CONSUMERS");
+            let consumers = kv::stream::consumer_info(&key, &group).await?;
+            RespValue::Array(
+                consumers
+                    .into_iter()
+                    .map(|consumer| {
+                        let inactive = consumer.inactive.map_or(-1, |inactive| inactive as i64);
+                        info_map(vec![
+                            ("name", RespValue::BulkString(consumer.name)),
+                            ("pending", RespValue::Integer(consumer.pending as i64)),
+                            ("idle", RespValue::Integer(consumer.idle as i64)),
+                            ("inactive", RespValue::Integer(inactive)),
+                        ])
+                    })
+                    .collect(),
+            )
+        }
+        Command::SetNx(key, value) => {
+            tracing::info!(?key, ?value, "Received SETNX");
+            let options = SetOptions {
+                condition: Some(SetCondition::IfAbsent),
+                ..SetOptions::default()
+            };
+            let (written, _) = kv::set(key, value, &options).await?;
+            RespValue::Integer(written as i64)
+        }
+        Command::MSet(pairs) => {
+            tracing::info!(?pairs, "Received MSET");
+            kv::string::set_many(pairs).await;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::MSetNx(pairs) => {
+            tracing::info!(?pairs, "Received MSETNX");
+            RespValue::Integer(kv::string::set_many_nx(pairs).await as i64)
+        }
+        Command::MGet(keys) => {
+            tracing::info!(?keys, "Received MGET");
+            let values = kv::string::get_many(&keys).await;
+            RespValue::Array(values.into_iter().map(bulk_or_null).collect())
+        }
+        Command::Append(key, value) => {
+            tracing::info!(?key, ?value, "Received APPEND");
+            RespValue::Integer(kv::string::append(key, &value).await? as i64)
+        }
+        Command::StrLen(key) => {
+            tracing::info!(?key, "Received STRLEN");
+            RespValue::Integer(kv::string::len(&key).await? as i64)
+        }
+        Command::GetRange(key, start, end) => {
+            tracing::info!(?key, start, end, "Received GETRANGE");
+            RespValue::BulkString(kv::string::get_range(&key, start, end).await?)
+        }
+        Command::SetRange(key, offset, value) => {
+            tracing::info!(?key, offset, ?value, "Received SETRANGE");
+            RespValue::Integer(kv::string::set_range(key, offset, &value).await? as i64)
+        }
+        Command::GetDel(key) => {
+            tracing::info!(?key, "Received GETDEL");
+            bulk_or_null(kv::string::get_del(&key).await?)
+        }
+        Command::GetEx(key, expiry) => {
+            tracing::info!(?key, ?expiry, "Received GETEX");
+            bulk_or_null(kv::string::get_ex(&key, expiry).await?)
+        }
+        Command::Lcs(key_a, key_b, options) => {
+            tracing::info!(?key_a, ?key_b, ?options, "Received LCS");
+            let lcs = kv::string::lcs(&key_a, &key_b, options.min_match_len).await?;
+            if options.idx {
+                let position = |(start, end): (usize, usize)| {
+                    RespValue::Array(vec![
+                        RespValue::Integer(start as i64),
+                        RespValue::Integer(end as i64),
+                    ])
+                };
+                let matches = lcs.matches.into_iter().map(|matched| {
+                    let mut reply = vec![position(matched.a), position(matched.b)];
+                    if options.with_match_len {
+                        reply.push(RespValue::Integer(matched.len as i64));
+                    }
+                    RespValue::Array(reply)
+                });
+                RespValue::Map(vec![
+                    (
+                        RespValue::BulkString("matches".into()),
+                        RespValue::Array(matches.collect()),
+                    ),
+                    (
+                        RespValue::BulkString("len".into()),
+                        RespValue::Integer(lcs.string.len() as i64),
+                    ),
+                ])
+            } else if options.len {
+                RespValue::Integer(lcs.string.len() as i64)
+            } else {
+                RespValue::BulkString(lcs.string)
+            }
+        }
+        Command::SetBit(key, offset, bit) => {
+            tracing::info!(?key, offset, bit, "Received SETBIT");
+            RespValue::Integer(kv::bitmap::set(key, offset, bit).await? as i64)
+        }
+        Command::GetBit(key, offset) => {
+            tracing::info!(?key, offset, "Received GETBIT");
+            RespValue::Integer(kv::bitmap::get(&key, offset).await? as i64)
+        }
+        Command::BitCount(key, range) => {
+            tracing::info!(?key, ?range, "Received BITCOUNT");
+            RespValue::Integer(kv::bitmap::count(&key, range).await? as i64)
+        }
+        Command::BitPos(key, bit, range) => {
+            tracing::info!(?key, bit, ?range, "Received BITPOS");
+            RespValue::Integer(kv::bitmap::position(&key, bit, range).await?)
+        }
+        Command::BitOp(op, destination, keys) => {
+            tracing::info!(?op, ?destination, ?keys, "Received BITOP");
+            RespValue::Integer(kv::bitmap::operation(op, destination, &keys).await? as i64)
+        }
+        Command::BitField(key, ops) => {
+            tracing::info!(?key, ?ops, "Received BITFIELD");
+            let replies = kv::bitmap::field(key, &ops).await?;
+            let replies = replies.into_iter().map(|reply| match reply {
+                Some(value) => RespValue::Integer(value),
+                None => RespValue::Null,
+            });
+            RespValue::Array(replies.collect())
+        }
+        Command::GeoAdd(key, options, members) => {
+            tracing::info!(?key, ?options, ?members, "Received GEOADD");
+            RespValue::Integer(kv::geo::add(key, &options, members).await? as i64)
+        }
+        Command::GeoDist(key, member_a, member_b, unit) => {
+            tracing::info!(?key, ?member_a, ?member_b, ?unit, "Received GEODIST");
+            let distance = kv::geo::dist(&key, &member_a, &member_b).await?;
+            bulk_or_null(distance.map(|distance| geo_distance(distance / unit.as_meters())))
+        }
+        Command::GeoHash(key, members) => {
+            tracing::info!(?key, ?members, "Received GEOHASH");
+            let hashes = kv::geo::hashes(&key, &members).await?;
+            let hashes = hashes
+                .into_iter()
+                .map(|hash| bulk_or_null(hash.map(Bytes::from)));
+            RespValue::Array(hashes.collect())
+        }
+        Command::GeoPos(key, members) => {
+            tracing::info!(?key, ?members, "Received GEOPOS");
+            let positions = kv::geo::positions(&key, &members).await?;
+            let positions = positions.into_iter().map(|position| match position {
+                Some(position) => geo_position(position, session.protocol),
+                None => RespValue::NullArray,
+            });
+            RespValue::Array(positions.collect())
+        }
+        Command::GeoSearch(key, options) => {
+            tracing::info!(?key, ?options, "Received GEOSEARCH");
+            let matches = kv::geo::search_members(&key, &options).await?;
+            let matches = matches.into_iter().map(|found| {
+                if !(options.with_dist || options.with_hash || options.with_coord) {
+                    return RespValue::BulkString(found.member);
+                }
+                let mut reply = vec![RespValue::BulkString(found.member)];
+                if options.with_dist {
+                    let distance = found.distance / options.unit.as_meters();
+                    reply.push(RespValue::BulkString(geo_distance(distance)));
+                }
+                if options.with_hash {
+                    reply.push(RespValue::Integer(found.score as i64));
+                }
+                if options.with_coord {
+                    reply.push(geo_position(found.position, session.protocol));
+                }
+                RespValue::Array(reply)
+            });
+            RespValue::Array(matches.collect())
+        }
+        Command::GeoSearchStore(destination, source, options, store_dist) => {
+            tracing::info!(
+                ?destination,
+                ?source,
+                ?options,
+                store_dist,
+                "Received GEOSEARCHSTORE"
+            );
+            let stored = kv::geo::search_store(destination, &source, &options, store_dist).await?;
+            RespValue::Integer(stored as i64)
+        }
+        Command::PfAdd(key, elements) => {
+            tracing::info!(?key, ?elements, "Received PFADD");
+            RespValue::Integer(kv::hyperloglog::add(key, &elements).await? as i64)
+        }
+        Command::PfCount(keys) => {
+            tracing::info!(?keys, "Received PFCOUNT");
+            RespValue::Integer(kv::hyperloglog::count(&keys).await? as i64)
+        }
+        Command::PfMerge(destination, sources) => {
+            tracing::info!(?destination, ?sources, "Received PFMERGE");
+            kv::hyperloglog::merge(destination, &sources).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::JsonArrAppend(key, path, values) => {
+            tracing::info!(?key, ?path, ?values, "Received JSON.ARRAPPEND");
+            let lengths = kv::json::arrappend(&key, &path, &values).await?;
+            let length = |length: Option<usize>| match length {
+                Some(length) => RespValue::Integer(length as i64),
+                None => RespValue::Null,
+            };
+            match path.legacy {
+                true => length(lengths.into_iter().last().flatten()),
+                false => RespValue::Array(lengths.into_iter().map(length).collect()),
+            }
+        }
+        Command::JsonDel(key, path) => {
+            tracing::info!(?key, ?path, "Received JSON.DEL");
+            RespValue::Integer(kv::json::del(&key, &path).await? as i64)
+        }
+        Command::JsonGet(key, format, paths) => {
+            tracing::info!(?key, ?format, ?paths, "Received JSON.GET");
+            let value = kv::json::get(&key, &paths, &format).await?;
+            bulk_or_null(value.map(Bytes::from))
+        }
+        Command::JsonNumIncrBy(key, path, increment) => {
+            tracing::info!(?key, ?path, ?increment, "Received JSON.NUMINCRBY");
+            let values = kv::json::numincrby(&key, &path, &increment).await?;
+            json_numbers(values, path.legacy, session.protocol)
+        }
+        Command::JsonSet(key, path, value, condition) => {
+            tracing::info!(?key, ?path, ?value, ?condition, "Received JSON.SET");
+            match kv::json::set(key, &path, value, condition).await? {
+                true => RespValue::SimpleString("OK".to_string()),
+                false => RespValue::Null,
+            }
+        }
+        Command::JsonType(key, path) => {
+            tracing::info!(?key, ?path, "Received JSON.TYPE");
+            let types = kv::json::types(&key, &path).await?;
+            let types = types.unwrap_or_default().into_iter();
+            let mut names = types.map(|name| RespValue::SimpleString(name.to_string()));
+            match path.legacy {
+                true => names.next_back().unwrap_or(RespValue::Null),
+                false => RespValue::Array(names.collect()),
+            }
+        }
+        Command::BfAdd(key, items, multi) => {
+            tracing::info!(?key, ?items, "Received BF.ADD or BF.MADD");
+            let added = kv::bloom::add(key, &items).await?;
+            match multi {
+                true => RespValue::Array(
+                    added
+                        .into_iter()
+                        .map(|added| match added {
+                            Ok(added) => RespValue::Integer(added as i64),
+                            Err(error) => RespValue::Error(error.to_string()),
+                        })
+                        .collect(),
+                ),
+                false => {
+                    let added = added.into_iter().next().unwrap_or(Ok(false));
+                    RespValue::Integer(added? as i64)
+                }
+            }
+        }
+        Command::BfExists(key, items, multi) => {
+            tracing::info!(?key, ?items, "Received BF.EXISTS or BF.MEXISTS");
+            exists_reply(kv::bloom::exists(&key, &items).await?, multi)
+        }
+        Command::BfInfo(key, field) => {
+            tracing::info!(?key, ?field, "Received BF.INFO");
+            let info = kv::bloom::info(&key).await?;
+            let value = |field| match field {
+                BloomInfoField::Capacity => RespValue::Integer(info.capacity as i64),
+                BloomInfoField::Size => RespValue::Integer(info.size as i64),
+                BloomInfoField::Filters => RespValue::Integer(info.filters as i64),
+                BloomInfoField::Items => RespValue::Integer(info.items as i64),
+                BloomInfoField::Expansion => match info.expansion {
+                    Some(expansion) => RespValue::Integer(expansion as i64),
+                    None => RespValue::Null,
+                },
+            };
+            match field {
+                Some(field) => RespValue::Array(vec![value(field)]),
+                None => info_map(vec![
+                    ("Capacity", value(BloomInfoField::Capacity)),
+                    ("Size", value(BloomInfoField::Size)),
+                    ("Number of filters", value(BloomInfoField::Filters)),
+                    ("Number of items inserted", value(BloomInfoField::Items)),
+                    ("Expansion rate", value(BloomInfoField::Expansion)),
+                ]),
+            }
+        }
+        Command::BfReserve(key, options) => {
+            tracing::info!(?key, ?options, "Received BF.RESERVE");
+            kv::bloom::reserve(key, &options).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::CfCount(key, item) => {
+            tracing::info!(?key, ?item, "Received CF.COUNT");
+            RespValue::Integer(kv::cuckoo::count(&key, &item).await? as i64)
+        }
+        Command::CfDel(key, item) => {
+            tracing::info!(?key, ?item, "Received CF.DEL");
+            RespValue::Integer(kv::cuckoo::del(&key, &item).await? as i64)
+        }
+        Command::CfExists(key, items, multi) => {
+            tracing::info!(?key, ?items, "Received CF.EXISTS or CF.MEXISTS");
+            exists_reply(kv::cuckoo::exists(&key, &items).await?, multi)
+        }
+        Command::CfInfo(key) => {
+            tracing::info!(?key, "Received CF.INFO");
+            let info = kv::cuckoo::info(&key).await?;
+            info_map(vec![
+                ("Size", RespValue::Integer(info.size as i64)),
+                ("Number of buckets", RespValue::Integer(info.buckets as i64)),
+                ("Number of filters", RespValue::Integer(info.filters as i64)),
+                (
+                    "Number of items inserted",
+                    RespValue::Integer(info.items as i64),
+                ),
+                (
+                    "Number of items deleted",
+                    RespValue::Integer(info.deletes as i64),
+                ),
+                ("Bucket size", RespValue::Integer(info.bucket_size as i64)),
+                ("Expansion rate", RespValue::Integer(info.expansion as i64)),
+                (
+                    "Max iterations",
+                    RespValue::Integer(info.max_iterations as i64),
+                ),
+            ])
+        }
+        Command::CfInsert(key, create, items, nx, multi) => {
+            tracing::info!(?key, ?create, ?items, nx, "Received CF.ADD or CF.INSERT");
+            let added = kv::cuckoo::insert(key, create.as_ref(), &items, nx).await?;
+            match multi {
+                // Items that didn't fit are -1 rather than errors.
+                true => RespValue::Array(
+                    added
+                        .into_iter()
+                        .map(|added| RespValue::Integer(added.map_or(-1, |added| added as i64)))
+                        .collect(),
+                ),
+                false => {
+                    let added = added.into_iter().next().unwrap_or(Ok(false));
+                    RespValue::Integer(added? as i64)
+                }
+            }
+        }
+        Command::CfReserve(key, options) => {
+            tracing::info!(?key, ?options, "Received CF.RESERVE");
+            kv::cuckoo::reserve(key, &options).await?;
+            RespValue::SimpleString("OK".to_string())
+        }
+        Command::IncrBy(key, increment) => {
+            tracing::info!(?key, increment, "Received INCR, DECR, INCRBY or DECRBY");
+            RespValue::Integer(kv::string::incr_by(key, increment).await?)
+        }
+        Command::IncrByFloat(key, increment) => {
+            tracing::info!(?key, increment, "Received INCRBYFLOAT");
+            RespValue::BulkString(kv::string::incr_by_float(key, increment).await?)
+        }
+        Command::Get(key) => {
+            tracing::info!(?key, "Received GET");
+            match kv::get(&key).await? {
+                Some(value) => RespValue::BulkString(value),
+                None => RespValue::NullBulkString,
+            }
+        }
+    };
+
+    Ok(reply)
+}
+
+/// Reply of TTL and HTTL: the time left in `unit`, rounded to the nearest
+/// second, -1 without a TTL and -2 if there is nothing to expire.
+fn ttl_reply(ttl: Option<Option<u64>>, unit: TimeUnit) -> RespValue {
+    match ttl {
+        Some(Some(ttl)) => RespValue::Integer(match unit {
+            TimeUnit::Seconds => (ttl + 500) / 1000,
+            TimeUnit::Milliseconds => ttl,
+        } as i64),
+        Some(None) => RespValue::Integer(-1),
+        None => RespValue::Integer(-2),
+    }
+}
+
+/// Reply of EXPIRETIME and HEXPIRETIME, with the same conventions as `ttl_reply`.
+fn expire_time_reply(expires_at: Option<Option<u64>>, unit: TimeUnit) -> RespValue {
+    match expires_at {
+        Some(Some(expires_at)) => RespValue::Integer(match unit {
+            TimeUnit::Seconds => expires_at / 1000,
+            TimeUnit::Milliseconds => expires_at,
+        } as i64),
+        Some(None) => RespValue::Integer(-1),
+        None => RespValue::Integer(-2),
+    }
+}
+
+/// How long a blocking command waits, where a timeout of 0 means forever.
+fn block_for(timeout_ms: u64) -> Option<Duration> {
+    (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms))
+}
+
+/// Reply of LMPOP and BLMPOP: the key popped from and its elements.
+fn key_and_elements(popped: Option<(Bytes, Vec<Bytes>)>) -> RespValue {
+    match popped {
+        Some((key, elements)) => {
+            RespValue::Array(vec![RespValue::BulkString(key), bulk_strings(elements)])
+        }
+        None => RespValue::NullArray,
+    }
+}
+
+fn bulk_or_null(value: Option<Bytes>) -> RespValue {
+    match value {
+        Some(value) => RespValue::BulkString(value),
+        None => RespValue::NullBulkString,
+    }
+}
+
+fn bulk_strings(values: Vec<Bytes>) -> RespValue {
+    RespValue::Array(values.into_iter().map(RespValue::BulkString).collect())
+}
+
+/// A set reply, which RESP2 clients get as an array.
+fn bulk_set(values: Vec<Bytes>) -> RespValue {
+    RespValue::Set(values.into_iter().map(RespValue::BulkString).collect())
+}
+
+/// Stream entries, each an array of its ID and its fields and values.
+fn stream_entries(entries: Vec<(StreamId, Fields)>) -> RespValue {
+    RespValue::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| stream_entry(id, Some(fields)))
+            .collect(),
+    )
+}
+
+/// A stream entry as `[id, [field, value, ...]]`, with null fields for
+/// entries deleted while pending.
+fn stream_entry(id: StreamId, fields: Option<Fields>) -> RespValue {
+    let fields = match fields {
+        Some(fields) => {
+            let fields = fields.into_iter().flat_map(|(field, value)| [field, value]);
+            bulk_strings(fields.collect())
+        }
+        None => RespValue::NullArray,
+    };
+    RespValue::Array(vec![stream_id(id), fields])
+}
+
+fn stream_id(id: StreamId) -> RespValue {
+    RespValue::BulkString(id.to_string().into())
+}
+
+/// Fields reported by the XINFO subcommands, a map in RESP3 and a flat array
+/// of names and values in RESP2.
+fn info_map(fields: Vec<(&str, RespValue)>) -> RespValue {
+    RespValue::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| (RespValue::BulkString(Bytes::from(name.to_string())), value))
+            .collect(),
+    )
+}
+
+/// Entries read from several streams: a map from each key to its entries in
+/// RESP3, and an array of `[key, entries]` pairs in RESP2.
+fn keyed_streams(streams: Vec<(Bytes, RespValue)>, protocol: Protocol) -> RespValue {
+    let streams = streams
+        .into_iter()
+        .map(|(key, entries)| (RespValue::BulkString(key), entries));
+    match protocol {
+        Protocol::Resp3 => RespValue::Map(streams.collect()),
+        Protocol::Resp2 => RespValue::Array(
+            streams
+                .map(|(key, entries)| RespValue::Array(vec![key, entries]))
+                .collect(),
+        ),
+    }
+}
+
+/// A sorted set score, which RESP3 clients get as a double.
+fn score_reply(score: f64) -> RespValue {
+    if score == f64::INFINITY {
+        RespValue::PositiveInfinity
+    } else if score == f64::NEG_INFINITY {
+        RespValue::NegativeInfinity
+    } else {
+        RespValue::Double(score)
+    }
+}
+
+/// A distance the way GEO commands reply with it, to 4 decimals.
+fn geo_distance(distance: f64) -> Bytes {
+    format!("{distance:.4}").into()
+}
+
+/// A `[longitude, latitude]` pair. RESP2 has them as strings with 17
+/// decimals and trailing zeros cut, like Redis.
+fn geo_position((lon, lat): (f64, f64), protocol: Protocol) -> RespValue {
+    let coordinate = |value: f64| match protocol {
+        Protocol::Resp3 => RespValue::Double(value),
+        Protocol::Resp2 => {
+            let value = format!("{value:.17}");
+            let value = value.trim_end_matches('0').trim_end_matches('.');
+            RespValue::BulkString(Bytes::copy_from_slice(value.as_bytes()))
+        }
+    };
+    RespValue::Array(vec![coordinate(lon), coordinate(lat)])
+}
+
+/// Reply of the commands checking for items in Bloom and cuckoo filters:
+/// 1 or 0 for each item, in an array if `multi`.
+fn exists_reply(found: Vec<bool>, multi: bool) -> RespValue {
+    let mut found = found
+        .into_iter()
+        .map(|found| RespValue::Integer(found as i64));
+    match multi {
+        true => RespValue::Array(found.collect()),
+        false => found.next().unwrap_or(RespValue::Integer(0)),
+    }
+}
+
+/// Reply of JSON.NUMINCRBY: the new values, with null for matches that
+/// aren't numbers, and only the last one for a legacy path. RESP2 gets them
+/// as JSON text and RESP3 as numbers.
+fn json_numbers(values: Vec<Option<Json>>, legacy: bool, protocol: Protocol) -> RespValue {
+    let mut values = values.into_iter().map(|value| value.unwrap_or(Json::Null));
+    if protocol == Protocol::Resp2 {
+        let value = match legacy {
+            true => values.next_back().unwrap_or(Json::Null),
+            false => Json::Array(values.collect()),
+        };
+        return RespValue::BulkString(value.serialize(&Format::default()).into());
+    }
+
+    let number = |value: Json| match value {
+        Json::Integer(value) => RespValue::Integer(value),
+        Json::Float(value) => RespValue::Double(value),
+        _ => RespValue::Null,
+    };
+    match legacy {
+        true => values.next_back().map_or(RespValue::Null, number),
+        false => RespValue::Array(values.map(number).collect()),
+    }
+}
+
+/// Members with their scores: a `[member, score]` pair for each member in
+/// RESP3, and members and scores interleaved in a flat array in RESP2.
+fn scored_members(members: Vec<(Bytes, f64)>, protocol: Protocol) -> RespValue {
+    let pairs = members
+        .into_iter()
+        .map(|(member, score)| [RespValue::BulkString(member), score_reply(score)]);
+    match protocol {
+        Protocol::Resp3 => RespValue::Array(
+            pairs
+                .map(|pair| RespValue::Array(Vec::from(pair)))
+                .collect(),
+        ),
+        Protocol::Resp2 => RespValue::Array(pairs.flatten().collect()),
+    }
+}
@@ -0,0 +1,494 @@
+use std::fmt::Write;
+
+pub mod path;
+
+/// Deepest nesting a document may have, as in RedisJSON.
+pub const MAX_DEPTH: usize = 128;
+
+/// A JSON value. Integers are kept apart from other numbers so that they
+/// round-trip exactly, and objects keep their keys in insertion order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+// Numbers are never NaN, so every value equals itself.
+impl Eq for Json {}
+
+/// Whitespace JSON.GET puts around values when pretty printing.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Format {
+    /// Repeated once per nesting level at the start of each line.
+    pub indent: String,
+    /// Written before each element of an array or object.
+    pub newline: String,
+    /// Written between an object key and its value.
+    pub space: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at line {line} column {column}")]
+pub struct ParseError {
+    message: &'static str,
+    line: usize,
+    column: usize,
+}
+
+impl Json {
+    /// Name of the value's type, as reported by JSON.TYPE.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Integer(_) => "integer",
+            Json::Float(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// How many arrays and objects deep the value nests, 0 for scalars.
+    pub fn depth(&self) -> usize {
+        match self {
+            Json::Array(array) => 1 + array.iter().map(Json::depth).max().unwrap_or(0),
+            Json::Object(object) => {
+                1 + object
+                    .iter()
+                    .map(|(_, value)| value.depth())
+                    .max()
+                    .unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    /// The value at `location`, if there still is one.
+    pub fn pointer_mut(&mut self, location: &[path::Step]) -> Option<&mut Json> {
+        location
+            .iter()
+            .try_fold(self, |value, step| match (value, step) {
+                (Json::Object(object), path::Step::Key(key)) => object
+                    .iter_mut()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value),
+                (Json::Array(array), path::Step::Index(index)) => array.get_mut(*index),
+                _ => None,
+            })
+    }
+
+    /// Removes the value at `location`, returning whether there was one.
+    pub fn remove(&mut self, location: &[path::Step]) -> bool {
+        let Some((last, parent)) = location.split_last() else {
+            return false;
+        };
+        match (self.pointer_mut(parent), last) {
+            (Some(Json::Object(object)), path::Step::Key(key)) => {
+                let len = object.len();
+                object.retain(|(name, _)| name != key);
+                object.len() != len
+            }
+            (Some(Json::Array(array)), path::Step::Index(index)) if *index < array.len() => {
+                array.remove(*index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Serializes the value, compactly unless `format` asks for whitespace.
+    pub fn serialize(&self, format: &Format) -> String {
+        let mut out = String::new();
+        self.write(&mut out, format, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, format: &Format, depth: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Json::Integer(value) => {
+                let _ = write!(out, "{value}");
+            }
+            // Debug keeps the `.0` of integral floats and switches to an
+            // exponent for very large or small ones, as JSON encoders do.
+            Json::Float(value) => {
+                let _ = write!(out, "{value:?}");
+            }
+            Json::String(value) => write_string(out, value),
+            Json::Array(array) => {
+                write_items(out, format, depth, ('[', ']'), array, |out, value| {
+                    value.write(out, format, depth + 1)
+                })
+            }
+            Json::Object(object) => write_items(
+                out,
+                format,
+                depth,
+                ('{', '}'),
+                object,
+                |out, (key, value)| {
+                    write_string(out, key);
+                    out.push(':');
+                    out.push_str(&format.space);
+                    value.write(out, format, depth + 1);
+                },
+            ),
+        }
+    }
+}
+
+/// Writes the elements of an array or object between `brackets`, one per
+/// line when `format` has a newline.
+fn write_items<T>(
+    out: &mut String,
+    format: &Format,
+    depth: usize,
+    brackets: (char, char),
+    items: &[T],
+    mut write_item: impl FnMut(&mut String, &T),
+) {
+    out.push(brackets.0);
+    if items.is_empty() {
+        out.push(brackets.1);
+        return;
+    }
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&format.newline);
+        out.push_str(&format.indent.repeat(depth + 1));
+        write_item(out, item);
+    }
+    out.push_str(&format.newline);
+    out.push_str(&format.indent.repeat(depth));
+    out.push(brackets.1);
+}
+
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses a JSON document.
+pub fn parse(text: &[u8]) -> Result<Json, ParseError> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> ParseError {
+        let before = &self.text[..self.pos.min(self.text.len())];
+        let line = before.iter().filter(|&&c| c == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|&c| c == b'\n')
+            .map_or(0, |i| i + 1);
+        ParseError {
+            message,
+            line,
+            column: before.len() - line_start + 1,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("EOF while parsing a value")),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'[' | b'{') if depth >= MAX_DEPTH => Err(self.error("recursion limit exceeded")),
+            Some(b'[') => self.array(depth + 1),
+            Some(b'{') => self.object(depth + 1),
+            Some(_) => Err(self.error("expected value")),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Result<Json, ParseError> {
+        if !self.text[self.pos..].starts_with(literal.as_bytes()) {
+            return Err(self.error("expected value"));
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> Result<Json, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        let int_start = self.pos;
+        if self.digits() == 0 {
+            return Err(self.error("invalid number"));
+        }
+        if self.text[int_start] == b'0' && self.pos - int_start > 1 {
+            return Err(self.error("invalid number"));
+        }
+        let mut is_float = false;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            is_float = true;
+            if self.digits() == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            is_float = true;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("invalid number"));
+            }
+        }
+        // Only ASCII was consumed, so this can't fail.
+        let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default();
+        if !is_float {
+            if let Ok(value) = text.parse() {
+                return Ok(Json::Integer(value));
+            }
+        }
+        match text.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Json::Float(value)),
+            _ => Err(self.error("number out of range")),
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("EOF while parsing a string"));
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("invalid escape"))?;
+                    if !b"\"\\/bfnrtu".contains(&escaped) {
+                        return Err(self.error("invalid escape"));
+                    }
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        _ => self.unicode_escape()?,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                c if c < b' ' => {
+                    return Err(self.error(
+                        "control character (\\u0000-\\u001F) found while parsing a string",
+                    ));
+                }
+                c => out.push(c),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid unicode code point"))
+    }
+
+    /// Decodes the code point of a `\u` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex_escape()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.text[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("lone leading surrogate in hex escape"));
+            }
+            self.pos += 2;
+            let low = self.hex_escape()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("lone leading surrogate in hex escape"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode code point"))
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Json, ParseError> {
+        self.pos += 1;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(array));
+        }
+        loop {
+            array.push(self.value(depth)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(array));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Json, ParseError> {
+        self.pos += 1;
+        let mut object: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(object));
+        }
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'"') => {}
+                None => return Err(self.error("EOF while parsing an object")),
+                Some(_) => return Err(self.error("key must be a string")),
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected `:`"));
+            }
+            self.pos += 1;
+            let value = self.value(depth)?;
+            // A repeated key keeps its first position but its last value.
+            match object.iter_mut().find(|(name, _)| *name == key) {
+                Some((_, existing)) => *existing = value,
+                None => object.push((key, value)),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(object));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compact(text: &str) -> String {
+        parse(text.as_bytes())
+            .unwrap()
+            .serialize(&Format::default())
+    }
+
+    #[test]
+    fn test_parse_and_serialize() {
+        assert_eq!(
+            compact(r#" { "a" : [1, -2.5, 3e2, true, null], "b": {"c": "d"} } "#),
+            r#"{"a":[1,-2.5,300.0,true,null],"b":{"c":"d"}}"#
+        );
+        assert_eq!(compact(r#""\u00e9\ud83d\ude00\n\"""#), "\"é😀\\n\\\"\"");
+        assert_eq!(compact(r#"{"a":1,"b":2,"a":3}"#), r#"{"a":3,"b":2}"#);
+        assert_eq!(compact("9223372036854775808"), "9.223372036854776e18");
+        assert_eq!(compact("[[], {}]"), "[[],{}]");
+
+        let value = parse(br#"{"a":[1,2],"b":{}}"#).unwrap();
+        let format = Format {
+            indent: "  ".to_string(),
+            newline: "\n".to_string(),
+            space: " ".to_string(),
+        };
+        assert_eq!(
+            value.serialize(&format),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let message = |text: &str| parse(text.as_bytes()).unwrap_err().to_string();
+        assert_eq!(message(""), "EOF while parsing a value at line 1 column 1");
+        assert_eq!(message("[1,]"), "expected value at line 1 column 4");
+        assert_eq!(message("{\n\"a\" 1}"), "expected `:` at line 2 column 5");
+        assert_eq!(message("01"), "invalid number at line 1 column 3");
+        assert_eq!(message("1 2"), "trailing characters at line 1 column 3");
+        assert_eq!(message("\"\\x\""), "invalid escape at line 1 column 3");
+        assert!(parse(&[b'['; MAX_DEPTH]).is_err());
+        assert!(
+            parse(format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH)).as_bytes()).is_ok()
+        );
+        assert!(parse(
+            format!("{}{}", "[".repeat(MAX_DEPTH + 1), "]".repeat(MAX_DEPTH + 1)).as_bytes()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,638 @@
+use std::cmp::Ordering;
+
+use super::{Json, MAX_DEPTH};
+
+/// A path into a JSON document.
+///
+/// Paths starting with `$` are JSONPath expressions and select every match.
+/// Anything else is a legacy path such as `.a.b`, `a[0]` or `.`, which
+/// commands treat as naming a single value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    /// The path as the client wrote it.
+    pub text: String,
+    pub legacy: bool,
+    segments: Vec<Segment>,
+}
+
+/// One step of a path, applying its selectors to the current values or, for
+/// `..`, to them and all their descendants.
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    descendants: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Key(String),
+    Wildcard,
+    Index(i64),
+    Slice(Option<i64>, Option<i64>, i64),
+    Filter(Filter),
+}
+
+/// A `?(...)` filter expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Or(Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Exists(Operand),
+    Compare(Operand, Comparison, Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// A path relative to the value being filtered (`@`).
+    Current(Vec<Segment>),
+    /// A path from the document root (`$`).
+    Root(Vec<Segment>),
+    Literal(Json),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// One key or index on the way from the root to a value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Where a value sits in a document.
+pub type Location = Vec<Step>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid JSONPath '{0}'")]
+pub struct PathError(String);
+
+impl Path {
+    pub fn parse(text: &str) -> Result<Path, PathError> {
+        let error = || PathError(text.to_string());
+        let (legacy, body) = match text.strip_prefix('$') {
+            Some(body) => (false, body.to_string()),
+            None if text == "." => (true, String::new()),
+            None if text.starts_with(['.', '[']) => (true, text.to_string()),
+            None => (true, format!(".{text}")),
+        };
+        let mut parser = PathParser {
+            text: body.as_bytes(),
+            pos: 0,
+            depth: 0,
+        };
+        let segments = parser.segments().ok_or_else(error)?;
+        if parser.pos < body.len() {
+            return Err(error());
+        }
+        Ok(Path {
+            text: text.to_string(),
+            legacy,
+            segments,
+        })
+    }
+
+    /// The legacy path to the whole document, used when none is given.
+    pub fn root() -> Path {
+        Path {
+            text: ".".to_string(),
+            legacy: true,
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// The values the path selects in `root`, with their locations, in
+    /// document order.
+    pub fn select<'a>(&self, root: &'a Json) -> Vec<(Location, &'a Json)> {
+        select(&self.segments, root, root)
+    }
+
+    /// Splits a path ending in a plain key into the path to its parent and
+    /// that key, which is where JSON.SET creates missing values.
+    pub fn parent_and_key(&self) -> Option<(Path, &str)> {
+        let (last, parent) = self.segments.split_last()?;
+        match last.selectors.as_slice() {
+            [Selector::Key(key)] if !last.descendants => {
+                let parent = Path {
+                    text: self.text.clone(),
+                    legacy: self.legacy,
+                    segments: parent.to_vec(),
+                };
+                Some((parent, key))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn select<'a>(segments: &[Segment], start: &'a Json, root: &'a Json) -> Vec<(Location, &'a Json)> {
+    let mut current = vec![(Location::new(), start)];
+    for segment in segments {
+        let mut next = Vec::new();
+        for (location, value) in current {
+            if segment.descendants {
+                let mut stack = vec![(location, value)];
+                while let Some((location, value)) = stack.pop() {
+                    apply(&segment.selectors, &location, value, root, &mut next);
+                    let children = children(&location, value);
+                    stack.extend(children.into_iter().rev());
+                }
+            } else {
+                apply(&segment.selectors, &location, value, root, &mut next);
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+fn child(location: &[Step], step: Step) -> Location {
+    let mut location = location.to_vec();
+    location.push(step);
+    location
+}
+
+fn children<'a>(location: &[Step], value: &'a Json) -> Vec<(Location, &'a Json)> {
+    match value {
+        Json::Array(array) => array
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (child(location, Step::Index(i)), value))
+            .collect(),
+        Json::Object(object) => object
+            .iter()
+            .map(|(key, value)| (child(location, Step::Key(key.clone())), value))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn apply<'a>(
+    selectors: &[Selector],
+    location: &[Step],
+    value: &'a Json,
+    root: &'a Json,
+    out: &mut Vec<(Location, &'a Json)>,
+) {
+    for selector in selectors {
+        match (selector, value) {
+            (Selector::Key(key), Json::Object(object)) => {
+                if let Some((_, value)) = object.iter().find(|(name, _)| name == key) {
+                    out.push((child(location, Step::Key(key.clone())), value));
+                }
+            }
+            (Selector::Wildcard, _) => out.extend(children(location, value)),
+            (Selector::Index(index), Json::Array(array)) => {
+                let index = match *index < 0 {
+                    true => array.len() as i64 + index,
+                    false => *index,
+                };
+                if let Some(value) = usize::try_from(index).ok().and_then(|i| array.get(i)) {
+                    out.push((child(location, Step::Index(index as usize)), value));
+                }
+            }
+            (Selector::Slice(start, end, step), Json::Array(array)) => {
+                for i in slice_indexes(array.len() as i64, *start, *end, *step) {
+                    out.push((child(location, Step::Index(i)), &array[i]));
+                }
+            }
+            (Selector::Filter(filter), _) => out.extend(
+                children(location, value)
+                    .into_iter()
+                    .filter(|(_, value)| filter.matches(value, root)),
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Indexes a `[start:end:step]` slice selects in an array of `len` elements,
+/// counting negative bounds from the end.
+fn slice_indexes(len: i64, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let normalize = |bound: i64| if bound < 0 { len + bound } else { bound };
+    let mut indexes = Vec::new();
+    match step.signum() {
+        1 => {
+            let start = start.map_or(0, normalize).clamp(0, len);
+            let end = end.map_or(len, normalize).clamp(0, len);
+            let mut i = start;
+            while i < end {
+                indexes.push(i as usize);
+                i += step;
+            }
+        }
+        -1 => {
+            let start = start.map_or(len - 1, normalize).clamp(-1, len - 1);
+            let end = end.map_or(-1, normalize).clamp(-1, len - 1);
+            let mut i = start;
+            while i > end {
+                indexes.push(i as usize);
+                i += step;
+            }
+        }
+        _ => {}
+    }
+    indexes
+}
+
+impl Filter {
+    fn matches(&self, current: &Json, root: &Json) -> bool {
+        match self {
+            Filter::Or(left, right) => left.matches(current, root) || right.matches(current, root),
+            Filter::And(left, right) => left.matches(current, root) && right.matches(current, root),
+            Filter::Not(filter) => !filter.matches(current, root),
+            Filter::Exists(operand) => operand.value(current, root).is_some(),
+            Filter::Compare(left, comparison, right) => {
+                let left = left.value(current, root);
+                let right = right.value(current, root);
+                let ordering = match (left, right) {
+                    (None, None) => Some(Ordering::Equal),
+                    (Some(left), Some(right)) => compare(left, right),
+                    _ => None,
+                };
+                match comparison {
+                    Comparison::Eq => ordering == Some(Ordering::Equal),
+                    Comparison::Ne => ordering != Some(Ordering::Equal),
+                    Comparison::Lt => ordering == Some(Ordering::Less),
+                    Comparison::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    Comparison::Gt => ordering == Some(Ordering::Greater),
+                    Comparison::Ge => {
+                        matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, current: &'a Json, root: &'a Json) -> Option<&'a Json> {
+        match self {
+            Operand::Current(segments) => select(segments, current, root).first().map(|m| m.1),
+            Operand::Root(segments) => select(segments, root, root).first().map(|m| m.1),
+            Operand::Literal(value) => Some(value),
+        }
+    }
+}
+
+/// Orders numbers and strings. Other values are only ever equal or not.
+fn compare(left: &Json, right: &Json) -> Option<Ordering> {
+    let number = |value: &Json| match value {
+        Json::Integer(value) => Some(*value as f64),
+        Json::Float(value) => Some(*value),
+        _ => None,
+    };
+    match (left, right) {
+        (Json::Integer(left), Json::Integer(right)) => Some(left.cmp(right)),
+        (Json::String(left), Json::String(right)) => Some(left.cmp(right)),
+        _ => match (number(left), number(right)) {
+            (Some(left), Some(right)) => left.partial_cmp(&right),
+            _ => (left == right).then_some(Ordering::Equal),
+        },
+    }
+}
+
+struct PathParser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    /// How many negations, parentheses and filters the parser is inside of.
+    depth: usize,
+}
+
+impl PathParser<'_> {
+    /// Runs `parse` one level deeper into a filter, failing past `MAX_DEPTH`
+    /// so nesting can't exhaust the stack.
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let matched = self.text[self.pos..].starts_with(token.as_bytes());
+        if matched {
+            self.pos += token.len();
+        }
+        matched
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek() == Some(b' ') {
+            self.pos += 1;
+        }
+    }
+
+    /// Parses segments for as long as the text continues with one.
+    fn segments(&mut self) -> Option<Vec<Segment>> {
+        let mut segments = Vec::new();
+        while matches!(self.peek(), Some(b'.' | b'[')) {
+            segments.push(self.segment()?);
+        }
+        Some(segments)
+    }
+
+    fn segment(&mut self) -> Option<Segment> {
+        let descendants = self.eat("..");
+        if !descendants && self.eat("[") {
+            return Some(Segment {
+                descendants,
+                selectors: self.bracket()?,
+            });
+        }
+        if !descendants && !self.eat(".") {
+            return None;
+        }
+        let selectors = if self.eat("*") {
+            vec![Selector::Wildcard]
+        } else if descendants && self.eat("[") {
+            self.bracket()?
+        } else {
+            vec![Selector::Key(self.name()?)]
+        };
+        Some(Segment {
+            descendants,
+            selectors,
+        })
+    }
+
+    /// Parses a member name written in dot notation.
+    fn name(&mut self) -> Option<String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == b'_' || c == b'-' || c >= 0x80)
+        {
+            self.pos += 1;
+        }
+        let name = std::str::from_utf8(&self.text[start..self.pos]).ok()?;
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    /// Parses the comma separated selectors of a `[...]` segment, after the
+    /// opening bracket.
+    fn bracket(&mut self) -> Option<Vec<Selector>> {
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let selector = match self.peek()? {
+                b'*' => {
+                    self.pos += 1;
+                    Selector::Wildcard
+                }
+                b'\'' | b'"' => Selector::Key(self.string()?),
+                b'?' => {
+                    self.pos += 1;
+                    Selector::Filter(self.nested(Self::or)?)
+                }
+                _ => self.index_or_slice()?,
+            };
+            selectors.push(selector);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Some(selectors);
+            }
+            if !self.eat(",") {
+                return None;
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Option<Option<i64>> {
+        self.skip_whitespace();
+        let start = self.pos;
+        self.eat("-");
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Some(None);
+        }
+        let text = std::str::from_utf8(&self.text[start..self.pos]).ok()?;
+        text.parse().ok().map(Some)
+    }
+
+    fn index_or_slice(&mut self) -> Option<Selector> {
+        let start = self.integer()?;
+        self.skip_whitespace();
+        if !self.eat(":") {
+            return start.map(Selector::Index);
+        }
+        let end = self.integer()?;
+        self.skip_whitespace();
+        let step = match self.eat(":") {
+            true => self.integer()?.unwrap_or(1),
+            false => 1,
+        };
+        Some(Selector::Slice(start, end, step))
+    }
+
+    /// Parses a quoted string, in either single or double quotes.
+    fn string(&mut self) -> Option<String> {
+        let quote = self.peek()?;
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.peek()? {
+                c if c == quote => break,
+                b'\\' => {
+                    self.pos += 1;
+                    out.push(self.peek()?);
+                }
+                c => out.push(c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        String::from_utf8(out).ok()
+    }
+
+    fn or(&mut self) -> Option<Filter> {
+        let mut filter = self.and()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("||") {
+                return Some(filter);
+            }
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+    }
+
+    fn and(&mut self) -> Option<Filter> {
+        let mut filter = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("&&") {
+                return Some(filter);
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Option<Filter> {
+        self.skip_whitespace();
+        if self.eat("!") {
+            return Some(Filter::Not(Box::new(self.nested(Self::unary)?)));
+        }
+        if self.eat("(") {
+            let filter = self.nested(Self::or)?;
+            self.skip_whitespace();
+            return self.eat(")").then_some(filter);
+        }
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let comparisons = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        match comparisons.into_iter().find(|(token, _)| self.eat(token)) {
+            Some((_, comparison)) => Some(Filter::Compare(left, comparison, self.operand()?)),
+            None if matches!(left, Operand::Literal(_)) => None,
+            None => Some(Filter::Exists(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Option<Operand> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'@' => {
+                self.pos += 1;
+                Some(Operand::Current(self.segments()?))
+            }
+            b'$' => {
+                self.pos += 1;
+                Some(Operand::Root(self.segments()?))
+            }
+            b'\'' | b'"' => Some(Operand::Literal(Json::String(self.string()?))),
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || matches!(c, b'-' | b'+' | b'.'))
+                {
+                    self.pos += 1;
+                }
+                super::parse(&self.text[start..self.pos])
+                    .ok()
+                    .map(Operand::Literal)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(path: &str, document: &str) -> String {
+        let document = super::super::parse(document.as_bytes()).unwrap();
+        let path = Path::parse(path).unwrap();
+        let matches = path.select(&document);
+        let values = matches
+            .into_iter()
+            .map(|(_, value)| value.clone())
+            .collect();
+        Json::Array(values).serialize(&Default::default())
+    }
+
+    #[test]
+    fn test_select() {
+        let document = r#"{"a":{"b":[1,2,3,4]},"c":[{"b":5},{"d":{"b":"x"}}]}"#;
+        assert_eq!(select("$", "1"), "[1]");
+        assert_eq!(select(".", "1"), "[1]");
+        assert_eq!(select("$.a.b[1]", document), "[2]");
+        assert_eq!(select("a.b[-1]", document), "[4]");
+        assert_eq!(select("$['a'][\"b\"][0,2]", document), "[1,3]");
+        assert_eq!(select("$.a.b[1:3]", document), "[2,3]");
+        assert_eq!(select("$.a.b[::-2]", document), "[4,2]");
+        assert_eq!(select("$.a.b[-2:]", document), "[3,4]");
+        assert_eq!(select("$..b", document), r#"[[1,2,3,4],5,"x"]"#);
+        assert_eq!(select("$.c[*].b", document), "[5]");
+        assert_eq!(select("$.c.*", document), r#"[{"b":5},{"d":{"b":"x"}}]"#);
+        assert_eq!(select("$.missing", document), "[]");
+        assert_eq!(select("$.a.b[9]", document), "[]");
+    }
+
+    #[test]
+    fn test_filters() {
+        let document = r#"{"books":[
+            {"title":"A","price":8.95,"tags":["x"]},
+            {"title":"B","price":12,"isbn":"1"},
+            {"title":"C","price":22.99,"isbn":"2"}]}"#;
+        assert_eq!(
+            select("$.books[?(@.price < 10)].title", document),
+            r#"["A"]"#
+        );
+        assert_eq!(select("$.books[?(@.isbn)].title", document), r#"["B","C"]"#);
+        assert_eq!(select("$.books[?(!@.isbn)].title", document), r#"["A"]"#);
+        assert_eq!(
+            select("$.books[?@.price>10 && @.title!='C'].title", document),
+            r#"["B"]"#
+        );
+        assert_eq!(
+            select("$.books[?(@.title=='A' || @.price==22.99)].title", document),
+            r#"["A","C"]"#
+        );
+        assert_eq!(
+            select("$.books[?(@.price > $.books[0].price)].title", document),
+            r#"["B","C"]"#
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(Path::parse("$").unwrap().is_root());
+        assert!(!Path::parse("$").unwrap().legacy);
+        assert!(Path::parse(".").unwrap().legacy);
+        assert!(Path::parse("a.b").unwrap().legacy);
+        for invalid in ["$.", "$a", "$[", "$[1", "$[?(@.a <)]", "a..", "$.a b"] {
+            assert_eq!(
+                Path::parse(invalid),
+                Err(PathError(invalid.to_string())),
+                "{invalid}"
+            );
+        }
+
+        let nested = |open: &str, close: &str, depth: usize| {
+            format!("${}.a{}", open.repeat(depth), close.repeat(depth))
+        };
+        assert!(Path::parse(&format!("$[?{}@.a]", "!".repeat(MAX_DEPTH - 1))).is_ok());
+        assert!(Path::parse(&nested("[?@", "]", MAX_DEPTH)).is_ok());
+        for deep in [
+            format!("$[?{}@.a]", "!".repeat(200_000)),
+            format!("$[?{}@.a{}]", "(".repeat(200_000), ")".repeat(200_000)),
+            nested("[?@", "]", MAX_DEPTH + 1),
+        ] {
+            assert_eq!(Path::parse(&deep), Err(PathError(deep.clone())));
+        }
+
+        let path = Path::parse("$.a[0].b").unwrap();
+        let (parent, key) = path.parent_and_key().unwrap();
+        assert_eq!(key, "b");
+        let document = super::super::parse(br#"{"a":[{"c":1}]}"#).unwrap();
+        assert_eq!(
+            parent.select(&document)[0].0,
+            vec![Step::Key("a".to_string()), Step::Index(0)]
+        );
+        assert!(Path::parse("$..b").unwrap().parent_and_key().is_none());
+    }
+}
@@ -1,75 +1,1281 @@
-use std::collections::HashMap;
-use std::sync::{Mutex, RwLock};
-use std::time::Duration;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Context;
+use bytes::Bytes;
+use thiserror::Error;
 use tokio::sync::{mpsc, OnceCell};
-use tokio::time::Instant;
 
-static KV: OnceCell<RwLock<HashMap<String, String>>> = OnceCell::const_new();
-static CRON: OnceCell<Mutex<Vec<(String, Instant)>>> = OnceCell::const_new();
+use crate::commands::{ExpireCondition, Expiry, SetCondition, SetOptions};
+use crate::glob;
 
-pub fn init() {
-    KV.set(RwLock::new(HashMap::new()))
-        .expect("KV should be set only once");
-    CRON.set(Mutex::new(Vec::new()))
-        .expect("CRON should be set only once");
+pub mod bitmap;
+pub mod blocking;
+pub mod bloom;
+pub mod cuckoo;
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod json;
+pub mod list;
+pub mod set;
+pub mod stream;
+pub mod string;
+pub mod zset;
 
-    tokio::spawn(async {
-        loop {
-            tokio::time::sleep(Duration::from_millis(100)).await;
+static KV: OnceCell<RwLock<Db>> = OnceCell::const_new();
+
+/// Upper bound on keys reclaimed per sweep so a burst of expirations can't
+/// hold the write lock for long.
+const SWEEP_LIMIT: usize = 1000;
+
+/// Size limits under which Redis keeps small values in compact encodings.
+const MAX_LISTPACK_ENTRIES: usize = 128;
+const MAX_LISTPACK_VALUE: usize = 64;
+const MAX_INTSET_ENTRIES: usize = 512;
+const MAX_EMBSTR_LEN: usize = 44;
+
+/// Most bytes a Bloom or cuckoo filter may take, the size of the largest
+/// string.
+const MAX_FILTER_LEN: u64 = 512 * 1024 * 1024;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("ERR source and destination objects are the same")]
+    SameObject,
+    #[error("ERR index out of range")]
+    IndexOutOfRange,
+    #[error("ERR hash value is not an integer")]
+    HashValueNotInteger,
+    #[error("ERR hash value is not a float")]
+    HashValueNotFloat,
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+    #[error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+    #[error("ERR The specified keys must contain string values")]
+    LcsWrongType,
+    #[error("ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len")]
+    LcsTooLarge,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR increment would produce NaN or Infinity")]
+    NotFinite,
+    #[error("ERR resulting score is not a number (NaN)")]
+    ScoreNaN,
+    #[error("ERR The ID specified in XADD is equal or smaller than the target stream top item")]
+    StreamIdTooSmall,
+    #[error("ERR The ID specified in XADD must be greater than 0-0")]
+    StreamIdZero,
+    #[error("ERR The stream has exhausted the last possible ID, unable to add more items")]
+    StreamExhausted,
+    #[error("NOGROUP No such consumer group '{1}' for key name '{0}'")]
+    NoSuchGroup(String, String),
+    #[error("NOGROUP No such key '{0}' or consumer group '{1}'")]
+    NoKeyOrGroup(String, String),
+    #[error("NOGROUP No such key '{0}' or consumer group '{1}' in XREADGROUP with GROUP option")]
+    NoReadGroup(String, String),
+    #[error("UNBLOCKED the stream key no longer exists")]
+    StreamKeyDeleted,
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    BusyGroup,
+    #[error("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")]
+    XGroupNoKey,
+    #[error("ERR could not decode requested zset member")]
+    NoSuchGeoMember,
+    #[error("WRONGTYPE Key is not a valid HyperLogLog string value.")]
+    NotHyperLogLog,
+    #[error("INVALIDOBJ Corrupted HLL object detected")]
+    CorruptedHyperLogLog,
+    #[error("ERR new objects must be created at the root")]
+    JsonNewAtRoot,
+    #[error("ERR could not perform this operation on a key that doesn't exist")]
+    JsonNoKey,
+    #[error("ERR Path '{0}' does not exist")]
+    JsonPathMissing(String),
+    #[error("WRONGTYPE wrong type of path value - expected {0} but found {1}")]
+    JsonWrongType(&'static str, &'static str),
+    #[error("ERR result is not a number")]
+    JsonNotFinite,
+    #[error("ERR recursion limit exceeded")]
+    JsonTooDeep,
+    #[error("ERR item exists")]
+    ItemExists,
+    #[error("ERR not found")]
+    FilterNotFound,
+    #[error("ERR non scaling filter is full")]
+    NonScalingFilterFull,
+    #[error("ERR Filter is full")]
+    CuckooFilterFull,
+    #[error("ERR Insufficient memory to create filter")]
+    FilterTooLarge,
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+}
 
-            let mut cron = CRON
-                .get()
-                .expect("CRON should be initialized")
-                .lock()
-                .expect("Failed to acquire lock");
-
-            let now = Instant::now();
-            cron.retain(|(key, expiry)| {
-                if now >= *expiry {
-                    let mut kv = KV
-                        .get()
-                        .expect("KV should be initialized")
-                        .write()
-                        .expect("Failed to acquire write lock");
-                    kv.remove(key);
-                    false
+/// A value stored in the keyspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(hash::Hash),
+    Set(set::Set),
+    SortedSet(zset::SortedSet),
+    Stream(stream::Stream),
+    Json(crate::json::Json),
+    Bloom(bloom::BloomFilter),
+    Cuckoo(cuckoo::CuckooFilter),
+}
+
+impl From<Bytes> for Value {
+    fn from(value: Bytes) -> Self {
+        Value::String(value)
+    }
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::Stream(_) => "stream",
+            Value::Json(_) => "ReJSON-RL",
+            Value::Bloom(_) => "MBbloom--",
+            Value::Cuckoo(_) => "MBbloomCF",
+        }
+    }
+
+    /// Name of the encoding Redis would use for this value, as reported by
+    /// OBJECT ENCODING. Values are always stored the same way here, so the
+    /// encoding is derived from their size rather than tracked.
+    fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(value) => {
+                let is_int = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .is_some_and(|int| int.to_string().as_bytes() == value);
+                if is_int {
+                    "int"
+                } else if value.len() <= MAX_EMBSTR_LEN {
+                    "embstr"
                 } else {
-                    true
+                    "raw"
                 }
+            }
+            Value::List(list) if is_small(list.len(), list) => "listpack",
+            Value::List(_) => "quicklist",
+            Value::Hash(hash)
+                if is_small(hash.len(), hash.fields.iter().flat_map(|(k, v)| [k, v])) =>
+            {
+                // Small hashes whose fields have TTLs keep the deadlines
+                // next to the fields.
+                match hash.expires.is_empty() {
+                    true => "listpack",
+                    false => "listpackex",
+                }
+            }
+            Value::Hash(_) => "hashtable",
+            Value::Set(set) => {
+                let is_intset = set.len() <= MAX_INTSET_ENTRIES
+                    && set.iter().all(|member| {
+                        std::str::from_utf8(member)
+                            .ok()
+                            .and_then(|member| member.parse::<i64>().ok())
+                            .is_some()
+                    });
+                if is_intset {
+                    "intset"
+                } else if is_small(set.len(), set) {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+            Value::SortedSet(zset) if is_small(zset.len(), zset.members()) => "listpack",
+            Value::SortedSet(_) => "skiplist",
+            Value::Stream(_) => "stream",
+            // Module types report the encoding of a plain object.
+            Value::Json(_) | Value::Bloom(_) | Value::Cuckoo(_) => "raw",
+        }
+    }
+}
+
+/// Whether a collection is small enough for Redis to store it as a listpack.
+fn is_small<'a>(len: usize, items: impl IntoIterator<Item = &'a Bytes>) -> bool {
+    len <= MAX_LISTPACK_ENTRIES
+        && items
+            .into_iter()
+            .all(|item| item.len() <= MAX_LISTPACK_VALUE)
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Value,
+    /// Absolute deadline in milliseconds since the Unix epoch.
+    expires_at: Option<u64>,
+    /// Last time the key was accessed, in milliseconds since the Unix epoch.
+    /// Atomic so that readers holding the shared lock can update it.
+    lru: AtomicU64,
+}
+
+impl Clone for Entry {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            expires_at: self.expires_at,
+            lru: AtomicU64::new(self.lru.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Entry {
+    fn new(value: Value, expires_at: Option<u64>, now: u64) -> Self {
+        Self {
+            value,
+            expires_at,
+            lru: AtomicU64::new(now),
+        }
+    }
+
+    /// Whether the key's deadline passed, or that of every field of a hash.
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+            || matches!(&self.value, Value::Hash(hash) if hash.is_expired(now))
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.value.type_name()
+    }
+
+    fn touch(&self, now: u64) {
+        self.lru.store(now, Ordering::Relaxed);
+    }
+
+    fn idle_time(&self, now: u64) -> u64 {
+        now.saturating_sub(self.lru.load(Ordering::Relaxed))
+    }
+}
+
+/// The keyspace together with indexes of key deadlines and scan order.
+///
+/// `expires` holds exactly one `(deadline, key)` pair for every entry that has
+/// a TTL, ordered by deadline, so the sweep only looks at keys that are due.
+///
+/// `scan_order` holds every key ordered by `scan_hash`. A SCAN cursor is the
+/// hash to resume from, so keys that exist for the whole scan are returned no
+/// matter what is inserted or deleted in between.
+///
+/// `field_expires` does the same for hash fields with a TTL, holding exactly
+/// one `(deadline, key, field)` triple for each of them.
+///
+/// `blocked` tracks the clients waiting for a list to appear under a key, or
+/// for entries to be added to a stream.
+#[derive(Debug, Default)]
+struct Db {
+    entries: HashMap<Bytes, Entry>,
+    expires: BTreeSet<(u64, Bytes)>,
+    field_expires: hash::FieldIndex,
+    scan_order: BTreeSet<(u64, Bytes)>,
+    blocked: blocking::Blocked,
+}
+
+fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Visits `order` from the `cursor` hash on, returning the items seen and the
+/// cursor to resume from, or 0 once everything was visited. At least `count`
+/// items are visited, more if several share the hash where the page ends,
+/// since a cursor cannot point between them.
+fn scan_page(order: &BTreeSet<(u64, Bytes)>, cursor: u64, count: usize) -> (u64, Vec<&Bytes>) {
+    let mut last_hash = None;
+    let mut items = Vec::new();
+
+    let range = order.range((cursor, Bytes::new())..);
+    for (visited, (hash, item)) in range.enumerate() {
+        if visited >= count && last_hash != Some(*hash) {
+            return (*hash, items);
+        }
+        last_hash = Some(*hash);
+        items.push(item);
+    }
+
+    (0, items)
+}
+
+/// Picks items at random the way HRANDFIELD and SRANDMEMBER do: a positive
+/// `count` returns up to that many distinct items, a negative one returns
+/// exactly `-count` items that may repeat.
+fn random_sample<T: Clone>(mut items: Vec<T>, count: i64) -> Vec<T> {
+    if items.is_empty() {
+        return items;
+    }
+    if count < 0 {
+        return (0..count.unsigned_abs())
+            .map(|_| items[random_index(items.len())].clone())
+            .collect();
+    }
+
+    let count = (count as usize).min(items.len());
+    for i in 0..count {
+        let j = i + random_index(items.len() - i);
+        items.swap(i, j);
+    }
+    items.truncate(count);
+    items
+}
+
+/// A random index below `len`, for commands that pick elements at random.
+fn random_index(len: usize) -> usize {
+    // Every `RandomState` is keyed differently, which is all the randomness
+    // these commands need.
+    let random = RandomState::new().build_hasher().finish();
+    (random % len as u64) as usize
+}
+
+impl Db {
+    /// Returns the live entry under `key` without refreshing its access time.
+    fn get(&self, key: &[u8], now: u64) -> Option<&Entry> {
+        self.entries.get(key).filter(|entry| !entry.is_expired(now))
+    }
+
+    /// Returns the live entry under `key` for modification, marking it accessed.
+    fn get_mut(&mut self, key: &[u8], now: u64) -> Option<&mut Entry> {
+        let entry = self
+            .entries
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(now))?;
+        entry.touch(now);
+        Some(entry)
+    }
+
+    /// Stores `value` under `key`, replacing both the previous value and its TTL.
+    fn insert(&mut self, key: Bytes, value: impl Into<Value>, expires_at: Option<u64>) {
+        self.insert_entry(key, Entry::new(value.into(), expires_at, now_ms()));
+    }
+
+    fn insert_entry(&mut self, key: Bytes, entry: Entry) {
+        let replaces_stream = matches!(
+            self.entries.get(&key).map(|entry| &entry.value),
+            Some(Value::Stream(_))
+        );
+        if replaces_stream || matches!(entry.value, Value::List(_) | Value::Stream(_)) {
+            self.blocked.signal(&key);
+        }
+
+        let expires_at = entry.expires_at;
+        if let Some(expires_at) = expires_at {
+            self.expires.insert((expires_at, key.clone()));
+        }
+
+        let previous = self.entries.insert(key.clone(), entry);
+        if let Some(Value::Hash(hash)) = previous.as_ref().map(|entry| &entry.value) {
+            hash.unindex(&key, &mut self.field_expires);
+        }
+        if let Some(Value::Hash(hash)) = self.entries.get(&key).map(|entry| &entry.value) {
+            hash.index(&key, &mut self.field_expires);
+        }
+        match previous.map(|entry| entry.expires_at) {
+            Some(Some(previous)) if Some(previous) != expires_at => {
+                self.expires.remove(&(previous, key));
+            }
+            Some(_) => {}
+            None => {
+                self.scan_order.insert((scan_hash(&key), key));
+            }
+        }
+    }
+
+    /// Removes `key` if it holds an empty collection, as Redis never keeps
+    /// empty lists, hashes, sets or sorted sets around.
+    fn remove_if_empty(&mut self, key: &[u8]) {
+        let empty = self
+            .entries
+            .get(key)
+            .is_some_and(|entry| match &entry.value {
+                Value::String(_) => false,
+                Value::List(list) => list.is_empty(),
+                Value::Hash(hash) => hash.is_empty(),
+                Value::Set(set) => set.is_empty(),
+                Value::SortedSet(zset) => zset.is_empty(),
+                // Empty streams are kept, along with the last ID they handed out.
+                Value::Stream(_) => false,
+                Value::Json(_) | Value::Bloom(_) | Value::Cuckoo(_) => false,
             });
+        if empty {
+            self.remove(key);
         }
-    });
+    }
+
+    fn set(
+        &mut self,
+        key: Bytes,
+        value: Bytes,
+        options: &SetOptions,
+        now: u64,
+    ) -> Result<(bool, Option<Bytes>), Error> {
+        let current = self.get(&key, now);
+        let previous = match current.map(|entry| &entry.value) {
+            Some(Value::String(previous)) => Some(previous.clone()),
+            Some(_) if options.get => return Err(Error::WrongType),
+            _ => None,
+        };
+        let write = match options.condition {
+            Some(SetCondition::IfAbsent) => current.is_none(),
+            Some(SetCondition::IfPresent) => current.is_some(),
+            None => true,
+        };
+
+        if write {
+            let expires_at = options.expiry.and_then(|expiry| {
+                deadline(expiry, now, current.and_then(|entry| entry.expires_at))
+            });
+            self.insert_entry(key, Entry::new(Value::String(value), expires_at, now));
+        }
+
+        Ok((write, previous))
+    }
+
+    /// Replaces the TTL of an existing key, keeping the deadline index in sync.
+    fn set_expiry(&mut self, key: &[u8], expires_at: Option<u64>) {
+        let Some((key, entry)) = self.entries.get_key_value(key) else {
+            return;
+        };
+        let key = key.clone();
+
+        if let Some(previous) = entry.expires_at {
+            self.expires.remove(&(previous, key.clone()));
+        }
+        if let Some(expires_at) = expires_at {
+            self.expires.insert((expires_at, key.clone()));
+        }
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.expires_at = expires_at;
+        }
+    }
+
+    fn expire(&mut self, key: &[u8], expiry: Expiry, condition: ExpireCondition, now: u64) -> bool {
+        let Some(entry) = self.get(key, now) else {
+            return false;
+        };
+        let current = entry.expires_at;
+        let Some(expires_at) = deadline(expiry, now, current) else {
+            return false;
+        };
+
+        if !expire_allowed(condition, current, expires_at) {
+            return false;
+        }
+
+        if expires_at <= now {
+            self.remove(key);
+        } else {
+            self.set_expiry(key, Some(expires_at));
+        }
+        true
+    }
+
+    fn persist(&mut self, key: &[u8], now: u64) -> bool {
+        let has_ttl = self
+            .get(key, now)
+            .and_then(|entry| entry.expires_at)
+            .is_some();
+        if !has_ttl {
+            return false;
+        }
+        self.set_expiry(key, None);
+        true
+    }
+
+    /// Removes `key`, returning whether it held a live value.
+    fn delete(&mut self, key: &[u8], now: u64) -> bool {
+        let live = self.get(key, now).is_some();
+        self.remove(key);
+        live
+    }
+
+    /// Moves the value and TTL of `key` to `new_key`. With `only_if_absent`
+    /// nothing happens if `new_key` already exists.
+    fn rename(
+        &mut self,
+        key: &[u8],
+        new_key: Bytes,
+        only_if_absent: bool,
+        now: u64,
+    ) -> Result<bool, Error> {
+        if self.get(key, now).is_none() {
+            return Err(Error::NoSuchKey);
+        }
+        if only_if_absent && self.get(&new_key, now).is_some() {
+            return Ok(false);
+        }
+        if key == new_key {
+            return Ok(true);
+        }
+
+        let entry = self.remove(key).expect("key should exist");
+        self.insert_entry(new_key, entry);
+        Ok(true)
+    }
+
+    /// Copies the value and TTL of `source` to `destination`. Unless `replace`
+    /// is set nothing happens if `destination` already exists.
+    fn copy(
+        &mut self,
+        source: &[u8],
+        destination: Bytes,
+        replace: bool,
+        now: u64,
+    ) -> Result<bool, Error> {
+        if source == destination {
+            return Err(Error::SameObject);
+        }
+        let Some(entry) = self.get(source, now).cloned() else {
+            return Ok(false);
+        };
+        if !replace && self.get(&destination, now).is_some() {
+            return Ok(false);
+        }
+
+        self.insert(destination, entry.value, entry.expires_at);
+        Ok(true)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<Entry> {
+        let (key, entry) = self.entries.remove_entry(key)?;
+        if let Value::Stream(_) = entry.value {
+            // Group readers blocked on the stream fail now that it's gone.
+            self.blocked.signal(&key);
+        }
+        if let Some(expires_at) = entry.expires_at {
+            self.expires.remove(&(expires_at, key.clone()));
+        }
+        if let Value::Hash(hash) = &entry.value {
+            hash.unindex(&key, &mut self.field_expires);
+        }
+        self.scan_order.remove(&(scan_hash(&key), key));
+        Some(entry)
+    }
+
+    fn remove_if_expired(&mut self, key: &[u8], now: u64) {
+        if self
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.is_expired(now))
+        {
+            self.remove(key);
+        }
+    }
+
+    /// Removes up to `limit` keys whose deadline has passed, earliest first.
+    fn remove_expired(&mut self, now: u64, limit: usize) -> usize {
+        let mut removed = 0;
+        while removed < limit {
+            match self.expires.first() {
+                Some((expires_at, _)) if *expires_at <= now => {}
+                _ => break,
+            }
+            let (_, key) = self.expires.pop_first().expect("index should not be empty");
+            self.remove(&key);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Returns the live keys matching `pattern`.
+    fn keys(&self, pattern: &[u8], now: u64) -> Vec<Bytes> {
+        self.entries
+            .iter()
+            .filter(|(key, entry)| !entry.is_expired(now) && glob::matches(pattern, key))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Visits about `count` keys in scan order starting at `cursor` and
+    /// returns the cursor to continue from (0 once done) with the live keys
+    /// among them. Keys sharing a hash are always visited together so that
+    /// the next cursor never lands in the middle of them.
+    fn scan(&self, cursor: u64, count: usize, now: u64) -> (u64, Vec<(&Bytes, &Entry)>) {
+        let (cursor, keys) = scan_page(&self.scan_order, cursor, count);
+        let entries = keys
+            .into_iter()
+            .filter_map(|key| self.entries.get_key_value(key))
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .collect();
+        (cursor, entries)
+    }
 }
 
-pub async fn get(key: &str) -> Option<String> {
-    let kv = KV
-        .get()
+/// Whether `condition` allows replacing the `current` deadline of a key or
+/// hash field with `expires_at`.
+fn expire_allowed(condition: ExpireCondition, current: Option<u64>, expires_at: u64) -> bool {
+    // No TTL counts as expiring never, i.e. later than any deadline.
+    match current {
+        None => !condition.xx && !condition.gt,
+        Some(current) => {
+            !condition.nx
+                && (!condition.gt || expires_at > current)
+                && (!condition.lt || expires_at < current)
+        }
+    }
+}
+
+/// Resolves `expiry` to an absolute deadline; `current` is the key's existing one.
+fn deadline(expiry: Expiry, now: u64, current: Option<u64>) -> Option<u64> {
+    match expiry {
+        Expiry::After(ttl) => Some(now.saturating_add(ttl)),
+        Expiry::At(deadline) => Some(deadline),
+        Expiry::KeepTtl => current,
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System clock should be after the Unix epoch")
+        .as_millis() as u64
+}
+
+fn read() -> RwLockReadGuard<'static, Db> {
+    KV.get()
         .expect("KV should be initialized")
         .read()
-        .expect("Failed to acquire read lock");
+        .expect("Failed to acquire read lock")
+}
+
+/// Exclusive access to the keyspace. Clients blocked on keys that the holder
+/// pushed to are served before the lock is released, so they observe the
+/// write as a whole and nobody else can take the elements first.
+struct WriteGuard(RwLockWriteGuard<'static, Db>);
+
+impl Deref for WriteGuard {
+    type Target = Db;
 
-    kv.get(key).cloned()
+    fn deref(&self) -> &Db {
+        &self.0
+    }
 }
 
-pub async fn set(key: &str, value: String, expiry: Option<u64>) {
-    let mut kv = KV
+impl DerefMut for WriteGuard {
+    fn deref_mut(&mut self) -> &mut Db {
+        &mut self.0
+    }
+}
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        self.0.serve_blocked(now_ms());
+    }
+}
+
+fn write() -> WriteGuard {
+    let guard = KV
         .get()
         .expect("KV should be initialized")
         .write()
         .expect("Failed to acquire write lock");
+    WriteGuard(guard)
+}
+
+pub fn init() {
+    KV.set(RwLock::new(Db::default()))
+        .expect("KV should be set only once");
 
-    kv.insert(key.to_string(), value);
+    // Expired keys are already invisible to readers, the sweep only gives
+    // their memory back.
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let now = now_ms();
+            let mut db = write();
+            db.remove_expired(now, SWEEP_LIMIT);
+            db.remove_expired_fields(now, SWEEP_LIMIT);
+        }
+    });
+}
+
+/// Sets up the keyspace for tests that go through it, however many of them
+/// do. Keys are shared, so each test uses its own.
+#[cfg(test)]
+pub fn init_for_tests() {
+    let _ = KV.set(RwLock::new(Db::default()));
+}
+
+/// Runs `f` on the live entry stored under `key`. An expired entry found on
+/// the way is dropped now rather than at the next sweep.
+fn with_entry<T>(key: &[u8], f: impl FnOnce(&Entry, u64) -> T) -> Option<T> {
+    let now = now_ms();
+    {
+        let db = read();
+        if let Some(entry) = db.get(key, now) {
+            return Some(f(entry, now));
+        }
+        if !db.entries.contains_key(key) {
+            return None;
+        }
+    }
+
+    write().remove_if_expired(key, now);
+    None
+}
+
+pub async fn get(key: &[u8]) -> Result<Option<Bytes>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::String(value) => Ok(value.clone()),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+/// Stores `value` under `key` unless the SET condition rules it out. Returns
+/// whether the value was written along with the string previously stored.
+pub async fn set(
+    key: Bytes,
+    value: Bytes,
+    options: &SetOptions,
+) -> Result<(bool, Option<Bytes>), Error> {
+    write().set(key, value, options, now_ms())
+}
+
+/// Applies `expiry` to an existing key. Returns whether the TTL was changed;
+/// a deadline that has already passed deletes the key.
+pub async fn expire(key: &[u8], expiry: Expiry, condition: ExpireCondition) -> bool {
+    write().expire(key, expiry, condition, now_ms())
+}
+
+/// Remaining time to live in milliseconds: `None` if the key doesn't exist,
+/// `Some(None)` if it has no TTL.
+pub async fn ttl(key: &[u8]) -> Option<Option<u64>> {
+    with_entry(key, |entry, now| {
+        entry
+            .expires_at
+            .map(|expires_at| expires_at.saturating_sub(now))
+    })
+}
+
+/// Absolute deadline in milliseconds since the Unix epoch, with the same
+/// conventions as `ttl`.
+pub async fn expire_time(key: &[u8]) -> Option<Option<u64>> {
+    with_entry(key, |entry, _| entry.expires_at)
+}
+
+/// Removes the TTL of `key`. Returns whether there was one.
+pub async fn persist(key: &[u8]) -> bool {
+    write().persist(key, now_ms())
+}
+
+/// Deletes every key in `keys`, returning how many existed.
+pub async fn del(keys: &[Bytes]) -> usize {
+    let now = now_ms();
+    let mut db = write();
+    keys.iter().filter(|key| db.delete(key, now)).count()
+}
+
+/// Counts the keys in `keys` that exist; a key given twice is counted twice.
+pub async fn exists(keys: &[Bytes]) -> usize {
+    let now = now_ms();
+    let db = read();
+    keys.iter().filter(|key| db.get(key, now).is_some()).count()
+}
+
+/// Counts the keys in `keys` that exist, marking them accessed.
+pub async fn touch(keys: &[Bytes]) -> usize {
+    let now = now_ms();
+    let db = read();
+    keys.iter()
+        .filter_map(|key| db.get(key, now))
+        .map(|entry| entry.touch(now))
+        .count()
+}
+
+pub async fn key_type(key: &[u8]) -> &'static str {
+    with_entry(key, |entry, _| entry.type_name()).unwrap_or("none")
+}
+
+pub async fn encoding(key: &[u8]) -> Option<&'static str> {
+    with_entry(key, |entry, _| entry.value.encoding())
+}
+
+/// Milliseconds since `key` was last accessed.
+pub async fn idle_time(key: &[u8]) -> Option<u64> {
+    with_entry(key, |entry, now| entry.idle_time(now))
+}
+
+pub async fn keys(pattern: &[u8]) -> Vec<Bytes> {
+    read().keys(pattern, now_ms())
+}
+
+/// One SCAN step: returns the next cursor and the keys found that match
+/// `pattern` and `type_name`, if given.
+pub async fn scan(
+    cursor: u64,
+    count: usize,
+    pattern: Option<&[u8]>,
+    type_name: Option<&str>,
+) -> (u64, Vec<Bytes>) {
+    let db = read();
+    let (cursor, keys) = db.scan(cursor, count, now_ms());
+    let keys = keys
+        .into_iter()
+        .filter(|(key, entry)| {
+            let pattern_matches = match pattern {
+                Some(pattern) => glob::matches(pattern, key),
+                None => true,
+            };
+            let type_matches = match type_name {
+                Some(type_name) => entry.type_name() == type_name,
+                None => true,
+            };
+            pattern_matches && type_matches
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    (cursor, keys)
+}
+
+pub async fn rename(key: &[u8], new_key: Bytes, only_if_absent: bool) -> Result<bool, Error> {
+    write().rename(key, new_key, only_if_absent, now_ms())
+}
+
+pub async fn copy(source: &[u8], destination: Bytes, replace: bool) -> Result<bool, Error> {
+    write().copy(source, destination, replace, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(db: &Db, key: &str, now: u64) -> Option<Bytes> {
+        db.get(key.as_bytes(), now).map(|entry| match &entry.value {
+            Value::String(value) => value.clone(),
+            value => panic!("Expected a string, got {:?}", value),
+        })
+    }
+
+    #[test]
+    fn get_hides_expired_entry() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), Some(100));
+
+        assert_eq!(value(&db, "key", 99), Some(Bytes::from("value")));
+        assert_eq!(value(&db, "key", 100), None);
+    }
+
+    #[test]
+    fn insert_without_expiry_clears_previous_ttl() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("v1"), Some(100));
+        db.insert(Bytes::from("key"), Bytes::from("v2"), None);
+
+        assert!(db.expires.is_empty());
+        assert_eq!(db.remove_expired(200, SWEEP_LIMIT), 0);
+        assert_eq!(value(&db, "key", 200), Some(Bytes::from("v2")));
+    }
+
+    #[test]
+    fn insert_replaces_previous_deadline() {
+        let mut db = Db::default();
+        for expires_at in [100, 200, 300] {
+            db.insert(Bytes::from("key"), Bytes::from("value"), Some(expires_at));
+        }
+
+        assert_eq!(db.expires.len(), 1);
+        assert_eq!(db.remove_expired(250, SWEEP_LIMIT), 0);
+        assert_eq!(db.remove_expired(300, SWEEP_LIMIT), 1);
+        assert!(db.entries.is_empty());
+    }
+
+    #[test]
+    fn remove_expired_stops_at_first_live_deadline() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("a"), Bytes::from("1"), Some(10));
+        db.insert(Bytes::from("b"), Bytes::from("2"), Some(20));
+        db.insert(Bytes::from("c"), Bytes::from("3"), Some(30));
+        db.insert(Bytes::from("d"), Bytes::from("4"), None);
+
+        assert_eq!(db.remove_expired(20, SWEEP_LIMIT), 2);
+        assert_eq!(db.entries.len(), 2);
+        assert_eq!(db.expires.len(), 1);
+    }
+
+    #[test]
+    fn remove_expired_respects_limit() {
+        let mut db = Db::default();
+        for i in 0..10 {
+            db.insert(Bytes::from(format!("key{i}")), Bytes::from("v"), Some(i));
+        }
+
+        assert_eq!(db.remove_expired(100, 4), 4);
+        assert_eq!(db.entries.len(), 6);
+        assert_eq!(db.remove_expired(100, 100), 6);
+        assert!(db.expires.is_empty());
+    }
+
+    fn options(expiry: Option<Expiry>, condition: Option<SetCondition>) -> SetOptions {
+        SetOptions {
+            expiry,
+            condition,
+            get: false,
+        }
+    }
+
+    #[test]
+    fn set_respects_condition() {
+        let mut db = Db::default();
+        let key = Bytes::from("key");
+        let if_absent = options(None, Some(SetCondition::IfAbsent));
+        let if_present = options(None, Some(SetCondition::IfPresent));
+
+        let outcome = db.set(key.clone(), Bytes::from("v1"), &if_present, 0);
+        assert_eq!(outcome, Ok((false, None)));
+        assert_eq!(value(&db, "key", 0), None);
+
+        let outcome = db.set(key.clone(), Bytes::from("v1"), &if_absent, 0);
+        assert_eq!(outcome, Ok((true, None)));
+
+        let outcome = db.set(key.clone(), Bytes::from("v2"), &if_absent, 0);
+        assert_eq!(outcome, Ok((false, Some(Bytes::from("v1")))));
+
+        let outcome = db.set(key, Bytes::from("v2"), &if_present, 0);
+        assert_eq!(outcome, Ok((true, Some(Bytes::from("v1")))));
+        assert_eq!(value(&db, "key", 0), Some(Bytes::from("v2")));
+    }
+
+    #[test]
+    fn set_treats_expired_key_as_absent() {
+        let mut db = Db::default();
+        let key = Bytes::from("key");
+        let expiring = options(Some(Expiry::After(10)), None);
+        db.set(key.clone(), Bytes::from("v1"), &expiring, 0)
+            .unwrap();
+
+        let if_absent = options(None, Some(SetCondition::IfAbsent));
+        let outcome = db.set(key, Bytes::from("v2"), &if_absent, 10);
+        assert_eq!(outcome, Ok((true, None)));
+    }
+
+    #[test]
+    fn set_keeps_or_clears_ttl() {
+        let mut db = Db::default();
+        let key = Bytes::from("key");
+        let expiring = options(Some(Expiry::At(100)), None);
+        db.set(key.clone(), Bytes::from("v1"), &expiring, 0)
+            .unwrap();
+
+        let keep_ttl = options(Some(Expiry::KeepTtl), None);
+        db.set(key.clone(), Bytes::from("v2"), &keep_ttl, 0)
+            .unwrap();
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, Some(100));
+
+        db.set(key, Bytes::from("v3"), &SetOptions::default(), 0)
+            .unwrap();
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, None);
+        assert!(db.expires.is_empty());
+    }
+
+    #[test]
+    fn set_overwrites_other_types_unless_get() {
+        let mut db = Db::default();
+        let key = Bytes::from("key");
+        db.insert(
+            key.clone(),
+            Value::List(VecDeque::from([Bytes::from("a")])),
+            None,
+        );
+
+        let get = SetOptions {
+            get: true,
+            ..SetOptions::default()
+        };
+        let outcome = db.set(key.clone(), Bytes::from("v1"), &get, 0);
+        assert_eq!(outcome, Err(Error::WrongType));
+        assert_eq!(db.get(b"key", 0).unwrap().type_name(), "list");
+
+        let outcome = db.set(key, Bytes::from("v1"), &SetOptions::default(), 0);
+        assert_eq!(outcome, Ok((true, None)));
+        assert_eq!(value(&db, "key", 0), Some(Bytes::from("v1")));
+    }
+
+    #[test]
+    fn encoding_follows_value_size() {
+        let string = |s: &str| Value::String(Bytes::from(s.to_string()));
+        assert_eq!(string("12345").encoding(), "int");
+        assert_eq!(string("012").encoding(), "embstr");
+        assert_eq!(string("hello").encoding(), "embstr");
+        assert_eq!(string(&"x".repeat(45)).encoding(), "raw");
+
+        let list: VecDeque<Bytes> = (0..128).map(|i| Bytes::from(i.to_string())).collect();
+        assert_eq!(Value::List(list.clone()).encoding(), "listpack");
+        let mut long = list;
+        long.push_back(Bytes::from("x"));
+        assert_eq!(Value::List(long).encoding(), "quicklist");
+
+        let fields = HashMap::from([(Bytes::from("f"), Bytes::from("x".repeat(65)))]);
+        let hash = hash::Hash {
+            fields,
+            ..Default::default()
+        };
+        assert_eq!(Value::Hash(hash).encoding(), "hashtable");
+
+        let ints: set::Set = (0..200).map(|i| Bytes::from(i.to_string())).collect();
+        assert_eq!(Value::Set(ints).encoding(), "intset");
+        let words = set::Set::from_iter([Bytes::from("a"), Bytes::from("1")]);
+        assert_eq!(Value::Set(words).encoding(), "listpack");
+    }
+
+    fn condition(flags: &str) -> ExpireCondition {
+        ExpireCondition {
+            nx: flags.contains("NX"),
+            xx: flags.contains("XX"),
+            gt: flags.contains("GT"),
+            lt: flags.contains("LT"),
+        }
+    }
+
+    #[test]
+    fn expire_sets_and_replaces_deadline() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), None);
+
+        assert!(db.expire(b"key", Expiry::After(100), condition(""), 0));
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, Some(100));
+
+        assert!(db.expire(b"key", Expiry::At(500), condition(""), 0));
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, Some(500));
+        assert_eq!(db.expires.len(), 1);
+
+        assert!(!db.expire(b"missing", Expiry::After(100), condition(""), 0));
+    }
+
+    #[test]
+    fn expire_in_the_past_deletes_key() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), None);
+
+        assert!(db.expire(b"key", Expiry::At(0), condition(""), 10));
+        assert!(db.entries.is_empty());
+    }
+
+    #[test]
+    fn expire_respects_nx_and_xx() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), None);
+
+        assert!(!db.expire(b"key", Expiry::At(100), condition("XX"), 0));
+        assert!(db.expire(b"key", Expiry::At(100), condition("NX"), 0));
+        assert!(!db.expire(b"key", Expiry::At(200), condition("NX"), 0));
+        assert!(db.expire(b"key", Expiry::At(200), condition("XX"), 0));
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, Some(200));
+    }
+
+    #[test]
+    fn expire_respects_gt_and_lt() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), None);
+
+        // No TTL counts as infinite: nothing is greater, everything is less.
+        assert!(!db.expire(b"key", Expiry::At(100), condition("GT"), 0));
+        assert!(db.expire(b"key", Expiry::At(100), condition("LT"), 0));
+
+        assert!(!db.expire(b"key", Expiry::At(150), condition("LT"), 0));
+        assert!(db.expire(b"key", Expiry::At(50), condition("LT"), 0));
+        assert!(!db.expire(b"key", Expiry::At(50), condition("GT"), 0));
+        assert!(db.expire(b"key", Expiry::At(80), condition("XX GT"), 0));
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, Some(80));
+    }
+
+    #[test]
+    fn persist_clears_ttl() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), Some(100));
+
+        assert!(db.persist(b"key", 0));
+        assert!(!db.persist(b"key", 0));
+        assert!(db.expires.is_empty());
+        assert_eq!(db.remove_expired(200, SWEEP_LIMIT), 0);
+        assert!(!db.persist(b"missing", 0));
+    }
+
+    #[test]
+    fn delete_reports_only_live_keys() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("live"), Bytes::from("value"), None);
+        db.insert(Bytes::from("expired"), Bytes::from("value"), Some(10));
+
+        assert!(db.delete(b"live", 20));
+        assert!(!db.delete(b"expired", 20));
+        assert!(!db.delete(b"missing", 20));
+        assert!(db.entries.is_empty());
+        assert!(db.expires.is_empty());
+    }
+
+    #[test]
+    fn rename_moves_value_and_ttl() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("src"), Bytes::from("value"), Some(100));
+        db.insert(Bytes::from("dst"), Bytes::from("old"), Some(50));
+
+        assert!(db.rename(b"src", Bytes::from("dst"), false, 0).unwrap());
+        assert!(db.get(b"src", 0).is_none());
+        let entry = db.get(b"dst", 0).unwrap();
+        assert_eq!(entry.value, Value::String(Bytes::from("value")));
+        assert_eq!(entry.expires_at, Some(100));
+        assert_eq!(db.expires.len(), 1);
+    }
+
+    #[test]
+    fn rename_missing_key_fails() {
+        let mut db = Db::default();
+        assert!(matches!(
+            db.rename(b"src", Bytes::from("dst"), false, 0),
+            Err(Error::NoSuchKey)
+        ));
+    }
+
+    #[test]
+    fn renamenx_keeps_existing_destination() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("src"), Bytes::from("value"), None);
+        db.insert(Bytes::from("dst"), Bytes::from("old"), None);
+
+        assert!(!db.rename(b"src", Bytes::from("dst"), true, 0).unwrap());
+        assert_eq!(value(&db, "dst", 0), Some(Bytes::from("old")));
+        assert!(!db.rename(b"src", Bytes::from("src"), true, 0).unwrap());
+        assert!(db.rename(b"src", Bytes::from("src"), false, 0).unwrap());
+        assert_eq!(value(&db, "src", 0), Some(Bytes::from("value")));
+    }
+
+    #[test]
+    fn copy_preserves_ttl_and_honours_replace() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("src"), Bytes::from("value"), Some(100));
+        db.insert(Bytes::from("dst"), Bytes::from("old"), None);
+
+        assert!(!db.copy(b"src", Bytes::from("dst"), false, 0).unwrap());
+        assert!(db.copy(b"src", Bytes::from("dst"), true, 0).unwrap());
+        assert_eq!(db.get(b"dst", 0).unwrap().expires_at, Some(100));
+        assert_eq!(value(&db, "src", 0), Some(Bytes::from("value")));
+        assert_eq!(db.expires.len(), 2);
+
+        assert!(!db.copy(b"missing", Bytes::from("dst"), true, 0).unwrap());
+        assert!(matches!(
+            db.copy(b"src", Bytes::from("src"), true, 0),
+            Err(Error::SameObject)
+        ));
+    }
+
+    fn scan_all(db: &Db, count: usize, now: u64) -> Vec<Bytes> {
+        let mut cursor = 0;
+        let mut keys = Vec::new();
+        loop {
+            let (next, batch) = db.scan(cursor, count, now);
+            keys.extend(batch.into_iter().map(|(key, _)| key.clone()));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn keys_matches_pattern() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("user:1"), Bytes::from("a"), None);
+        db.insert(Bytes::from("user:2"), Bytes::from("b"), Some(10));
+        db.insert(Bytes::from("order:1"), Bytes::from("c"), None);
+
+        let mut keys = db.keys(b"user:*", 0);
+        keys.sort();
+        assert_eq!(keys, vec![Bytes::from("user:1"), Bytes::from("user:2")]);
+        assert_eq!(db.keys(b"user:*", 10), vec![Bytes::from("user:1")]);
+        assert_eq!(db.keys(b"*", 10).len(), 2);
+    }
+
+    #[test]
+    fn scan_returns_every_key_once() {
+        let mut db = Db::default();
+        let mut expected = Vec::new();
+        for i in 0..100 {
+            let key = Bytes::from(format!("key{i}"));
+            db.insert(key.clone(), Bytes::from("v"), None);
+            expected.push(key);
+        }
+        expected.sort();
+
+        assert_eq!(scan_all(&db, 7, 0), expected);
+        assert_eq!(scan_all(&db, 1000, 0), expected);
+    }
+
+    #[test]
+    fn scan_tolerates_concurrent_changes() {
+        let mut db = Db::default();
+        for i in 0..50 {
+            db.insert(Bytes::from(format!("stable{i}")), Bytes::from("v"), None);
+            db.insert(Bytes::from(format!("doomed{i}")), Bytes::from("v"), None);
+        }
+
+        let mut cursor = 0;
+        let mut seen = Vec::new();
+        let mut round = 0;
+        loop {
+            let (next, batch) = db.scan(cursor, 10, 0);
+            seen.extend(batch.into_iter().map(|(key, _)| key.clone()));
+
+            db.remove(format!("doomed{round}").as_bytes());
+            for i in 0..20 {
+                let key = Bytes::from(format!("new{round}-{i}"));
+                db.insert(key, Bytes::from("v"), None);
+            }
+            round += 1;
+
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        for i in 0..50 {
+            let key = Bytes::from(format!("stable{i}"));
+            assert_eq!(seen.iter().filter(|seen| **seen == key).count(), 1);
+        }
+    }
+
+    #[test]
+    fn scan_skips_expired_keys() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("live"), Bytes::from("v"), None);
+        db.insert(Bytes::from("expired"), Bytes::from("v"), Some(10));
+
+        assert_eq!(scan_all(&db, 10, 20), vec![Bytes::from("live")]);
+    }
 
-    if let Some(expiry) = expiry {
-        let expiry = Instant::now() + Duration::from_millis(expiry);
-        let mut cron = CRON
-            .get()
-            .expect("CRON should be initialized")
-            .lock()
-            .expect("Failed to acquire lock");
+    #[test]
+    fn remove_drops_index_entry() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), Some(100));
 
-        cron.push((key.to_string(), expiry));
+        assert!(db.remove(b"key").is_some());
+        assert!(db.expires.is_empty());
+        assert!(db.scan_order.is_empty());
     }
 }
@@ -0,0 +1,433 @@
+use bytes::{Bytes, BytesMut};
+
+use super::{now_ms, with_entry, write, Db, Entry, Error, Value};
+use crate::commands::{BitField, BitFieldOp, BitOperation, BitRange, Overflow, RangeUnit};
+
+/// Bit `offset` of `value`, counting from the most significant bit of the
+/// first byte. Bits past the end of the string are 0.
+fn bit(value: &[u8], offset: usize) -> bool {
+    value
+        .get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset & 7)) != 0)
+}
+
+fn set_bit(value: &mut [u8], offset: usize, bit: bool) {
+    let mask = 0x80 >> (offset & 7);
+    match bit {
+        true => value[offset / 8] |= mask,
+        false => value[offset / 8] &= !mask,
+    }
+}
+
+/// Resolves `start` and `end` against a string of `len` bytes to an
+/// inclusive range of bit offsets, where negative values count from the end
+/// of the string. Returns `None` if the range is empty.
+fn bit_range(len: usize, start: i64, end: i64, unit: RangeUnit) -> Option<(usize, usize)> {
+    let total = match unit {
+        RangeUnit::Byte => len,
+        RangeUnit::Bit => len * 8,
+    } as i64;
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+    let start = if start < 0 { start + total } else { start }.max(0);
+    let end = if end < 0 { end + total } else { end }
+        .max(0)
+        .min(total - 1);
+    if start > end {
+        return None;
+    }
+
+    let (start, end) = (start as usize, end as usize);
+    Some(match unit {
+        RangeUnit::Byte => (start * 8, end * 8 + 7),
+        RangeUnit::Bit => (start, end),
+    })
+}
+
+/// Counts the set bits from `start` to `end` inclusive.
+fn count_bits(value: &[u8], start: usize, end: usize) -> usize {
+    let (first, last) = (start / 8, end / 8);
+    value[first..=last]
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| {
+            let mut byte = *byte;
+            if index == 0 {
+                byte &= 0xff >> (start & 7);
+            }
+            if index == last - first {
+                byte &= 0xff << (7 - (end & 7));
+            }
+            byte.count_ones() as usize
+        })
+        .sum()
+}
+
+/// Offset of the first bit equal to `bit` from `start` to `end` inclusive.
+fn find_bit(value: &[u8], bit: bool, start: usize, end: usize) -> Option<usize> {
+    let skipped = if bit { 0x00 } else { 0xff };
+    let mut offset = start;
+    while offset <= end {
+        // Whole bytes without the bit are skipped at once.
+        if offset & 7 == 0 && offset + 7 <= end && value[offset / 8] == skipped {
+            offset += 8;
+            continue;
+        }
+        if self::bit(value, offset) == bit {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+    None
+}
+
+/// Reads the `bits` wide integer at bit `offset`, most significant bit
+/// first, sign-extending it if `signed`.
+fn get_field(value: &[u8], offset: usize, field: BitField) -> i64 {
+    let raw = (0..field.bits as usize).fold(0u64, |raw, index| {
+        (raw << 1) | bit(value, offset + index) as u64
+    });
+    match field.signed && field.bits < 64 && raw >> (field.bits - 1) & 1 == 1 {
+        true => (raw | u64::MAX << field.bits) as i64,
+        false => raw as i64,
+    }
+}
+
+fn set_field(value: &mut [u8], offset: usize, field: BitField, field_value: i64) {
+    let raw = field_value as u64;
+    for index in 0..field.bits as usize {
+        let shift = field.bits as usize - 1 - index;
+        set_bit(value, offset + index, raw >> shift & 1 == 1);
+    }
+}
+
+/// Fits `value` in `field` as `overflow` says, returning `None` if it
+/// doesn't fit and overflows FAIL.
+fn fit_field(value: i128, field: BitField, overflow: Overflow) -> Option<i64> {
+    let span = 1i128 << field.bits;
+    let (min, max) = match field.signed {
+        true => (-(span / 2), span / 2 - 1),
+        false => (0, span - 1),
+    };
+    if (min..=max).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        Overflow::Wrap => {
+            let wrapped = value.rem_euclid(span);
+            Some(if wrapped > max {
+                wrapped - span
+            } else {
+                wrapped
+            } as i64)
+        }
+        Overflow::Sat => Some(value.clamp(min, max) as i64),
+        Overflow::Fail => None,
+    }
+}
+
+/// Runs BITFIELD `ops` on `value`, which must be long enough for every
+/// write. Returns the reply of each operation other than OVERFLOW.
+fn run_bitfield(value: &mut [u8], ops: &[BitFieldOp]) -> Vec<Option<i64>> {
+    let mut overflow = Overflow::Wrap;
+    let mut replies = Vec::new();
+    for op in ops {
+        match *op {
+            BitFieldOp::Overflow(mode) => overflow = mode,
+            BitFieldOp::Get(field, offset) => replies.push(Some(get_field(value, offset, field))),
+            BitFieldOp::Set(field, offset, new) => {
+                // Unsigned fields take the value as unsigned, so that
+                // negative ones overflow past the maximum.
+                let new = match field.signed {
+                    true => new as i128,
+                    false => new as u64 as i128,
+                };
+                let old = get_field(value, offset, field);
+                replies.push(fit_field(new, field, overflow).map(|new| {
+                    set_field(value, offset, field, new);
+                    old
+                }));
+            }
+            BitFieldOp::IncrBy(field, offset, increment) => {
+                let old = get_field(value, offset, field);
+                let new = fit_field(old as i128 + increment as i128, field, overflow);
+                if let Some(new) = new {
+                    set_field(value, offset, field, new);
+                }
+                replies.push(new);
+            }
+        }
+    }
+    replies
+}
+
+impl Db {
+    /// Sets bit `offset` of the string under `key`, growing it with zero
+    /// bytes as needed. Returns the previous bit.
+    fn setbit(&mut self, key: Bytes, offset: usize, bit: bool, now: u64) -> Result<bool, Error> {
+        self.update_string(key, now, |current| {
+            let mut value = BytesMut::from(current.map_or(&[][..], |current| &current[..]));
+            if value.len() <= offset / 8 {
+                value.resize(offset / 8 + 1, 0);
+            }
+            let previous = self::bit(&value, offset);
+            set_bit(&mut value, offset, bit);
+            Ok((value.freeze(), previous))
+        })
+    }
+
+    /// Stores the result of `op` on the strings under `keys` in
+    /// `destination`, missing keys counting as strings of zero bytes. Returns
+    /// the length of the result, which is deleted rather than stored if empty.
+    fn bitop(
+        &mut self,
+        op: BitOperation,
+        destination: Bytes,
+        keys: &[Bytes],
+        now: u64,
+    ) -> Result<usize, Error> {
+        let mut values = Vec::new();
+        for key in keys {
+            values.push(self.string_ref(key, now)?.cloned().unwrap_or_default());
+        }
+        let len = values.iter().map(|value| value.len()).max().unwrap_or(0);
+        if len == 0 {
+            self.remove(&destination);
+            return Ok(0);
+        }
+
+        let byte = |value: &Bytes, index: usize| value.get(index).copied().unwrap_or(0);
+        let result: Vec<u8> = (0..len)
+            .map(|index| {
+                let mut bytes = values.iter().map(|value| byte(value, index));
+                let first = bytes.next().unwrap_or(0);
+                match op {
+                    BitOperation::And => bytes.fold(first, |result, byte| result & byte),
+                    BitOperation::Or => bytes.fold(first, |result, byte| result | byte),
+                    BitOperation::Xor => bytes.fold(first, |result, byte| result ^ byte),
+                    BitOperation::Not => !first,
+                }
+            })
+            .collect();
+        let entry = Entry::new(Value::String(result.into()), None, now);
+        self.insert_entry(destination, entry);
+        Ok(len)
+    }
+
+    /// Runs BITFIELD `ops` that write to the string under `key`, growing it
+    /// to fit the furthest field written first.
+    fn bitfield(
+        &mut self,
+        key: Bytes,
+        ops: &[BitFieldOp],
+        now: u64,
+    ) -> Result<Vec<Option<i64>>, Error> {
+        let len = ops
+            .iter()
+            .filter_map(|op| match *op {
+                BitFieldOp::Set(field, offset, _) | BitFieldOp::IncrBy(field, offset, _) => {
+                    Some((offset + field.bits as usize).div_ceil(8))
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        self.update_string(key, now, |current| {
+            let mut value = BytesMut::from(current.map_or(&[][..], |current| &current[..]));
+            if value.len() < len {
+                value.resize(len, 0);
+            }
+            let replies = run_bitfield(&mut value, ops);
+            Ok((value.freeze(), replies))
+        })
+    }
+}
+
+/// The string under `key`, marking it accessed.
+fn string(key: &[u8]) -> Result<Option<Bytes>, Error> {
+    let value = with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::String(value) => Ok(value.clone()),
+            _ => Err(Error::WrongType),
+        }
+    });
+    value.transpose()
+}
+
+/// See `Db::setbit`.
+pub async fn set(key: Bytes, offset: usize, bit: bool) -> Result<bool, Error> {
+    write().setbit(key, offset, bit, now_ms())
+}
+
+pub async fn get(key: &[u8], offset: usize) -> Result<bool, Error> {
+    Ok(string(key)?.is_some_and(|value| bit(&value, offset)))
+}
+
+/// Counts the set bits of the string under `key`, within `range` if given.
+pub async fn count(key: &[u8], range: Option<BitRange>) -> Result<usize, Error> {
+    let Some(value) = string(key)? else {
+        return Ok(0);
+    };
+    let range = match range {
+        Some(range) => {
+            let end = range.end.unwrap_or(-1);
+            bit_range(value.len(), range.start, end, range.unit)
+        }
+        None if value.is_empty() => None,
+        None => Some((0, value.len() * 8 - 1)),
+    };
+    Ok(range.map_or(0, |(start, end)| count_bits(&value, start, end)))
+}
+
+/// Offset of the first bit equal to `bit` in the string under `key`, within
+/// `range` if given, or -1 if there is none. Without an end to the range,
+/// the string counts as padded with zeros on the right.
+pub async fn position(key: &[u8], bit: bool, range: Option<BitRange>) -> Result<i64, Error> {
+    let Some(value) = string(key)? else {
+        return Ok(if bit { -1 } else { 0 });
+    };
+    let (start, end, unit) = match range {
+        Some(range) => (range.start, range.end, range.unit),
+        None => (0, None, RangeUnit::Byte),
+    };
+    let Some((start, last)) = bit_range(value.len(), start, end.unwrap_or(-1), unit) else {
+        return Ok(-1);
+    };
+    Ok(match find_bit(&value, bit, start, last) {
+        Some(offset) => offset as i64,
+        None if !bit && end.is_none() => last as i64 + 1,
+        None => -1,
+    })
+}
+
+/// See `Db::bitop`.
+pub async fn operation(
+    op: BitOperation,
+    destination: Bytes,
+    keys: &[Bytes],
+) -> Result<usize, Error> {
+    write().bitop(op, destination, keys, now_ms())
+}
+
+/// Runs BITFIELD `ops` on the string under `key`. Reads alone don't create
+/// the key. Returns the reply of each operation other than OVERFLOW, `None`
+/// for writes that overflowed with FAIL.
+pub async fn field(key: Bytes, ops: &[BitFieldOp]) -> Result<Vec<Option<i64>>, Error> {
+    let writes = ops
+        .iter()
+        .any(|op| matches!(op, BitFieldOp::Set(..) | BitFieldOp::IncrBy(..)));
+    if writes {
+        return write().bitfield(key, ops, now_ms());
+    }
+    let mut value = string(&key)?.map(Vec::from).unwrap_or_default();
+    Ok(run_bitfield(&mut value, ops))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(signed: bool, bits: u32) -> BitField {
+        BitField { signed, bits }
+    }
+
+    fn value(db: &Db, key: &str) -> Bytes {
+        db.string_ref(key.as_bytes(), 0).unwrap().unwrap().clone()
+    }
+
+    #[test]
+    fn setbit_grows_the_string() {
+        let mut db = Db::default();
+        assert_eq!(db.setbit(Bytes::from("bits"), 7, true, 0), Ok(false));
+        assert_eq!(db.setbit(Bytes::from("bits"), 7, true, 0), Ok(true));
+        assert_eq!(db.setbit(Bytes::from("bits"), 17, true, 0), Ok(false));
+        assert_eq!(value(&db, "bits"), Bytes::from_static(&[0x01, 0x00, 0x40]));
+        assert_eq!(db.setbit(Bytes::from("bits"), 7, false, 0), Ok(true));
+        assert_eq!(value(&db, "bits"), Bytes::from_static(&[0x00, 0x00, 0x40]));
+    }
+
+    #[test]
+    fn ranges_count_and_find_bits() {
+        let value = b"\xff\xf0\x00";
+        assert_eq!(bit_range(3, 0, -1, RangeUnit::Byte), Some((0, 23)));
+        assert_eq!(bit_range(3, 5, 30, RangeUnit::Bit), Some((5, 23)));
+        assert_eq!(bit_range(3, -1, -2, RangeUnit::Byte), None);
+        assert_eq!(bit_range(3, 2, 1, RangeUnit::Bit), None);
+
+        assert_eq!(count_bits(value, 0, 23), 12);
+        assert_eq!(count_bits(value, 5, 10), 6);
+        assert_eq!(count_bits(value, 12, 23), 0);
+
+        assert_eq!(find_bit(value, false, 0, 23), Some(12));
+        assert_eq!(find_bit(value, true, 2, 23), Some(2));
+        assert_eq!(find_bit(value, true, 12, 23), None);
+    }
+
+    #[test]
+    fn bitop_pads_shorter_strings() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("a"), Bytes::from_static(b"\x0f\xff"), None);
+        db.insert(Bytes::from("b"), Bytes::from_static(b"\xf0"), None);
+        let keys = [Bytes::from("a"), Bytes::from("b")];
+
+        assert_eq!(
+            db.bitop(BitOperation::Or, Bytes::from("or"), &keys, 0),
+            Ok(2)
+        );
+        assert_eq!(value(&db, "or"), Bytes::from_static(b"\xff\xff"));
+        assert_eq!(
+            db.bitop(BitOperation::And, Bytes::from("and"), &keys, 0),
+            Ok(2)
+        );
+        assert_eq!(value(&db, "and"), Bytes::from_static(b"\x00\x00"));
+        let not = db.bitop(BitOperation::Not, Bytes::from("not"), &keys[1..], 0);
+        assert_eq!(not, Ok(1));
+        assert_eq!(value(&db, "not"), Bytes::from_static(b"\x0f"));
+
+        let missing = [Bytes::from("missing")];
+        assert_eq!(
+            db.bitop(BitOperation::Xor, Bytes::from("or"), &missing, 0),
+            Ok(0)
+        );
+        assert!(db.get(b"or", 0).is_none());
+    }
+
+    #[test]
+    fn bitfield_handles_overflow() {
+        let mut value = vec![0; 2];
+        let ops = [
+            BitFieldOp::Set(field(false, 8), 0, 255),
+            BitFieldOp::IncrBy(field(false, 8), 0, 10),
+            BitFieldOp::Overflow(Overflow::Sat),
+            BitFieldOp::IncrBy(field(true, 4), 8, 100),
+            BitFieldOp::Set(field(false, 8), 0, -1),
+            BitFieldOp::Overflow(Overflow::Fail),
+            BitFieldOp::IncrBy(field(true, 4), 8, 1),
+            BitFieldOp::Get(field(true, 8), 0),
+        ];
+        let replies = run_bitfield(&mut value, &ops);
+        assert_eq!(
+            replies,
+            vec![Some(0), Some(9), Some(7), Some(9), None, Some(-1)]
+        );
+        assert_eq!(value, vec![0xff, 0x70]);
+    }
+
+    #[test]
+    fn bitfield_writes_grow_the_string() {
+        let mut db = Db::default();
+        let ops = [BitFieldOp::IncrBy(field(true, 64), 4, -1)];
+        assert_eq!(
+            db.bitfield(Bytes::from("bits"), &ops, 0),
+            Ok(vec![Some(-1)])
+        );
+        assert_eq!(value(&db, "bits").len(), 9);
+        let ops = [BitFieldOp::Get(field(false, 4), 0)];
+        assert_eq!(
+            run_bitfield(&mut value(&db, "bits").to_vec(), &ops),
+            vec![Some(0)]
+        );
+    }
+}
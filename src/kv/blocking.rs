@@ -0,0 +1,443 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use super::stream::Fields;
+use super::{now_ms, write, Db, Error, Value};
+use crate::commands::{End, ReadGroup, StreamId};
+
+/// What a blocked client does with the first of its keys it can be served from.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Pop up to `count` elements from one end (BLPOP, BRPOP and BLMPOP).
+    Pop(End, usize),
+    /// Move one element to the end of the destination list (BLMOVE).
+    Move(Bytes, End, End),
+    /// Read up to `count` entries added to a stream after the ID given for
+    /// its key (XREAD).
+    Read(HashMap<Bytes, StreamId>, Option<usize>),
+    /// Read up to `count` entries not delivered to a consumer group yet
+    /// (XREADGROUP). Fails if the stream is deleted or replaced meanwhile.
+    ReadGroup(ReadGroup, Option<usize>),
+}
+
+/// What a blocked client got: list elements or stream entries.
+#[derive(Debug, PartialEq)]
+pub(super) enum Elements {
+    List(Vec<Bytes>),
+    Stream(Vec<(StreamId, Fields)>),
+}
+
+impl Elements {
+    fn into_list(self) -> Vec<Bytes> {
+        match self {
+            Elements::List(elements) => elements,
+            Elements::Stream(_) => unreachable!("list operations serve list elements"),
+        }
+    }
+
+    pub(super) fn into_stream(self) -> Vec<(StreamId, Fields)> {
+        match self {
+            Elements::Stream(entries) => entries,
+            Elements::List(_) => unreachable!("stream reads serve stream entries"),
+        }
+    }
+}
+
+/// The key a client was served from and what it got.
+pub(super) type Served = Result<(Bytes, Elements), Error>;
+
+#[derive(Debug)]
+struct Waiter {
+    keys: Vec<Bytes>,
+    op: Op,
+    sender: oneshot::Sender<Served>,
+}
+
+/// Clients waiting for elements to be pushed to list keys or for entries to be
+/// added to streams.
+///
+/// Every waiter is queued on each of its keys in the order the clients
+/// blocked, so the longest waiting client is served first. Writes that create
+/// a list or add to a stream on a key someone waits for mark it ready, and
+/// ready keys are served before the write lock is released.
+#[derive(Debug, Default)]
+pub(super) struct Blocked {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    queues: HashMap<Bytes, VecDeque<u64>>,
+    ready: Vec<Bytes>,
+}
+
+impl Blocked {
+    /// Marks `key` as ready if a client is waiting on it.
+    pub(super) fn signal(&mut self, key: &Bytes) {
+        if self.queues.contains_key(key) && !self.ready.contains(key) {
+            self.ready.push(key.clone());
+        }
+    }
+
+    pub(super) fn block(&mut self, keys: Vec<Bytes>, op: Op) -> (u64, oneshot::Receiver<Served>) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        for key in &keys {
+            self.queues.entry(key.clone()).or_default().push_back(id);
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.insert(id, Waiter { keys, op, sender });
+        (id, receiver)
+    }
+
+    fn unblock(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|waiting| *waiting != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+}
+
+impl Db {
+    /// Whether running `op` on `key` would serve the client: the key holds a
+    /// list to pop from, or a stream with entries to read.
+    fn can_serve(&self, key: &[u8], op: &Op, now: u64) -> bool {
+        match (op, self.get(key, now).map(|entry| &entry.value)) {
+            (Op::Pop(..) | Op::Move(..), Some(Value::List(_))) => true,
+            (Op::Read(after, _), Some(Value::Stream(_))) => {
+                let after = after.get(key).copied().unwrap_or(StreamId::MAX);
+                self.stream_ref(key, now)
+                    .ok()
+                    .flatten()
+                    .is_some_and(|stream| !stream.after(after, Some(1)).is_empty())
+            }
+            (Op::ReadGroup(read, _), Some(Value::Stream(_))) => self
+                .stream_ref(key, now)
+                .ok()
+                .flatten()
+                .is_some_and(|stream| stream.can_read_group(&read.group)),
+            // Reading fails once the key holds no stream, see `serve_op`.
+            (Op::ReadGroup(..), _) => true,
+            _ => false,
+        }
+    }
+
+    /// Runs `op` on `key`, returning `None` if it got nothing to serve.
+    fn serve_op(&mut self, key: &[u8], op: &Op, now: u64) -> Result<Option<Elements>, Error> {
+        match op {
+            Op::Pop(end, count) => Ok(self.pop(key, *end, *count, now)?.map(Elements::List)),
+            Op::Move(destination, from, to) => {
+                let moved = self.lmove(key, destination.clone(), *from, *to, now)?;
+                Ok(moved.map(|element| Elements::List(vec![element])))
+            }
+            Op::Read(after, count) => {
+                let entries = match (self.stream_ref(key, now)?, after.get(key)) {
+                    (Some(stream), Some(after)) => stream.after(*after, *count),
+                    _ => Vec::new(),
+                };
+                Ok((!entries.is_empty()).then_some(Elements::Stream(entries)))
+            }
+            Op::ReadGroup(read, count) => {
+                if self.get(key, now).is_none() {
+                    return Err(Error::StreamKeyDeleted);
+                }
+                let entries = self.xreadgroup_new(key, read, *count, now)?;
+                Ok((!entries.is_empty()).then_some(Elements::Stream(entries)))
+            }
+        }
+    }
+
+    /// Serves the clients waiting on keys that became ready, oldest first,
+    /// for as long as the lists have elements left. Every client waiting on a
+    /// stream gets the entries added after the ID it waits from.
+    pub(super) fn serve_blocked(&mut self, now: u64) {
+        while !self.blocked.ready.is_empty() {
+            for key in std::mem::take(&mut self.blocked.ready) {
+                self.serve_key(&key, now);
+            }
+        }
+    }
+
+    fn serve_key(&mut self, key: &Bytes, now: u64) {
+        let queued: Vec<u64> = match self.blocked.queues.get(key) {
+            Some(queue) => queue.iter().copied().collect(),
+            None => return,
+        };
+        for id in queued {
+            let Some(waiter) = self.blocked.waiters.get(&id) else {
+                continue;
+            };
+            if !waiter.sender.is_closed() && !self.can_serve(key, &waiter.op, now) {
+                continue;
+            }
+
+            let waiter = self
+                .blocked
+                .unblock(id)
+                .expect("queued waiter is registered");
+            if waiter.sender.is_closed() {
+                continue;
+            }
+            let served = self.serve_op(key, &waiter.op, now).map(|elements| {
+                let elements = elements.expect("waiter can be served");
+                (key.clone(), elements)
+            });
+            let _ = waiter.sender.send(served);
+        }
+    }
+}
+
+/// Removes a blocked client from the queues when it stops waiting, including
+/// when its connection closes and the command is dropped mid-wait.
+struct Unblock(u64);
+
+impl Drop for Unblock {
+    fn drop(&mut self) {
+        write().blocked.unblock(self.0);
+    }
+}
+
+/// Runs `op` on the first of `keys` that holds a list. If none does, waits
+/// until another client pushes to one of them or `timeout` elapses, forever
+/// if it is `None`. Returns the key served from and the elements popped, or
+/// `None` on timeout.
+pub async fn pop(
+    keys: Vec<Bytes>,
+    op: Op,
+    timeout: Option<Duration>,
+) -> Result<Option<(Bytes, Vec<Bytes>)>, Error> {
+    let (id, receiver) = {
+        let mut db = write();
+        let now = now_ms();
+        for key in &keys {
+            if let Some(elements) = db.serve_op(key, &op, now)? {
+                return Ok(Some((key.clone(), elements.into_list())));
+            }
+        }
+        db.blocked.block(keys, op)
+    };
+    let served = wait(id, receiver, timeout).await?;
+    Ok(served.map(|(key, elements)| (key, elements.into_list())))
+}
+
+/// Waits until the client blocked as `id` is served through `receiver` or
+/// `timeout` elapses, forever if it is `None`. Returns `None` on timeout.
+pub(super) async fn wait(
+    id: u64,
+    mut receiver: oneshot::Receiver<Served>,
+    timeout: Option<Duration>,
+) -> Result<Option<(Bytes, Elements)>, Error> {
+    let unblock = Unblock(id);
+
+    let served = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, &mut receiver).await.ok(),
+        None => Some((&mut receiver).await),
+    };
+    let served = match served {
+        Some(served) => served.expect("waiter is only dropped once served"),
+        None => {
+            // The client may have been served between the timeout firing and
+            // taking the lock to unblock it.
+            drop(unblock);
+            match receiver.try_recv() {
+                Ok(served) => served,
+                Err(_) => return Ok(None),
+            }
+        }
+    };
+    served.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::XAddId;
+
+    fn push(db: &mut Db, key: &str, items: &[&str]) {
+        let list = items.iter().map(|item| Bytes::from(item.to_string()));
+        db.insert(
+            Bytes::from(key.to_string()),
+            Value::List(list.collect()),
+            None,
+        );
+        db.serve_blocked(0);
+    }
+
+    fn served(key: &str, items: &[&str]) -> Served {
+        let items = items
+            .iter()
+            .map(|item| Bytes::from(item.to_string()))
+            .collect();
+        Ok((Bytes::from(key.to_string()), Elements::List(items)))
+    }
+
+    #[test]
+    fn serve_waiters_in_order() {
+        let mut db = Db::default();
+        let keys = vec![Bytes::from("a"), Bytes::from("b")];
+        let (_, mut first) = db.blocked.block(keys.clone(), Op::Pop(End::Left, 1));
+        let (_, mut second) = db.blocked.block(keys, Op::Pop(End::Left, 2));
+        let (_, mut third) = db
+            .blocked
+            .block(vec![Bytes::from("b")], Op::Pop(End::Left, 1));
+
+        push(&mut db, "b", &["1", "2", "3"]);
+        assert_eq!(first.try_recv().unwrap(), served("b", &["1"]));
+        assert_eq!(second.try_recv().unwrap(), served("b", &["2", "3"]));
+        assert!(third.try_recv().is_err());
+        assert!(db.get(b"b", 0).is_none());
+
+        push(&mut db, "b", &["4"]);
+        assert_eq!(third.try_recv().unwrap(), served("b", &["4"]));
+        assert!(db.blocked.waiters.is_empty());
+        assert!(db.blocked.queues.is_empty());
+    }
+
+    #[test]
+    fn serve_skips_waiters_that_left() {
+        let mut db = Db::default();
+        let (_, gone) = db
+            .blocked
+            .block(vec![Bytes::from("a")], Op::Pop(End::Left, 1));
+        let (id, mut unblocked) = db
+            .blocked
+            .block(vec![Bytes::from("a")], Op::Pop(End::Left, 1));
+        let (_, mut waiting) = db
+            .blocked
+            .block(vec![Bytes::from("a")], Op::Pop(End::Right, 1));
+        drop(gone);
+        db.blocked.unblock(id);
+
+        push(&mut db, "a", &["1", "2"]);
+        assert!(unblocked.try_recv().is_err());
+        assert_eq!(waiting.try_recv().unwrap(), served("a", &["2"]));
+        assert!(db.get(b"a", 0).is_some());
+    }
+
+    #[test]
+    fn serve_moves_chain_to_destination_waiters() {
+        let mut db = Db::default();
+        let op = Op::Move(Bytes::from("b"), End::Left, End::Right);
+        let (_, mut mover) = db.blocked.block(vec![Bytes::from("a")], op);
+        let (_, mut popper) = db
+            .blocked
+            .block(vec![Bytes::from("b")], Op::Pop(End::Left, 1));
+
+        push(&mut db, "a", &["1"]);
+        assert_eq!(mover.try_recv().unwrap(), served("a", &["1"]));
+        assert_eq!(popper.try_recv().unwrap(), served("b", &["1"]));
+        assert!(db.entries.is_empty());
+    }
+
+    #[test]
+    fn serve_every_reader_of_a_stream() {
+        let mut db = Db::default();
+        let key = Bytes::from("s");
+        let id = |ms| StreamId { ms, seq: 0 };
+        let after = |ms| HashMap::from([(key.clone(), id(ms))]);
+        let (_, mut first) = db
+            .blocked
+            .block(vec![key.clone()], Op::Read(after(0), None));
+        let (_, mut second) = db
+            .blocked
+            .block(vec![key.clone()], Op::Read(after(0), Some(1)));
+        let (_, mut later) = db
+            .blocked
+            .block(vec![key.clone()], Op::Read(after(5), None));
+
+        let fields = vec![(Bytes::from("f"), Bytes::from("v"))];
+        let xadd = |db: &mut Db, ms| {
+            let id = XAddId::Explicit(id(ms));
+            db.xadd(key.clone(), false, None, id, fields.clone(), 0)
+                .unwrap();
+            db.serve_blocked(0);
+        };
+        xadd(&mut db, 3);
+        let entries = Elements::Stream(vec![(id(3), fields.clone())]);
+        assert_eq!(first.try_recv().unwrap(), Ok((key.clone(), entries)));
+        let entries = Elements::Stream(vec![(id(3), fields.clone())]);
+        assert_eq!(second.try_recv().unwrap(), Ok((key.clone(), entries)));
+        assert!(later.try_recv().is_err());
+
+        xadd(&mut db, 6);
+        let entries = Elements::Stream(vec![(id(6), fields.clone())]);
+        assert_eq!(later.try_recv().unwrap(), Ok((key.clone(), entries)));
+        assert!(db.blocked.waiters.is_empty());
+    }
+
+    #[test]
+    fn serve_group_readers_distinct_entries() {
+        let mut db = Db::default();
+        let key = Bytes::from("s");
+        db.xgroup_create(key.clone(), Bytes::from("g"), None, true, None, 0)
+            .unwrap();
+        let read = |consumer: &str| {
+            let read = ReadGroup {
+                group: Bytes::from("g"),
+                consumer: Bytes::from(consumer.to_string()),
+                noack: false,
+            };
+            Op::ReadGroup(read, Some(1))
+        };
+        let (_, mut alice) = db.blocked.block(vec![key.clone()], read("alice"));
+        let (_, mut bob) = db.blocked.block(vec![key.clone()], read("bob"));
+
+        let fields = vec![(Bytes::from("f"), Bytes::from("v"))];
+        let id = StreamId { ms: 1, seq: 0 };
+        db.xadd(
+            key.clone(),
+            false,
+            None,
+            XAddId::Explicit(id),
+            fields.clone(),
+            0,
+        )
+        .unwrap();
+        db.serve_blocked(0);
+        let entries = Elements::Stream(vec![(id, fields)]);
+        assert_eq!(alice.try_recv().unwrap(), Ok((key.clone(), entries)));
+        assert!(bob.try_recv().is_err());
+        assert_eq!(db.blocked.waiters.len(), 1);
+    }
+
+    #[test]
+    fn group_readers_fail_when_the_stream_goes_away() {
+        let mut db = Db::default();
+        let group = |db: &mut Db, key: &str| {
+            let key = Bytes::from(key.to_string());
+            db.xgroup_create(key.clone(), Bytes::from("g"), None, true, None, 0)
+                .unwrap();
+            let read = ReadGroup {
+                group: Bytes::from("g"),
+                consumer: Bytes::from("alice"),
+                noack: false,
+            };
+            let (_, group_reader) = db
+                .blocked
+                .block(vec![key.clone()], Op::ReadGroup(read, None));
+            let after = HashMap::from([(key.clone(), StreamId::MAX)]);
+            let (_, reader) = db.blocked.block(vec![key], Op::Read(after, None));
+            (group_reader, reader)
+        };
+
+        let (mut deleted, mut reader) = group(&mut db, "deleted");
+        db.remove(b"deleted");
+        db.serve_blocked(0);
+        assert_eq!(deleted.try_recv().unwrap(), Err(Error::StreamKeyDeleted));
+        // Plain readers keep waiting for the stream to come back.
+        assert!(reader.try_recv().is_err());
+
+        let (mut replaced, _reader) = group(&mut db, "replaced");
+        db.insert(Bytes::from("replaced"), Bytes::from("x"), None);
+        db.serve_blocked(0);
+        assert_eq!(replaced.try_recv().unwrap(), Err(Error::WrongType));
+        assert_eq!(db.blocked.waiters.len(), 2);
+    }
+}
@@ -0,0 +1,287 @@
+use std::f64::consts::LN_2;
+
+use bytes::Bytes;
+
+use super::hyperloglog::murmurhash64a;
+use super::{now_ms, with_entry, write, Db, Entry, Error, Value, MAX_FILTER_LEN};
+use crate::commands::BloomOptions;
+
+/// Seed of the first of the two hashes bit positions are derived from.
+const HASH_SEED: u64 = 0xc6a4_a793_5bd1_e995;
+/// Factor each new sub-filter's error rate is tightened by, which keeps the
+/// error rate of the whole chain within the one it was created with.
+const ERROR_TIGHTENING: f64 = 0.5;
+
+/// A scalable Bloom filter: a chain of sub-filters, each larger and with a
+/// lower error rate than the last, where a new one is started whenever the
+/// last is full.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    filters: Vec<SubFilter>,
+    /// How many times larger each sub-filter is than the last, or `None` if
+    /// the filter doesn't scale.
+    expansion: Option<u32>,
+}
+
+// Error rates are never NaN, so every filter equals itself.
+impl Eq for BloomFilter {}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SubFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+    capacity: u64,
+    error_rate: f64,
+    items: u64,
+}
+
+/// What BF.INFO reports.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BloomInfo {
+    pub capacity: u64,
+    pub size: usize,
+    pub filters: usize,
+    pub items: u64,
+    pub expansion: Option<u32>,
+}
+
+/// The pair of hashes bit positions are derived from.
+fn hash(item: &[u8]) -> (u64, u64) {
+    let a = murmurhash64a(item, HASH_SEED);
+    (a, murmurhash64a(item, a))
+}
+
+impl SubFilter {
+    /// A sub-filter sized for `capacity` items at `error_rate`, with its bits
+    /// rounded up to whole 64-bit words.
+    fn new(capacity: u64, error_rate: f64) -> Result<Self, Error> {
+        let bits_per_item = -error_rate.ln() / (LN_2 * LN_2);
+        let bits = capacity as f64 * bits_per_item;
+        if bits / 8.0 > MAX_FILTER_LEN as f64 {
+            return Err(Error::FilterTooLarge);
+        }
+        let len = (bits as u64).div_ceil(64) * 8;
+        Ok(Self {
+            bits: vec![0; len.max(8) as usize],
+            hashes: (LN_2 * bits_per_item).ceil() as u32,
+            capacity,
+            error_rate,
+            items: 0,
+        })
+    }
+
+    fn positions(&self, (a, b): (u64, u64)) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| (a.wrapping_add(i.wrapping_mul(b)) % len) as usize)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit & 7)) != 0)
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        let positions: Vec<usize> = self.positions(hash).collect();
+        for bit in positions {
+            self.bits[bit / 8] |= 1 << (bit & 7);
+        }
+        self.items += 1;
+    }
+}
+
+impl BloomFilter {
+    fn new(options: &BloomOptions) -> Result<Self, Error> {
+        Ok(Self {
+            filters: vec![SubFilter::new(options.capacity, options.error_rate)?],
+            expansion: options.expansion,
+        })
+    }
+
+    fn contains(&self, item: &[u8]) -> bool {
+        let hash = hash(item);
+        self.filters.iter().any(|filter| filter.contains(hash))
+    }
+
+    /// Adds `item`, returning whether it wasn't there already. Starts a new
+    /// sub-filter if the last one is full.
+    fn add(&mut self, item: &[u8]) -> Result<bool, Error> {
+        let hash = hash(item);
+        if self.filters.iter().any(|filter| filter.contains(hash)) {
+            return Ok(false);
+        }
+
+        let last = self.filters.last().expect("filters have a sub-filter");
+        if last.items >= last.capacity {
+            let expansion = self.expansion.ok_or(Error::NonScalingFilterFull)?;
+            let next = SubFilter::new(
+                last.capacity.saturating_mul(expansion as u64),
+                last.error_rate * ERROR_TIGHTENING,
+            )?;
+            self.filters.push(next);
+        }
+        let last = self.filters.last_mut().expect("filters have a sub-filter");
+        last.insert(hash);
+        Ok(true)
+    }
+
+    fn info(&self) -> BloomInfo {
+        BloomInfo {
+            capacity: self.filters.iter().map(|filter| filter.capacity).sum(),
+            size: self.filters.iter().map(|filter| filter.bits.len()).sum(),
+            filters: self.filters.len(),
+            items: self.filters.iter().map(|filter| filter.items).sum(),
+            expansion: self.expansion,
+        }
+    }
+}
+
+impl Db {
+    fn bloom_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut BloomFilter>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::Bloom(filter)) => Ok(Some(filter)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates an empty filter under `key`, which must not exist.
+    fn bf_reserve(&mut self, key: Bytes, options: &BloomOptions, now: u64) -> Result<(), Error> {
+        if self.get(&key, now).is_some() {
+            return Err(Error::ItemExists);
+        }
+        let filter = BloomFilter::new(options)?;
+        self.insert_entry(key, Entry::new(Value::Bloom(filter), None, now));
+        Ok(())
+    }
+
+    /// Adds `items`, creating the filter with default options if needed.
+    /// Returns for each item whether it was added, or why it couldn't be.
+    fn bf_add(
+        &mut self,
+        key: Bytes,
+        items: &[Bytes],
+        now: u64,
+    ) -> Result<Vec<Result<bool, Error>>, Error> {
+        if self.bloom_mut(&key, now)?.is_none() {
+            let filter = BloomFilter::new(&BloomOptions::default())?;
+            self.insert_entry(key.clone(), Entry::new(Value::Bloom(filter), None, now));
+        }
+        let filter = self.bloom_mut(&key, now)?.expect("filter was just created");
+        Ok(items.iter().map(|item| filter.add(item)).collect())
+    }
+}
+
+/// Runs `f` on the filter under `key`, marking it accessed.
+fn with_bloom<T>(key: &[u8], f: impl FnOnce(&BloomFilter) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::Bloom(filter) => Ok(f(filter)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+/// Creates an empty filter under `key`.
+pub async fn reserve(key: Bytes, options: &BloomOptions) -> Result<(), Error> {
+    write().bf_reserve(key, options, now_ms())
+}
+
+/// Adds `items` to the filter under `key`.
+pub async fn add(key: Bytes, items: &[Bytes]) -> Result<Vec<Result<bool, Error>>, Error> {
+    write().bf_add(key, items, now_ms())
+}
+
+/// Whether each of `items` may have been added to the filter under `key`.
+pub async fn exists(key: &[u8], items: &[Bytes]) -> Result<Vec<bool>, Error> {
+    let found = with_bloom(key, |filter| {
+        items.iter().map(|item| filter.contains(item)).collect()
+    })?;
+    Ok(found.unwrap_or_else(|| vec![false; items.len()]))
+}
+
+pub async fn info(key: &[u8]) -> Result<BloomInfo, Error> {
+    with_bloom(key, BloomFilter::info)?.ok_or(Error::FilterNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(range: std::ops::Range<usize>) -> Vec<Bytes> {
+        range.map(|i| Bytes::from(format!("item:{i}"))).collect()
+    }
+
+    #[test]
+    fn sub_filters_are_sized_for_their_error_rate() {
+        let filter = SubFilter::new(100, 0.01).unwrap();
+        assert_eq!(filter.bits.len(), 120);
+        assert_eq!(filter.hashes, 7);
+        let filter = SubFilter::new(1000, 0.001).unwrap();
+        assert_eq!(filter.bits.len(), 1800);
+        assert_eq!(filter.hashes, 10);
+        assert_eq!(SubFilter::new(u64::MAX, 0.01), Err(Error::FilterTooLarge));
+    }
+
+    #[test]
+    fn bf_add_scales_and_stays_within_the_error_rate() {
+        let mut db = Db::default();
+        let key = Bytes::from("bf");
+        let added = db.bf_add(key.clone(), &items(0..1000), 0).unwrap();
+        let count = added.iter().filter(|added| **added == Ok(true)).count();
+        assert!(count >= 980, "{count}");
+        let added = db.bf_add(key.clone(), &items(0..10), 0).unwrap();
+        assert!(added.iter().all(|added| *added == Ok(false)));
+
+        // Each sub-filter has half the error rate of the last, so the chain
+        // stays under twice the first one's.
+        let filter = db.bloom_mut(b"bf", 0).unwrap().unwrap();
+        assert!(items(0..1000).iter().all(|item| filter.contains(item)));
+        let false_positives = items(1000..11000)
+            .iter()
+            .filter(|item| filter.contains(item))
+            .count();
+        assert!(false_positives < 200, "{false_positives}");
+
+        let info = filter.info();
+        assert_eq!(info.filters, 4);
+        assert_eq!(info.capacity, 100 + 200 + 400 + 800);
+        assert_eq!(info.items as usize, count);
+        assert_eq!(info.expansion, Some(2));
+    }
+
+    #[test]
+    fn bf_reserve_creates_non_scaling_filters() {
+        let mut db = Db::default();
+        let options = BloomOptions {
+            error_rate: 0.001,
+            capacity: 3,
+            expansion: None,
+        };
+        assert_eq!(db.bf_reserve(Bytes::from("bf"), &options, 0), Ok(()));
+        assert_eq!(
+            db.bf_reserve(Bytes::from("bf"), &options, 0),
+            Err(Error::ItemExists)
+        );
+        assert_eq!(
+            db.bf_add(Bytes::from("bf"), &items(0..4), 0).unwrap(),
+            vec![
+                Ok(true),
+                Ok(true),
+                Ok(true),
+                Err(Error::NonScalingFilterFull)
+            ]
+        );
+
+        db.insert(Bytes::from("string"), Bytes::from("x"), None);
+        assert_eq!(
+            db.bf_add(Bytes::from("string"), &items(0..1), 0),
+            Err(Error::WrongType)
+        );
+        assert_eq!(
+            db.bf_reserve(Bytes::from("string"), &options, 0),
+            Err(Error::ItemExists)
+        );
+    }
+}
@@ -0,0 +1,439 @@
+use bytes::Bytes;
+
+use super::hyperloglog::murmurhash64a;
+use super::{now_ms, with_entry, write, Db, Entry, Error, Value, MAX_FILTER_LEN};
+use crate::commands::CuckooOptions;
+
+/// A scalable cuckoo filter: a chain of sub-filters of 8-bit fingerprints,
+/// where a new, larger one is started whenever an item fits nowhere else.
+/// Unlike a Bloom filter, items can be deleted again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuckooFilter {
+    /// Fingerprints of each sub-filter, `bucket_size` to a bucket, with 0 for
+    /// an empty slot. Every sub-filter has a power of two buckets.
+    filters: Vec<Vec<u8>>,
+    bucket_size: usize,
+    max_iterations: u16,
+    /// How many times more buckets each sub-filter has than the last, or 0
+    /// if the filter doesn't scale.
+    expansion: u16,
+    items: u64,
+    deletes: u64,
+}
+
+/// What CF.INFO reports.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CuckooInfo {
+    pub size: usize,
+    pub buckets: usize,
+    pub filters: usize,
+    pub items: u64,
+    pub deletes: u64,
+    pub bucket_size: usize,
+    pub expansion: u16,
+    pub max_iterations: u16,
+}
+
+/// An item's fingerprint and the hashes of the two buckets it may be in.
+struct Lookup {
+    fingerprint: u8,
+    h1: u64,
+    h2: u64,
+}
+
+impl Lookup {
+    fn new(item: &[u8]) -> Self {
+        let hash = murmurhash64a(item, 0);
+        let fingerprint = (hash % 255 + 1) as u8;
+        Self {
+            fingerprint,
+            h1: hash,
+            h2: alternate(fingerprint, hash),
+        }
+    }
+}
+
+/// The other bucket a fingerprint may be in. Applying it twice gives back
+/// the first bucket, also modulo the power of two number of buckets.
+fn alternate(fingerprint: u8, hash: u64) -> u64 {
+    hash ^ (fingerprint as u64).wrapping_mul(0x5bd1_e995)
+}
+
+/// The slots of each of the lookup's two buckets in `filter`, the same one
+/// twice if both hashes pick it.
+fn buckets(filter: &[u8], bucket_size: usize, lookup: &Lookup) -> [std::ops::Range<usize>; 2] {
+    let len = (filter.len() / bucket_size) as u64;
+    [lookup.h1, lookup.h2].map(|hash| {
+        let start = (hash % len) as usize * bucket_size;
+        start..start + bucket_size
+    })
+}
+
+/// Stores the fingerprint in `filter` by evicting fingerprints to their
+/// alternate buckets, up to `max_iterations` times. If that fails, the
+/// evictions are undone and the filter is left as it was.
+fn kick_insert(
+    filter: &mut [u8],
+    bucket_size: usize,
+    max_iterations: u16,
+    lookup: &Lookup,
+) -> bool {
+    let len = (filter.len() / bucket_size) as u64;
+    let mut fingerprint = lookup.fingerprint;
+    let mut index = lookup.h1 % len;
+    let mut victim = 0;
+    for _ in 0..max_iterations {
+        std::mem::swap(
+            &mut filter[index as usize * bucket_size + victim],
+            &mut fingerprint,
+        );
+        index = alternate(fingerprint, index) % len;
+        let start = index as usize * bucket_size;
+        let bucket = &mut filter[start..start + bucket_size];
+        if let Some(slot) = bucket.iter_mut().find(|slot| **slot == 0) {
+            *slot = fingerprint;
+            return true;
+        }
+        victim = (victim + 1) % bucket_size;
+    }
+
+    for _ in 0..max_iterations {
+        victim = (victim + bucket_size - 1) % bucket_size;
+        index = alternate(fingerprint, index) % len;
+        std::mem::swap(
+            &mut filter[index as usize * bucket_size + victim],
+            &mut fingerprint,
+        );
+    }
+    false
+}
+
+impl CuckooFilter {
+    fn new(options: &CuckooOptions) -> Result<Self, Error> {
+        let bucket_size = options.bucket_size as usize;
+        let buckets = (options.capacity / bucket_size as u64)
+            .max(1)
+            .checked_next_power_of_two()
+            .filter(|buckets| buckets.saturating_mul(bucket_size as u64) <= MAX_FILTER_LEN)
+            .ok_or(Error::FilterTooLarge)?;
+        // Bucket counts stay powers of two as the filter grows.
+        let expansion = match options.expansion {
+            0 => 0,
+            expansion => expansion.next_power_of_two(),
+        };
+        Ok(Self {
+            filters: vec![vec![0; buckets as usize * bucket_size]],
+            bucket_size,
+            max_iterations: options.max_iterations,
+            expansion,
+            items: 0,
+            deletes: 0,
+        })
+    }
+
+    fn contains(&self, lookup: &Lookup) -> bool {
+        self.filters.iter().any(|filter| {
+            buckets(filter, self.bucket_size, lookup)
+                .into_iter()
+                .any(|bucket| filter[bucket].contains(&lookup.fingerprint))
+        })
+    }
+
+    /// How many times the fingerprint was added, which may overcount items
+    /// sharing fingerprints and buckets.
+    fn count(&self, lookup: &Lookup) -> usize {
+        let occurrences = |slots: &[u8]| {
+            let matching = slots.iter().filter(|slot| **slot == lookup.fingerprint);
+            matching.count()
+        };
+        self.filters
+            .iter()
+            .map(|filter| match buckets(filter, self.bucket_size, lookup) {
+                [first, second] if first == second => occurrences(&filter[first]),
+                [first, second] => occurrences(&filter[first]) + occurrences(&filter[second]),
+            })
+            .sum()
+    }
+
+    /// Adds the fingerprint to the first free slot of its buckets, newest
+    /// sub-filter first, then by eviction in the newest one. Starts a new
+    /// sub-filter if neither finds room.
+    fn insert(&mut self, lookup: &Lookup) -> Result<(), Error> {
+        let bucket_size = self.bucket_size;
+        loop {
+            for filter in self.filters.iter_mut().rev() {
+                let [first, second] = buckets(filter, bucket_size, lookup);
+                let slot = first.chain(second).find(|slot| filter[*slot] == 0);
+                if let Some(slot) = slot {
+                    filter[slot] = lookup.fingerprint;
+                    self.items += 1;
+                    return Ok(());
+                }
+            }
+
+            let last = self.filters.last_mut().expect("filters have a sub-filter");
+            if kick_insert(last, bucket_size, self.max_iterations, lookup) {
+                self.items += 1;
+                return Ok(());
+            }
+            if self.expansion == 0 {
+                return Err(Error::CuckooFilterFull);
+            }
+            let len = (last.len() as u64).saturating_mul(self.expansion as u64);
+            if len > MAX_FILTER_LEN {
+                return Err(Error::FilterTooLarge);
+            }
+            self.filters.push(vec![0; len as usize]);
+        }
+    }
+
+    /// Removes one occurrence of the fingerprint, newest sub-filter first.
+    /// Returns whether there was one.
+    fn delete(&mut self, lookup: &Lookup) -> bool {
+        for filter in self.filters.iter_mut().rev() {
+            let [first, second] = buckets(filter, self.bucket_size, lookup);
+            let slot = first
+                .chain(second)
+                .find(|slot| filter[*slot] == lookup.fingerprint);
+            if let Some(slot) = slot {
+                filter[slot] = 0;
+                self.items -= 1;
+                self.deletes += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn info(&self) -> CuckooInfo {
+        CuckooInfo {
+            size: self.filters.iter().map(Vec::len).sum(),
+            buckets: self.filters[0].len() / self.bucket_size,
+            filters: self.filters.len(),
+            items: self.items,
+            deletes: self.deletes,
+            bucket_size: self.bucket_size,
+            expansion: self.expansion,
+            max_iterations: self.max_iterations,
+        }
+    }
+}
+
+impl Db {
+    fn cuckoo_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut CuckooFilter>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::Cuckoo(filter)) => Ok(Some(filter)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Creates an empty filter under `key`, which must not exist.
+    fn cf_reserve(&mut self, key: Bytes, options: &CuckooOptions, now: u64) -> Result<(), Error> {
+        if self.get(&key, now).is_some() {
+            return Err(Error::ItemExists);
+        }
+        let filter = CuckooFilter::new(options)?;
+        self.insert_entry(key, Entry::new(Value::Cuckoo(filter), None, now));
+        Ok(())
+    }
+
+    /// Adds `items`, creating the filter with `create` unless it's `None`.
+    /// With `nx`, items that may already be there are skipped. Returns for
+    /// each item whether it was added, or why it couldn't be.
+    fn cf_insert(
+        &mut self,
+        key: Bytes,
+        create: Option<&CuckooOptions>,
+        items: &[Bytes],
+        nx: bool,
+        now: u64,
+    ) -> Result<Vec<Result<bool, Error>>, Error> {
+        if self.cuckoo_mut(&key, now)?.is_none() {
+            let options = create.ok_or(Error::FilterNotFound)?;
+            let filter = CuckooFilter::new(options)?;
+            self.insert_entry(key.clone(), Entry::new(Value::Cuckoo(filter), None, now));
+        }
+        let filter = self
+            .cuckoo_mut(&key, now)?
+            .expect("filter was just created");
+        let added = items.iter().map(|item| {
+            let lookup = Lookup::new(item);
+            if nx && filter.contains(&lookup) {
+                return Ok(false);
+            }
+            filter.insert(&lookup).map(|()| true)
+        });
+        Ok(added.collect())
+    }
+
+    fn cf_del(&mut self, key: &[u8], item: &[u8], now: u64) -> Result<bool, Error> {
+        let filter = self.cuckoo_mut(key, now)?.ok_or(Error::FilterNotFound)?;
+        Ok(filter.delete(&Lookup::new(item)))
+    }
+}
+
+/// Runs `f` on the filter under `key`, marking it accessed.
+fn with_cuckoo<T>(key: &[u8], f: impl FnOnce(&CuckooFilter) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::Cuckoo(filter) => Ok(f(filter)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+/// Creates an empty filter under `key`.
+pub async fn reserve(key: Bytes, options: &CuckooOptions) -> Result<(), Error> {
+    write().cf_reserve(key, options, now_ms())
+}
+
+/// Adds `items` to the filter under `key`.
+pub async fn insert(
+    key: Bytes,
+    create: Option<&CuckooOptions>,
+    items: &[Bytes],
+    nx: bool,
+) -> Result<Vec<Result<bool, Error>>, Error> {
+    write().cf_insert(key, create, items, nx, now_ms())
+}
+
+/// Whether each of `items` may be in the filter under `key`.
+pub async fn exists(key: &[u8], items: &[Bytes]) -> Result<Vec<bool>, Error> {
+    let found = with_cuckoo(key, |filter| {
+        let found = items.iter().map(|item| filter.contains(&Lookup::new(item)));
+        found.collect()
+    })?;
+    Ok(found.unwrap_or_else(|| vec![false; items.len()]))
+}
+
+/// Deletes one occurrence of `item` from the filter under `key`.
+pub async fn del(key: &[u8], item: &[u8]) -> Result<bool, Error> {
+    write().cf_del(key, item, now_ms())
+}
+
+/// Estimates how many times `item` is in the filter under `key`.
+pub async fn count(key: &[u8], item: &[u8]) -> Result<usize, Error> {
+    let count = with_cuckoo(key, |filter| filter.count(&Lookup::new(item)))?;
+    Ok(count.unwrap_or(0))
+}
+
+pub async fn info(key: &[u8]) -> Result<CuckooInfo, Error> {
+    with_cuckoo(key, CuckooFilter::info)?.ok_or(Error::FilterNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(range: std::ops::Range<usize>) -> Vec<Bytes> {
+        range.map(|i| Bytes::from(format!("item:{i}"))).collect()
+    }
+
+    fn options(capacity: u64, bucket_size: u8, expansion: u16) -> CuckooOptions {
+        CuckooOptions {
+            capacity,
+            bucket_size,
+            expansion,
+            ..CuckooOptions::default()
+        }
+    }
+
+    #[test]
+    fn cf_insert_finds_added_items_and_deletes_them() {
+        let mut db = Db::default();
+        let key = Bytes::from("cf");
+        let added = db
+            .cf_insert(
+                key.clone(),
+                Some(&options(1000, 4, 1)),
+                &items(0..500),
+                false,
+                0,
+            )
+            .unwrap();
+        assert!(added.len() == 500 && added.iter().all(|added| *added == Ok(true)));
+
+        let filter = db.cuckoo_mut(b"cf", 0).unwrap().unwrap();
+        assert!(items(0..500)
+            .iter()
+            .all(|item| filter.contains(&Lookup::new(item))));
+        let false_positives = items(500..10500)
+            .iter()
+            .filter(|item| filter.contains(&Lookup::new(item)))
+            .count();
+        assert!(false_positives < 500, "{false_positives}");
+
+        assert_eq!(
+            db.cf_insert(key.clone(), None, &items(0..2), true, 0)
+                .unwrap(),
+            vec![Ok(false), Ok(false)]
+        );
+        db.cf_insert(key.clone(), None, &items(0..1), false, 0)
+            .unwrap();
+        let filter = db.cuckoo_mut(b"cf", 0).unwrap().unwrap();
+        assert_eq!(filter.count(&Lookup::new(b"item:0")), 2);
+
+        assert_eq!(db.cf_del(b"cf", b"item:0", 0), Ok(true));
+        assert_eq!(db.cf_del(b"cf", b"item:0", 0), Ok(true));
+        assert_eq!(db.cf_del(b"cf", b"item:0", 0), Ok(false));
+        assert_eq!(
+            db.cf_del(b"missing", b"item:0", 0),
+            Err(Error::FilterNotFound)
+        );
+        let filter = db.cuckoo_mut(b"cf", 0).unwrap().unwrap();
+        assert!(!filter.contains(&Lookup::new(b"item:0")));
+        let info = filter.info();
+        assert_eq!((info.items, info.deletes), (499, 2));
+        assert_eq!((info.buckets, info.size, info.filters), (256, 1024, 1));
+    }
+
+    #[test]
+    fn cf_insert_grows_or_fills_up() {
+        let mut db = Db::default();
+        let added = db
+            .cf_insert(
+                Bytes::from("cf"),
+                Some(&options(8, 2, 3)),
+                &items(0..100),
+                false,
+                0,
+            )
+            .unwrap();
+        assert!(added.len() == 100 && added.iter().all(|added| *added == Ok(true)));
+        let filter = db.cuckoo_mut(b"cf", 0).unwrap().unwrap();
+        let info = filter.info();
+        assert_eq!((info.buckets, info.expansion), (4, 4));
+        assert!(info.filters > 1);
+        assert!(items(0..100)
+            .iter()
+            .all(|item| filter.contains(&Lookup::new(item))));
+
+        let added = db
+            .cf_insert(
+                Bytes::from("full"),
+                Some(&options(8, 2, 0)),
+                &items(0..100),
+                false,
+                0,
+            )
+            .unwrap();
+        let full = added.iter().position(|added| added.is_err()).unwrap();
+        assert!(full <= 8);
+        assert_eq!(added[full], Err(Error::CuckooFilterFull));
+        // Evictions that didn't find room are undone, so nothing is lost.
+        let filter = db.cuckoo_mut(b"full", 0).unwrap().unwrap();
+        let kept = items(0..100)
+            .into_iter()
+            .zip(&added)
+            .filter(|(_, added)| **added == Ok(true))
+            .all(|(item, _)| filter.contains(&Lookup::new(&item)));
+        assert!(kept);
+        assert_eq!(
+            db.cf_insert(Bytes::from("missing"), None, &items(0..1), false, 0),
+            Err(Error::FilterNotFound)
+        );
+    }
+}
@@ -0,0 +1,621 @@
+use bytes::Bytes;
+
+use super::zset::SortedSet;
+use super::{now_ms, read, write, Db, Entry, Error, Value};
+use crate::commands::{
+    GeoOrigin, GeoSearch, GeoShape, RangeBy, ScoreBound, ScoreRange, SortOrder, ZAddOptions,
+    ZRangeOptions, MAX_LATITUDE, MAX_LONGITUDE,
+};
+
+/// Bits per coordinate of the geohash scores members are stored with, 52
+/// bits in all so that scores hold them exactly.
+const STEP_MAX: u32 = 26;
+/// Earth's radius in meters, as Redis measures distances with.
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+/// Half the circumference of Web Mercator's Earth in meters.
+const MERCATOR_MAX: f64 = 20_037_726.37;
+/// Characters of the standard geohash strings GEOHASH replies with.
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Range {
+    min: f64,
+    max: f64,
+}
+
+const LONGITUDES: Range = Range {
+    min: -MAX_LONGITUDE,
+    max: MAX_LONGITUDE,
+};
+const LATITUDES: Range = Range {
+    min: -MAX_LATITUDE,
+    max: MAX_LATITUDE,
+};
+
+/// A cell of the geohash grid, dividing each coordinate in `2^step` parts.
+/// Its bits interleave the part of the latitude in the even bits with the
+/// part of the longitude in the odd ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    bits: u64,
+    step: u32,
+}
+
+impl Cell {
+    /// The cell `(longitude, latitude)` falls in within `lon_range` and
+    /// `lat_range`.
+    fn encode(lon: f64, lat: f64, step: u32, lon_range: Range, lat_range: Range) -> Self {
+        let parts = (1u64 << step) as f64;
+        let lat = (lat - lat_range.min) / (lat_range.max - lat_range.min) * parts;
+        let lon = (lon - lon_range.min) / (lon_range.max - lon_range.min) * parts;
+        let bits = spread(lat as u32) | spread(lon as u32) << 1;
+        Cell { bits, step }
+    }
+
+    /// The longitudes and latitudes the cell covers.
+    fn area(self) -> (Range, Range) {
+        let parts = (1u64 << self.step) as f64;
+        let range = |part: u32, range: Range| {
+            let scale = range.max - range.min;
+            Range {
+                min: range.min + (part as f64 / parts) * scale,
+                max: range.min + ((part as f64 + 1.0) / parts) * scale,
+            }
+        };
+        let lon = range(squash(self.bits >> 1), LONGITUDES);
+        let lat = range(squash(self.bits), LATITUDES);
+        (lon, lat)
+    }
+
+    /// The cell `dx` cells east and `dy` cells north of this one, wrapping
+    /// around the grid.
+    fn moved(self, dx: i8, dy: i8) -> Self {
+        let shift = 64 - self.step * 2;
+        let step_part = |bits: u64, mask: u64, delta: i8| {
+            let part = bits & mask;
+            // Bits of the other coordinate are set in between so that
+            // carries and borrows skip over them.
+            let filler = !mask >> shift;
+            let moved = match delta.signum() {
+                0 => part,
+                1 => part.wrapping_add(filler + 1),
+                _ => (part | filler).wrapping_sub(filler + 1),
+            };
+            moved & (mask >> shift)
+        };
+        let lon = step_part(self.bits, 0xaaaa_aaaa_aaaa_aaaa, dx);
+        let lat = step_part(self.bits, 0x5555_5555_5555_5555, dy);
+        Cell {
+            bits: lon | lat,
+            step: self.step,
+        }
+    }
+
+    /// Scores of the members within the cell: from the first inclusive to
+    /// the second exclusive.
+    fn scores(self) -> (f64, f64) {
+        let shift = STEP_MAX * 2 - self.step * 2;
+        (
+            (self.bits << shift) as f64,
+            ((self.bits + 1) << shift) as f64,
+        )
+    }
+}
+
+/// Spreads the bits of `value` over the even bits of the result.
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | x << 16) & 0x0000_ffff_0000_ffff;
+    x = (x | x << 8) & 0x00ff_00ff_00ff_00ff;
+    x = (x | x << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | x << 2) & 0x3333_3333_3333_3333;
+    (x | x << 1) & 0x5555_5555_5555_5555
+}
+
+/// Gathers the even bits of `value`, undoing `spread`.
+fn squash(value: u64) -> u32 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | x >> 1) & 0x3333_3333_3333_3333;
+    x = (x | x >> 2) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | x >> 4) & 0x00ff_00ff_00ff_00ff;
+    x = (x | x >> 8) & 0x0000_ffff_0000_ffff;
+    (x | x >> 16) as u32
+}
+
+/// The score a member at `(lon, lat)` is stored with.
+fn score(lon: f64, lat: f64) -> f64 {
+    Cell::encode(lon, lat, STEP_MAX, LONGITUDES, LATITUDES).bits as f64
+}
+
+/// The position a member stored with `score` is at: the center of its cell.
+fn position(score: f64) -> (f64, f64) {
+    let cell = Cell {
+        bits: score as u64,
+        step: STEP_MAX,
+    };
+    let (lon, lat) = cell.area();
+    let lon = ((lon.min + lon.max) / 2.0).clamp(-MAX_LONGITUDE, MAX_LONGITUDE);
+    let lat = ((lat.min + lat.max) / 2.0).clamp(-MAX_LATITUDE, MAX_LATITUDE);
+    (lon, lat)
+}
+
+/// The standard 11 character geohash of the member stored with `score`,
+/// which covers latitudes from -90 to 90 rather than the ones scores do.
+fn geohash(score: f64) -> String {
+    let (lon, lat) = position(score);
+    let latitudes = Range {
+        min: -90.0,
+        max: 90.0,
+    };
+    let bits = Cell::encode(lon, lat, STEP_MAX, LONGITUDES, latitudes).bits;
+    (1..=10)
+        .map(|i| (bits >> (52 - i * 5)) & 0x1f)
+        // 52 bits make 10 characters; the 11th is always 0.
+        .chain([0])
+        .map(|index| GEOHASH_ALPHABET[index as usize] as char)
+        .collect()
+}
+
+fn radians(degrees: f64) -> f64 {
+    degrees * (std::f64::consts::PI / 180.0)
+}
+
+fn degrees(radians: f64) -> f64 {
+    radians / (std::f64::consts::PI / 180.0)
+}
+
+fn latitude_distance(lat_a: f64, lat_b: f64) -> f64 {
+    EARTH_RADIUS * (radians(lat_b) - radians(lat_a)).abs()
+}
+
+/// The haversine distance in meters between two positions.
+fn distance((lon_a, lat_a): (f64, f64), (lon_b, lat_b): (f64, f64)) -> f64 {
+    let v = ((radians(lon_b) - radians(lon_a)) / 2.0).sin();
+    if v == 0.0 {
+        return latitude_distance(lat_a, lat_b);
+    }
+    let (lat_a, lat_b) = (radians(lat_a), radians(lat_b));
+    let u = ((lat_b - lat_a) / 2.0).sin();
+    let a = u * u + lat_a.cos() * lat_b.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// The distance from `center` to `point` if `point` is within `shape`
+/// around it.
+fn distance_within(shape: GeoShape, center: (f64, f64), point: (f64, f64)) -> Option<f64> {
+    match shape {
+        GeoShape::Radius(radius) => Some(distance(center, point)).filter(|d| *d <= radius),
+        GeoShape::Box(width, height) => {
+            if latitude_distance(point.1, center.1) > height / 2.0 {
+                return None;
+            }
+            if distance((point.0, point.1), (center.0, point.1)) > width / 2.0 {
+                return None;
+            }
+            Some(distance(center, point))
+        }
+    }
+}
+
+/// Bits per coordinate of the cells to search for `shape`, so that it fits
+/// in a cell and its neighbors.
+fn search_step(shape: GeoShape, lat: f64) -> u32 {
+    let mut range = match shape {
+        GeoShape::Radius(radius) => radius,
+        GeoShape::Box(width, height) => ((width / 2.0).powi(2) + (height / 2.0).powi(2)).sqrt(),
+    };
+    if range == 0.0 {
+        return STEP_MAX;
+    }
+    let mut step: i32 = 1;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+    // Cells narrow towards the poles, so they take a coarser step.
+    if lat.abs() > 66.0 {
+        step -= 1;
+        if lat.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+/// The longitudes and latitudes `shape` around `center` spans.
+fn bounding_box(shape: GeoShape, (lon, lat): (f64, f64)) -> (Range, Range) {
+    let (width, height) = match shape {
+        GeoShape::Radius(radius) => (radius, radius),
+        GeoShape::Box(width, height) => (width / 2.0, height / 2.0),
+    };
+    let lat_delta = degrees(height / EARTH_RADIUS);
+    // The box spans the most longitude on its side nearer the pole.
+    let edge = if lat < 0.0 {
+        lat - lat_delta
+    } else {
+        lat + lat_delta
+    };
+    let lon_delta = degrees(width / EARTH_RADIUS / radians(edge).cos());
+    let lon = Range {
+        min: lon - lon_delta,
+        max: lon + lon_delta,
+    };
+    let lat = Range {
+        min: lat - lat_delta,
+        max: lat + lat_delta,
+    };
+    (lon, lat)
+}
+
+/// The cells covering `shape` around `center`: the cell of the center and
+/// those of its neighbors the shape reaches into, as Redis picks them.
+fn search_cells(shape: GeoShape, center: (f64, f64)) -> Vec<Cell> {
+    let (lons, lats) = bounding_box(shape, center);
+    let mut step = search_step(shape, center.1);
+    let mut cell = Cell::encode(center.0, center.1, step, LONGITUDES, LATITUDES);
+
+    // Neighbors may still fall short of the box, in which case a step
+    // coarser does.
+    let reaches = |cell: Cell| {
+        let (_, north) = cell.moved(0, 1).area();
+        let (_, south) = cell.moved(0, -1).area();
+        let (east, _) = cell.moved(1, 0).area();
+        let (west, _) = cell.moved(-1, 0).area();
+        north.max >= lats.max
+            && south.min <= lats.min
+            && east.max >= lons.max
+            && west.min <= lons.min
+    };
+    if step > 1 && !reaches(cell) {
+        step -= 1;
+        cell = Cell::encode(center.0, center.1, step, LONGITUDES, LATITUDES);
+    }
+
+    // Leave out the neighbors on the sides the box doesn't leave the cell
+    // on.
+    let (lon, lat) = cell.area();
+    let skip_south = step >= 2 && lat.min < lats.min;
+    let skip_north = step >= 2 && lat.max > lats.max;
+    let skip_west = step >= 2 && lon.min < lons.min;
+    let skip_east = step >= 2 && lon.max > lons.max;
+    let neighbors = [
+        (0, 0, false),
+        (0, 1, skip_north),
+        (0, -1, skip_south),
+        (1, 0, skip_east),
+        (-1, 0, skip_west),
+        (1, 1, skip_north || skip_east),
+        (-1, 1, skip_north || skip_west),
+        (1, -1, skip_south || skip_east),
+        (-1, -1, skip_south || skip_west),
+    ];
+
+    let mut cells: Vec<Cell> = Vec::new();
+    for (dx, dy, skip) in neighbors {
+        let neighbor = cell.moved(dx, dy);
+        // Neighbors of large cells can be the same cell.
+        if !skip && cells.last() != Some(&neighbor) {
+            cells.push(neighbor);
+        }
+    }
+    cells
+}
+
+/// A member found by GEOSEARCH.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+    pub member: Bytes,
+    /// Distance from the center of the search, in meters.
+    pub distance: f64,
+    pub score: f64,
+    pub position: (f64, f64),
+}
+
+/// Members of `zset` within `shape` around `center`, stopping at `limit` if
+/// given.
+fn search(
+    zset: &SortedSet,
+    shape: GeoShape,
+    center: (f64, f64),
+    limit: Option<usize>,
+) -> Vec<GeoMatch> {
+    let mut matches = Vec::new();
+    for cell in search_cells(shape, center) {
+        let (min, max) = cell.scores();
+        let options = ZRangeOptions {
+            by: RangeBy::Score(ScoreRange {
+                min: ScoreBound {
+                    value: min,
+                    exclusive: false,
+                },
+                max: ScoreBound {
+                    value: max,
+                    exclusive: true,
+                },
+            }),
+            rev: false,
+            limit: None,
+            with_scores: true,
+        };
+        for (member, score) in zset.range(&options) {
+            if limit.is_some_and(|limit| matches.len() >= limit) {
+                return matches;
+            }
+            let position = position(score);
+            if let Some(distance) = distance_within(shape, center, position) {
+                matches.push(GeoMatch {
+                    member,
+                    distance,
+                    score,
+                    position,
+                });
+            }
+        }
+    }
+    matches
+}
+
+impl Db {
+    /// The sorted set under `key`, marking it accessed.
+    fn geo_zset(&self, key: &[u8], now: u64) -> Result<Option<&SortedSet>, Error> {
+        let Some(entry) = self.get(key, now) else {
+            return Ok(None);
+        };
+        entry.touch(now);
+        match &entry.value {
+            Value::SortedSet(zset) => Ok(Some(zset)),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    /// Members matching `options` in the sorted set under `key`, ordered and
+    /// limited as it asks.
+    fn geosearch(&self, key: &[u8], options: &GeoSearch, now: u64) -> Result<Vec<GeoMatch>, Error> {
+        let Some(zset) = self.geo_zset(key, now)? else {
+            return Ok(Vec::new());
+        };
+        let center = match &options.origin {
+            GeoOrigin::LonLat(lon, lat) => (*lon, *lat),
+            GeoOrigin::Member(member) => {
+                let score = zset.score(member).ok_or(Error::NoSuchGeoMember)?;
+                position(score)
+            }
+        };
+
+        let any_limit = match options.count {
+            Some((count, true)) => Some(count),
+            _ => None,
+        };
+        let mut matches = search(zset, options.shape, center, any_limit);
+        // COUNT without ANY wants the closest matches.
+        let order = match options.count {
+            Some((_, false)) => options.order.or(Some(SortOrder::Asc)),
+            _ => options.order,
+        };
+        match order {
+            Some(SortOrder::Asc) => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(SortOrder::Desc) => matches.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        if let Some((count, _)) = options.count {
+            matches.truncate(count);
+        }
+        Ok(matches)
+    }
+
+    /// Stores the members `options` matches in `source` under
+    /// `destination`, replacing it, with their distance in the unit of the
+    /// search as score if `store_dist`. Returns how many were stored; none
+    /// deletes `destination`.
+    fn geosearch_store(
+        &mut self,
+        destination: Bytes,
+        source: &[u8],
+        options: &GeoSearch,
+        store_dist: bool,
+        now: u64,
+    ) -> Result<usize, Error> {
+        let matches = self.geosearch(source, options, now)?;
+        if matches.is_empty() {
+            self.remove(&destination);
+            return Ok(0);
+        }
+
+        let mut zset = SortedSet::default();
+        for found in &matches {
+            let score = match store_dist {
+                true => found.distance / options.unit.as_meters(),
+                false => found.score,
+            };
+            zset.insert(found.member.clone(), score);
+        }
+        let len = zset.len();
+        self.insert_entry(destination, Entry::new(Value::SortedSet(zset), None, now));
+        Ok(len)
+    }
+}
+
+/// Adds or updates the members in `members`, given by longitude, latitude
+/// and name, as ZADD would with `options`.
+pub async fn add(
+    key: Bytes,
+    options: &ZAddOptions,
+    members: Vec<(f64, f64, Bytes)>,
+) -> Result<usize, Error> {
+    let pairs = members
+        .into_iter()
+        .map(|(lon, lat, member)| (score(lon, lat), member))
+        .collect();
+    let (count, _) = write().zadd(key, pairs, options, now_ms())?;
+    Ok(count)
+}
+
+/// Positions of `members`, `None` for those missing.
+pub async fn positions(key: &[u8], members: &[Bytes]) -> Result<Vec<Option<(f64, f64)>>, Error> {
+    let now = now_ms();
+    let db = read();
+    let zset = db.geo_zset(key, now)?;
+    Ok(members
+        .iter()
+        .map(|member| zset.and_then(|zset| zset.score(member)).map(position))
+        .collect())
+}
+
+/// Standard geohashes of `members`, `None` for those missing.
+pub async fn hashes(key: &[u8], members: &[Bytes]) -> Result<Vec<Option<String>>, Error> {
+    let now = now_ms();
+    let db = read();
+    let zset = db.geo_zset(key, now)?;
+    Ok(members
+        .iter()
+        .map(|member| zset.and_then(|zset| zset.score(member)).map(geohash))
+        .collect())
+}
+
+/// Distance in meters between `member_a` and `member_b`, `None` if either is
+/// missing.
+pub async fn dist(key: &[u8], member_a: &[u8], member_b: &[u8]) -> Result<Option<f64>, Error> {
+    let now = now_ms();
+    let db = read();
+    let Some(zset) = db.geo_zset(key, now)? else {
+        return Ok(None);
+    };
+    let (Some(a), Some(b)) = (zset.score(member_a), zset.score(member_b)) else {
+        return Ok(None);
+    };
+    Ok(Some(distance(position(a), position(b))))
+}
+
+/// See `Db::geosearch`.
+pub async fn search_members(key: &[u8], options: &GeoSearch) -> Result<Vec<GeoMatch>, Error> {
+    read().geosearch(key, options, now_ms())
+}
+
+/// See `Db::geosearch_store`.
+pub async fn search_store(
+    destination: Bytes,
+    source: &[u8],
+    options: &GeoSearch,
+    store_dist: bool,
+) -> Result<usize, Error> {
+    write().geosearch_store(destination, source, options, store_dist, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::DistanceUnit;
+
+    const PALERMO: (f64, f64) = (13.361389, 38.115556);
+    const CATANIA: (f64, f64) = (15.087269, 37.502669);
+
+    fn sicily() -> Db {
+        let mut db = Db::default();
+        let pairs = vec![
+            (score(PALERMO.0, PALERMO.1), Bytes::from("Palermo")),
+            (score(CATANIA.0, CATANIA.1), Bytes::from("Catania")),
+            (score(12.758489, 38.788135), Bytes::from("edge1")),
+            (score(17.241510, 38.788135), Bytes::from("edge2")),
+        ];
+        db.zadd(Bytes::from("Sicily"), pairs, &ZAddOptions::default(), 0)
+            .unwrap();
+        db
+    }
+
+    fn search_options(origin: GeoOrigin, shape: GeoShape) -> GeoSearch {
+        GeoSearch {
+            origin,
+            shape,
+            unit: DistanceUnit::Kilometers,
+            order: None,
+            count: None,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+        }
+    }
+
+    fn members(matches: &[GeoMatch]) -> Vec<&str> {
+        matches
+            .iter()
+            .map(|found| std::str::from_utf8(&found.member).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn scores_round_trip_positions() {
+        let score = score(PALERMO.0, PALERMO.1);
+        assert_eq!(score, 3479099956230698.0);
+        let (lon, lat) = position(score);
+        assert_eq!(format!("{lon:.17}"), "13.36138933897018433");
+        assert_eq!(format!("{lat:.17}"), "38.11555639549629859");
+        assert_eq!(geohash(score), "sqc8b49rny0");
+    }
+
+    #[test]
+    fn distances_match_redis() {
+        let palermo = position(score(PALERMO.0, PALERMO.1));
+        let catania = position(score(CATANIA.0, CATANIA.1));
+        assert_eq!(format!("{:.4}", distance(palermo, catania)), "166274.1516");
+    }
+
+    #[test]
+    fn neighbors_wrap_around() {
+        let cell = Cell::encode(0.0, 0.0, 1, LONGITUDES, LATITUDES);
+        assert_eq!(cell.bits, 0b11);
+        assert_eq!(cell.moved(1, 0).bits, 0b01);
+        assert_eq!(cell.moved(0, 1).bits, 0b10);
+        assert_eq!(cell.moved(-1, -1).bits, 0b00);
+    }
+
+    #[test]
+    fn geosearch_by_radius_and_box() {
+        let db = sicily();
+        let mut options =
+            search_options(GeoOrigin::LonLat(15.0, 37.0), GeoShape::Radius(200_000.0));
+        options.order = Some(SortOrder::Asc);
+        let matches = db.geosearch(b"Sicily", &options, 0).unwrap();
+        assert_eq!(members(&matches), ["Catania", "Palermo"]);
+        assert_eq!(format!("{:.4}", matches[0].distance / 1000.0), "56.4413");
+
+        let mut options = search_options(
+            GeoOrigin::LonLat(15.0, 37.0),
+            GeoShape::Box(400_000.0, 400_000.0),
+        );
+        options.order = Some(SortOrder::Desc);
+        let matches = db.geosearch(b"Sicily", &options, 0).unwrap();
+        assert_eq!(members(&matches), ["edge1", "edge2", "Palermo", "Catania"]);
+
+        options.count = Some((1, false));
+        options.order = None;
+        let matches = db.geosearch(b"Sicily", &options, 0).unwrap();
+        assert_eq!(members(&matches), ["Catania"]);
+    }
+
+    #[test]
+    fn geosearch_from_member() {
+        let mut db = sicily();
+        let mut options = search_options(
+            GeoOrigin::Member(Bytes::from("Palermo")),
+            GeoShape::Radius(100_000.0),
+        );
+        options.order = Some(SortOrder::Asc);
+        let matches = db.geosearch(b"Sicily", &options, 0).unwrap();
+        assert_eq!(members(&matches), ["Palermo", "edge1"]);
+
+        let missing = search_options(
+            GeoOrigin::Member(Bytes::from("Rome")),
+            GeoShape::Radius(100_000.0),
+        );
+        let result = db.geosearch(b"Sicily", &missing, 0);
+        assert_eq!(result, Err(Error::NoSuchGeoMember));
+
+        let stored = db.geosearch_store(Bytes::from("near"), b"Sicily", &options, true, 0);
+        assert_eq!(stored, Ok(2));
+        let Some(Value::SortedSet(near)) = db.get(b"near", 0).map(|entry| &entry.value) else {
+            panic!("expected a sorted set");
+        };
+        assert_eq!(near.score(b"Palermo"), Some(0.0));
+    }
+}
@@ -0,0 +1,728 @@
+use std::collections::{BTreeSet, HashMap};
+
+use bytes::Bytes;
+
+use super::{
+    deadline, expire_allowed, now_ms, random_sample, scan_hash, scan_page, with_entry, write, Db,
+    Entry, Error, Value,
+};
+use crate::commands::{ExpireCondition, Expiry};
+use crate::glob;
+use crate::resp::format_double;
+
+/// Deadlines of hash fields as `(deadline, key, field)`, ordered by deadline.
+pub(super) type FieldIndex = BTreeSet<(u64, Bytes, Bytes)>;
+
+/// The fields of a hash together with the deadlines of those that have a TTL.
+///
+/// A field whose deadline passed is hidden from readers right away. Writers
+/// drop such fields before looking at the hash, and the sweep reclaims the
+/// others through `Db::field_expires`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Hash {
+    pub(super) fields: HashMap<Bytes, Bytes>,
+    /// Absolute deadline in milliseconds since the Unix epoch of every field
+    /// with a TTL.
+    pub(super) expires: HashMap<Bytes, u64>,
+}
+
+impl Hash {
+    fn is_live(&self, field: &[u8], now: u64) -> bool {
+        match self.expires.get(field) {
+            Some(expires_at) => *expires_at > now,
+            None => true,
+        }
+    }
+
+    fn get(&self, field: &[u8], now: u64) -> Option<&Bytes> {
+        self.fields.get(field).filter(|_| self.is_live(field, now))
+    }
+
+    fn iter(&self, now: u64) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        self.fields
+            .iter()
+            .filter(move |(field, _)| self.is_live(field, now))
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Whether every field has expired, which makes the whole key expired.
+    pub(super) fn is_expired(&self, now: u64) -> bool {
+        !self.expires.is_empty()
+            && self.expires.len() == self.fields.len()
+            && self.expires.values().all(|expires_at| *expires_at <= now)
+    }
+
+    /// Adds the deadlines of this hash, stored under `key`, to `index`.
+    pub(super) fn index(&self, key: &Bytes, index: &mut FieldIndex) {
+        for (field, expires_at) in &self.expires {
+            index.insert((*expires_at, key.clone(), field.clone()));
+        }
+    }
+
+    /// Removes the deadlines of this hash, stored under `key`, from `index`.
+    pub(super) fn unindex(&self, key: &Bytes, index: &mut FieldIndex) {
+        for (field, expires_at) in &self.expires {
+            index.remove(&(*expires_at, key.clone(), field.clone()));
+        }
+    }
+}
+
+impl Db {
+    fn hash_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut Hash>, Error> {
+        self.remove_expired_fields_of(key, now);
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::Hash(hash)) => Ok(Some(hash)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the hash under `key`, creating an empty one if the key is missing.
+    fn hash_or_default(&mut self, key: Bytes, now: u64) -> Result<&mut Hash, Error> {
+        if self.hash_mut(&key, now)?.is_none() {
+            let entry = Entry::new(Value::Hash(Hash::default()), None, now);
+            self.insert_entry(key.clone(), entry);
+        }
+        Ok(self.hash_mut(&key, now)?.expect("hash was just created"))
+    }
+
+    /// Drops the fields of the hash under `key` whose deadline passed, and
+    /// the key itself if no field is left.
+    fn remove_expired_fields_of(&mut self, key: &[u8], now: u64) {
+        let Some(Entry {
+            value: Value::Hash(hash),
+            ..
+        }) = self.entries.get_mut(key)
+        else {
+            return;
+        };
+        let expired: Vec<(Bytes, u64)> = hash
+            .expires
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(field, expires_at)| (field.clone(), *expires_at))
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+
+        let key = Bytes::copy_from_slice(key);
+        for (field, expires_at) in expired {
+            hash.fields.remove(&field);
+            hash.expires.remove(&field);
+            self.field_expires.remove(&(expires_at, key.clone(), field));
+        }
+        self.remove_if_empty(&key);
+    }
+
+    /// Removes up to `limit` hash fields whose deadline has passed, earliest
+    /// first, along with the keys left without fields.
+    pub(super) fn remove_expired_fields(&mut self, now: u64, limit: usize) -> usize {
+        let mut removed = 0;
+        while removed < limit {
+            match self.field_expires.first() {
+                Some((expires_at, _, _)) if *expires_at <= now => {}
+                _ => break,
+            }
+            let (_, key, field) = self
+                .field_expires
+                .pop_first()
+                .expect("index should not be empty");
+            if let Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) = self.entries.get_mut(&key)
+            {
+                hash.fields.remove(&field);
+                hash.expires.remove(&field);
+            }
+            self.remove_if_empty(&key);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Replaces the TTL of `field` in the hash under `key`, keeping the
+    /// deadline index in sync.
+    fn set_field_expiry(&mut self, key: &[u8], field: &Bytes, expires_at: Option<u64>) {
+        let Some(Entry {
+            value: Value::Hash(hash),
+            ..
+        }) = self.entries.get_mut(key)
+        else {
+            return;
+        };
+
+        let previous = match expires_at {
+            Some(expires_at) => hash.expires.insert(field.clone(), expires_at),
+            None => hash.expires.remove(field),
+        };
+        if previous == expires_at {
+            return;
+        }
+        let key = Bytes::copy_from_slice(key);
+        if let Some(previous) = previous {
+            self.field_expires
+                .remove(&(previous, key.clone(), field.clone()));
+        }
+        if let Some(expires_at) = expires_at {
+            self.field_expires.insert((expires_at, key, field.clone()));
+        }
+    }
+
+    /// Sets every field in `pairs`, returning how many of them are new.
+    /// Overwritten fields lose their TTL.
+    fn hset(&mut self, key: Bytes, pairs: Vec<(Bytes, Bytes)>, now: u64) -> Result<usize, Error> {
+        let hash = self.hash_or_default(key.clone(), now)?;
+        let added = pairs
+            .iter()
+            .filter(|(field, value)| hash.fields.insert(field.clone(), value.clone()).is_none())
+            .count();
+        for (field, _) in &pairs {
+            self.set_field_expiry(&key, field, None);
+        }
+        Ok(added)
+    }
+
+    fn hsetnx(&mut self, key: Bytes, field: Bytes, value: Bytes, now: u64) -> Result<bool, Error> {
+        let hash = self.hash_or_default(key, now)?;
+        if hash.fields.contains_key(&field) {
+            return Ok(false);
+        }
+        hash.fields.insert(field, value);
+        Ok(true)
+    }
+
+    fn hdel(&mut self, key: &[u8], fields: &[Bytes], now: u64) -> Result<usize, Error> {
+        let Some(hash) = self.hash_mut(key, now)? else {
+            return Ok(0);
+        };
+
+        let removed = fields
+            .iter()
+            .filter(|field| hash.fields.remove(*field).is_some())
+            .count();
+        for field in fields {
+            self.set_field_expiry(key, field, None);
+        }
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Applies `expiry` to each of `fields`, replying per field the way
+    /// HEXPIRE does: -2 if the field doesn't exist, 0 if `condition` rules the
+    /// new deadline out, 1 if it was set and 2 if the field was deleted
+    /// because the deadline already passed.
+    fn hexpire(
+        &mut self,
+        key: &[u8],
+        fields: &[Bytes],
+        expiry: Expiry,
+        condition: ExpireCondition,
+        now: u64,
+    ) -> Result<Vec<i64>, Error> {
+        let mut replies = Vec::with_capacity(fields.len());
+        for field in fields {
+            let Some(hash) = self.hash_mut(key, now)? else {
+                replies.push(-2);
+                continue;
+            };
+            if !hash.fields.contains_key(field) {
+                replies.push(-2);
+                continue;
+            }
+            let current = hash.expires.get(field).copied();
+            let expires_at = match deadline(expiry, now, current) {
+                Some(expires_at) if expire_allowed(condition, current, expires_at) => expires_at,
+                _ => {
+                    replies.push(0);
+                    continue;
+                }
+            };
+
+            if expires_at <= now {
+                hash.fields.remove(field);
+                self.set_field_expiry(key, field, None);
+                replies.push(2);
+            } else {
+                self.set_field_expiry(key, field, Some(expires_at));
+                replies.push(1);
+            }
+        }
+        self.remove_if_empty(key);
+        Ok(replies)
+    }
+
+    /// Removes the TTL of each of `fields`, replying per field -2 if it
+    /// doesn't exist, -1 if it has no TTL and 1 if the TTL was removed.
+    fn hpersist(&mut self, key: &[u8], fields: &[Bytes], now: u64) -> Result<Vec<i64>, Error> {
+        let mut replies = Vec::with_capacity(fields.len());
+        for field in fields {
+            let reply = match self.hash_mut(key, now)? {
+                Some(hash) if hash.expires.contains_key(field) => 1,
+                Some(hash) if hash.fields.contains_key(field) => -1,
+                _ => -2,
+            };
+            self.set_field_expiry(key, field, None);
+            replies.push(reply);
+        }
+        Ok(replies)
+    }
+
+    fn hincrby(
+        &mut self,
+        key: Bytes,
+        field: Bytes,
+        increment: i64,
+        now: u64,
+    ) -> Result<i64, Error> {
+        let hash = self.hash_or_default(key, now)?;
+        let current = match hash.fields.get(&field) {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or(Error::HashValueNotInteger)?,
+            None => 0,
+        };
+
+        let value = current.checked_add(increment).ok_or(Error::Overflow)?;
+        hash.fields.insert(field, value.to_string().into());
+        Ok(value)
+    }
+
+    fn hincrbyfloat(
+        &mut self,
+        key: Bytes,
+        field: Bytes,
+        increment: f64,
+        now: u64,
+    ) -> Result<Bytes, Error> {
+        let hash = self.hash_or_default(key, now)?;
+        let current = match hash.fields.get(&field) {
+            Some(value) => std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|value| !value.is_nan())
+                .ok_or(Error::HashValueNotFloat)?,
+            None => 0.0,
+        };
+
+        let value = current + increment;
+        if !value.is_finite() {
+            return Err(Error::NotFinite);
+        }
+        let value = Bytes::from(format_double(value));
+        hash.fields.insert(field, value.clone());
+        Ok(value)
+    }
+}
+
+/// Runs `f` on the hash under `key` at time `now`, marking it accessed.
+fn with_hash<T>(key: &[u8], f: impl FnOnce(&Hash, u64) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::Hash(hash) => Ok(f(hash, now)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+pub async fn set(key: Bytes, pairs: Vec<(Bytes, Bytes)>) -> Result<usize, Error> {
+    write().hset(key, pairs, now_ms())
+}
+
+pub async fn set_nx(key: Bytes, field: Bytes, value: Bytes) -> Result<bool, Error> {
+    write().hsetnx(key, field, value, now_ms())
+}
+
+pub async fn get(key: &[u8], field: &[u8]) -> Result<Option<Bytes>, Error> {
+    Ok(with_hash(key, |hash, now| hash.get(field, now).cloned())?.flatten())
+}
+
+pub async fn get_many(key: &[u8], fields: &[Bytes]) -> Result<Vec<Option<Bytes>>, Error> {
+    let values = with_hash(key, |hash, now| {
+        fields
+            .iter()
+            .map(|field| hash.get(field, now).cloned())
+            .collect()
+    })?;
+    Ok(values.unwrap_or_else(|| vec![None; fields.len()]))
+}
+
+pub async fn get_all(key: &[u8]) -> Result<Vec<(Bytes, Bytes)>, Error> {
+    let pairs = with_hash(key, |hash, now| {
+        hash.iter(now)
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect()
+    })?;
+    Ok(pairs.unwrap_or_default())
+}
+
+pub async fn delete(key: &[u8], fields: &[Bytes]) -> Result<usize, Error> {
+    write().hdel(key, fields, now_ms())
+}
+
+pub async fn incr_by(key: Bytes, field: Bytes, increment: i64) -> Result<i64, Error> {
+    write().hincrby(key, field, increment, now_ms())
+}
+
+pub async fn incr_by_float(key: Bytes, field: Bytes, increment: f64) -> Result<Bytes, Error> {
+    write().hincrbyfloat(key, field, increment, now_ms())
+}
+
+pub async fn keys(key: &[u8]) -> Result<Vec<Bytes>, Error> {
+    let fields = with_hash(key, |hash, now| {
+        hash.iter(now).map(|(field, _)| field.clone()).collect()
+    })?;
+    Ok(fields.unwrap_or_default())
+}
+
+pub async fn values(key: &[u8]) -> Result<Vec<Bytes>, Error> {
+    let values = with_hash(key, |hash, now| {
+        hash.iter(now).map(|(_, value)| value.clone()).collect()
+    })?;
+    Ok(values.unwrap_or_default())
+}
+
+pub async fn len(key: &[u8]) -> Result<usize, Error> {
+    Ok(with_hash(key, |hash, now| hash.iter(now).count())?.unwrap_or(0))
+}
+
+pub async fn exists(key: &[u8], field: &[u8]) -> Result<bool, Error> {
+    Ok(with_hash(key, |hash, now| hash.get(field, now).is_some())?.unwrap_or(false))
+}
+
+/// Picks fields with their values at random, see `random_sample` for how
+/// `count` is interpreted.
+pub async fn random_fields(key: &[u8], count: i64) -> Result<Vec<(Bytes, Bytes)>, Error> {
+    let pairs = get_all(key).await?;
+    Ok(random_sample(pairs, count))
+}
+
+/// Returns a page of fields and values, resuming from `cursor` the same way
+/// SCAN does over keys.
+pub async fn scan(
+    key: &[u8],
+    cursor: u64,
+    count: usize,
+    pattern: Option<&[u8]>,
+) -> Result<(u64, Vec<(Bytes, Bytes)>), Error> {
+    let page = with_hash(key, |hash, now| {
+        let order: BTreeSet<(u64, Bytes)> = hash
+            .iter(now)
+            .map(|(field, _)| (scan_hash(field), field.clone()))
+            .collect();
+        let (cursor, fields) = scan_page(&order, cursor, count);
+        let pairs = fields
+            .into_iter()
+            .filter(|field| match pattern {
+                Some(pattern) => glob::matches(pattern, field),
+                None => true,
+            })
+            .map(|field| (field.clone(), hash.fields[field].clone()))
+            .collect();
+        (cursor, pairs)
+    })?;
+    Ok(page.unwrap_or((0, Vec::new())))
+}
+
+/// See `Db::hexpire`.
+pub async fn expire(
+    key: &[u8],
+    fields: &[Bytes],
+    expiry: Expiry,
+    condition: ExpireCondition,
+) -> Result<Vec<i64>, Error> {
+    write().hexpire(key, fields, expiry, condition, now_ms())
+}
+
+/// Remaining time to live in milliseconds of each of `fields`: `None` if the
+/// field doesn't exist, `Some(None)` if it has no TTL.
+pub async fn ttl(key: &[u8], fields: &[Bytes]) -> Result<Vec<Option<Option<u64>>>, Error> {
+    with_deadlines(key, fields, |expires_at, now| {
+        expires_at.saturating_sub(now)
+    })
+}
+
+/// Absolute deadline in milliseconds since the Unix epoch of each of
+/// `fields`, with the same conventions as `ttl`.
+pub async fn expire_times(key: &[u8], fields: &[Bytes]) -> Result<Vec<Option<Option<u64>>>, Error> {
+    with_deadlines(key, fields, |expires_at, _| expires_at)
+}
+
+/// Maps the deadline of each live field in `fields` with `f`, which also gets
+/// the current time.
+fn with_deadlines(
+    key: &[u8],
+    fields: &[Bytes],
+    f: impl Fn(u64, u64) -> u64,
+) -> Result<Vec<Option<Option<u64>>>, Error> {
+    let deadlines = with_hash(key, |hash, now| {
+        fields
+            .iter()
+            .map(|field| {
+                hash.get(field, now).map(|_| {
+                    hash.expires
+                        .get(field)
+                        .map(|expires_at| f(*expires_at, now))
+                })
+            })
+            .collect()
+    })?;
+    Ok(deadlines.unwrap_or_else(|| vec![None; fields.len()]))
+}
+
+/// See `Db::hpersist`.
+pub async fn persist(key: &[u8], fields: &[Bytes]) -> Result<Vec<i64>, Error> {
+    write().hpersist(key, fields, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(Bytes, Bytes)> {
+        items
+            .iter()
+            .map(|(field, value)| {
+                (
+                    Bytes::from(field.to_string()),
+                    Bytes::from(value.to_string()),
+                )
+            })
+            .collect()
+    }
+
+    fn field(db: &Db, field: &str) -> Option<Bytes> {
+        match db.get(b"hash", 0).map(|entry| &entry.value) {
+            Some(Value::Hash(hash)) => hash.get(field.as_bytes(), 0).cloned(),
+            Some(value) => panic!("Expected a hash, got {:?}", value),
+            None => None,
+        }
+    }
+
+    #[test]
+    fn hset_counts_new_fields() {
+        let mut db = Db::default();
+        let key = Bytes::from("hash");
+        assert_eq!(
+            db.hset(key.clone(), pairs(&[("a", "1"), ("b", "2")]), 0),
+            Ok(2)
+        );
+        assert_eq!(
+            db.hset(key.clone(), pairs(&[("a", "3"), ("c", "4")]), 0),
+            Ok(1)
+        );
+        assert_eq!(field(&db, "a"), Some(Bytes::from("3")));
+
+        assert_eq!(
+            db.hsetnx(key.clone(), Bytes::from("a"), Bytes::from("5"), 0),
+            Ok(false)
+        );
+        assert_eq!(
+            db.hsetnx(key, Bytes::from("d"), Bytes::from("5"), 0),
+            Ok(true)
+        );
+        assert_eq!(field(&db, "a"), Some(Bytes::from("3")));
+    }
+
+    #[test]
+    fn hdel_removes_emptied_hash() {
+        let mut db = Db::default();
+        db.hset(Bytes::from("hash"), pairs(&[("a", "1"), ("b", "2")]), 0)
+            .unwrap();
+        let fields = [Bytes::from("a"), Bytes::from("x")];
+        assert_eq!(db.hdel(b"hash", &fields, 0), Ok(1));
+        assert_eq!(db.hdel(b"hash", &[Bytes::from("b")], 0), Ok(1));
+        assert!(db.get(b"hash", 0).is_none());
+    }
+
+    #[test]
+    fn hincrby_checks_value_and_overflow() {
+        let mut db = Db::default();
+        let key = Bytes::from("hash");
+        let counter = Bytes::from("counter");
+        assert_eq!(db.hincrby(key.clone(), counter.clone(), 5, 0), Ok(5));
+        assert_eq!(db.hincrby(key.clone(), counter.clone(), -7, 0), Ok(-2));
+        assert_eq!(
+            db.hincrby(key.clone(), counter, i64::MIN, 0),
+            Err(Error::Overflow)
+        );
+
+        db.hset(key.clone(), pairs(&[("name", "abc")]), 0).unwrap();
+        assert_eq!(
+            db.hincrby(key, Bytes::from("name"), 1, 0),
+            Err(Error::HashValueNotInteger)
+        );
+    }
+
+    #[test]
+    fn hincrbyfloat_formats_result() {
+        let mut db = Db::default();
+        let key = Bytes::from("hash");
+        db.hset(
+            key.clone(),
+            pairs(&[("price", "10.50"), ("name", "abc")]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            db.hincrbyfloat(key.clone(), Bytes::from("price"), 0.1, 0),
+            Ok(Bytes::from("10.6"))
+        );
+        assert_eq!(
+            db.hincrbyfloat(key.clone(), Bytes::from("new"), 3.0, 0),
+            Ok(Bytes::from("3"))
+        );
+        assert_eq!(
+            db.hincrbyfloat(key.clone(), Bytes::from("name"), 1.0, 0),
+            Err(Error::HashValueNotFloat)
+        );
+        assert_eq!(
+            db.hincrbyfloat(key, Bytes::from("price"), f64::INFINITY, 0),
+            Err(Error::NotFinite)
+        );
+    }
+
+    fn fields(names: &[&str]) -> Vec<Bytes> {
+        names
+            .iter()
+            .map(|name| Bytes::from(name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn hexpire_replies_per_field() {
+        let mut db = Db::default();
+        db.hset(Bytes::from("hash"), pairs(&[("a", "1"), ("b", "2")]), 0)
+            .unwrap();
+        let none = ExpireCondition::default();
+        let nx = ExpireCondition {
+            nx: true,
+            ..ExpireCondition::default()
+        };
+        let gt = ExpireCondition {
+            gt: true,
+            ..ExpireCondition::default()
+        };
+
+        let expiry = Expiry::After(100);
+        assert_eq!(
+            db.hexpire(b"hash", &fields(&["a", "x"]), expiry, none, 0),
+            Ok(vec![1, -2])
+        );
+        assert_eq!(
+            db.hexpire(b"hash", &fields(&["a", "b"]), expiry, nx, 0),
+            Ok(vec![0, 1])
+        );
+        assert_eq!(
+            db.hexpire(b"hash", &fields(&["a"]), Expiry::At(50), gt, 0),
+            Ok(vec![0])
+        );
+        assert_eq!(
+            db.hexpire(b"hash", &fields(&["b"]), Expiry::At(0), none, 0),
+            Ok(vec![2])
+        );
+        assert_eq!(field(&db, "b"), None);
+        assert_eq!(db.field_expires.len(), 1);
+
+        assert_eq!(
+            db.hpersist(b"hash", &fields(&["a", "a", "x"]), 0),
+            Ok(vec![1, -1, -2])
+        );
+        assert!(db.field_expires.is_empty());
+        assert_eq!(
+            db.hexpire(b"missing", &fields(&["a"]), expiry, none, 0),
+            Ok(vec![-2])
+        );
+    }
+
+    #[test]
+    fn expired_fields_are_reclaimed_with_the_key() {
+        let mut db = Db::default();
+        let key = Bytes::from("hash");
+        db.hset(key.clone(), pairs(&[("a", "1"), ("b", "2")]), 0)
+            .unwrap();
+        let none = ExpireCondition::default();
+        db.hexpire(b"hash", &fields(&["a"]), Expiry::At(10), none, 0)
+            .unwrap();
+        db.hexpire(b"hash", &fields(&["b"]), Expiry::At(20), none, 0)
+            .unwrap();
+
+        assert_eq!(db.remove_expired_fields(15, 100), 1);
+        assert!(db.get(b"hash", 15).is_some());
+        // Once every field is due the key is gone even before the sweep.
+        assert!(db.get(b"hash", 20).is_none());
+        assert_eq!(db.remove_expired_fields(20, 100), 1);
+        assert!(db.entries.is_empty());
+        assert!(db.field_expires.is_empty());
+    }
+
+    #[test]
+    fn expiring_the_key_forgets_its_field_ttls() {
+        let mut db = Db::default();
+        let key = Bytes::from("hash");
+        db.hset(key.clone(), pairs(&[("a", "1")]), 0).unwrap();
+        let none = ExpireCondition::default();
+        db.hexpire(b"hash", &fields(&["a"]), Expiry::At(30), none, 0)
+            .unwrap();
+        db.expire(b"hash", Expiry::At(10), none, 0);
+
+        assert_eq!(db.remove_expired(10, 100), 1);
+        assert!(db.field_expires.is_empty());
+
+        // A hash stored under the same key keeps its fields past the old TTL.
+        db.hset(key.clone(), pairs(&[("a", "2")]), 20).unwrap();
+        assert_eq!(db.remove_expired_fields(30, 100), 0);
+        assert_eq!(field(&db, "a"), Some(Bytes::from("2")));
+    }
+
+    #[test]
+    fn overwriting_fields_clears_their_ttl() {
+        let mut db = Db::default();
+        let key = Bytes::from("hash");
+        db.hset(key.clone(), pairs(&[("a", "1"), ("b", "2")]), 0)
+            .unwrap();
+        let none = ExpireCondition::default();
+        db.hexpire(b"hash", &fields(&["a", "b"]), Expiry::At(10), none, 0)
+            .unwrap();
+
+        // Writers drop fields that are due instead of overwriting them.
+        assert_eq!(db.hset(key.clone(), pairs(&[("a", "3")]), 10), Ok(1));
+        assert!(db.field_expires.is_empty());
+        assert_eq!(field(&db, "a"), Some(Bytes::from("3")));
+
+        db.hexpire(b"hash", &fields(&["a"]), Expiry::At(30), none, 20)
+            .unwrap();
+        db.rename(b"hash", Bytes::from("other"), false, 20).unwrap();
+        assert_eq!(
+            db.field_expires.first(),
+            Some(&(30, Bytes::from("other"), Bytes::from("a")))
+        );
+        db.insert(Bytes::from("other"), Bytes::from("value"), None);
+        assert!(db.field_expires.is_empty());
+    }
+
+    #[test]
+    fn hash_commands_on_wrong_type() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("hash"), Bytes::from("value"), None);
+        assert_eq!(
+            db.hset(Bytes::from("hash"), pairs(&[("a", "1")]), 0),
+            Err(Error::WrongType)
+        );
+        assert_eq!(
+            db.hdel(b"hash", &[Bytes::from("a")], 0),
+            Err(Error::WrongType)
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use bytes::{Bytes, BytesMut};
+
+use super::{now_ms, read, write, Db, Error, Value};
+
+/// Bits of the hash picking a register, for 2^14 registers and a standard
+/// error of 0.81%.
+const P: u32 = 14;
+const REGISTERS: usize = 1 << P;
+/// Bits of the hash left to count the run of trailing zeros in.
+const Q: usize = 64 - P as usize;
+const REGISTER_BITS: usize = 6;
+const HEADER_LEN: usize = 16;
+const DENSE_LEN: usize = HEADER_LEN + REGISTERS * REGISTER_BITS / 8;
+/// Largest sparse value before it's promoted to dense, Redis' default
+/// `hll-sparse-max-bytes`.
+const SPARSE_MAX_LEN: usize = 3000;
+/// Largest register value the sparse encoding holds.
+const SPARSE_MAX_VALUE: u8 = 32;
+/// 1 / (2 ln 2), the bias correction of the estimator as registers go to
+/// infinity.
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+const SEED: u64 = 0xadc8_3b19;
+
+/// Values follow Redis' layout: the `HYLL` magic, the encoding, three unused
+/// bytes and the cached cardinality in little endian, whose top bit is set
+/// once it's stale.
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+const STALE_CACHE: u8 = 0x80;
+
+/// Registers of a HyperLogLog, one byte each.
+type Registers = Vec<u8>;
+
+/// MurmurHash64A, the hash Redis picks registers with, and RedisBloom
+/// hashes filter items with.
+pub(super) fn murmurhash64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut hash = seed ^ (data.len() as u64).wrapping_mul(M);
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        hash ^= k;
+        hash = hash.wrapping_mul(M);
+    }
+    if !tail.is_empty() {
+        for (index, byte) in tail.iter().enumerate() {
+            hash ^= (*byte as u64) << (8 * index);
+        }
+        hash = hash.wrapping_mul(M);
+    }
+
+    hash ^= hash >> R;
+    hash = hash.wrapping_mul(M);
+    hash ^ hash >> R
+}
+
+/// Register `element` falls in, and the value it raises the register to:
+/// one more than the run of zeros at the end of the rest of its hash.
+fn position(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, SEED);
+    let index = hash as usize & (REGISTERS - 1);
+    let rest = hash >> P | 1 << Q;
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+/// The value a new key starts with: sparse, every register zero and a
+/// cached cardinality of 0.
+fn empty() -> Bytes {
+    let mut value = BytesMut::from(MAGIC);
+    value.extend_from_slice(&[SPARSE, 0, 0, 0]);
+    value.extend_from_slice(&[0; 8]);
+    value.extend_from_slice(&xzero(REGISTERS));
+    value.freeze()
+}
+
+/// Whether `value` is dense rather than sparse, checking that it's a
+/// HyperLogLog at all.
+fn is_dense(value: &[u8]) -> Result<bool, Error> {
+    if value.len() < HEADER_LEN || &value[..MAGIC.len()] != MAGIC {
+        return Err(Error::NotHyperLogLog);
+    }
+    match value[MAGIC.len()] {
+        DENSE if value.len() == DENSE_LEN => Ok(true),
+        SPARSE => Ok(false),
+        _ => Err(Error::NotHyperLogLog),
+    }
+}
+
+fn cached_cardinality(value: &[u8]) -> Option<u64> {
+    let cache: [u8; 8] = value[8..HEADER_LEN].try_into().unwrap();
+    (cache[7] & STALE_CACHE == 0).then(|| u64::from_le_bytes(cache))
+}
+
+/// Register `index` of dense `registers`, packed 6 bits each from the least
+/// significant bit of each byte up.
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit & 7);
+    let high = registers.get(byte + 1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([registers[byte], high]);
+    (word >> shift) as u8 & 0x3f
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let bit = index * REGISTER_BITS;
+    let (byte, shift) = (bit / 8, bit & 7);
+    let high = registers.get(byte + 1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([registers[byte], high]);
+    let word = word & !(0x3f << shift) | (value as u16) << shift;
+    let [low, high] = word.to_le_bytes();
+    registers[byte] = low;
+    if let Some(byte) = registers.get_mut(byte + 1) {
+        *byte = high;
+    }
+}
+
+/// The XZERO opcode for a run of `len` zero registers, up to 16384.
+fn xzero(len: usize) -> [u8; 2] {
+    let len = len - 1;
+    [0x40 | (len >> 8) as u8, len as u8]
+}
+
+/// Decodes the registers of the HyperLogLog `value`.
+fn registers(value: &[u8]) -> Result<Registers, Error> {
+    if is_dense(value)? {
+        let dense = &value[HEADER_LEN..];
+        return Ok((0..REGISTERS)
+            .map(|index| dense_get(dense, index))
+            .collect());
+    }
+
+    // Sparse values are runs of registers: ZERO (00xxxxxx) and XZERO
+    // (01xxxxxx yyyyyyyy) for up to 64 and 16384 zeros, and VAL (1vvvvvxx)
+    // for up to 4 registers of value 1 to 32.
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut opcodes = value[HEADER_LEN..].iter();
+    while let Some(&opcode) = opcodes.next() {
+        let (value, len) = match opcode >> 6 {
+            0b00 => (0, (opcode & 0x3f) as usize + 1),
+            0b01 => {
+                let low = *opcodes.next().ok_or(Error::CorruptedHyperLogLog)?;
+                (0, (((opcode & 0x3f) as usize) << 8 | low as usize) + 1)
+            }
+            _ => ((opcode >> 2 & 0x1f) + 1, (opcode & 0x03) as usize + 1),
+        };
+        if registers.len() + len > REGISTERS {
+            return Err(Error::CorruptedHyperLogLog);
+        }
+        registers.resize(registers.len() + len, value);
+    }
+    if registers.len() != REGISTERS {
+        return Err(Error::CorruptedHyperLogLog);
+    }
+    Ok(registers)
+}
+
+/// The sparse opcodes for `registers`, or `None` if one is too large for the
+/// sparse encoding or they take more than `SPARSE_MAX_LEN`.
+fn sparse_opcodes(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut opcodes = Vec::new();
+    let mut rest = registers;
+    while let Some(&value) = rest.first() {
+        let run = rest
+            .iter()
+            .take_while(|register| **register == value)
+            .count();
+        rest = &rest[run..];
+        match value {
+            0 if run <= 64 => opcodes.push(run as u8 - 1),
+            0 => opcodes.extend_from_slice(&xzero(run)),
+            1..=SPARSE_MAX_VALUE => {
+                let (full, last) = (run / 4, run & 3);
+                let opcode = 0x80 | (value - 1) << 2;
+                opcodes.resize(opcodes.len() + full, opcode | 3);
+                if last > 0 {
+                    opcodes.push(opcode | (last as u8 - 1));
+                }
+            }
+            _ => return None,
+        }
+        if HEADER_LEN + opcodes.len() > SPARSE_MAX_LEN {
+            return None;
+        }
+    }
+    Some(opcodes)
+}
+
+/// Encodes `registers` with a stale cached cardinality, as sparse if
+/// `sparse` and they fit.
+fn encode(registers: &[u8], sparse: bool) -> Bytes {
+    let mut value = BytesMut::from(MAGIC);
+    value.extend_from_slice(&[DENSE, 0, 0, 0]);
+    value.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, STALE_CACHE]);
+    if let Some(opcodes) = sparse.then(|| sparse_opcodes(registers)).flatten() {
+        value[MAGIC.len()] = SPARSE;
+        value.extend_from_slice(&opcodes);
+        return value.freeze();
+    }
+
+    value.resize(DENSE_LEN, 0);
+    let dense = &mut value[HEADER_LEN..];
+    for (index, register) in registers.iter().enumerate() {
+        dense_set(dense, index, *register);
+    }
+    value.freeze()
+}
+
+/// Ertl's improved estimate of the cardinality counted by `registers`, as
+/// Redis computes it.
+fn estimate(registers: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+    let mut histogram = [0u32; 64];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+
+    let mut z = m * tau((m - histogram[Q + 1] as f64) / m);
+    for count in histogram[1..=Q].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+impl Db {
+    /// Adds `elements` to the HyperLogLog under `key`, creating it if
+    /// missing. Returns whether it changed.
+    fn pfadd(&mut self, key: Bytes, elements: &[Bytes], now: u64) -> Result<bool, Error> {
+        self.update_string(key, now, |current| {
+            let (created, current) = match current {
+                Some(current) => (false, current.clone()),
+                None => (true, empty()),
+            };
+            let mut registers = registers(&current)?;
+            let mut updated = false;
+            for element in elements {
+                let (index, count) = position(element);
+                if registers[index] < count {
+                    registers[index] = count;
+                    updated = true;
+                }
+            }
+
+            match updated {
+                true => Ok((encode(&registers, !is_dense(&current)?), true)),
+                false => Ok((current, created)),
+            }
+        })
+    }
+
+    /// Estimates the cardinality of the HyperLogLog under `key`, caching it
+    /// in the value until the next change.
+    fn pfcount(&mut self, key: &[u8], now: u64) -> Result<u64, Error> {
+        let Some(entry) = self.get_mut(key, now) else {
+            return Ok(0);
+        };
+        let Value::String(value) = &mut entry.value else {
+            return Err(Error::WrongType);
+        };
+        is_dense(value)?;
+        if let Some(cardinality) = cached_cardinality(value) {
+            return Ok(cardinality);
+        }
+
+        let cardinality = estimate(&registers(value)?);
+        let mut cached = BytesMut::from(&value[..]);
+        cached[8..HEADER_LEN].copy_from_slice(&cardinality.to_le_bytes());
+        *value = cached.freeze();
+        Ok(cardinality)
+    }
+
+    /// The registers of the union of the HyperLogLogs under `keys`, missing
+    /// keys counting as empty, and whether any of them is dense.
+    fn pfunion(&self, keys: &[Bytes], now: u64) -> Result<(Registers, bool), Error> {
+        let mut union = vec![0; REGISTERS];
+        let mut dense = false;
+        for key in keys {
+            let Some(value) = self.string_ref(key, now)? else {
+                continue;
+            };
+            dense |= is_dense(value)?;
+            for (max, register) in union.iter_mut().zip(registers(value)?) {
+                *max = register.max(*max);
+            }
+        }
+        Ok((union, dense))
+    }
+
+    /// Stores the union of the HyperLogLogs under `destination` and
+    /// `sources` in `destination`, keeping it dense if any of them is.
+    fn pfmerge(&mut self, destination: Bytes, sources: &[Bytes], now: u64) -> Result<(), Error> {
+        let mut keys = vec![destination.clone()];
+        keys.extend_from_slice(sources);
+        let (registers, dense) = self.pfunion(&keys, now)?;
+        self.update_string(destination, now, |_| Ok((encode(&registers, !dense), ())))
+    }
+}
+
+/// See `Db::pfadd`.
+pub async fn add(key: Bytes, elements: &[Bytes]) -> Result<bool, Error> {
+    write().pfadd(key, elements, now_ms())
+}
+
+/// Estimates the cardinality of the union of the HyperLogLogs under `keys`.
+pub async fn count(keys: &[Bytes]) -> Result<u64, Error> {
+    let now = now_ms();
+    if let [key] = keys {
+        return write().pfcount(key, now);
+    }
+
+    let db = read();
+    for key in keys {
+        if let Some(entry) = db.get(key, now) {
+            entry.touch(now);
+        }
+    }
+    let (registers, _) = db.pfunion(keys, now)?;
+    Ok(estimate(&registers))
+}
+
+/// See `Db::pfmerge`.
+pub async fn merge(destination: Bytes, sources: &[Bytes]) -> Result<(), Error> {
+    write().pfmerge(destination, sources, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements(range: std::ops::Range<usize>) -> Vec<Bytes> {
+        range.map(|i| Bytes::from(format!("element:{i}"))).collect()
+    }
+
+    fn value(db: &Db, key: &str) -> Bytes {
+        db.string_ref(key.as_bytes(), 0).unwrap().unwrap().clone()
+    }
+
+    #[test]
+    fn empty_value_matches_redis() {
+        let value = empty();
+        assert_eq!(
+            &value[..],
+            b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        );
+        assert_eq!(registers(&value), Ok(vec![0; REGISTERS]));
+        assert_eq!(cached_cardinality(&value), Some(0));
+        assert_eq!(estimate(&vec![0; REGISTERS]), 0);
+    }
+
+    #[test]
+    fn encodings_round_trip() {
+        let mut registers = vec![0; REGISTERS];
+        registers[0] = 3;
+        registers[100..110].fill(7);
+        registers[REGISTERS - 1] = 32;
+
+        let sparse = encode(&registers, true);
+        assert_eq!(is_dense(&sparse), Ok(false));
+        assert_eq!(super::registers(&sparse), Ok(registers.clone()));
+        assert_eq!(cached_cardinality(&sparse), None);
+
+        let dense = encode(&registers, false);
+        assert_eq!(dense.len(), DENSE_LEN);
+        assert_eq!(super::registers(&dense), Ok(registers.clone()));
+
+        // Registers past 32 only fit the dense encoding.
+        registers[1] = 33;
+        let promoted = encode(&registers, true);
+        assert_eq!(is_dense(&promoted), Ok(true));
+        assert_eq!(super::registers(&promoted), Ok(registers));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(registers(b"not a hyperloglog"), Err(Error::NotHyperLogLog));
+        let mut truncated = BytesMut::from(&empty()[..HEADER_LEN]);
+        truncated.extend_from_slice(&[0x7f]);
+        assert_eq!(registers(&truncated), Err(Error::CorruptedHyperLogLog));
+        let mut short = BytesMut::from(&empty()[..HEADER_LEN]);
+        short.extend_from_slice(&[0x00]);
+        assert_eq!(registers(&short), Err(Error::CorruptedHyperLogLog));
+    }
+
+    #[test]
+    fn pfadd_and_pfcount_estimate_cardinality() {
+        let mut db = Db::default();
+        assert_eq!(db.pfadd(Bytes::from("hll"), &[], 0), Ok(true));
+        assert_eq!(db.pfadd(Bytes::from("hll"), &[], 0), Ok(false));
+        assert_eq!(
+            db.pfadd(Bytes::from("hll"), &elements(0..1000), 0),
+            Ok(true)
+        );
+        assert_eq!(
+            db.pfadd(Bytes::from("hll"), &elements(0..1000), 0),
+            Ok(false)
+        );
+        assert_eq!(is_dense(&value(&db, "hll")), Ok(false));
+
+        let count = db.pfcount(b"hll", 0).unwrap();
+        assert!(count.abs_diff(1000) <= 10, "{count}");
+        assert_eq!(cached_cardinality(&value(&db, "hll")), Some(count));
+
+        assert_eq!(
+            db.pfadd(Bytes::from("hll"), &elements(1000..20000), 0),
+            Ok(true)
+        );
+        assert_eq!(is_dense(&value(&db, "hll")), Ok(true));
+        let count = db.pfcount(b"hll", 0).unwrap();
+        assert!(count.abs_diff(20000) <= 400, "{count}");
+    }
+
+    #[test]
+    fn pfmerge_counts_the_union() {
+        let mut db = Db::default();
+        db.pfadd(Bytes::from("a"), &elements(0..3000), 0).unwrap();
+        db.pfadd(Bytes::from("b"), &elements(2000..5000), 0)
+            .unwrap();
+        let sources = [Bytes::from("a"), Bytes::from("b")];
+        assert_eq!(db.pfmerge(Bytes::from("union"), &sources, 0), Ok(()));
+
+        let count = db.pfcount(b"union", 0).unwrap();
+        assert!(count.abs_diff(5000) <= 100, "{count}");
+        let (registers, _) = db.pfunion(&sources, 0).unwrap();
+        assert_eq!(estimate(&registers), count);
+
+        db.insert(Bytes::from("string"), Bytes::from("value"), None);
+        let sources = [Bytes::from("string")];
+        let merged = db.pfmerge(Bytes::from("union"), &sources, 0);
+        assert_eq!(merged, Err(Error::NotHyperLogLog));
+    }
+}
@@ -0,0 +1,518 @@
+use bytes::Bytes;
+
+use super::{now_ms, with_entry, write, Db, Entry, Error, Value};
+use crate::commands::SetCondition;
+use crate::json::path::{Location, Path};
+use crate::json::{Format, Json, MAX_DEPTH};
+
+impl Db {
+    fn json_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut Json>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::Json(json)) => Ok(Some(json)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`Db::json_mut`], for commands that can't create the document.
+    fn existing_json_mut(&mut self, key: &[u8], now: u64) -> Result<&mut Json, Error> {
+        self.json_mut(key, now)?.ok_or(Error::JsonNoKey)
+    }
+
+    /// Sets the values `path` selects to `value`, or creates the key the
+    /// path ends with in each object its parent selects. Returns whether
+    /// anything was set.
+    fn json_set(
+        &mut self,
+        key: Bytes,
+        path: &Path,
+        value: Json,
+        condition: Option<SetCondition>,
+        now: u64,
+    ) -> Result<bool, Error> {
+        let Some(document) = self.json_mut(&key, now)? else {
+            if !path.is_root() {
+                return Err(Error::JsonNewAtRoot);
+            }
+            if condition == Some(SetCondition::IfPresent) {
+                return Ok(false);
+            }
+            self.insert_entry(key, Entry::new(Value::Json(value), None, now));
+            return Ok(true);
+        };
+
+        let matches = locations(path, document);
+        if !matches.is_empty() {
+            if condition == Some(SetCondition::IfAbsent) {
+                return Ok(false);
+            }
+            check_depth(&matches, value.depth())?;
+            // Earlier matches may contain later ones, which are then gone.
+            for location in matches {
+                if let Some(target) = document.pointer_mut(&location) {
+                    *target = value.clone();
+                }
+            }
+            return Ok(true);
+        }
+
+        if condition == Some(SetCondition::IfPresent) {
+            return Ok(false);
+        }
+        let Some((parent, name)) = path.parent_and_key() else {
+            return Ok(false);
+        };
+        let parents: Vec<Location> = locations(&parent, document)
+            .into_iter()
+            .filter(|location| matches!(document.pointer_mut(location), Some(Json::Object(_))))
+            .collect();
+        check_depth(&parents, value.depth() + 1)?;
+        for location in &parents {
+            if let Some(Json::Object(object)) = document.pointer_mut(location) {
+                object.push((name.to_string(), value.clone()));
+            }
+        }
+        Ok(!parents.is_empty())
+    }
+
+    /// Deletes the values `path` selects, or the whole key for the root.
+    /// Returns how many values were deleted.
+    fn json_del(&mut self, key: &[u8], path: &Path, now: u64) -> Result<usize, Error> {
+        let Some(document) = self.json_mut(key, now)? else {
+            return Ok(0);
+        };
+        if path.is_root() {
+            self.remove(key);
+            return Ok(1);
+        }
+
+        // Drop matches inside other matches, then delete from the back so
+        // that array indexes stay valid.
+        let mut locations = locations(path, document);
+        locations.sort();
+        let mut outermost: Vec<Location> = Vec::new();
+        for location in locations {
+            if !outermost.iter().any(|outer| location.starts_with(outer)) {
+                outermost.push(location);
+            }
+        }
+        for location in outermost.iter().rev() {
+            document.remove(location);
+        }
+        Ok(outermost.len())
+    }
+
+    /// Adds `increment` to the numbers `path` selects. Returns the new
+    /// values, with `None` for matches that aren't numbers.
+    fn json_numincrby(
+        &mut self,
+        key: &[u8],
+        path: &Path,
+        increment: &Json,
+        now: u64,
+    ) -> Result<Vec<Option<Json>>, Error> {
+        let document = self.existing_json_mut(key, now)?;
+        check_legacy(document, path, "a number", |value| {
+            matches!(value, Json::Integer(_) | Json::Float(_))
+        })?;
+        let locations = locations(path, document);
+        let mut results = Vec::with_capacity(locations.len());
+        for location in &locations {
+            let value = document
+                .pointer_mut(location)
+                .expect("location was just found");
+            let result = match (&*value, increment) {
+                (Json::Integer(a), Json::Integer(b)) => match a.checked_add(*b) {
+                    Some(sum) => Json::Integer(sum),
+                    None => Json::Float(*a as f64 + *b as f64),
+                },
+                (Json::Integer(_) | Json::Float(_), _) => {
+                    let sum = number(value) + number(increment);
+                    if !sum.is_finite() {
+                        return Err(Error::JsonNotFinite);
+                    }
+                    Json::Float(sum)
+                }
+                _ => {
+                    results.push(None);
+                    continue;
+                }
+            };
+            *value = result.clone();
+            results.push(Some(result));
+        }
+        Ok(results)
+    }
+
+    /// Appends `values` to the arrays `path` selects. Returns their new
+    /// lengths, with `None` for matches that aren't arrays.
+    fn json_arrappend(
+        &mut self,
+        key: &[u8],
+        path: &Path,
+        values: &[Json],
+        now: u64,
+    ) -> Result<Vec<Option<usize>>, Error> {
+        let document = self.existing_json_mut(key, now)?;
+        check_legacy(document, path, "an array", |value| {
+            matches!(value, Json::Array(_))
+        })?;
+        let locations = locations(path, document);
+        let depth = values.iter().map(Json::depth).max().unwrap_or(0);
+        check_depth(&locations, depth + 1)?;
+        let lengths = locations
+            .iter()
+            .map(|location| match document.pointer_mut(location) {
+                Some(Json::Array(array)) => {
+                    array.extend_from_slice(values);
+                    Some(array.len())
+                }
+                _ => None,
+            })
+            .collect();
+        Ok(lengths)
+    }
+}
+
+fn locations(path: &Path, document: &Json) -> Vec<Location> {
+    let matches = path.select(document);
+    matches.into_iter().map(|(location, _)| location).collect()
+}
+
+/// Fails if putting values `depth` deep at any of `locations` would nest the
+/// document deeper than parsing it allows. Serializing and dropping recurse,
+/// so documents must stay shallow enough for the stack.
+fn check_depth(locations: &[Location], depth: usize) -> Result<(), Error> {
+    match locations
+        .iter()
+        .any(|location| location.len() + depth > MAX_DEPTH)
+    {
+        true => Err(Error::JsonTooDeep),
+        false => Ok(()),
+    }
+}
+
+/// Legacy paths must select something, and only values of the `expected`
+/// type, which is checked up front so that nothing changes on error.
+fn check_legacy(
+    document: &Json,
+    path: &Path,
+    expected: &'static str,
+    is_expected: fn(&Json) -> bool,
+) -> Result<(), Error> {
+    if !path.legacy {
+        return Ok(());
+    }
+    let matches = path.select(document);
+    if matches.is_empty() {
+        return Err(Error::JsonPathMissing(path.text.clone()));
+    }
+    match matches.into_iter().find(|(_, value)| !is_expected(value)) {
+        Some((_, value)) => Err(Error::JsonWrongType(expected, value.type_name())),
+        None => Ok(()),
+    }
+}
+
+fn number(value: &Json) -> f64 {
+    match value {
+        Json::Integer(value) => *value as f64,
+        Json::Float(value) => *value,
+        _ => 0.0,
+    }
+}
+
+/// Runs `f` on the document under `key`, marking it accessed.
+fn with_json<T>(key: &[u8], f: impl FnOnce(&Json) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::Json(json) => Ok(f(json)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+/// What a path selects for JSON.GET: every match for a JSONPath, or the
+/// first one for a legacy path.
+fn get_path(document: &Json, path: &Path) -> Result<Json, Error> {
+    let matches = path.select(document);
+    if !path.legacy {
+        return Ok(Json::Array(
+            matches
+                .into_iter()
+                .map(|(_, value)| value.clone())
+                .collect(),
+        ));
+    }
+    match matches.first() {
+        Some((_, value)) => Ok((*value).clone()),
+        None => Err(Error::JsonPathMissing(path.text.clone())),
+    }
+}
+
+/// Serializes what `paths` select in the document under `key`. Several
+/// paths give an object keyed by path, with every match for each path if
+/// any of them is a JSONPath.
+pub async fn get(key: &[u8], paths: &[Path], format: &Format) -> Result<Option<String>, Error> {
+    with_json(key, |document| {
+        let value = match paths {
+            [] => document.clone(),
+            [path] => get_path(document, path)?,
+            paths => {
+                let legacy = paths.iter().all(|path| path.legacy);
+                let mut object = Vec::with_capacity(paths.len());
+                for path in paths {
+                    let value = match legacy {
+                        true => get_path(document, path)?,
+                        false => Json::Array(
+                            path.select(document)
+                                .into_iter()
+                                .map(|(_, value)| value.clone())
+                                .collect(),
+                        ),
+                    };
+                    object.push((path.text.clone(), value));
+                }
+                Json::Object(object)
+            }
+        };
+        Ok(value.serialize(format))
+    })?
+    .transpose()
+}
+
+/// Sets `value` at `path` under `key`, returning whether it was set.
+pub async fn set(
+    key: Bytes,
+    path: &Path,
+    value: Json,
+    condition: Option<SetCondition>,
+) -> Result<bool, Error> {
+    write().json_set(key, path, value, condition, now_ms())
+}
+
+/// Deletes the values `path` selects under `key`, returning how many were
+/// deleted.
+pub async fn del(key: &[u8], path: &Path) -> Result<usize, Error> {
+    write().json_del(key, path, now_ms())
+}
+
+/// Adds `increment` to the numbers `path` selects under `key`.
+pub async fn numincrby(
+    key: &[u8],
+    path: &Path,
+    increment: &Json,
+) -> Result<Vec<Option<Json>>, Error> {
+    write().json_numincrby(key, path, increment, now_ms())
+}
+
+/// Appends `values` to the arrays `path` selects under `key`.
+pub async fn arrappend(
+    key: &[u8],
+    path: &Path,
+    values: &[Json],
+) -> Result<Vec<Option<usize>>, Error> {
+    write().json_arrappend(key, path, values, now_ms())
+}
+
+/// Type names of the values `path` selects under `key`, only the first for
+/// a legacy path. `None` if the key doesn't exist.
+pub async fn types(key: &[u8], path: &Path) -> Result<Option<Vec<&'static str>>, Error> {
+    with_json(key, |document| {
+        let matches = path.select(document);
+        let take = if path.legacy { 1 } else { matches.len() };
+        matches
+            .into_iter()
+            .take(take)
+            .map(|(_, value)| value.type_name())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(text: &str) -> Json {
+        crate::json::parse(text.as_bytes()).unwrap()
+    }
+
+    fn path(text: &str) -> Path {
+        Path::parse(text).unwrap()
+    }
+
+    fn document(db: &mut Db, key: &str) -> String {
+        let document = db.json_mut(key.as_bytes(), 0).unwrap().unwrap();
+        document.serialize(&Format::default())
+    }
+
+    #[test]
+    fn json_set_creates_and_replaces_values() {
+        let mut db = Db::default();
+        let key = Bytes::from("doc");
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.a"), json("1"), None, 0),
+            Err(Error::JsonNewAtRoot)
+        );
+        let condition = Some(SetCondition::IfPresent);
+        assert_eq!(
+            db.json_set(key.clone(), &path("$"), json("1"), condition, 0),
+            Ok(false)
+        );
+        let value = json(r#"{"a":{"b":1},"c":[{"b":2},3]}"#);
+        assert_eq!(
+            db.json_set(key.clone(), &path("$"), value, None, 0),
+            Ok(true)
+        );
+
+        assert_eq!(
+            db.json_set(key.clone(), &path("$..b"), json("true"), None, 0),
+            Ok(true)
+        );
+        assert_eq!(
+            document(&mut db, "doc"),
+            r#"{"a":{"b":true},"c":[{"b":true},3]}"#
+        );
+        assert_eq!(
+            db.json_set(key.clone(), &path(".a.d"), json("[]"), None, 0),
+            Ok(true)
+        );
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.c[*].e"), json("0"), None, 0),
+            Ok(true)
+        );
+        assert_eq!(
+            document(&mut db, "doc"),
+            r#"{"a":{"b":true,"d":[]},"c":[{"b":true,"e":0},3]}"#
+        );
+
+        let condition = Some(SetCondition::IfAbsent);
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.a"), json("0"), condition, 0),
+            Ok(false)
+        );
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.x.y"), json("0"), None, 0),
+            Ok(false)
+        );
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.c[5]"), json("0"), None, 0),
+            Ok(false)
+        );
+
+        db.insert(Bytes::from("string"), Bytes::from("x"), None);
+        assert_eq!(
+            db.json_set(Bytes::from("string"), &path("$"), json("0"), None, 0),
+            Err(Error::WrongType)
+        );
+    }
+
+    #[test]
+    fn json_writes_keep_documents_within_the_depth_limit() {
+        let mut db = Db::default();
+        let key = Bytes::from("deep");
+        let nested = |depth: usize| json(&format!("{}{}", "[".repeat(depth), "]".repeat(depth)));
+        db.json_set(key.clone(), &path("$"), nested(MAX_DEPTH), None, 0)
+            .unwrap();
+        let inner = format!("${}", "[0]".repeat(MAX_DEPTH - 2));
+        assert_eq!(
+            db.json_set(key.clone(), &path(&inner), nested(2), None, 0),
+            Ok(true)
+        );
+        assert_eq!(
+            db.json_set(key.clone(), &path(&inner), nested(3), None, 0),
+            Err(Error::JsonTooDeep)
+        );
+        assert_eq!(
+            db.json_arrappend(b"deep", &path(&inner), &[json("1"), nested(2)], 0),
+            Err(Error::JsonTooDeep)
+        );
+        assert_eq!(
+            db.json_arrappend(b"deep", &path(&inner), &[json("1")], 0),
+            Ok(vec![Some(2)])
+        );
+
+        db.json_set(key.clone(), &path("$"), json(r#"{"a":{}}"#), None, 0)
+            .unwrap();
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.a.b"), nested(MAX_DEPTH - 1), None, 0),
+            Err(Error::JsonTooDeep)
+        );
+        assert_eq!(
+            db.json_set(key.clone(), &path("$.a.b"), nested(MAX_DEPTH - 2), None, 0),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn json_del_removes_outermost_matches() {
+        let mut db = Db::default();
+        let value = json(r#"{"a":{"a":1,"b":[1,2,3]},"b":[4,5,6]}"#);
+        db.json_set(Bytes::from("doc"), &path("$"), value, None, 0)
+            .unwrap();
+
+        assert_eq!(db.json_del(b"doc", &path("$..b[0,2]"), 0), Ok(4));
+        assert_eq!(document(&mut db, "doc"), r#"{"a":{"a":1,"b":[2]},"b":[5]}"#);
+        assert_eq!(db.json_del(b"doc", &path("$..a"), 0), Ok(1));
+        assert_eq!(document(&mut db, "doc"), r#"{"b":[5]}"#);
+        assert_eq!(db.json_del(b"doc", &path("$.missing"), 0), Ok(0));
+        assert_eq!(db.json_del(b"doc", &path("."), 0), Ok(1));
+        assert_eq!(db.json_del(b"doc", &path("."), 0), Ok(0));
+    }
+
+    #[test]
+    fn json_numincrby_and_arrappend_update_matches() {
+        let mut db = Db::default();
+        let value = json(r#"{"a":1,"b":{"a":2.5},"c":{"a":"x"},"d":[1],"e":{"d":{}}}"#);
+        db.json_set(Bytes::from("doc"), &path("$"), value, None, 0)
+            .unwrap();
+
+        assert_eq!(
+            db.json_numincrby(b"doc", &path("$..a"), &json("2"), 0),
+            Ok(vec![Some(json("3")), Some(json("4.5")), None])
+        );
+        assert_eq!(
+            db.json_numincrby(b"doc", &path(".c.a"), &json("2"), 0),
+            Err(Error::JsonWrongType("a number", "string"))
+        );
+        assert_eq!(
+            db.json_numincrby(b"doc", &path(".x"), &json("2"), 0),
+            Err(Error::JsonPathMissing(".x".to_string()))
+        );
+        assert_eq!(
+            db.json_numincrby(b"missing", &path("$"), &json("2"), 0),
+            Err(Error::JsonNoKey)
+        );
+        db.json_set(
+            Bytes::from("doc"),
+            &path("$.a"),
+            json("9223372036854775807"),
+            None,
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            db.json_numincrby(b"doc", &path("a"), &json("1"), 0),
+            Ok(vec![Some(json("9223372036854775808.0"))])
+        );
+
+        let values = [json("2"), json("\"x\"")];
+        assert_eq!(
+            db.json_arrappend(b"doc", &path("$..d"), &values, 0),
+            Ok(vec![Some(3), None])
+        );
+        assert_eq!(
+            db.json_arrappend(b"doc", &path(".e.d"), &values, 0),
+            Err(Error::JsonWrongType("an array", "object"))
+        );
+        assert_eq!(
+            db.json_arrappend(b"doc", &path(".d"), &values, 0),
+            Ok(vec![Some(5)])
+        );
+        assert_eq!(
+            document(&mut db, "doc"),
+            r#"{"a":9.223372036854776e18,"b":{"a":4.5},"c":{"a":"x"},"d":[1,2,"x",2,"x"],"e":{"d":{}}}"#
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::{now_ms, with_entry, write, Db, Entry, Error, Value};
+use crate::commands::{End, InsertPosition, LPosOptions};
+
+/// Resolves a possibly negative `index` into a list of `len` elements.
+fn resolve(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Resolves the inclusive range `start..=stop` the way LRANGE and LTRIM do:
+/// negative indexes count from the tail and out of range ends are clamped.
+pub(super) fn resolve_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
+
+fn pop_end(list: &mut VecDeque<Bytes>, end: End) -> Option<Bytes> {
+    match end {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+}
+
+fn push_end(list: &mut VecDeque<Bytes>, element: Bytes, end: End) {
+    match end {
+        End::Left => list.push_front(element),
+        End::Right => list.push_back(element),
+    }
+}
+
+impl Db {
+    fn list_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut VecDeque<Bytes>>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::List(list)) => Ok(Some(list)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the list under `key`, creating an empty one if the key is missing.
+    fn list_or_default(&mut self, key: Bytes, now: u64) -> Result<&mut VecDeque<Bytes>, Error> {
+        if self.list_mut(&key, now)?.is_none() {
+            let entry = Entry::new(Value::List(VecDeque::new()), None, now);
+            self.insert_entry(key.clone(), entry);
+        }
+        Ok(self.list_mut(&key, now)?.expect("list was just created"))
+    }
+
+    /// Pushes `elements` one after the other, so LPUSH reverses their order.
+    /// Returns the length of the list, or 0 if `only_existing` is set and
+    /// there is no list to push to.
+    fn push(
+        &mut self,
+        key: Bytes,
+        elements: Vec<Bytes>,
+        end: End,
+        only_existing: bool,
+        now: u64,
+    ) -> Result<usize, Error> {
+        if only_existing && self.list_mut(&key, now)?.is_none() {
+            return Ok(0);
+        }
+
+        let list = self.list_or_default(key, now)?;
+        for element in elements {
+            push_end(list, element, end);
+        }
+        Ok(list.len())
+    }
+
+    pub(super) fn pop(
+        &mut self,
+        key: &[u8],
+        end: End,
+        count: usize,
+        now: u64,
+    ) -> Result<Option<Vec<Bytes>>, Error> {
+        let Some(list) = self.list_mut(key, now)? else {
+            return Ok(None);
+        };
+
+        let popped = (0..count.min(list.len()))
+            .filter_map(|_| pop_end(list, end))
+            .collect();
+        self.remove_if_empty(key);
+        Ok(Some(popped))
+    }
+
+    fn lset(&mut self, key: &[u8], index: i64, element: Bytes, now: u64) -> Result<(), Error> {
+        let list = self.list_mut(key, now)?.ok_or(Error::NoSuchKey)?;
+        let index = resolve(index, list.len()).ok_or(Error::IndexOutOfRange)?;
+        list[index] = element;
+        Ok(())
+    }
+
+    /// Removes up to `count` occurrences of `element`, scanning from the tail
+    /// when `count` is negative. A count of 0 removes every occurrence.
+    fn lrem(&mut self, key: &[u8], count: i64, element: &[u8], now: u64) -> Result<usize, Error> {
+        let Some(list) = self.list_mut(key, now)? else {
+            return Ok(0);
+        };
+
+        let limit = match count {
+            0 => usize::MAX,
+            count => count.unsigned_abs() as usize,
+        };
+        let mut removed = 0;
+        if count < 0 {
+            list.make_contiguous().reverse();
+        }
+        list.retain(|item| {
+            let remove = removed < limit && item == element;
+            removed += remove as usize;
+            !remove
+        });
+        if count < 0 {
+            list.make_contiguous().reverse();
+        }
+
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    fn ltrim(&mut self, key: &[u8], start: i64, stop: i64, now: u64) -> Result<(), Error> {
+        let Some(list) = self.list_mut(key, now)? else {
+            return Ok(());
+        };
+
+        match resolve_range(start, stop, list.len()) {
+            Some((start, stop)) => {
+                list.truncate(stop + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+        self.remove_if_empty(key);
+        Ok(())
+    }
+
+    /// Inserts `element` next to the first occurrence of `pivot`. Returns the
+    /// new length, -1 if `pivot` was not found and 0 if the key is missing.
+    fn linsert(
+        &mut self,
+        key: &[u8],
+        position: InsertPosition,
+        pivot: &[u8],
+        element: Bytes,
+        now: u64,
+    ) -> Result<i64, Error> {
+        let Some(list) = self.list_mut(key, now)? else {
+            return Ok(0);
+        };
+        let Some(index) = list.iter().position(|item| item == pivot) else {
+            return Ok(-1);
+        };
+
+        let index = match position {
+            InsertPosition::Before => index,
+            InsertPosition::After => index + 1,
+        };
+        list.insert(index, element);
+        Ok(list.len() as i64)
+    }
+
+    /// Pops from `from` of `source` and pushes the element to `to` of
+    /// `destination`, which may be the same list.
+    pub(super) fn lmove(
+        &mut self,
+        source: &[u8],
+        destination: Bytes,
+        from: End,
+        to: End,
+        now: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        // Check the destination first so a type error leaves the source alone.
+        if let Some(entry) = self.get(&destination, now) {
+            if !matches!(entry.value, Value::List(_)) {
+                return Err(Error::WrongType);
+            }
+        }
+        let Some(element) = self
+            .list_mut(source, now)?
+            .and_then(|list| pop_end(list, from))
+        else {
+            return Ok(None);
+        };
+
+        // Push before dropping an emptied source, so that rotating a single
+        // element list keeps the key and its TTL.
+        push_end(self.list_or_default(destination, now)?, element.clone(), to);
+        self.remove_if_empty(source);
+        Ok(Some(element))
+    }
+}
+
+/// Positions of `element` in `list` as selected by LPOS options.
+fn positions(list: &VecDeque<Bytes>, element: &[u8], options: &LPosOptions) -> Vec<usize> {
+    let limit = match options.maxlen {
+        0 => list.len(),
+        maxlen => maxlen,
+    };
+    let wanted = match options.count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1,
+    };
+    let indexes: Box<dyn Iterator<Item = usize>> = if options.rank > 0 {
+        Box::new(0..list.len())
+    } else {
+        Box::new((0..list.len()).rev())
+    };
+
+    indexes
+        .take(limit)
+        .filter(|&index| list[index] == element)
+        .skip(options.rank.unsigned_abs() as usize - 1)
+        .take(wanted)
+        .collect()
+}
+
+/// Runs `f` on the list under `key`, marking it accessed.
+fn with_list<T>(key: &[u8], f: impl FnOnce(&VecDeque<Bytes>) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::List(list) => Ok(f(list)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+pub async fn push(
+    key: Bytes,
+    elements: Vec<Bytes>,
+    end: End,
+    only_existing: bool,
+) -> Result<usize, Error> {
+    write().push(key, elements, end, only_existing, now_ms())
+}
+
+/// Pops up to `count` elements, or `None` if the key is missing.
+pub async fn pop(key: &[u8], end: End, count: usize) -> Result<Option<Vec<Bytes>>, Error> {
+    write().pop(key, end, count, now_ms())
+}
+
+/// Pops up to `count` elements from the first of `keys` holding a list.
+pub async fn mpop(
+    keys: &[Bytes],
+    end: End,
+    count: usize,
+) -> Result<Option<(Bytes, Vec<Bytes>)>, Error> {
+    let mut db = write();
+    let now = now_ms();
+    for key in keys {
+        if let Some(popped) = db.pop(key, end, count, now)? {
+            return Ok(Some((key.clone(), popped)));
+        }
+    }
+    Ok(None)
+}
+
+pub async fn len(key: &[u8]) -> Result<usize, Error> {
+    Ok(with_list(key, |list| list.len())?.unwrap_or(0))
+}
+
+pub async fn range(key: &[u8], start: i64, stop: i64) -> Result<Vec<Bytes>, Error> {
+    let range = with_list(key, |list| match resolve_range(start, stop, list.len()) {
+        Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+        None => Vec::new(),
+    })?;
+    Ok(range.unwrap_or_default())
+}
+
+pub async fn index(key: &[u8], index: i64) -> Result<Option<Bytes>, Error> {
+    let element = with_list(key, |list| {
+        resolve(index, list.len()).map(|index| list[index].clone())
+    })?;
+    Ok(element.flatten())
+}
+
+pub async fn position(
+    key: &[u8],
+    element: &[u8],
+    options: &LPosOptions,
+) -> Result<Vec<usize>, Error> {
+    Ok(with_list(key, |list| positions(list, element, options))?.unwrap_or_default())
+}
+
+pub async fn set(key: &[u8], index: i64, element: Bytes) -> Result<(), Error> {
+    write().lset(key, index, element, now_ms())
+}
+
+pub async fn remove(key: &[u8], count: i64, element: &[u8]) -> Result<usize, Error> {
+    write().lrem(key, count, element, now_ms())
+}
+
+pub async fn trim(key: &[u8], start: i64, stop: i64) -> Result<(), Error> {
+    write().ltrim(key, start, stop, now_ms())
+}
+
+pub async fn insert(
+    key: &[u8],
+    position: InsertPosition,
+    pivot: &[u8],
+    element: Bytes,
+) -> Result<i64, Error> {
+    write().linsert(key, position, pivot, element, now_ms())
+}
+
+pub async fn lmove(
+    source: &[u8],
+    destination: Bytes,
+    from: End,
+    to: End,
+) -> Result<Option<Bytes>, Error> {
+    write().lmove(source, destination, from, to, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list<'a>(db: &'a Db, key: &str) -> Vec<&'a str> {
+        match db.get(key.as_bytes(), 0).map(|entry| &entry.value) {
+            Some(Value::List(list)) => list
+                .iter()
+                .map(|item| std::str::from_utf8(item).unwrap())
+                .collect(),
+            Some(value) => panic!("Expected a list, got {:?}", value),
+            None => Vec::new(),
+        }
+    }
+
+    fn elements(items: &[&str]) -> Vec<Bytes> {
+        items
+            .iter()
+            .map(|item| Bytes::from(item.to_string()))
+            .collect()
+    }
+
+    fn db_with(items: &[&str]) -> Db {
+        let mut db = Db::default();
+        db.push(Bytes::from("list"), elements(items), End::Right, false, 0)
+            .unwrap();
+        db
+    }
+
+    #[test]
+    fn resolve_negative_indexes() {
+        assert_eq!(resolve(0, 3), Some(0));
+        assert_eq!(resolve(-1, 3), Some(2));
+        assert_eq!(resolve(-3, 3), Some(0));
+        assert_eq!(resolve(-4, 3), None);
+        assert_eq!(resolve(3, 3), None);
+
+        assert_eq!(resolve_range(0, -1, 3), Some((0, 2)));
+        assert_eq!(resolve_range(-100, 100, 3), Some((0, 2)));
+        assert_eq!(resolve_range(1, 0, 3), None);
+        assert_eq!(resolve_range(5, 10, 3), None);
+        assert_eq!(resolve_range(0, -1, 0), None);
+        assert_eq!(resolve_range(i64::MIN, i64::MAX, 3), Some((0, 2)));
+    }
+
+    #[test]
+    fn push_to_both_ends() {
+        let mut db = Db::default();
+        let key = Bytes::from("list");
+        let len = db.push(key.clone(), elements(&["a", "b"]), End::Left, false, 0);
+        assert_eq!(len, Ok(2));
+        let len = db.push(key, elements(&["c"]), End::Right, false, 0);
+        assert_eq!(len, Ok(3));
+        assert_eq!(list(&db, "list"), ["b", "a", "c"]);
+
+        let len = db.push(Bytes::from("other"), elements(&["a"]), End::Left, true, 0);
+        assert_eq!(len, Ok(0));
+        assert!(db.get(b"other", 0).is_none());
+    }
+
+    #[test]
+    fn push_to_wrong_type() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("string"), Bytes::from("value"), None);
+        let len = db.push(Bytes::from("string"), elements(&["a"]), End::Left, false, 0);
+        assert_eq!(len, Err(Error::WrongType));
+    }
+
+    #[test]
+    fn pop_with_count_deletes_emptied_list() {
+        let mut db = db_with(&["a", "b", "c"]);
+        assert_eq!(db.pop(b"list", End::Left, 1, 0), Ok(Some(elements(&["a"]))));
+        assert_eq!(db.pop(b"list", End::Right, 0, 0), Ok(Some(Vec::new())));
+        assert_eq!(
+            db.pop(b"list", End::Right, 10, 0),
+            Ok(Some(elements(&["c", "b"])))
+        );
+        assert!(db.get(b"list", 0).is_none());
+        assert_eq!(db.pop(b"list", End::Left, 1, 0), Ok(None));
+    }
+
+    #[test]
+    fn lset_checks_index() {
+        let mut db = db_with(&["a", "b"]);
+        db.lset(b"list", -1, Bytes::from("z"), 0).unwrap();
+        assert_eq!(list(&db, "list"), ["a", "z"]);
+        assert_eq!(
+            db.lset(b"list", 2, Bytes::from("z"), 0),
+            Err(Error::IndexOutOfRange)
+        );
+        assert_eq!(
+            db.lset(b"missing", 0, Bytes::from("z"), 0),
+            Err(Error::NoSuchKey)
+        );
+    }
+
+    #[test]
+    fn lrem_from_either_end() {
+        let mut db = db_with(&["a", "b", "a", "c", "a"]);
+        assert_eq!(db.lrem(b"list", -2, b"a", 0), Ok(2));
+        assert_eq!(list(&db, "list"), ["a", "b", "c"]);
+
+        let mut db = db_with(&["a", "b", "a", "c", "a"]);
+        assert_eq!(db.lrem(b"list", 1, b"a", 0), Ok(1));
+        assert_eq!(list(&db, "list"), ["b", "a", "c", "a"]);
+        assert_eq!(db.lrem(b"list", 0, b"a", 0), Ok(2));
+        assert_eq!(list(&db, "list"), ["b", "c"]);
+    }
+
+    #[test]
+    fn ltrim_keeps_range() {
+        let mut db = db_with(&["a", "b", "c", "d"]);
+        db.ltrim(b"list", 1, -2, 0).unwrap();
+        assert_eq!(list(&db, "list"), ["b", "c"]);
+        db.ltrim(b"list", 5, 10, 0).unwrap();
+        assert!(db.get(b"list", 0).is_none());
+    }
+
+    #[test]
+    fn linsert_around_pivot() {
+        let mut db = db_with(&["a", "c"]);
+        let len = db.linsert(b"list", InsertPosition::Before, b"c", Bytes::from("b"), 0);
+        assert_eq!(len, Ok(3));
+        let len = db.linsert(b"list", InsertPosition::After, b"c", Bytes::from("d"), 0);
+        assert_eq!(len, Ok(4));
+        assert_eq!(list(&db, "list"), ["a", "b", "c", "d"]);
+
+        let len = db.linsert(b"list", InsertPosition::After, b"x", Bytes::from("y"), 0);
+        assert_eq!(len, Ok(-1));
+        let len = db.linsert(b"missing", InsertPosition::After, b"x", Bytes::from("y"), 0);
+        assert_eq!(len, Ok(0));
+    }
+
+    #[test]
+    fn lmove_between_and_within_lists() {
+        let mut db = db_with(&["a", "b", "c"]);
+        let moved = db.lmove(b"list", Bytes::from("other"), End::Left, End::Right, 0);
+        assert_eq!(moved, Ok(Some(Bytes::from("a"))));
+        assert_eq!(list(&db, "other"), ["a"]);
+
+        let moved = db.lmove(b"list", Bytes::from("list"), End::Right, End::Left, 0);
+        assert_eq!(moved, Ok(Some(Bytes::from("c"))));
+        assert_eq!(list(&db, "list"), ["c", "b"]);
+
+        let moved = db.lmove(b"other", Bytes::from("other"), End::Left, End::Right, 0);
+        assert_eq!(moved, Ok(Some(Bytes::from("a"))));
+        assert_eq!(list(&db, "other"), ["a"]);
+
+        db.insert(Bytes::from("string"), Bytes::from("value"), None);
+        let moved = db.lmove(b"list", Bytes::from("string"), End::Left, End::Right, 0);
+        assert_eq!(moved, Err(Error::WrongType));
+        assert_eq!(list(&db, "list"), ["c", "b"]);
+    }
+
+    #[test]
+    fn positions_with_rank_count_and_maxlen() {
+        let list = VecDeque::from(elements(&["a", "b", "a", "c", "a"]));
+        let options = |rank, count, maxlen| LPosOptions {
+            rank,
+            count,
+            maxlen,
+        };
+        assert_eq!(positions(&list, b"a", &options(1, None, 0)), [0]);
+        assert_eq!(positions(&list, b"a", &options(2, None, 0)), [2]);
+        assert_eq!(positions(&list, b"a", &options(-1, None, 0)), [4]);
+        assert_eq!(positions(&list, b"a", &options(1, Some(0), 0)), [0, 2, 4]);
+        assert_eq!(positions(&list, b"a", &options(-2, Some(0), 0)), [2, 0]);
+        assert_eq!(positions(&list, b"a", &options(1, Some(0), 3)), [0, 2]);
+        assert!(positions(&list, b"x", &options(1, None, 0)).is_empty());
+    }
+}
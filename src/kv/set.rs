@@ -0,0 +1,522 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use bytes::Bytes;
+
+use super::{
+    now_ms, random_index, read, scan_hash, scan_page, with_entry, write, Db, Entry, Error, Value,
+};
+use crate::commands::SetOp;
+use crate::glob;
+
+/// The members of a set, kept in a vector so SPOP and SRANDMEMBER can pick
+/// them at random in constant time, along with where each one is.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Set {
+    members: Vec<Bytes>,
+    positions: HashMap<Bytes, usize>,
+}
+
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|member| other.contains(member))
+    }
+}
+
+impl Eq for Set {}
+
+impl Set {
+    pub(super) fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub(super) fn contains(&self, member: &[u8]) -> bool {
+        self.positions.contains_key(member)
+    }
+
+    pub(super) fn iter(&self) -> std::slice::Iter<'_, Bytes> {
+        self.members.iter()
+    }
+
+    /// Adds `member`, returning whether it was new.
+    fn insert(&mut self, member: Bytes) -> bool {
+        if self.contains(&member) {
+            return false;
+        }
+        self.positions.insert(member.clone(), self.members.len());
+        self.members.push(member);
+        true
+    }
+
+    /// Removes `member`, returning whether it was there.
+    fn remove(&mut self, member: &[u8]) -> bool {
+        match self.positions.remove(member) {
+            Some(position) => {
+                self.take(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes the member at `position` out of the vector, moving the last one
+    /// into its place. The caller forgets its position.
+    fn take(&mut self, position: usize) -> Bytes {
+        let member = self.members.swap_remove(position);
+        if let Some(moved) = self.members.get(position) {
+            self.positions.insert(moved.clone(), position);
+        }
+        member
+    }
+
+    fn pop_random(&mut self) -> Option<Bytes> {
+        if self.is_empty() {
+            return None;
+        }
+        let member = self.take(random_index(self.len()));
+        self.positions.remove(&member);
+        Some(member)
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&Bytes) -> bool) {
+        let members = std::mem::take(&mut self.members);
+        self.positions.clear();
+        self.extend(members.into_iter().filter(|member| keep(member)));
+    }
+
+    /// Picks members at random: a positive `count` returns up to that many
+    /// distinct members, a negative one returns exactly `-count` members that
+    /// may repeat.
+    fn sample(&self, count: i64) -> Vec<Bytes> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        if count < 0 {
+            return (0..count.unsigned_abs())
+                .map(|_| self.members[random_index(self.len())].clone())
+                .collect();
+        }
+
+        let count = count as usize;
+        if count >= self.len() {
+            return self.members.clone();
+        }
+        // Floyd's algorithm picks `count` distinct positions with as many
+        // draws, whatever the size of the set.
+        let mut picked = HashSet::with_capacity(count);
+        for bound in self.len() - count..self.len() {
+            let position = random_index(bound + 1);
+            if !picked.insert(position) {
+                picked.insert(bound);
+            }
+        }
+        picked
+            .into_iter()
+            .map(|position| self.members[position].clone())
+            .collect()
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(members: I) -> Self {
+        let mut set = Set::default();
+        set.extend(members);
+        set
+    }
+}
+
+impl Extend<Bytes> for Set {
+    fn extend<I: IntoIterator<Item = Bytes>>(&mut self, members: I) {
+        for member in members {
+            self.insert(member);
+        }
+    }
+}
+
+impl IntoIterator for Set {
+    type Item = Bytes;
+    type IntoIter = std::vec::IntoIter<Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Set {
+    type Item = &'a Bytes;
+    type IntoIter = std::slice::Iter<'a, Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.iter()
+    }
+}
+
+impl Db {
+    /// Returns the set under `key` without marking it accessed.
+    fn set_ref(&self, key: &[u8], now: u64) -> Result<Option<&Set>, Error> {
+        match self.get(key, now).map(|entry| &entry.value) {
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn set_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut Set>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the set under `key`, creating an empty one if the key is missing.
+    fn set_or_default(&mut self, key: Bytes, now: u64) -> Result<&mut Set, Error> {
+        if self.set_mut(&key, now)?.is_none() {
+            let entry = Entry::new(Value::Set(Set::default()), None, now);
+            self.insert_entry(key.clone(), entry);
+        }
+        Ok(self.set_mut(&key, now)?.expect("set was just created"))
+    }
+
+    /// Adds `members`, returning how many of them were new.
+    fn sadd(&mut self, key: Bytes, members: Vec<Bytes>, now: u64) -> Result<usize, Error> {
+        let set = self.set_or_default(key, now)?;
+        Ok(members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count())
+    }
+
+    fn srem(&mut self, key: &[u8], members: &[Bytes], now: u64) -> Result<usize, Error> {
+        let Some(set) = self.set_mut(key, now)? else {
+            return Ok(0);
+        };
+
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Removes up to `count` random members, returning `None` if the key is
+    /// missing.
+    fn spop(&mut self, key: &[u8], count: usize, now: u64) -> Result<Option<Vec<Bytes>>, Error> {
+        let Some(set) = self.set_mut(key, now)? else {
+            return Ok(None);
+        };
+
+        let popped = match count >= set.len() {
+            true => std::mem::take(set).into_iter().collect(),
+            false => (0..count).filter_map(|_| set.pop_random()).collect(),
+        };
+        self.remove_if_empty(key);
+        Ok(Some(popped))
+    }
+
+    /// Moves `member` from the set under `source` to the one under
+    /// `destination`, returning whether it was in `source`.
+    fn smove(
+        &mut self,
+        source: &[u8],
+        destination: Bytes,
+        member: Bytes,
+        now: u64,
+    ) -> Result<bool, Error> {
+        self.set_ref(&destination, now)?;
+        let Some(set) = self.set_mut(source, now)? else {
+            return Ok(false);
+        };
+        if source == destination {
+            return Ok(set.contains(&member));
+        }
+        if !set.remove(&member) {
+            return Ok(false);
+        }
+
+        self.remove_if_empty(source);
+        self.set_or_default(destination, now)?.insert(member);
+        Ok(true)
+    }
+
+    /// Combines the sets under `keys`, missing keys counting as empty sets.
+    fn combine(&self, op: SetOp, keys: &[Bytes], now: u64) -> Result<Set, Error> {
+        let sets = keys
+            .iter()
+            .map(|key| self.set_ref(key, now))
+            .collect::<Result<Vec<_>, _>>()?;
+        let empty = Set::default();
+        let mut sets = sets.into_iter().map(|set| set.unwrap_or(&empty));
+        let Some(first) = sets.next() else {
+            return Ok(Set::default());
+        };
+
+        let mut result = first.clone();
+        for set in sets {
+            match op {
+                SetOp::Inter => result.retain(|member| set.contains(member)),
+                SetOp::Union => result.extend(set.iter().cloned()),
+                SetOp::Diff => result.retain(|member| !set.contains(member)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Stores the combination of `keys` under `destination`, replacing it, and
+    /// returns its size. An empty result deletes `destination`.
+    fn combine_store(
+        &mut self,
+        op: SetOp,
+        destination: Bytes,
+        keys: &[Bytes],
+        now: u64,
+    ) -> Result<usize, Error> {
+        let result = self.combine(op, keys, now)?;
+        let len = result.len();
+        if result.is_empty() {
+            self.remove(&destination);
+        } else {
+            self.insert_entry(destination, Entry::new(Value::Set(result), None, now));
+        }
+        Ok(len)
+    }
+}
+
+/// Runs `f` on the set under `key`, marking it accessed.
+fn with_set<T>(key: &[u8], f: impl FnOnce(&Set) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::Set(set) => Ok(f(set)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+pub async fn add(key: Bytes, members: Vec<Bytes>) -> Result<usize, Error> {
+    write().sadd(key, members, now_ms())
+}
+
+pub async fn remove(key: &[u8], members: &[Bytes]) -> Result<usize, Error> {
+    write().srem(key, members, now_ms())
+}
+
+pub async fn members(key: &[u8]) -> Result<Vec<Bytes>, Error> {
+    Ok(with_set(key, |set| set.iter().cloned().collect())?.unwrap_or_default())
+}
+
+pub async fn is_member(key: &[u8], member: &[u8]) -> Result<bool, Error> {
+    Ok(with_set(key, |set| set.contains(member))?.unwrap_or(false))
+}
+
+pub async fn are_members(key: &[u8], members: &[Bytes]) -> Result<Vec<bool>, Error> {
+    let found = with_set(key, |set| {
+        members.iter().map(|member| set.contains(member)).collect()
+    })?;
+    Ok(found.unwrap_or_else(|| vec![false; members.len()]))
+}
+
+pub async fn card(key: &[u8]) -> Result<usize, Error> {
+    Ok(with_set(key, |set| set.len())?.unwrap_or(0))
+}
+
+/// Removes up to `count` random members, returning `None` if the key is
+/// missing.
+pub async fn pop(key: &[u8], count: usize) -> Result<Option<Vec<Bytes>>, Error> {
+    write().spop(key, count, now_ms())
+}
+
+/// Picks members at random, see `Set::sample` for how `count` is
+/// interpreted.
+pub async fn random_members(key: &[u8], count: i64) -> Result<Vec<Bytes>, Error> {
+    Ok(with_set(key, |set| set.sample(count))?.unwrap_or_default())
+}
+
+pub async fn move_member(source: &[u8], destination: Bytes, member: Bytes) -> Result<bool, Error> {
+    write().smove(source, destination, member, now_ms())
+}
+
+/// SINTER, SUNION and SDIFF.
+pub async fn combine(op: SetOp, keys: &[Bytes]) -> Result<Vec<Bytes>, Error> {
+    let result = read().combine(op, keys, now_ms())?;
+    Ok(result.into_iter().collect())
+}
+
+/// SINTERSTORE, SUNIONSTORE and SDIFFSTORE.
+pub async fn combine_store(op: SetOp, destination: Bytes, keys: &[Bytes]) -> Result<usize, Error> {
+    write().combine_store(op, destination, keys, now_ms())
+}
+
+/// Size of the intersection of `keys`, counting no further than `limit`
+/// unless it is 0.
+pub async fn inter_card(keys: &[Bytes], limit: usize) -> Result<usize, Error> {
+    let len = read().combine(SetOp::Inter, keys, now_ms())?.len();
+    Ok(match limit {
+        0 => len,
+        limit => len.min(limit),
+    })
+}
+
+/// Returns a page of members, resuming from `cursor` the same way SCAN does
+/// over keys.
+pub async fn scan(
+    key: &[u8],
+    cursor: u64,
+    count: usize,
+    pattern: Option<&[u8]>,
+) -> Result<(u64, Vec<Bytes>), Error> {
+    let page = with_set(key, |set| {
+        let order: BTreeSet<(u64, Bytes)> = set
+            .iter()
+            .map(|member| (scan_hash(member), member.clone()))
+            .collect();
+        let (cursor, members) = scan_page(&order, cursor, count);
+        let members = members
+            .into_iter()
+            .filter(|member| match pattern {
+                Some(pattern) => glob::matches(pattern, member),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        (cursor, members)
+    })?;
+    Ok(page.unwrap_or((0, Vec::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(items: &[&str]) -> Vec<Bytes> {
+        items
+            .iter()
+            .map(|item| Bytes::from(item.to_string()))
+            .collect()
+    }
+
+    fn sorted(set: Set) -> Vec<Bytes> {
+        let mut members: Vec<Bytes> = set.into_iter().collect();
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn sadd_and_srem_count_changes() {
+        let mut db = Db::default();
+        let key = Bytes::from("set");
+        assert_eq!(db.sadd(key.clone(), members(&["a", "b", "a"]), 0), Ok(2));
+        assert_eq!(db.sadd(key.clone(), members(&["b", "c"]), 0), Ok(1));
+        assert_eq!(db.srem(b"set", &members(&["a", "x"]), 0), Ok(1));
+        assert_eq!(db.srem(b"set", &members(&["b", "c"]), 0), Ok(2));
+        assert!(db.get(b"set", 0).is_none());
+    }
+
+    #[test]
+    fn spop_removes_what_it_returns() {
+        let mut db = Db::default();
+        db.sadd(Bytes::from("set"), members(&["a", "b", "c"]), 0)
+            .unwrap();
+        let popped = db.spop(b"set", 2, 0).unwrap().unwrap();
+        assert_eq!(popped.len(), 2);
+        let left = db.set_ref(b"set", 0).unwrap().unwrap();
+        assert_eq!(left.len(), 1);
+        assert!(popped.iter().all(|member| !left.contains(member)));
+
+        assert_eq!(db.spop(b"set", 5, 0).unwrap().unwrap().len(), 1);
+        assert_eq!(db.spop(b"set", 1, 0), Ok(None));
+    }
+
+    #[test]
+    fn members_keep_their_positions() {
+        let mut set: Set = members(&["a", "b", "c", "d"]).into_iter().collect();
+        assert!(!set.insert(Bytes::from("a")));
+        assert!(set.remove(b"a"));
+        assert!(!set.remove(b"a"));
+        assert_eq!(
+            set.pop_random().map(|member| set.contains(&member)),
+            Some(false)
+        );
+        for (position, member) in set.iter().enumerate() {
+            assert_eq!(set.positions.get(member), Some(&position));
+        }
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.positions.len(), 2);
+    }
+
+    #[test]
+    fn sample_picks_distinct_members_unless_count_is_negative() {
+        let set: Set = members(&["a", "b", "c", "d", "e"]).into_iter().collect();
+        for count in 0..=6 {
+            let picked: HashSet<Bytes> = set.sample(count).into_iter().collect();
+            assert_eq!(picked.len(), (count as usize).min(set.len()));
+            assert!(picked.iter().all(|member| set.contains(member)));
+        }
+        let picked = set.sample(-20);
+        assert_eq!(picked.len(), 20);
+        assert!(picked.iter().all(|member| set.contains(member)));
+        assert!(Set::default().sample(-3).is_empty());
+    }
+
+    #[test]
+    fn combine_sets() {
+        let mut db = Db::default();
+        db.sadd(Bytes::from("a"), members(&["1", "2", "3"]), 0)
+            .unwrap();
+        db.sadd(Bytes::from("b"), members(&["2", "3", "4"]), 0)
+            .unwrap();
+        let keys = members(&["a", "b"]);
+
+        let inter = db.combine(SetOp::Inter, &keys, 0).unwrap();
+        assert_eq!(sorted(inter), members(&["2", "3"]));
+        let union = db.combine(SetOp::Union, &keys, 0).unwrap();
+        assert_eq!(sorted(union), members(&["1", "2", "3", "4"]));
+        let diff = db.combine(SetOp::Diff, &keys, 0).unwrap();
+        assert_eq!(sorted(diff), members(&["1"]));
+
+        let missing = members(&["a", "missing"]);
+        assert!(db.combine(SetOp::Inter, &missing, 0).unwrap().is_empty());
+        let diff = db.combine(SetOp::Diff, &missing, 0).unwrap();
+        assert_eq!(diff.len(), 3);
+    }
+
+    #[test]
+    fn combine_store_replaces_destination() {
+        let mut db = Db::default();
+        db.sadd(Bytes::from("a"), members(&["1", "2"]), 0).unwrap();
+        db.insert(Bytes::from("dest"), Bytes::from("value"), Some(100));
+
+        let keys = members(&["a", "a"]);
+        assert_eq!(
+            db.combine_store(SetOp::Inter, Bytes::from("dest"), &keys, 0),
+            Ok(2)
+        );
+        assert_eq!(db.get(b"dest", 0).unwrap().expires_at, None);
+
+        let keys = members(&["a", "missing"]);
+        assert_eq!(
+            db.combine_store(SetOp::Inter, Bytes::from("dest"), &keys, 0),
+            Ok(0)
+        );
+        assert!(db.get(b"dest", 0).is_none());
+    }
+
+    #[test]
+    fn smove_between_sets() {
+        let mut db = Db::default();
+        db.sadd(Bytes::from("a"), members(&["1"]), 0).unwrap();
+        let moved = db.smove(b"a", Bytes::from("b"), Bytes::from("1"), 0);
+        assert_eq!(moved, Ok(true));
+        assert!(db.get(b"a", 0).is_none());
+        let moved = db.smove(b"b", Bytes::from("b"), Bytes::from("1"), 0);
+        assert_eq!(moved, Ok(true));
+
+        db.insert(Bytes::from("string"), Bytes::from("value"), None);
+        let moved = db.smove(b"b", Bytes::from("string"), Bytes::from("1"), 0);
+        assert_eq!(moved, Err(Error::WrongType));
+        let moved = db.smove(b"b", Bytes::from("c"), Bytes::from("2"), 0);
+        assert_eq!(moved, Ok(false));
+    }
+}
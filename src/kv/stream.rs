@@ -0,0 +1,1382 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::blocking::{self, Op};
+use super::{now_ms, with_entry, write, Db, Entry, Error, Value};
+use crate::commands::{
+    Claim, ClaimOptions, DeliveryTime, PendingRange, ReadGroup, ReadId, StreamId, StreamTrim,
+    TrimStrategy, XAddId,
+};
+
+/// Entries Redis packs into a single node of a stream, the unit approximate
+/// trimming evicts.
+const NODE_MAX_ENTRIES: usize = 100;
+
+/// Field-value pairs of a stream entry, in the order they were given.
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// An append-only log of entries ordered by ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    /// ID of the last entry ever added, which new IDs must be greater than
+    /// even once that entry is deleted.
+    last_id: StreamId,
+    /// Entries ever added, which consumer groups count their reads against.
+    entries_added: u64,
+    /// Greatest ID removed with XDEL, leaving a gap groups can't count past.
+    max_deleted_id: StreamId,
+    groups: BTreeMap<Bytes, Group>,
+}
+
+/// An entry delivered to a consumer and not acknowledged yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pending {
+    consumer: Bytes,
+    delivered_at: u64,
+    deliveries: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Consumer {
+    /// When the consumer last read or claimed, successfully or not.
+    seen_at: u64,
+    /// When the consumer last got entries, if it ever did.
+    active_at: Option<u64>,
+    pending: BTreeSet<StreamId>,
+}
+
+/// A consumer group, which delivers each entry to one of its consumers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Group {
+    /// ID of the last entry delivered, which new reads continue after.
+    last_id: StreamId,
+    /// Entries of the stream the group read, if it can still tell.
+    entries_read: Option<u64>,
+    pending: BTreeMap<StreamId, Pending>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl Group {
+    /// The consumer named `name`, created if it doesn't exist, marked as
+    /// seen at `now`.
+    fn consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.clone()).or_default();
+        consumer.seen_at = now;
+        consumer
+    }
+
+    /// Makes entry `id` pending for `consumer` as delivered at
+    /// `delivered_at`, taking it from the consumer it was pending for if any.
+    fn claim(
+        &mut self,
+        id: StreamId,
+        consumer: &Bytes,
+        delivered_at: u64,
+        now: u64,
+    ) -> &mut Pending {
+        if let Some(pending) = self.pending.get(&id) {
+            if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        let owner = self.consumers.entry(consumer.clone()).or_default();
+        owner.pending.insert(id);
+        owner.active_at = Some(now);
+
+        let pending = self.pending.entry(id).or_insert(Pending {
+            consumer: consumer.clone(),
+            delivered_at,
+            deliveries: 1,
+        });
+        pending.consumer = consumer.clone();
+        pending.delivered_at = delivered_at;
+        pending
+    }
+
+    /// Removes entry `id` from the pending entries, returning whether it was
+    /// pending.
+    fn acknowledge(&mut self, id: StreamId) -> bool {
+        let Some(pending) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(owner) = self.consumers.get_mut(&pending.consumer) {
+            owner.pending.remove(&id);
+        }
+        true
+    }
+}
+
+impl Stream {
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Picks the ID of a new entry added at `now`.
+    fn next_id(&self, id: XAddId, now: u64) -> Result<StreamId, Error> {
+        let last = self.last_id;
+        let id = match id {
+            XAddId::Auto if now > last.ms => StreamId { ms: now, seq: 0 },
+            XAddId::Auto => return last.next().ok_or(Error::StreamExhausted),
+            XAddId::AutoSeq(ms) if ms == last.ms => {
+                return last
+                    .seq
+                    .checked_add(1)
+                    .map(|seq| StreamId { ms, seq })
+                    .ok_or(Error::StreamIdTooSmall);
+            }
+            // 0-0 is never a valid ID, so a stream starts at 0-1.
+            XAddId::AutoSeq(0) => StreamId { ms: 0, seq: 1 },
+            XAddId::AutoSeq(ms) => StreamId { ms, seq: 0 },
+            XAddId::Explicit(StreamId::MIN) => return Err(Error::StreamIdZero),
+            XAddId::Explicit(id) => id,
+        };
+        match id > last {
+            true => Ok(id),
+            false => Err(Error::StreamIdTooSmall),
+        }
+    }
+
+    /// Evicts the oldest entries as `trim` asks, returning how many were
+    /// evicted. Approximate trimming only evicts whole nodes.
+    fn trim(&mut self, trim: &StreamTrim) -> usize {
+        let mut evict = match trim.strategy {
+            TrimStrategy::MaxLen(maxlen) => self.len().saturating_sub(maxlen),
+            TrimStrategy::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+        if trim.approximate {
+            let limit = match trim.limit {
+                Some(0) => usize::MAX,
+                Some(limit) => limit,
+                None => 100 * NODE_MAX_ENTRIES,
+            };
+            evict = evict.min(limit) / NODE_MAX_ENTRIES * NODE_MAX_ENTRIES;
+        }
+        for _ in 0..evict {
+            self.entries.pop_first();
+        }
+        evict
+    }
+
+    /// Entries with IDs from `start` to `end`, at most `count` of them, from
+    /// the last one down if `rev`.
+    pub(super) fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Vec<(StreamId, Fields)> {
+        if start > end {
+            return Vec::new();
+        }
+        let range = self.entries.range(start..=end);
+        let count = count.unwrap_or(usize::MAX);
+        let clone = |(id, fields): (&StreamId, &Fields)| (*id, fields.clone());
+        match rev {
+            true => range.rev().take(count).map(clone).collect(),
+            false => range.take(count).map(clone).collect(),
+        }
+    }
+
+    fn first_id(&self) -> StreamId {
+        self.entries
+            .first_key_value()
+            .map_or(StreamId::MIN, |(id, _)| *id)
+    }
+
+    /// Whether entries from `start` on were deleted, which leaves the read
+    /// counters of groups behind them unable to count the rest.
+    fn has_tombstones(&self, start: StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && start <= self.max_deleted_id
+    }
+
+    /// How many entries were added up to `id`, if it can be told without a
+    /// counter: at either end of the stream, or when nothing was deleted.
+    fn entries_added_until(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.entries.is_empty() && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            std::cmp::Ordering::Equal => return Some(self.entries_added),
+            std::cmp::Ordering::Greater => return None,
+            std::cmp::Ordering::Less => {}
+        }
+
+        let first = self.first_id();
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first {
+            let before_first = self.entries_added - self.len() as u64;
+            match id.cmp(&first) {
+                std::cmp::Ordering::Less => return Some(before_first),
+                std::cmp::Ordering::Equal => return Some(before_first + 1),
+                std::cmp::Ordering::Greater => {}
+            }
+        }
+        None
+    }
+
+    /// How many entries `group` has yet to read, if it can be told.
+    fn lag(&self, group: &Group) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read)
+                if !self.has_tombstones(group.last_id) && group.last_id >= self.first_id() =>
+            {
+                Some(read)
+            }
+            _ => self.entries_added_until(group.last_id),
+        };
+        entries_read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    /// Up to `count` entries with an ID greater than `id`.
+    pub(super) fn after(&self, id: StreamId, count: Option<usize>) -> Vec<(StreamId, Fields)> {
+        match id.next() {
+            Some(start) => self.range(start, StreamId::MAX, count, false),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Db {
+    pub(super) fn stream_ref(&self, key: &[u8], now: u64) -> Result<Option<&Stream>, Error> {
+        match self.get(key, now).map(|entry| &entry.value) {
+            Some(Value::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn stream_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut Stream>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Appends an entry and trims the stream, creating it unless
+    /// `nomkstream` is set. Returns the ID of the entry, or `None` if the
+    /// stream didn't exist and wasn't created.
+    pub(super) fn xadd(
+        &mut self,
+        key: Bytes,
+        nomkstream: bool,
+        trim: Option<&StreamTrim>,
+        id: XAddId,
+        fields: Fields,
+        now: u64,
+    ) -> Result<Option<StreamId>, Error> {
+        let id = match self.stream_ref(&key, now)? {
+            Some(stream) => stream.next_id(id, now)?,
+            None if nomkstream => return Ok(None),
+            None => Stream::default().next_id(id, now)?,
+        };
+        if self.stream_ref(&key, now)?.is_none() {
+            let entry = Entry::new(Value::Stream(Stream::default()), None, now);
+            self.insert_entry(key.clone(), entry);
+        }
+
+        let stream = self
+            .stream_mut(&key, now)?
+            .expect("stream was just created");
+        stream.entries.insert(id, fields);
+        stream.last_id = id;
+        stream.entries_added += 1;
+        if let Some(trim) = trim {
+            stream.trim(trim);
+        }
+        self.blocked.signal(&key);
+        Ok(Some(id))
+    }
+
+    fn xtrim(&mut self, key: &[u8], trim: &StreamTrim, now: u64) -> Result<usize, Error> {
+        Ok(match self.stream_mut(key, now)? {
+            Some(stream) => stream.trim(trim),
+            None => 0,
+        })
+    }
+
+    fn xdel(&mut self, key: &[u8], ids: &[StreamId], now: u64) -> Result<usize, Error> {
+        let Some(stream) = self.stream_mut(key, now)? else {
+            return Ok(0);
+        };
+        let mut deleted = 0;
+        for id in ids {
+            if stream.entries.remove(id).is_some() {
+                stream.max_deleted_id = stream.max_deleted_id.max(*id);
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+/// Runs `f` on the stream under `key`, marking it accessed.
+fn with_stream<T>(key: &[u8], f: impl FnOnce(&Stream) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::Stream(stream) => Ok(f(stream)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+/// See `Db::xadd`.
+pub async fn add(
+    key: Bytes,
+    nomkstream: bool,
+    trim: Option<&StreamTrim>,
+    id: XAddId,
+    fields: Fields,
+) -> Result<Option<StreamId>, Error> {
+    write().xadd(key, nomkstream, trim, id, fields, now_ms())
+}
+
+pub async fn trim(key: &[u8], trim: &StreamTrim) -> Result<usize, Error> {
+    write().xtrim(key, trim, now_ms())
+}
+
+pub async fn delete(key: &[u8], ids: &[StreamId]) -> Result<usize, Error> {
+    write().xdel(key, ids, now_ms())
+}
+
+pub async fn len(key: &[u8]) -> Result<usize, Error> {
+    Ok(with_stream(key, |stream| stream.len())?.unwrap_or(0))
+}
+
+pub async fn range(
+    key: &[u8],
+    start: StreamId,
+    end: StreamId,
+    count: Option<usize>,
+    rev: bool,
+) -> Result<Vec<(StreamId, Fields)>, Error> {
+    let entries = with_stream(key, |stream| stream.range(start, end, count, rev))?;
+    Ok(entries.unwrap_or_default())
+}
+
+/// Entries read from each stream, leaving out those with nothing to read.
+type Read = Vec<(Bytes, Vec<(StreamId, Fields)>)>;
+
+impl Db {
+    /// Reads up to `count` entries from each of `keys` after the matching ID
+    /// in `ids`. Also returns the ID to wait for entries after on each key if
+    /// nothing was read: the last ID of the stream for `$`, and for `+` when
+    /// the stream is empty.
+    fn xread(
+        &self,
+        keys: &[Bytes],
+        ids: Vec<ReadId>,
+        count: Option<usize>,
+        now: u64,
+    ) -> Result<(Read, HashMap<Bytes, StreamId>), Error> {
+        let mut streams = Vec::new();
+        let mut after = HashMap::new();
+        for (key, id) in keys.iter().zip(ids) {
+            let stream = self.stream_ref(key, now)?;
+            let entries = match (stream, id) {
+                (Some(stream), ReadId::Last) => {
+                    stream.range(StreamId::MIN, StreamId::MAX, Some(1), true)
+                }
+                (Some(stream), ReadId::After(id)) => stream.after(id, count),
+                _ => Vec::new(),
+            };
+            let id = match id {
+                ReadId::New | ReadId::Last => stream.map_or(StreamId::MIN, |stream| stream.last_id),
+                ReadId::After(id) => id,
+            };
+            after.insert(key.clone(), id);
+            if !entries.is_empty() {
+                streams.push((key.clone(), entries));
+            }
+        }
+        Ok((streams, after))
+    }
+}
+
+/// Reads up to `count` entries from each of `keys` after the matching ID in
+/// `ids`. If there is nothing to read and `block` is set, waits until an
+/// entry is added to one of the streams or the timeout elapses, forever if it
+/// is `None`, and returns what was read from that stream alone. Returns
+/// `None` if there was nothing to read.
+pub async fn read(
+    keys: Vec<Bytes>,
+    ids: Vec<ReadId>,
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+) -> Result<Option<Read>, Error> {
+    let Some(timeout) = block else {
+        let (streams, _) = super::read().xread(&keys, ids, count, now_ms())?;
+        return Ok((!streams.is_empty()).then_some(streams));
+    };
+
+    let (id, receiver) = {
+        let mut db = write();
+        let (streams, after) = db.xread(&keys, ids, count, now_ms())?;
+        if !streams.is_empty() {
+            return Ok(Some(streams));
+        }
+        db.blocked.block(keys, Op::Read(after, count))
+    };
+    let served = blocking::wait(id, receiver, timeout).await?;
+    Ok(served.map(|(key, entries)| vec![(key, entries.into_stream())]))
+}
+
+/// Entries read for a consumer group from each stream. Entries read again
+/// from the pending entries have no fields once deleted from the stream.
+type GroupRead = Vec<(Bytes, Vec<(StreamId, Option<Fields>)>)>;
+
+/// The ID XAUTOCLAIM continues from, the entries it claimed and the IDs it
+/// dropped from the pending entries as they were deleted.
+type AutoClaimed = (StreamId, Vec<(StreamId, Fields)>, Vec<StreamId>);
+
+/// What XPENDING reports without a range.
+#[derive(Debug, PartialEq)]
+pub struct PendingSummary {
+    pub count: usize,
+    /// The smallest and greatest pending IDs, if any is pending.
+    pub bounds: Option<(StreamId, StreamId)>,
+    /// Consumers with pending entries and how many they have.
+    pub consumers: Vec<(Bytes, usize)>,
+}
+
+/// A pending entry listed by XPENDING.
+#[derive(Debug, PartialEq)]
+pub struct PendingEntry {
+    pub id: StreamId,
+    pub consumer: Bytes,
+    pub idle: u64,
+    pub deliveries: u64,
+}
+
+/// What XINFO STREAM reports.
+#[derive(Debug, PartialEq)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub last_generated_id: StreamId,
+    pub max_deleted_id: StreamId,
+    pub entries_added: u64,
+    pub first_recorded_id: StreamId,
+    pub groups: usize,
+    pub first_entry: Option<(StreamId, Fields)>,
+    pub last_entry: Option<(StreamId, Fields)>,
+}
+
+/// What XINFO GROUPS reports for each group.
+#[derive(Debug, PartialEq)]
+pub struct GroupInfo {
+    pub name: Bytes,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered_id: StreamId,
+    pub entries_read: Option<u64>,
+    pub lag: Option<u64>,
+}
+
+/// What XINFO CONSUMERS reports for each consumer.
+#[derive(Debug, PartialEq)]
+pub struct ConsumerInfo {
+    pub name: Bytes,
+    pub pending: usize,
+    /// Milliseconds since the consumer was last seen.
+    pub idle: u64,
+    /// Milliseconds since the consumer last got entries, if it ever did.
+    pub inactive: Option<u64>,
+}
+
+impl Stream {
+    /// Delivers up to `count` entries `read.group` hasn't delivered yet to
+    /// `read.consumer`, which must exist.
+    fn read_new(
+        &mut self,
+        read: &ReadGroup,
+        count: Option<usize>,
+        now: u64,
+    ) -> Vec<(StreamId, Fields)> {
+        let Some(last_id) = self.groups.get(&read.group).map(|group| group.last_id) else {
+            return Vec::new();
+        };
+        let entries = self.after(last_id, count);
+        for (id, _) in &entries {
+            let id = *id;
+            let entries_read = self.groups[&read.group].entries_read;
+            let entries_read = match entries_read {
+                Some(read) if !self.has_tombstones(id) => Some(read + 1),
+                _ if self.entries_added > 0 => self.entries_added_until(id),
+                entries_read => entries_read,
+            };
+
+            let group = self.groups.get_mut(&read.group).expect("group exists");
+            group.last_id = id;
+            group.entries_read = entries_read;
+            if !read.noack {
+                group.claim(id, &read.consumer, now, now).deliveries = 1;
+            }
+        }
+        entries
+    }
+
+    /// Delivers again up to `count` entries pending for `read.consumer` with
+    /// an ID greater than `after`.
+    fn read_history(
+        &mut self,
+        read: &ReadGroup,
+        after: StreamId,
+        count: Option<usize>,
+        now: u64,
+    ) -> Vec<(StreamId, Option<Fields>)> {
+        let Self {
+            entries, groups, ..
+        } = self;
+        let Some(group) = groups.get_mut(&read.group) else {
+            return Vec::new();
+        };
+        let ids: Vec<StreamId> = match group.consumers.get(&read.consumer) {
+            Some(consumer) => consumer
+                .pending
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .take(count.unwrap_or(usize::MAX))
+                .copied()
+                .collect(),
+            None => Vec::new(),
+        };
+        ids.into_iter()
+            .map(|id| {
+                if let Some(pending) = group.pending.get_mut(&id) {
+                    pending.delivered_at = now;
+                    pending.deliveries += 1;
+                }
+                (id, entries.get(&id).cloned())
+            })
+            .collect()
+    }
+
+    /// Claims the entries `ids` pending in `claim.group` for
+    /// `claim.consumer`, forgetting those deleted from the stream. Returns
+    /// the entries claimed.
+    fn claim(
+        &mut self,
+        claim: &Claim,
+        ids: &[StreamId],
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let Self {
+            entries, groups, ..
+        } = self;
+        let group = groups.get_mut(&claim.group)?;
+        group.consumer(&claim.consumer, now);
+        if let Some(last_id) = options.last_id {
+            group.last_id = group.last_id.max(last_id);
+        }
+        let delivered_at = match options.delivery_time {
+            Some(DeliveryTime::Idle(idle)) => now.checked_sub(idle).unwrap_or(now),
+            Some(DeliveryTime::At(at)) if at <= now => at,
+            _ => now,
+        };
+
+        let mut claimed = Vec::new();
+        for &id in ids {
+            let Some(fields) = entries.get(&id) else {
+                group.acknowledge(id);
+                continue;
+            };
+            match group.pending.get(&id) {
+                Some(pending) if now.saturating_sub(pending.delivered_at) < claim.min_idle => {
+                    continue;
+                }
+                Some(_) => {}
+                None if options.force => {}
+                None => continue,
+            }
+
+            let pending = group.claim(id, &claim.consumer, delivered_at, now);
+            match options.retry_count {
+                Some(retry_count) => pending.deliveries = retry_count,
+                None if !options.just_id => pending.deliveries += 1,
+                None => {}
+            }
+            claimed.push((id, fields.clone()));
+        }
+        Some(claimed)
+    }
+
+    /// Claims up to `count` entries pending in `claim.group` from `start` on
+    /// for `claim.consumer`, looking at no more than ten times as many.
+    /// Returns the ID to continue from, 0-0 once done, the entries claimed
+    /// and the IDs forgotten as they were deleted from the stream.
+    fn autoclaim(
+        &mut self,
+        claim: &Claim,
+        start: StreamId,
+        count: usize,
+        just_id: bool,
+        now: u64,
+    ) -> Option<AutoClaimed> {
+        let Self {
+            entries, groups, ..
+        } = self;
+        let group = groups.get_mut(&claim.group)?;
+        group.consumer(&claim.consumer, now);
+
+        let attempts = count.saturating_mul(10);
+        let ids: Vec<StreamId> = group
+            .pending
+            .range(start..)
+            .take(attempts)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        let mut last = None;
+        for id in ids {
+            if claimed.len() == count {
+                break;
+            }
+            last = Some(id);
+            let Some(fields) = entries.get(&id) else {
+                group.acknowledge(id);
+                deleted.push(id);
+                continue;
+            };
+            if now.saturating_sub(group.pending[&id].delivered_at) < claim.min_idle {
+                continue;
+            }
+            let pending = group.claim(id, &claim.consumer, now, now);
+            if !just_id {
+                pending.deliveries += 1;
+            }
+            claimed.push((id, fields.clone()));
+        }
+
+        let cursor = last
+            .and_then(|last| {
+                let mut rest = group
+                    .pending
+                    .range((Bound::Excluded(last), Bound::Unbounded));
+                rest.next().map(|(id, _)| *id)
+            })
+            .unwrap_or(StreamId::MIN);
+        Some((cursor, claimed, deleted))
+    }
+
+    fn pending_summary(&self, group: &[u8]) -> Option<PendingSummary> {
+        let group = self.groups.get(group)?;
+        let first = group.pending.first_key_value().map(|(id, _)| *id);
+        let last = group.pending.last_key_value().map(|(id, _)| *id);
+        Some(PendingSummary {
+            count: group.pending.len(),
+            bounds: first.zip(last),
+            consumers: group
+                .consumers
+                .iter()
+                .filter(|(_, consumer)| !consumer.pending.is_empty())
+                .map(|(name, consumer)| (name.clone(), consumer.pending.len()))
+                .collect(),
+        })
+    }
+
+    fn pending(&self, group: &[u8], range: &PendingRange, now: u64) -> Option<Vec<PendingEntry>> {
+        let group = self.groups.get(group)?;
+        if range.start > range.end {
+            return Some(Vec::new());
+        }
+        let ids: Box<dyn Iterator<Item = &StreamId>> = match &range.consumer {
+            Some(consumer) => match group.consumers.get(consumer) {
+                Some(consumer) => Box::new(consumer.pending.range(range.start..=range.end)),
+                None => return Some(Vec::new()),
+            },
+            None => Box::new(
+                group
+                    .pending
+                    .range(range.start..=range.end)
+                    .map(|(id, _)| id),
+            ),
+        };
+        Some(
+            ids.map(|id| {
+                let pending = &group.pending[id];
+                PendingEntry {
+                    id: *id,
+                    consumer: pending.consumer.clone(),
+                    idle: now.saturating_sub(pending.delivered_at),
+                    deliveries: pending.deliveries,
+                }
+            })
+            .filter(|entry| entry.idle >= range.min_idle.unwrap_or(0))
+            .take(range.count)
+            .collect(),
+        )
+    }
+
+    fn info(&self) -> StreamInfo {
+        let first_entry = self.range(StreamId::MIN, StreamId::MAX, Some(1), false);
+        let last_entry = self.range(StreamId::MIN, StreamId::MAX, Some(1), true);
+        StreamInfo {
+            length: self.len(),
+            last_generated_id: self.last_id,
+            max_deleted_id: self.max_deleted_id,
+            entries_added: self.entries_added,
+            first_recorded_id: self.first_id(),
+            groups: self.groups.len(),
+            first_entry: first_entry.into_iter().next(),
+            last_entry: last_entry.into_iter().next(),
+        }
+    }
+
+    fn group_info(&self) -> Vec<GroupInfo> {
+        self.groups
+            .iter()
+            .map(|(name, group)| GroupInfo {
+                name: name.clone(),
+                consumers: group.consumers.len(),
+                pending: group.pending.len(),
+                last_delivered_id: group.last_id,
+                entries_read: group.entries_read,
+                lag: self.lag(group),
+            })
+            .collect()
+    }
+
+    fn consumer_info(&self, group: &[u8], now: u64) -> Option<Vec<ConsumerInfo>> {
+        let group = self.groups.get(group)?;
+        Some(
+            group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| ConsumerInfo {
+                    name: name.clone(),
+                    pending: consumer.pending.len(),
+                    idle: now.saturating_sub(consumer.seen_at),
+                    inactive: consumer
+                        .active_at
+                        .map(|active_at| now.saturating_sub(active_at)),
+                })
+                .collect(),
+        )
+    }
+
+    /// Whether a client blocked reading new entries for `group` can be
+    /// served: there are some, or the group is gone and it gets an error.
+    pub(super) fn can_read_group(&self, group: &[u8]) -> bool {
+        match self.groups.get(group) {
+            Some(group) => !self.after(group.last_id, Some(1)).is_empty(),
+            None => true,
+        }
+    }
+}
+
+fn key_and_group(key: &[u8], group: &[u8]) -> (String, String) {
+    (
+        String::from_utf8_lossy(key).into_owned(),
+        String::from_utf8_lossy(group).into_owned(),
+    )
+}
+
+impl Db {
+    /// The stream under `key` for the XGROUP subcommands, which require it
+    /// to exist.
+    fn group_stream(&mut self, key: &[u8], now: u64) -> Result<&mut Stream, Error> {
+        self.stream_mut(key, now)?.ok_or(Error::XGroupNoKey)
+    }
+
+    /// `group` of the stream under `key` for the XGROUP subcommands.
+    fn group_mut(&mut self, key: &[u8], group: &[u8], now: u64) -> Result<&mut Group, Error> {
+        let stream = self.group_stream(key, now)?;
+        stream.groups.get_mut(group).ok_or_else(|| {
+            let (key, group) = key_and_group(key, group);
+            Error::NoSuchGroup(key, group)
+        })
+    }
+
+    /// Creates `group` on the stream under `key`, delivering entries after
+    /// `id` or, if `None`, after the last one. The stream is created if
+    /// `mkstream` is set.
+    pub(super) fn xgroup_create(
+        &mut self,
+        key: Bytes,
+        group: Bytes,
+        id: Option<StreamId>,
+        mkstream: bool,
+        entries_read: Option<u64>,
+        now: u64,
+    ) -> Result<(), Error> {
+        if mkstream && self.stream_ref(&key, now)?.is_none() {
+            let entry = Entry::new(Value::Stream(Stream::default()), None, now);
+            self.insert_entry(key.clone(), entry);
+        }
+        let stream = self.group_stream(&key, now)?;
+        if stream.groups.contains_key(&group) {
+            return Err(Error::BusyGroup);
+        }
+        let last_id = id.unwrap_or(stream.last_id);
+        stream.groups.insert(
+            group,
+            Group {
+                last_id,
+                entries_read,
+                ..Group::default()
+            },
+        );
+        Ok(())
+    }
+
+    fn xgroup_set_id(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        id: Option<StreamId>,
+        entries_read: Option<u64>,
+        now: u64,
+    ) -> Result<(), Error> {
+        let last_id = self.group_stream(key, now)?.last_id;
+        let group = self.group_mut(key, group, now)?;
+        group.last_id = id.unwrap_or(last_id);
+        group.entries_read = entries_read;
+        Ok(())
+    }
+
+    /// Destroys `group`, failing the clients blocked reading for it.
+    fn xgroup_destroy(&mut self, key: &Bytes, group: &[u8], now: u64) -> Result<bool, Error> {
+        let destroyed = self.group_stream(key, now)?.groups.remove(group).is_some();
+        if destroyed {
+            self.blocked.signal(key);
+        }
+        Ok(destroyed)
+    }
+
+    /// Creates `consumer` in `group`, returning whether it didn't exist.
+    fn xgroup_create_consumer(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        consumer: Bytes,
+        now: u64,
+    ) -> Result<bool, Error> {
+        let group = self.group_mut(key, group, now)?;
+        if group.consumers.contains_key(&consumer) {
+            return Ok(false);
+        }
+        group.consumer(&consumer, now);
+        Ok(true)
+    }
+
+    /// Deletes `consumer` from `group` along with its pending entries,
+    /// returning how many it had.
+    fn xgroup_del_consumer(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        consumer: &[u8],
+        now: u64,
+    ) -> Result<usize, Error> {
+        let group = self.group_mut(key, group, now)?;
+        let Some(consumer) = group.consumers.remove(consumer) else {
+            return Ok(0);
+        };
+        for id in &consumer.pending {
+            group.pending.remove(id);
+        }
+        Ok(consumer.pending.len())
+    }
+
+    fn xack(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        ids: &[StreamId],
+        now: u64,
+    ) -> Result<usize, Error> {
+        let group = self
+            .stream_mut(key, now)?
+            .and_then(|stream| stream.groups.get_mut(group));
+        Ok(match group {
+            Some(group) => ids.iter().filter(|id| group.acknowledge(**id)).count(),
+            None => 0,
+        })
+    }
+
+    /// The stream under `key` that `read.consumer` reads from, created in
+    /// `read.group` if needed.
+    fn read_group_stream(
+        &mut self,
+        key: &[u8],
+        read: &ReadGroup,
+        now: u64,
+    ) -> Result<&mut Stream, Error> {
+        let no_group = || {
+            let (key, group) = key_and_group(key, &read.group);
+            Error::NoReadGroup(key, group)
+        };
+        let stream = self.stream_mut(key, now)?.ok_or_else(no_group)?;
+        let group = stream.groups.get_mut(&read.group).ok_or_else(no_group)?;
+        group.consumer(&read.consumer, now);
+        Ok(stream)
+    }
+
+    /// Reads for `read.consumer` from each of `keys`: entries not delivered
+    /// to the group yet for `>`, and entries pending for the consumer after
+    /// the ID given otherwise. Streams with no new entries are left out,
+    /// while those read from the pending entries always make it.
+    fn xreadgroup(
+        &mut self,
+        read: &ReadGroup,
+        keys: &[Bytes],
+        ids: &[ReadId],
+        count: Option<usize>,
+        now: u64,
+    ) -> Result<GroupRead, Error> {
+        for key in keys {
+            let stream = self.stream_ref(key, now)?;
+            if !stream.is_some_and(|stream| stream.groups.contains_key(&read.group)) {
+                let (key, group) = key_and_group(key, &read.group);
+                return Err(Error::NoReadGroup(key, group));
+            }
+        }
+
+        let mut streams = Vec::new();
+        for (key, id) in keys.iter().zip(ids) {
+            let stream = self.read_group_stream(key, read, now)?;
+            match *id {
+                ReadId::After(after) => {
+                    let entries = stream.read_history(read, after, count, now);
+                    streams.push((key.clone(), entries));
+                }
+                _ => {
+                    let entries = stream.read_new(read, count, now);
+                    if !entries.is_empty() {
+                        let entries = entries.into_iter().map(|(id, fields)| (id, Some(fields)));
+                        streams.push((key.clone(), entries.collect()));
+                    }
+                }
+            }
+        }
+        Ok(streams)
+    }
+
+    /// Reads entries not delivered to `read.group` yet from `key` for a
+    /// blocked client.
+    pub(super) fn xreadgroup_new(
+        &mut self,
+        key: &[u8],
+        read: &ReadGroup,
+        count: Option<usize>,
+        now: u64,
+    ) -> Result<Vec<(StreamId, Fields)>, Error> {
+        Ok(self
+            .read_group_stream(key, read, now)?
+            .read_new(read, count, now))
+    }
+
+    /// The stream under `key` for XCLAIM and XAUTOCLAIM, run through `f`,
+    /// which returns `None` without `group`.
+    fn claim_stream<T>(
+        &mut self,
+        key: &[u8],
+        group: &[u8],
+        now: u64,
+        f: impl FnOnce(&mut Stream) -> Option<T>,
+    ) -> Result<T, Error> {
+        self.stream_mut(key, now)?.and_then(f).ok_or_else(|| {
+            let (key, group) = key_and_group(key, group);
+            Error::NoKeyOrGroup(key, group)
+        })
+    }
+}
+
+/// Runs `f` on the stream under `key` for the commands that report on
+/// consumer groups, failing if the stream or the group `f` looks for,
+/// returning `None` without it, don't exist.
+fn with_group<T>(
+    key: &[u8],
+    group: &[u8],
+    f: impl FnOnce(&Stream) -> Option<T>,
+) -> Result<T, Error> {
+    with_stream(key, f)?.flatten().ok_or_else(|| {
+        let (key, group) = key_and_group(key, group);
+        Error::NoKeyOrGroup(key, group)
+    })
+}
+
+/// See `Db::xgroup_create`.
+pub async fn create_group(
+    key: Bytes,
+    group: Bytes,
+    id: Option<StreamId>,
+    mkstream: bool,
+    entries_read: Option<u64>,
+) -> Result<(), Error> {
+    write().xgroup_create(key, group, id, mkstream, entries_read, now_ms())
+}
+
+pub async fn set_group_id(
+    key: &[u8],
+    group: &[u8],
+    id: Option<StreamId>,
+    entries_read: Option<u64>,
+) -> Result<(), Error> {
+    write().xgroup_set_id(key, group, id, entries_read, now_ms())
+}
+
+pub async fn destroy_group(key: &Bytes, group: &[u8]) -> Result<bool, Error> {
+    write().xgroup_destroy(key, group, now_ms())
+}
+
+pub async fn create_consumer(key: &[u8], group: &[u8], consumer: Bytes) -> Result<bool, Error> {
+    write().xgroup_create_consumer(key, group, consumer, now_ms())
+}
+
+pub async fn delete_consumer(key: &[u8], group: &[u8], consumer: &[u8]) -> Result<usize, Error> {
+    write().xgroup_del_consumer(key, group, consumer, now_ms())
+}
+
+pub async fn acknowledge(key: &[u8], group: &[u8], ids: &[StreamId]) -> Result<usize, Error> {
+    write().xack(key, group, ids, now_ms())
+}
+
+/// Reads for `read.consumer` from each of `keys` as `Db::xreadgroup` does.
+/// If there is nothing to read, only new entries were asked for and `block`
+/// is set, waits as `read` does. Returns `None` if there was nothing to read.
+pub async fn read_group(
+    read: ReadGroup,
+    keys: Vec<Bytes>,
+    ids: Vec<ReadId>,
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+) -> Result<Option<GroupRead>, Error> {
+    let (id, receiver, timeout) = {
+        let mut db = write();
+        let streams = db.xreadgroup(&read, &keys, &ids, count, now_ms())?;
+        let blocks = ids.iter().all(|id| *id == ReadId::New);
+        match block {
+            Some(timeout) if streams.is_empty() && blocks => {
+                let (id, receiver) = db.blocked.block(keys, Op::ReadGroup(read, count));
+                (id, receiver, timeout)
+            }
+            _ => return Ok((!streams.is_empty()).then_some(streams)),
+        }
+    };
+    let served = blocking::wait(id, receiver, timeout).await?;
+    Ok(served.map(|(key, entries)| {
+        let entries = entries.into_stream().into_iter();
+        vec![(
+            key,
+            entries.map(|(id, fields)| (id, Some(fields))).collect(),
+        )]
+    }))
+}
+
+/// See `Stream::claim`.
+pub async fn claim(
+    key: &[u8],
+    claim: &Claim,
+    ids: &[StreamId],
+    options: &ClaimOptions,
+) -> Result<Vec<(StreamId, Fields)>, Error> {
+    let now = now_ms();
+    write().claim_stream(key, &claim.group, now, |stream| {
+        stream.claim(claim, ids, options, now)
+    })
+}
+
+/// See `Stream::autoclaim`.
+pub async fn autoclaim(
+    key: &[u8],
+    claim: &Claim,
+    start: StreamId,
+    count: usize,
+    just_id: bool,
+) -> Result<AutoClaimed, Error> {
+    let now = now_ms();
+    write().claim_stream(key, &claim.group, now, |stream| {
+        stream.autoclaim(claim, start, count, just_id, now)
+    })
+}
+
+pub async fn pending_summary(key: &[u8], group: &[u8]) -> Result<PendingSummary, Error> {
+    with_group(key, group, |stream| stream.pending_summary(group))
+}
+
+pub async fn pending(
+    key: &[u8],
+    group: &[u8],
+    range: &PendingRange,
+) -> Result<Vec<PendingEntry>, Error> {
+    let now = now_ms();
+    with_group(key, group, |stream| stream.pending(group, range, now))
+}
+
+pub async fn info(key: &[u8]) -> Result<StreamInfo, Error> {
+    with_stream(key, |stream| stream.info())?.ok_or(Error::NoSuchKey)
+}
+
+pub async fn group_info(key: &[u8]) -> Result<Vec<GroupInfo>, Error> {
+    with_stream(key, |stream| stream.group_info())?.ok_or(Error::NoSuchKey)
+}
+
+pub async fn consumer_info(key: &[u8], group: &[u8]) -> Result<Vec<ConsumerInfo>, Error> {
+    let now = now_ms();
+    let consumers = with_stream(key, |stream| stream.consumer_info(group, now))?;
+    consumers.ok_or(Error::NoSuchKey)?.ok_or_else(|| {
+        let (key, group) = key_and_group(key, group);
+        Error::NoSuchGroup(key, group)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    fn fields() -> Fields {
+        vec![(Bytes::from("field"), Bytes::from("value"))]
+    }
+
+    fn add(db: &mut Db, id: XAddId, now: u64) -> Result<Option<StreamId>, Error> {
+        db.xadd(Bytes::from("stream"), false, None, id, fields(), now)
+    }
+
+    #[test]
+    fn xadd_picks_increasing_ids() {
+        let mut db = Db::default();
+        assert_eq!(add(&mut db, XAddId::AutoSeq(0), 5), Ok(Some(id(0, 1))));
+        assert_eq!(add(&mut db, XAddId::Auto, 5), Ok(Some(id(5, 0))));
+        assert_eq!(add(&mut db, XAddId::Auto, 3), Ok(Some(id(5, 1))));
+        assert_eq!(add(&mut db, XAddId::AutoSeq(5), 3), Ok(Some(id(5, 2))));
+        assert_eq!(
+            add(&mut db, XAddId::AutoSeq(4), 3),
+            Err(Error::StreamIdTooSmall)
+        );
+        let explicit = XAddId::Explicit(id(5, 2));
+        assert_eq!(add(&mut db, explicit, 3), Err(Error::StreamIdTooSmall));
+        let zero = XAddId::Explicit(StreamId::MIN);
+        assert_eq!(add(&mut db, zero, 3), Err(Error::StreamIdZero));
+
+        let max = XAddId::Explicit(StreamId::MAX);
+        assert_eq!(add(&mut db, max, 3), Ok(Some(StreamId::MAX)));
+        assert_eq!(add(&mut db, XAddId::Auto, 3), Err(Error::StreamExhausted));
+
+        let missing = db.xadd(
+            Bytes::from("missing"),
+            true,
+            None,
+            XAddId::Auto,
+            fields(),
+            0,
+        );
+        assert_eq!(missing, Ok(None));
+        assert!(db.get(b"missing", 0).is_none());
+    }
+
+    #[test]
+    fn deleted_ids_are_not_reused() {
+        let mut db = Db::default();
+        add(&mut db, XAddId::Explicit(id(1, 0)), 0).unwrap();
+        assert_eq!(db.xdel(b"stream", &[id(1, 0), id(2, 0)], 0), Ok(1));
+        assert_eq!(add(&mut db, XAddId::AutoSeq(1), 0), Ok(Some(id(1, 1))));
+    }
+
+    #[test]
+    fn trim_by_maxlen_and_minid() {
+        let mut db = Db::default();
+        for ms in 1..=250 {
+            add(&mut db, XAddId::Explicit(id(ms, 0)), 0).unwrap();
+        }
+        let trim = |strategy, approximate, limit| StreamTrim {
+            strategy,
+            approximate,
+            limit,
+        };
+
+        let approximate = trim(TrimStrategy::MaxLen(60), true, None);
+        assert_eq!(db.xtrim(b"stream", &approximate, 0), Ok(100));
+        let limited = trim(TrimStrategy::MaxLen(10), true, Some(120));
+        assert_eq!(db.xtrim(b"stream", &limited, 0), Ok(100));
+        let exact = trim(TrimStrategy::MinId(id(240, 0)), false, None);
+        assert_eq!(db.xtrim(b"stream", &exact, 0), Ok(39));
+
+        let stream = db.stream_ref(b"stream", 0).unwrap().unwrap();
+        assert_eq!(stream.len(), 11);
+        let first = stream.range(StreamId::MIN, StreamId::MAX, Some(1), false);
+        assert_eq!(first[0].0, id(240, 0));
+        let last = stream.after(id(249, 5), None);
+        assert_eq!(last.len(), 1);
+    }
+
+    fn group() -> ReadGroup {
+        ReadGroup {
+            group: Bytes::from("group"),
+            consumer: Bytes::from("alice"),
+            noack: false,
+        }
+    }
+
+    fn create_group(db: &mut Db) {
+        db.xgroup_create(
+            Bytes::from("stream"),
+            Bytes::from("group"),
+            Some(StreamId::MIN),
+            false,
+            None,
+            0,
+        )
+        .unwrap();
+    }
+
+    fn read_group(db: &mut Db, read: &ReadGroup, id: ReadId, now: u64) -> GroupRead {
+        db.xreadgroup(read, &[Bytes::from("stream")], &[id], None, now)
+            .unwrap()
+    }
+
+    #[test]
+    fn xreadgroup_delivers_new_entries_once() {
+        let mut db = Db::default();
+        let create = db.xgroup_create(
+            Bytes::from("stream"),
+            Bytes::from("group"),
+            None,
+            false,
+            None,
+            0,
+        );
+        assert_eq!(create, Err(Error::XGroupNoKey));
+        for ms in 1..=3 {
+            add(&mut db, XAddId::Explicit(id(ms, 0)), 0).unwrap();
+        }
+        create_group(&mut db);
+
+        let alice = group();
+        let read = db.xreadgroup(&alice, &[Bytes::from("stream")], &[ReadId::New], Some(2), 5);
+        let delivered = vec![(id(1, 0), Some(fields())), (id(2, 0), Some(fields()))];
+        assert_eq!(read, Ok(vec![(Bytes::from("stream"), delivered)]));
+        let bob = ReadGroup {
+            consumer: Bytes::from("bob"),
+            ..group()
+        };
+        assert_eq!(read_group(&mut db, &bob, ReadId::New, 5).len(), 1);
+        assert!(read_group(&mut db, &bob, ReadId::New, 5).is_empty());
+
+        db.xdel(b"stream", &[id(1, 0)], 0).unwrap();
+        let history = read_group(&mut db, &alice, ReadId::After(StreamId::MIN), 7);
+        let pending = vec![(id(1, 0), None), (id(2, 0), Some(fields()))];
+        assert_eq!(history, vec![(Bytes::from("stream"), pending)]);
+
+        assert_eq!(
+            db.xack(b"stream", b"group", &[id(1, 0), id(3, 0)], 0),
+            Ok(2)
+        );
+        let stream = db.stream_ref(b"stream", 9).unwrap().unwrap();
+        let summary = stream.pending_summary(b"group").unwrap();
+        assert_eq!(summary.bounds, Some((id(2, 0), id(2, 0))));
+        assert_eq!(summary.consumers, vec![(Bytes::from("alice"), 1)]);
+        let range = PendingRange {
+            min_idle: None,
+            start: StreamId::MIN,
+            end: StreamId::MAX,
+            count: 10,
+            consumer: None,
+        };
+        let entry = PendingEntry {
+            id: id(2, 0),
+            consumer: Bytes::from("alice"),
+            idle: 2,
+            deliveries: 2,
+        };
+        assert_eq!(stream.pending(b"group", &range, 9), Some(vec![entry]));
+
+        let missing = ReadGroup {
+            group: Bytes::from("missing"),
+            ..group()
+        };
+        let read = db.xreadgroup(&missing, &[Bytes::from("stream")], &[ReadId::New], None, 0);
+        let error = Error::NoReadGroup("stream".to_string(), "missing".to_string());
+        assert_eq!(read, Err(error));
+    }
+
+    #[test]
+    fn claim_moves_idle_entries() {
+        let mut db = Db::default();
+        for ms in 1..=3 {
+            add(&mut db, XAddId::Explicit(id(ms, 0)), 0).unwrap();
+        }
+        create_group(&mut db);
+        read_group(&mut db, &group(), ReadId::New, 0);
+        db.xdel(b"stream", &[id(2, 0)], 0).unwrap();
+
+        let bob = Claim {
+            group: Bytes::from("group"),
+            consumer: Bytes::from("bob"),
+            min_idle: 10,
+        };
+        let stream = db.stream_mut(b"stream", 0).unwrap().unwrap();
+        let options = ClaimOptions::default();
+        let ids = [id(1, 0), id(2, 0)];
+        assert_eq!(stream.claim(&bob, &ids, &options, 5), Some(Vec::new()));
+        let claimed = stream.claim(&bob, &ids, &options, 10);
+        assert_eq!(claimed, Some(vec![(id(1, 0), fields())]));
+        assert_eq!(stream.groups[&bob.group].pending[&id(1, 0)].deliveries, 2);
+
+        let carol = Claim {
+            consumer: Bytes::from("carol"),
+            ..bob
+        };
+        let (cursor, claimed, deleted) = stream
+            .autoclaim(&carol, StreamId::MIN, 1, true, 30)
+            .unwrap();
+        assert_eq!((cursor, claimed.len(), deleted), (id(3, 0), 1, vec![]));
+        let (cursor, claimed, deleted) = stream.autoclaim(&carol, cursor, 1, true, 30).unwrap();
+        assert_eq!((cursor, claimed.len(), deleted), (StreamId::MIN, 1, vec![]));
+
+        let group = &stream.groups[&carol.group];
+        assert_eq!(group.pending.len(), 2);
+        assert_eq!(group.consumers[&Bytes::from("carol")].pending.len(), 2);
+        assert!(group.consumers[&Bytes::from("alice")].pending.is_empty());
+        assert_eq!(group.pending[&id(1, 0)].deliveries, 2);
+    }
+
+    #[test]
+    fn group_lag_counts_unread_entries() {
+        let mut db = Db::default();
+        for ms in 1..=4 {
+            add(&mut db, XAddId::Explicit(id(ms, 0)), 0).unwrap();
+        }
+        create_group(&mut db);
+        let lag = |db: &Db| {
+            let stream = db.stream_ref(b"stream", 0).unwrap().unwrap();
+            let info = stream.group_info().pop().unwrap();
+            (info.entries_read, info.lag)
+        };
+        assert_eq!(lag(&db), (None, Some(4)));
+
+        let read = db.xreadgroup(
+            &group(),
+            &[Bytes::from("stream")],
+            &[ReadId::New],
+            Some(1),
+            0,
+        );
+        assert!(read.is_ok());
+        assert_eq!(lag(&db), (Some(1), Some(3)));
+
+        db.xdel(b"stream", &[id(3, 0)], 0).unwrap();
+        assert_eq!(lag(&db), (Some(1), None));
+        read_group(&mut db, &group(), ReadId::New, 0);
+        assert_eq!(lag(&db), (Some(4), Some(0)));
+    }
+}
@@ -0,0 +1,509 @@
+use bytes::{Bytes, BytesMut};
+
+use super::{now_ms, read, with_entry, write, Db, Entry, Error, Value};
+use crate::commands::{ExpireCondition, Expiry};
+use crate::resp::format_double;
+
+/// Largest string Redis stores, its default `proto-max-bulk-len`.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// Parses a string value as an integer the way Redis does, only accepting
+/// the canonical form: no sign for positive values, no leading zeros and no
+/// spaces.
+fn parse_integer(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|int| int.to_string().as_bytes() == value)
+}
+
+impl Db {
+    /// Replaces the string under `key` with the one `f` makes from it, or
+    /// from `None` if the key doesn't exist, keeping its TTL. Returns the
+    /// rest of what `f` returned.
+    pub(super) fn update_string<T>(
+        &mut self,
+        key: Bytes,
+        now: u64,
+        f: impl FnOnce(Option<&Bytes>) -> Result<(Bytes, T), Error>,
+    ) -> Result<T, Error> {
+        let Some(entry) = self.get_mut(&key, now) else {
+            let (value, result) = f(None)?;
+            self.insert_entry(key, Entry::new(Value::String(value), None, now));
+            return Ok(result);
+        };
+        let Value::String(current) = &mut entry.value else {
+            return Err(Error::WrongType);
+        };
+        let (value, result) = f(Some(current))?;
+        *current = value;
+        Ok(result)
+    }
+
+    pub(super) fn string_ref(&self, key: &[u8], now: u64) -> Result<Option<&Bytes>, Error> {
+        match self.get(key, now).map(|entry| &entry.value) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    fn append(&mut self, key: Bytes, value: &[u8], now: u64) -> Result<usize, Error> {
+        self.update_string(key, now, |current| {
+            let current = current.map_or(&[][..], |current| &current[..]);
+            if current.len() + value.len() > MAX_STRING_LEN {
+                return Err(Error::StringTooLong);
+            }
+            let mut appended = BytesMut::with_capacity(current.len() + value.len());
+            appended.extend_from_slice(current);
+            appended.extend_from_slice(value);
+            let len = appended.len();
+            Ok((appended.freeze(), len))
+        })
+    }
+
+    /// Overwrites the string under `key` with `value` from `offset` on,
+    /// padding it with zero bytes up to `offset` first. Returns the length of
+    /// the string, which isn't created if `value` is empty.
+    fn set_range(
+        &mut self,
+        key: Bytes,
+        offset: usize,
+        value: &[u8],
+        now: u64,
+    ) -> Result<usize, Error> {
+        if value.is_empty() {
+            return Ok(self
+                .string_ref(&key, now)?
+                .map_or(0, |current| current.len()));
+        }
+        if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+            return Err(Error::StringTooLong);
+        }
+        self.update_string(key, now, |current| {
+            let current = current.map_or(&[][..], |current| &current[..]);
+            let mut updated = BytesMut::from(current);
+            let end = offset + value.len();
+            if updated.len() < end {
+                updated.resize(end, 0);
+            }
+            updated[offset..end].copy_from_slice(value);
+            let len = updated.len();
+            Ok((updated.freeze(), len))
+        })
+    }
+
+    fn get_del(&mut self, key: &[u8], now: u64) -> Result<Option<Bytes>, Error> {
+        let value = self.string_ref(key, now)?.cloned();
+        if value.is_some() {
+            self.remove(key);
+        }
+        Ok(value)
+    }
+
+    /// Returns the string under `key`, changing its TTL if `expiry` is set:
+    /// to the given expiry, or to none for `None`.
+    fn get_ex(
+        &mut self,
+        key: &[u8],
+        expiry: Option<Option<Expiry>>,
+        now: u64,
+    ) -> Result<Option<Bytes>, Error> {
+        let value = self.string_ref(key, now)?.cloned();
+        if value.is_some() {
+            match expiry {
+                Some(Some(expiry)) => {
+                    self.expire(key, expiry, ExpireCondition::default(), now);
+                }
+                Some(None) => {
+                    self.persist(key, now);
+                }
+                None => {}
+            }
+        }
+        Ok(value)
+    }
+
+    fn mset(&mut self, pairs: Vec<(Bytes, Bytes)>, now: u64) {
+        for (key, value) in pairs {
+            self.insert_entry(key, Entry::new(Value::String(value), None, now));
+        }
+    }
+
+    /// Sets every key of `pairs` unless any of them exists, returning whether
+    /// they were set.
+    fn mset_nx(&mut self, pairs: Vec<(Bytes, Bytes)>, now: u64) -> bool {
+        if pairs.iter().any(|(key, _)| self.get(key, now).is_some()) {
+            return false;
+        }
+        self.mset(pairs, now);
+        true
+    }
+
+    fn incr_by(&mut self, key: Bytes, increment: i64, now: u64) -> Result<i64, Error> {
+        self.update_string(key, now, |current| {
+            let current = match current {
+                Some(current) => parse_integer(current).ok_or(Error::NotInteger)?,
+                None => 0,
+            };
+            let value = current.checked_add(increment).ok_or(Error::Overflow)?;
+            Ok((value.to_string().into(), value))
+        })
+    }
+
+    fn incr_by_float(&mut self, key: Bytes, increment: f64, now: u64) -> Result<Bytes, Error> {
+        self.update_string(key, now, |current| {
+            let current = match current {
+                Some(current) => std::str::from_utf8(current)
+                    .ok()
+                    .and_then(|current| current.parse::<f64>().ok())
+                    .filter(|current| !current.is_nan())
+                    .ok_or(Error::NotFloat)?,
+                None => 0.0,
+            };
+            let value = current + increment;
+            if !value.is_finite() {
+                return Err(Error::NotFinite);
+            }
+            let value = Bytes::from(format_double(value));
+            Ok((value.clone(), value))
+        })
+    }
+}
+
+/// Appends `value` to the string under `key`, creating it if needed, and
+/// returns its new length.
+pub async fn append(key: Bytes, value: &[u8]) -> Result<usize, Error> {
+    write().append(key, value, now_ms())
+}
+
+pub async fn len(key: &[u8]) -> Result<usize, Error> {
+    let len = with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::String(value) => Ok(value.len()),
+            _ => Err(Error::WrongType),
+        }
+    });
+    Ok(len.transpose()?.unwrap_or(0))
+}
+
+/// The bytes of the string under `key` from `start` to `end` inclusive,
+/// where negative offsets count from the end of the string.
+pub async fn get_range(key: &[u8], start: i64, end: i64) -> Result<Bytes, Error> {
+    let Some(value) = super::get(key).await? else {
+        return Ok(Bytes::new());
+    };
+    if start < 0 && end < 0 && start > end {
+        return Ok(Bytes::new());
+    }
+
+    let len = value.len() as i64;
+    let start = if start < 0 { start + len } else { start }.max(0);
+    let end = if end < 0 { end + len } else { end }.max(0).min(len - 1);
+    if start > end || len == 0 {
+        return Ok(Bytes::new());
+    }
+    Ok(value.slice(start as usize..=end as usize))
+}
+
+/// See `Db::set_range`.
+pub async fn set_range(key: Bytes, offset: usize, value: &[u8]) -> Result<usize, Error> {
+    write().set_range(key, offset, value, now_ms())
+}
+
+/// Removes the string under `key`, returning it.
+pub async fn get_del(key: &[u8]) -> Result<Option<Bytes>, Error> {
+    write().get_del(key, now_ms())
+}
+
+/// See `Db::get_ex`.
+pub async fn get_ex(key: &[u8], expiry: Option<Option<Expiry>>) -> Result<Option<Bytes>, Error> {
+    write().get_ex(key, expiry, now_ms())
+}
+
+/// The strings under `keys`, `None` for keys that don't exist or hold
+/// another type.
+pub async fn get_many(keys: &[Bytes]) -> Vec<Option<Bytes>> {
+    let now = now_ms();
+    let db = read();
+    keys.iter()
+        .map(|key| {
+            let entry = db.get(key, now)?;
+            entry.touch(now);
+            match &entry.value {
+                Value::String(value) => Some(value.clone()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Sets every key of `pairs`, clearing their TTLs.
+pub async fn set_many(pairs: Vec<(Bytes, Bytes)>) {
+    write().mset(pairs, now_ms())
+}
+
+/// See `Db::mset_nx`.
+pub async fn set_many_nx(pairs: Vec<(Bytes, Bytes)>) -> bool {
+    write().mset_nx(pairs, now_ms())
+}
+
+/// The longest common subsequence of two strings, with the runs of it that
+/// are contiguous in both, from the last one to the first.
+#[derive(Debug, PartialEq)]
+pub struct Lcs {
+    pub string: Bytes,
+    pub matches: Vec<LcsMatch>,
+}
+
+/// A run of the longest common subsequence, with the inclusive ranges it
+/// spans in each string.
+#[derive(Debug, PartialEq)]
+pub struct LcsMatch {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+    pub len: usize,
+}
+
+/// Computes the longest common subsequence of `a` and `b` with dynamic
+/// programming, keeping the matches at least `min_match_len` long. Like
+/// Redis, refuses inputs whose table would take more than a string may.
+fn longest_common_subsequence(a: &[u8], b: &[u8], min_match_len: usize) -> Result<Lcs, Error> {
+    // lengths[i][j] is the length of the LCS of a[..i] and b[..j].
+    let width = b.len() + 1;
+    let cells = (a.len() + 1)
+        .checked_mul(width)
+        .filter(|cells| cells.saturating_mul(std::mem::size_of::<u32>()) <= MAX_STRING_LEN)
+        .ok_or(Error::LcsTooLarge)?;
+    let mut lengths = vec![0u32; cells];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            let length = match x == y {
+                true => lengths[i * width + j] + 1,
+                false => lengths[i * width + j + 1].max(lengths[(i + 1) * width + j]),
+            };
+            lengths[(i + 1) * width + j + 1] = length;
+        }
+    }
+
+    // Walk back from the end, collecting the subsequence and its runs.
+    let mut string = vec![0; lengths[a.len() * width + b.len()] as usize];
+    let mut index = string.len();
+    let mut matches = Vec::new();
+    let mut run: Option<LcsMatch> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        let mut emit = false;
+        if a[i - 1] == b[j - 1] {
+            index -= 1;
+            string[index] = a[i - 1];
+            match &mut run {
+                Some(run) if run.a.0 == i && run.b.0 == j => {
+                    run.a.0 -= 1;
+                    run.b.0 -= 1;
+                    run.len += 1;
+                }
+                Some(_) => emit = true,
+                None => {
+                    run = Some(LcsMatch {
+                        a: (i - 1, i - 1),
+                        b: (j - 1, j - 1),
+                        len: 1,
+                    });
+                }
+            }
+            emit |= run.as_ref().is_some_and(|run| run.a.0 == 0 || run.b.0 == 0);
+            i -= 1;
+            j -= 1;
+        } else {
+            if lengths[(i - 1) * width + j] > lengths[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            emit = run.is_some();
+        }
+
+        if emit {
+            if let Some(run) = run.take() {
+                if run.len >= min_match_len {
+                    matches.push(run);
+                }
+            }
+        }
+    }
+
+    Ok(Lcs {
+        string: string.into(),
+        matches,
+    })
+}
+
+/// See `longest_common_subsequence`; keys that don't exist count as empty
+/// strings.
+pub async fn lcs(key_a: &[u8], key_b: &[u8], min_match_len: usize) -> Result<Lcs, Error> {
+    let (a, b) = {
+        let db = read();
+        let now = now_ms();
+        let value = |key| match db.string_ref(key, now) {
+            Ok(value) => Ok(value.cloned().unwrap_or_default()),
+            Err(_) => Err(Error::LcsWrongType),
+        };
+        (value(key_a)?, value(key_b)?)
+    };
+    longest_common_subsequence(&a, &b, min_match_len)
+}
+
+/// Adds `increment` to the integer stored under `key`, starting from 0 if
+/// it doesn't exist, and returns the result.
+pub async fn incr_by(key: Bytes, increment: i64) -> Result<i64, Error> {
+    write().incr_by(key, increment, now_ms())
+}
+
+/// Adds `increment` to the number stored under `key`, starting from 0 if it
+/// doesn't exist, and returns the result as stored.
+pub async fn incr_by_float(key: Bytes, increment: f64) -> Result<Bytes, Error> {
+    write().incr_by_float(key, increment, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(db: &Db, key: &str) -> Option<Bytes> {
+        match db.get(key.as_bytes(), 0).map(|entry| &entry.value) {
+            Some(Value::String(value)) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn incr_by_checks_value_and_overflow() {
+        let mut db = Db::default();
+        assert_eq!(db.incr_by(Bytes::from("counter"), 5, 0), Ok(5));
+        assert_eq!(db.incr_by(Bytes::from("counter"), -7, 0), Ok(-2));
+        assert_eq!(value(&db, "counter"), Some(Bytes::from("-2")));
+
+        db.insert(Bytes::from("max"), Bytes::from(i64::MAX.to_string()), None);
+        assert_eq!(db.incr_by(Bytes::from("max"), 1, 0), Err(Error::Overflow));
+        for value in ["1.5", " 1", "+1", "01", ""] {
+            db.insert(Bytes::from("text"), Bytes::from(value), None);
+            let incr = db.incr_by(Bytes::from("text"), 1, 0);
+            assert_eq!(incr, Err(Error::NotInteger), "{value:?}");
+        }
+
+        db.insert(Bytes::from("list"), Value::List(Default::default()), None);
+        assert_eq!(db.incr_by(Bytes::from("list"), 1, 0), Err(Error::WrongType));
+    }
+
+    #[test]
+    fn incr_keeps_ttl() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("counter"), Bytes::from("1"), Some(100));
+        assert_eq!(db.incr_by(Bytes::from("counter"), 1, 0), Ok(2));
+        assert_eq!(db.get(b"counter", 0).unwrap().expires_at, Some(100));
+        assert_eq!(db.incr_by(Bytes::from("counter"), 1, 100), Ok(1));
+        assert_eq!(db.get(b"counter", 100).unwrap().expires_at, None);
+    }
+
+    #[test]
+    fn incr_by_float_formats_result() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("float"), Bytes::from("10.50"), None);
+        let incr = db.incr_by_float(Bytes::from("float"), 0.1, 0);
+        assert_eq!(incr, Ok(Bytes::from("10.6")));
+        let incr = db.incr_by_float(Bytes::from("float"), -10.6, 0);
+        assert_eq!(incr, Ok(Bytes::from("0")));
+
+        db.insert(Bytes::from("text"), Bytes::from("nan"), None);
+        let incr = db.incr_by_float(Bytes::from("text"), 1.0, 0);
+        assert_eq!(incr, Err(Error::NotFloat));
+        let incr = db.incr_by_float(Bytes::from("float"), f64::INFINITY, 0);
+        assert_eq!(incr, Err(Error::NotFinite));
+    }
+
+    #[test]
+    fn append_and_set_range_grow_the_string() {
+        let mut db = Db::default();
+        assert_eq!(db.append(Bytes::from("key"), b"Hello", 0), Ok(5));
+        assert_eq!(db.append(Bytes::from("key"), b" World", 0), Ok(11));
+        assert_eq!(db.set_range(Bytes::from("key"), 6, b"Redis", 0), Ok(11));
+        assert_eq!(value(&db, "key"), Some(Bytes::from("Hello Redis")));
+
+        assert_eq!(db.set_range(Bytes::from("padded"), 3, b"x", 0), Ok(4));
+        assert_eq!(value(&db, "padded"), Some(Bytes::from_static(b"\0\0\0x")));
+        assert_eq!(db.set_range(Bytes::from("missing"), 3, b"", 0), Ok(0));
+        assert!(db.get(b"missing", 0).is_none());
+        let too_long = db.set_range(Bytes::from("key"), MAX_STRING_LEN, b"x", 0);
+        assert_eq!(too_long, Err(Error::StringTooLong));
+    }
+
+    #[test]
+    fn getdel_and_getex() {
+        let mut db = Db::default();
+        db.insert(Bytes::from("key"), Bytes::from("value"), None);
+        let ex = db.get_ex(b"key", Some(Some(Expiry::After(100))), 0);
+        assert_eq!(ex, Ok(Some(Bytes::from("value"))));
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, Some(100));
+        db.get_ex(b"key", Some(None), 0).unwrap();
+        assert_eq!(db.get(b"key", 0).unwrap().expires_at, None);
+
+        assert_eq!(db.get_del(b"key", 0), Ok(Some(Bytes::from("value"))));
+        assert_eq!(db.get_del(b"key", 0), Ok(None));
+        db.insert(Bytes::from("list"), Value::List(Default::default()), None);
+        assert_eq!(db.get_del(b"list", 0), Err(Error::WrongType));
+    }
+
+    #[test]
+    fn msetnx_sets_all_keys_or_none() {
+        let mut db = Db::default();
+        let pairs = |keys: &[&str]| {
+            keys.iter()
+                .map(|key| (Bytes::from(key.to_string()), Bytes::from("value")))
+                .collect()
+        };
+        assert!(db.mset_nx(pairs(&["a", "b"]), 0));
+        assert!(!db.mset_nx(pairs(&["b", "c"]), 0));
+        assert!(db.get(b"c", 0).is_none());
+    }
+
+    #[test]
+    fn lcs_reports_matches_from_the_end() {
+        let lcs = longest_common_subsequence(b"ohmytext", b"mynewtext", 0).unwrap();
+        assert_eq!(lcs.string, Bytes::from("mytext"));
+        let matches = vec![
+            LcsMatch {
+                a: (4, 7),
+                b: (5, 8),
+                len: 4,
+            },
+            LcsMatch {
+                a: (2, 3),
+                b: (0, 1),
+                len: 2,
+            },
+        ];
+        assert_eq!(lcs.matches, matches);
+
+        let lcs = longest_common_subsequence(b"ohmytext", b"mynewtext", 3).unwrap();
+        assert_eq!(lcs.matches.len(), 1);
+        let lcs = longest_common_subsequence(b"", b"abc", 0).unwrap();
+        assert!(lcs.string.is_empty());
+    }
+
+    #[test]
+    fn lcs_refuses_tables_larger_than_a_string() {
+        // 12001 * 12001 cells of 4 bytes is just over 512MB.
+        let long = vec![b'a'; 12_000];
+        assert_eq!(
+            longest_common_subsequence(&long, &long, 0).map(|lcs| lcs.string.len()),
+            Err(Error::LcsTooLarge)
+        );
+        let huge = vec![b'a'; 4 * 1024 * 1024];
+        assert_eq!(
+            longest_common_subsequence(&huge, &huge, 0).map(|lcs| lcs.string.len()),
+            Err(Error::LcsTooLarge)
+        );
+    }
+}
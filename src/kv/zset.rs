@@ -0,0 +1,1100 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+
+use super::list::resolve_range;
+use super::set::Set;
+use super::{now_ms, random_index, read, with_entry, write, Db, Entry, Error, Value};
+use crate::commands::{
+    Aggregate, LexBound, LexRange, RangeBy, ScoreEnd, ScoreRange, SetOp, ZAddOptions,
+    ZCombineOptions, ZRangeOptions,
+};
+
+/// Levels a skiplist node can have at most, enough for 4^32 members.
+const MAX_LEVEL: usize = 32;
+
+/// Index of the head node, which holds no member.
+const HEAD: usize = 0;
+
+/// Whether `(score, member)` sorts before `(other_score, other_member)`:
+/// by score, then by member for equal scores.
+fn precedes(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> bool {
+    score < other_score || (score == other_score && member < other_member)
+}
+
+#[derive(Debug, Clone)]
+struct Link {
+    next: Option<usize>,
+    /// Number of nodes the link skips over, counting the one it leads to.
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    links: Vec<Link>,
+    prev: Option<usize>,
+}
+
+/// Members ordered by score, then by member, the way Redis orders them.
+///
+/// Every node links forward to the next node on each of its levels, a node
+/// getting one more level with probability 1/4, so lookups skip over most of
+/// the list. Links record how many nodes they span, which turns finding the
+/// rank of a member and the member at a rank into O(log n) walks too.
+///
+/// Nodes live in a vector and refer to each other by index; removed nodes are
+/// recycled through `free`.
+#[derive(Debug, Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    /// Number of levels in use by the head.
+    level: usize,
+    len: usize,
+    tail: Option<usize>,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: f64::NEG_INFINITY,
+            links: vec![
+                Link {
+                    next: None,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+            prev: None,
+        };
+        Self {
+            nodes: vec![head],
+            free: Vec::new(),
+            level: 1,
+            len: 0,
+            tail: None,
+        }
+    }
+}
+
+impl SkipList {
+    fn random_level() -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL && random_index(4) == 0 {
+            level += 1;
+        }
+        level
+    }
+
+    fn first(&self) -> Option<usize> {
+        self.nodes[HEAD].links[0].next
+    }
+
+    /// The node after `id`, or before it if `rev` is set.
+    fn step(&self, id: usize, rev: bool) -> Option<usize> {
+        match rev {
+            true => self.nodes[id].prev,
+            false => self.nodes[id].links[0].next,
+        }
+    }
+
+    /// Returns, for each level, the last node sorting before `(score, member)`.
+    fn predecessors(&self, score: f64, member: &[u8]) -> [usize; MAX_LEVEL] {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for (level, update) in update.iter_mut().enumerate().take(self.level).rev() {
+            while let Some(next) = self.nodes[x].links[level].next {
+                let node = &self.nodes[next];
+                if !precedes(node.score, &node.member, score, member) {
+                    break;
+                }
+                x = next;
+            }
+            *update = x;
+        }
+        update
+    }
+
+    /// Inserts a member that isn't in the list yet.
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for level in (0..self.level).rev() {
+            rank[level] = if level == self.level - 1 {
+                0
+            } else {
+                rank[level + 1]
+            };
+            while let Some(next) = self.nodes[x].links[level].next {
+                let node = &self.nodes[next];
+                if !precedes(node.score, &node.member, score, &member) {
+                    break;
+                }
+                rank[level] += self.nodes[x].links[level].span;
+                x = next;
+            }
+            update[level] = x;
+        }
+
+        let height = Self::random_level();
+        if height > self.level {
+            for level in self.level..height {
+                rank[level] = 0;
+                update[level] = HEAD;
+                self.nodes[HEAD].links[level].span = self.len;
+            }
+            self.level = height;
+        }
+
+        let node = Node {
+            member,
+            score,
+            links: vec![
+                Link {
+                    next: None,
+                    span: 0
+                };
+                height
+            ],
+            prev: (update[0] != HEAD).then_some(update[0]),
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for level in 0..height {
+            let before = &mut self.nodes[update[level]].links[level];
+            let link = Link {
+                next: before.next,
+                span: before.span - (rank[0] - rank[level]),
+            };
+            *before = Link {
+                next: Some(id),
+                span: rank[0] - rank[level] + 1,
+            };
+            self.nodes[id].links[level] = link;
+        }
+        for (level, before) in update.iter().enumerate().take(self.level).skip(height) {
+            self.nodes[*before].links[level].span += 1;
+        }
+
+        match self.nodes[id].links[0].next {
+            Some(next) => self.nodes[next].prev = Some(id),
+            None => self.tail = Some(id),
+        }
+        self.len += 1;
+    }
+
+    /// Removes `member`, which must have `score`, returning whether it was found.
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let update = self.predecessors(score, member);
+        let Some(id) = self.nodes[update[0]].links[0].next else {
+            return false;
+        };
+        if self.nodes[id].score != score || self.nodes[id].member != member {
+            return false;
+        }
+
+        for (level, before) in update.iter().enumerate().take(self.level) {
+            if self.nodes[*before].links[level].next == Some(id) {
+                let link = self.nodes[id].links[level].clone();
+                let before = &mut self.nodes[*before].links[level];
+                before.span = before.span + link.span - 1;
+                before.next = link.next;
+            } else {
+                self.nodes[*before].links[level].span -= 1;
+            }
+        }
+        let prev = self.nodes[id].prev;
+        match self.nodes[id].links[0].next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+        while self.level > 1 && self.nodes[HEAD].links[self.level - 1].next.is_none() {
+            self.level -= 1;
+        }
+
+        // Release the member right away rather than when the slot is reused.
+        self.nodes[id].member = Bytes::new();
+        self.nodes[id].links = Vec::new();
+        self.free.push(id);
+        self.len -= 1;
+        true
+    }
+
+    /// 1-based rank of `member`, which must have `score`.
+    fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[level].next {
+                let node = &self.nodes[next];
+                if precedes(score, member, node.score, &node.member) {
+                    break;
+                }
+                rank += self.nodes[x].links[level].span;
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].member == member {
+                return Some(rank);
+            }
+        }
+        None
+    }
+
+    /// Node at the 1-based `rank`.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[level].next {
+                let span = self.nodes[x].links[level].span;
+                if traversed + span > rank {
+                    break;
+                }
+                traversed += span;
+                x = next;
+            }
+            if traversed == rank && x != HEAD {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// First node for which `before` doesn't hold, given that it holds for a
+    /// prefix of the list.
+    fn first_after(&self, before: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut x = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[level].next {
+                if !before(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        self.nodes[x].links[0].next
+    }
+
+    /// Last node for which `within` holds, given that it holds for a prefix
+    /// of the list.
+    fn last_within(&self, within: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut x = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[level].next {
+                if !within(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        (x != HEAD).then_some(x)
+    }
+}
+
+fn below_min_score(range: &ScoreRange, score: f64) -> bool {
+    match range.min.exclusive {
+        true => score <= range.min.value,
+        false => score < range.min.value,
+    }
+}
+
+fn within_max_score(range: &ScoreRange, score: f64) -> bool {
+    match range.max.exclusive {
+        true => score < range.max.value,
+        false => score <= range.max.value,
+    }
+}
+
+fn below_min_lex(range: &LexRange, member: &[u8]) -> bool {
+    match &range.min {
+        LexBound::Min => false,
+        LexBound::Max => true,
+        LexBound::Inclusive(min) => member < min,
+        LexBound::Exclusive(min) => member <= min,
+    }
+}
+
+fn within_max_lex(range: &LexRange, member: &[u8]) -> bool {
+    match &range.max {
+        LexBound::Min => false,
+        LexBound::Max => true,
+        LexBound::Inclusive(max) => member <= max,
+        LexBound::Exclusive(max) => member < max,
+    }
+}
+
+/// A sorted set: the score of each member, and the members in order.
+#[derive(Debug, Clone, Default)]
+pub(super) struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    list: SkipList,
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.scores == other.scores
+    }
+}
+
+// Scores are never NaN, so every sorted set equals itself.
+impl Eq for SortedSet {}
+
+impl SortedSet {
+    pub(super) fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub(super) fn members(&self) -> impl Iterator<Item = &Bytes> {
+        self.scores.keys()
+    }
+
+    pub(super) fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning whether it is new.
+    pub(super) fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(previous) if previous == score => false,
+            Some(previous) => {
+                self.list.remove(previous, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    pub(super) fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// 0-based rank of `member`, counting from the highest score if `rev`.
+    pub(super) fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member)?;
+        Some(match rev {
+            true => self.len() - rank,
+            false => rank - 1,
+        })
+    }
+
+    fn entry(&self, id: usize) -> (Bytes, f64) {
+        let node = &self.list.nodes[id];
+        (node.member.clone(), node.score)
+    }
+
+    /// Members with their scores, from `start` on while `within` holds,
+    /// skipping the first `offset` and returning at most `count` unless it is
+    /// negative.
+    fn walk(
+        &self,
+        start: Option<usize>,
+        rev: bool,
+        within: impl Fn(&Node) -> bool,
+        offset: usize,
+        count: i64,
+    ) -> Vec<(Bytes, f64)> {
+        let mut items = Vec::new();
+        let mut id = start;
+        let mut skip = offset;
+        while let Some(current) = id {
+            if !within(&self.list.nodes[current]) || items.len() as i64 == count {
+                break;
+            }
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                items.push(self.entry(current));
+            }
+            id = self.list.step(current, rev);
+        }
+        items
+    }
+
+    /// Members with their scores selected by `options`.
+    pub(super) fn range(&self, options: &ZRangeOptions) -> Vec<(Bytes, f64)> {
+        let (offset, count) = options.limit.unwrap_or((0, -1));
+        let Ok(offset) = usize::try_from(offset) else {
+            return Vec::new();
+        };
+        let rev = options.rev;
+
+        match &options.by {
+            RangeBy::Rank(start, stop) => {
+                let Some((start, stop)) = resolve_range(*start, *stop, self.len()) else {
+                    return Vec::new();
+                };
+                let first = match rev {
+                    true => self.list.by_rank(self.len() - start),
+                    false => self.list.by_rank(start + 1),
+                };
+                let count = (stop - start + 1) as i64;
+                self.walk(first, rev, |_| true, 0, count)
+            }
+            RangeBy::Score(range) => {
+                let below = |node: &Node| below_min_score(range, node.score);
+                let within = |node: &Node| within_max_score(range, node.score);
+                match rev {
+                    true => {
+                        let first = self.list.last_within(within);
+                        self.walk(first, true, |node| !below(node), offset, count)
+                    }
+                    false => {
+                        let first = self.list.first_after(below);
+                        self.walk(first, false, within, offset, count)
+                    }
+                }
+            }
+            RangeBy::Lex(range) => {
+                let below = |node: &Node| below_min_lex(range, &node.member);
+                let within = |node: &Node| within_max_lex(range, &node.member);
+                match rev {
+                    true => {
+                        let first = self.list.last_within(within);
+                        self.walk(first, true, |node| !below(node), offset, count)
+                    }
+                    false => {
+                        let first = self.list.first_after(below);
+                        self.walk(first, false, within, offset, count)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of members between the first one `below` doesn't hold for and
+    /// the last one `within` holds for, found from their ranks.
+    fn count_between(
+        &self,
+        below: impl Fn(&Node) -> bool,
+        within: impl Fn(&Node) -> bool,
+    ) -> usize {
+        let (Some(first), Some(last)) = (
+            self.list.first_after(&below),
+            self.list.last_within(&within),
+        ) else {
+            return 0;
+        };
+        let rank = |id: usize| {
+            let node = &self.list.nodes[id];
+            self.list
+                .rank(node.score, &node.member)
+                .expect("node is in the list")
+        };
+        (rank(last) + 1).saturating_sub(rank(first))
+    }
+
+    pub(super) fn count_by_score(&self, range: &ScoreRange) -> usize {
+        self.count_between(
+            |node| below_min_score(range, node.score),
+            |node| within_max_score(range, node.score),
+        )
+    }
+
+    pub(super) fn count_by_lex(&self, range: &LexRange) -> usize {
+        self.count_between(
+            |node| below_min_lex(range, &node.member),
+            |node| within_max_lex(range, &node.member),
+        )
+    }
+
+    /// Members with their scores, in order.
+    pub(super) fn entries(&self) -> Vec<(Bytes, f64)> {
+        self.walk(self.list.first(), false, |_| true, 0, -1)
+    }
+
+    /// Removes up to `count` members from the `end` of the set.
+    pub(super) fn pop(&mut self, end: ScoreEnd, count: usize) -> Vec<(Bytes, f64)> {
+        let mut popped = Vec::new();
+        while popped.len() < count {
+            let id = match end {
+                ScoreEnd::Min => self.list.first(),
+                ScoreEnd::Max => self.list.tail,
+            };
+            let Some(id) = id else {
+                break;
+            };
+            let (member, score) = self.entry(id);
+            self.remove(&member);
+            popped.push((member, score));
+        }
+        popped
+    }
+}
+
+/// An input of the sorted set algebra commands, where plain sets count as
+/// sorted sets with every score 1.
+enum Input<'a> {
+    Sorted(&'a SortedSet),
+    Plain(&'a Set),
+}
+
+impl Input<'_> {
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Input::Sorted(zset) => zset.score(member),
+            Input::Plain(set) => set.contains(member).then_some(1.0),
+        }
+    }
+
+    fn scores(&self) -> Box<dyn Iterator<Item = (&Bytes, f64)> + '_> {
+        match self {
+            Input::Sorted(zset) => {
+                Box::new(zset.scores.iter().map(|(member, score)| (member, *score)))
+            }
+            Input::Plain(set) => Box::new(set.iter().map(|member| (member, 1.0))),
+        }
+    }
+}
+
+/// Multiplies `score` by `weight`, where 0 times infinity counts as 0 the way
+/// it does in Redis.
+fn weighted(score: f64, weight: f64) -> f64 {
+    match score * weight {
+        score if score.is_nan() => 0.0,
+        score => score,
+    }
+}
+
+fn aggregate(aggregate: Aggregate, score: f64, other: f64) -> f64 {
+    match aggregate {
+        // Infinities of opposite signs add up to 0 rather than NaN.
+        Aggregate::Sum => match score + other {
+            sum if sum.is_nan() => 0.0,
+            sum => sum,
+        },
+        Aggregate::Min => score.min(other),
+        Aggregate::Max => score.max(other),
+    }
+}
+
+impl Db {
+    fn zset_mut(&mut self, key: &[u8], now: u64) -> Result<Option<&mut SortedSet>, Error> {
+        match self.get_mut(key, now).map(|entry| &mut entry.value) {
+            Some(Value::SortedSet(zset)) => Ok(Some(zset)),
+            Some(_) => Err(Error::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the sorted set under `key`, creating an empty one if the key
+    /// is missing.
+    fn zset_or_default(&mut self, key: Bytes, now: u64) -> Result<&mut SortedSet, Error> {
+        if self.zset_mut(&key, now)?.is_none() {
+            let entry = Entry::new(Value::SortedSet(SortedSet::default()), None, now);
+            self.insert_entry(key.clone(), entry);
+        }
+        Ok(self
+            .zset_mut(&key, now)?
+            .expect("sorted set was just created"))
+    }
+
+    /// Adds or updates the members in `pairs` as allowed by `options`.
+    /// Returns how many members were added, or also changed with `ch`, and
+    /// the score of the last member written, which is what INCR replies.
+    pub(super) fn zadd(
+        &mut self,
+        key: Bytes,
+        pairs: Vec<(f64, Bytes)>,
+        options: &ZAddOptions,
+        now: u64,
+    ) -> Result<(usize, Option<f64>), Error> {
+        let zset = self.zset_or_default(key.clone(), now)?;
+        let mut added = 0;
+        let mut changed = 0;
+        let mut last = None;
+
+        for (score, member) in pairs {
+            let current = zset.score(&member);
+            let score = match (current, options.incr) {
+                (Some(current), true) => current + score,
+                _ => score,
+            };
+            if score.is_nan() {
+                self.remove_if_empty(&key);
+                return Err(Error::ScoreNaN);
+            }
+
+            let allowed = match current {
+                None => !options.xx,
+                Some(current) => {
+                    !options.nx
+                        && (!options.gt || score > current)
+                        && (!options.lt || score < current)
+                }
+            };
+            if !allowed {
+                continue;
+            }
+            last = Some(score);
+            match current {
+                None => added += 1,
+                Some(current) if current != score => changed += 1,
+                Some(_) => {}
+            }
+            zset.insert(member, score);
+        }
+
+        self.remove_if_empty(&key);
+        let count = match options.ch {
+            true => added + changed,
+            false => added,
+        };
+        Ok((count, last))
+    }
+
+    fn zrem(&mut self, key: &[u8], members: &[Bytes], now: u64) -> Result<usize, Error> {
+        let Some(zset) = self.zset_mut(key, now)? else {
+            return Ok(0);
+        };
+
+        let removed = members.iter().filter(|member| zset.remove(member)).count();
+        self.remove_if_empty(key);
+        Ok(removed)
+    }
+
+    /// Removes up to `count` members from the `end` of the sorted set,
+    /// returning `None` if the key is missing.
+    fn zpop(
+        &mut self,
+        key: &[u8],
+        end: ScoreEnd,
+        count: usize,
+        now: u64,
+    ) -> Result<Option<Vec<(Bytes, f64)>>, Error> {
+        let Some(zset) = self.zset_mut(key, now)? else {
+            return Ok(None);
+        };
+
+        let popped = zset.pop(end, count);
+        self.remove_if_empty(key);
+        Ok(Some(popped))
+    }
+}
+
+impl Db {
+    /// Combines the sorted sets or sets under `keys`, missing keys counting as
+    /// empty. The scores of each input are weighted before they are
+    /// aggregated; ZDIFF keeps the scores of the first input as they are.
+    fn zcombine(
+        &self,
+        op: SetOp,
+        keys: &[Bytes],
+        options: &ZCombineOptions,
+        now: u64,
+    ) -> Result<SortedSet, Error> {
+        let inputs = keys
+            .iter()
+            .map(|key| match self.get(key, now).map(|entry| &entry.value) {
+                Some(Value::SortedSet(zset)) => Ok(Some(Input::Sorted(zset))),
+                Some(Value::Set(set)) => Ok(Some(Input::Plain(set))),
+                Some(_) => Err(Error::WrongType),
+                None => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let weight = |index: usize| match &options.weights {
+            Some(weights) => weights[index],
+            None => 1.0,
+        };
+
+        let mut scores: HashMap<Bytes, f64> = HashMap::new();
+        match op {
+            SetOp::Union => {
+                for (index, input) in inputs.iter().enumerate() {
+                    let Some(input) = input else {
+                        continue;
+                    };
+                    for (member, score) in input.scores() {
+                        let score = weighted(score, weight(index));
+                        scores
+                            .entry(member.clone())
+                            .and_modify(|current| {
+                                *current = aggregate(options.aggregate, *current, score)
+                            })
+                            .or_insert(score);
+                    }
+                }
+            }
+            SetOp::Inter | SetOp::Diff => {
+                let Some((Some(first), rest)) = inputs.split_first() else {
+                    return Ok(SortedSet::default());
+                };
+                'members: for (member, score) in first.scores() {
+                    let mut score = weighted(score, weight(0));
+                    for (index, input) in rest.iter().enumerate() {
+                        let other = input.as_ref().and_then(|input| input.score(member));
+                        match (op, other) {
+                            (SetOp::Inter, Some(other)) => {
+                                let other = weighted(other, weight(index + 1));
+                                score = aggregate(options.aggregate, score, other);
+                            }
+                            (SetOp::Diff, None) => {}
+                            _ => continue 'members,
+                        }
+                    }
+                    scores.insert(member.clone(), score);
+                }
+            }
+        }
+
+        let mut result = SortedSet::default();
+        for (member, score) in scores {
+            result.insert(member, score);
+        }
+        Ok(result)
+    }
+
+    /// Stores the combination of `keys` under `destination`, replacing it, and
+    /// returns its size. An empty result deletes `destination`.
+    fn zcombine_store(
+        &mut self,
+        op: SetOp,
+        destination: Bytes,
+        keys: &[Bytes],
+        options: &ZCombineOptions,
+        now: u64,
+    ) -> Result<usize, Error> {
+        let result = self.zcombine(op, keys, options, now)?;
+        let len = result.len();
+        if result.is_empty() {
+            self.remove(&destination);
+        } else {
+            self.insert_entry(destination, Entry::new(Value::SortedSet(result), None, now));
+        }
+        Ok(len)
+    }
+}
+
+/// Runs `f` on the sorted set under `key`, marking it accessed.
+fn with_zset<T>(key: &[u8], f: impl FnOnce(&SortedSet) -> T) -> Result<Option<T>, Error> {
+    with_entry(key, |entry, now| {
+        entry.touch(now);
+        match &entry.value {
+            Value::SortedSet(zset) => Ok(f(zset)),
+            _ => Err(Error::WrongType),
+        }
+    })
+    .transpose()
+}
+
+/// See `Db::zadd`.
+pub async fn add(
+    key: Bytes,
+    pairs: Vec<(f64, Bytes)>,
+    options: &ZAddOptions,
+) -> Result<(usize, Option<f64>), Error> {
+    write().zadd(key, pairs, options, now_ms())
+}
+
+/// Adds `increment` to the score of `member`, returning the new score.
+pub async fn incr_by(key: Bytes, increment: f64, member: Bytes) -> Result<f64, Error> {
+    let options = ZAddOptions {
+        incr: true,
+        ..ZAddOptions::default()
+    };
+    let (_, score) = write().zadd(key, vec![(increment, member)], &options, now_ms())?;
+    Ok(score.expect("a plain increment is always applied"))
+}
+
+pub async fn remove(key: &[u8], members: &[Bytes]) -> Result<usize, Error> {
+    write().zrem(key, members, now_ms())
+}
+
+pub async fn pop(
+    key: &[u8],
+    end: ScoreEnd,
+    count: usize,
+) -> Result<Option<Vec<(Bytes, f64)>>, Error> {
+    write().zpop(key, end, count, now_ms())
+}
+
+pub async fn card(key: &[u8]) -> Result<usize, Error> {
+    Ok(with_zset(key, |zset| zset.len())?.unwrap_or(0))
+}
+
+pub async fn score(key: &[u8], member: &[u8]) -> Result<Option<f64>, Error> {
+    Ok(with_zset(key, |zset| zset.score(member))?.flatten())
+}
+
+pub async fn scores(key: &[u8], members: &[Bytes]) -> Result<Vec<Option<f64>>, Error> {
+    let scores = with_zset(key, |zset| {
+        members.iter().map(|member| zset.score(member)).collect()
+    })?;
+    Ok(scores.unwrap_or_else(|| vec![None; members.len()]))
+}
+
+/// Rank of `member` along with its score.
+pub async fn rank(key: &[u8], member: &[u8], rev: bool) -> Result<Option<(usize, f64)>, Error> {
+    let rank = with_zset(key, |zset| {
+        let rank = zset.rank(member, rev)?;
+        Some((rank, zset.score(member)?))
+    })?;
+    Ok(rank.flatten())
+}
+
+pub async fn range(key: &[u8], options: &ZRangeOptions) -> Result<Vec<(Bytes, f64)>, Error> {
+    Ok(with_zset(key, |zset| zset.range(options))?.unwrap_or_default())
+}
+
+pub async fn count(key: &[u8], range: &ScoreRange) -> Result<usize, Error> {
+    Ok(with_zset(key, |zset| zset.count_by_score(range))?.unwrap_or(0))
+}
+
+pub async fn lex_count(key: &[u8], range: &LexRange) -> Result<usize, Error> {
+    Ok(with_zset(key, |zset| zset.count_by_lex(range))?.unwrap_or(0))
+}
+
+/// ZINTER, ZUNION and ZDIFF, ordered by score.
+pub async fn combine(
+    op: SetOp,
+    keys: &[Bytes],
+    options: &ZCombineOptions,
+) -> Result<Vec<(Bytes, f64)>, Error> {
+    Ok(read().zcombine(op, keys, options, now_ms())?.entries())
+}
+
+/// ZINTERSTORE, ZUNIONSTORE and ZDIFFSTORE.
+pub async fn combine_store(
+    op: SetOp,
+    destination: Bytes,
+    keys: &[Bytes],
+    options: &ZCombineOptions,
+) -> Result<usize, Error> {
+    write().zcombine_store(op, destination, keys, options, now_ms())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::ScoreBound;
+
+    fn zset(items: &[(&str, f64)]) -> SortedSet {
+        let mut zset = SortedSet::default();
+        for (member, score) in items {
+            zset.insert(Bytes::from(member.to_string()), *score);
+        }
+        zset
+    }
+
+    fn members(items: Vec<(Bytes, f64)>) -> Vec<String> {
+        items
+            .into_iter()
+            .map(|(member, _)| String::from_utf8(member.to_vec()).unwrap())
+            .collect()
+    }
+
+    fn by(by: RangeBy, rev: bool, limit: Option<(i64, i64)>) -> ZRangeOptions {
+        ZRangeOptions {
+            by,
+            rev,
+            limit,
+            with_scores: false,
+        }
+    }
+
+    fn scores(min: f64, max: f64, exclusive: bool) -> ScoreRange {
+        ScoreRange {
+            min: ScoreBound {
+                value: min,
+                exclusive,
+            },
+            max: ScoreBound {
+                value: max,
+                exclusive,
+            },
+        }
+    }
+
+    #[test]
+    fn skiplist_keeps_ranks_through_updates() {
+        let mut zset = SortedSet::default();
+        for i in 0..500 {
+            zset.insert(Bytes::from(format!("m{i:03}")), ((i * 7) % 500) as f64);
+        }
+        for i in (0..500).step_by(3) {
+            zset.remove(format!("m{i:03}").as_bytes());
+        }
+        zset.insert(Bytes::from("m001"), -1.0);
+
+        let mut expected: Vec<(f64, String)> = zset
+            .scores
+            .iter()
+            .map(|(member, score)| (*score, String::from_utf8(member.to_vec()).unwrap()))
+            .collect();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for (rank, (_, member)) in expected.iter().enumerate() {
+            assert_eq!(zset.rank(member.as_bytes(), false), Some(rank));
+            let id = zset.list.by_rank(rank + 1).unwrap();
+            assert_eq!(zset.list.nodes[id].member, member.as_bytes());
+        }
+        let all = zset.range(&by(RangeBy::Rank(0, -1), true, None));
+        assert_eq!(all.len(), expected.len());
+        assert_eq!(
+            members(all).first(),
+            expected.last().map(|(_, member)| member)
+        );
+    }
+
+    #[test]
+    fn range_by_rank_score_and_lex() {
+        let zset = zset(&[("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)]);
+
+        let range = zset.range(&by(RangeBy::Rank(1, -2), false, None));
+        assert_eq!(members(range), ["b", "c"]);
+        let range = zset.range(&by(RangeBy::Rank(0, 1), true, None));
+        assert_eq!(members(range), ["d", "c"]);
+
+        let range = zset.range(&by(RangeBy::Score(scores(1.0, 3.0, true)), false, None));
+        assert_eq!(members(range), ["b", "c"]);
+        let all = scores(f64::NEG_INFINITY, f64::INFINITY, false);
+        let range = zset.range(&by(RangeBy::Score(all), true, Some((1, 2))));
+        assert_eq!(members(range), ["c", "b"]);
+        let range = zset.range(&by(RangeBy::Score(all), false, Some((-1, 2))));
+        assert!(range.is_empty());
+
+        let lex = LexRange {
+            min: LexBound::Exclusive(Bytes::from("a")),
+            max: LexBound::Max,
+        };
+        let range = zset.range(&by(RangeBy::Lex(lex.clone()), false, None));
+        assert_eq!(members(range), ["b", "c", "d"]);
+        assert_eq!(zset.count_by_lex(&lex), 3);
+    }
+
+    #[test]
+    fn count_by_score_uses_bounds() {
+        let zset = zset(&[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+        assert_eq!(zset.count_by_score(&scores(1.0, 3.0, false)), 3);
+        assert_eq!(zset.count_by_score(&scores(1.0, 3.0, true)), 1);
+        assert_eq!(zset.count_by_score(&scores(4.0, 5.0, false)), 0);
+        assert_eq!(zset.count_by_score(&scores(3.0, 1.0, false)), 0);
+    }
+
+    #[test]
+    fn zadd_follows_flags() {
+        let mut db = Db::default();
+        let key = Bytes::from("zset");
+        let pairs = vec![(1.0, Bytes::from("a")), (2.0, Bytes::from("b"))];
+        let options = ZAddOptions::default();
+        assert_eq!(db.zadd(key.clone(), pairs, &options, 0), Ok((2, Some(2.0))));
+
+        let options = ZAddOptions {
+            gt: true,
+            ch: true,
+            ..ZAddOptions::default()
+        };
+        let pairs = vec![(0.5, Bytes::from("a")), (3.0, Bytes::from("b"))];
+        assert_eq!(db.zadd(key.clone(), pairs, &options, 0), Ok((1, Some(3.0))));
+
+        let options = ZAddOptions {
+            xx: true,
+            ..ZAddOptions::default()
+        };
+        let pairs = vec![(1.0, Bytes::from("new"))];
+        assert_eq!(
+            db.zadd(Bytes::from("other"), pairs, &options, 0),
+            Ok((0, None))
+        );
+        assert!(db.get(b"other", 0).is_none());
+
+        let options = ZAddOptions {
+            incr: true,
+            ..ZAddOptions::default()
+        };
+        let pairs = vec![(f64::INFINITY, Bytes::from("a"))];
+        db.zadd(key.clone(), pairs, &options, 0).unwrap();
+        let pairs = vec![(f64::NEG_INFINITY, Bytes::from("a"))];
+        assert_eq!(db.zadd(key, pairs, &options, 0), Err(Error::ScoreNaN));
+    }
+
+    #[test]
+    fn zpop_takes_from_either_end() {
+        let mut db = Db::default();
+        let pairs = vec![
+            (1.0, Bytes::from("a")),
+            (2.0, Bytes::from("b")),
+            (3.0, Bytes::from("c")),
+        ];
+        db.zadd(Bytes::from("zset"), pairs, &ZAddOptions::default(), 0)
+            .unwrap();
+
+        let popped = db.zpop(b"zset", ScoreEnd::Max, 2, 0).unwrap().unwrap();
+        assert_eq!(members(popped), ["c", "b"]);
+        let popped = db.zpop(b"zset", ScoreEnd::Min, 5, 0).unwrap().unwrap();
+        assert_eq!(members(popped), ["a"]);
+        assert!(db.get(b"zset", 0).is_none());
+        assert_eq!(db.zpop(b"zset", ScoreEnd::Min, 1, 0), Ok(None));
+    }
+
+    #[test]
+    fn zcombine_weights_and_aggregates() {
+        let mut db = Db::default();
+        let pairs = vec![(1.0, Bytes::from("a")), (2.0, Bytes::from("b"))];
+        db.zadd(Bytes::from("z"), pairs, &ZAddOptions::default(), 0)
+            .unwrap();
+        let set = Set::from_iter([Bytes::from("b"), Bytes::from("c")]);
+        db.insert(Bytes::from("s"), Value::Set(set), None);
+        let keys = vec![Bytes::from("z"), Bytes::from("s")];
+
+        let options = ZCombineOptions {
+            weights: Some(vec![2.0, 10.0]),
+            ..ZCombineOptions::default()
+        };
+        let union = db.zcombine(SetOp::Union, &keys, &options, 0).unwrap();
+        let expected = [("a", 2.0), ("c", 10.0), ("b", 14.0)]
+            .map(|(member, score)| (Bytes::from(member), score));
+        assert_eq!(union.entries(), expected);
+
+        let options = ZCombineOptions {
+            aggregate: Aggregate::Max,
+            ..ZCombineOptions::default()
+        };
+        let inter = db.zcombine(SetOp::Inter, &keys, &options, 0).unwrap();
+        assert_eq!(inter.entries(), [(Bytes::from("b"), 2.0)]);
+        let diff = db.zcombine(SetOp::Diff, &keys, &options, 0).unwrap();
+        assert_eq!(diff.entries(), [(Bytes::from("a"), 1.0)]);
+
+        let options = ZCombineOptions {
+            weights: Some(vec![f64::INFINITY, 0.0]),
+            ..ZCombineOptions::default()
+        };
+        let union = db.zcombine(SetOp::Union, &keys, &options, 0).unwrap();
+        assert_eq!(union.score(b"c"), Some(0.0));
+
+        let keys = vec![Bytes::from("z"), Bytes::from("missing")];
+        let stored = db.zcombine_store(SetOp::Inter, Bytes::from("s"), &keys, &options, 0);
+        assert_eq!(stored, Ok(0));
+        assert!(db.get(b"s", 0).is_none());
+
+        db.insert(Bytes::from("string"), Bytes::from("value"), None);
+        let keys = vec![Bytes::from("z"), Bytes::from("string")];
+        let options = ZCombineOptions::default();
+        let union = db.zcombine(SetOp::Union, &keys, &options, 0);
+        assert_eq!(union.err(), Some(Error::WrongType));
+    }
+}
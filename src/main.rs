@@ -1,16 +1,18 @@
 #![allow(unused_imports)]
 use std::{
     collections::HashMap,
+    future::Future,
     io::{Read, Write},
     sync::Arc,
+    task::Poll,
 };
 
 use anyhow::Context;
-use commands::Command;
+use decoder::Decoder;
 use resp::RespValue;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpListener,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
     sync::Mutex,
 };
 
@@ -18,6 +20,10 @@ mod cli;
 mod commands;
 mod config;
 mod cursor;
+mod decoder;
+mod glob;
+mod handler;
+mod json;
 mod kv;
 mod resp;
 
@@ -38,93 +44,151 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Server listening on 127.0.0.1:6379");
 
     loop {
-        let (mut socket, _addr) = listener.accept().await?;
-
-        async fn send(socket: &mut tokio::net::TcpStream, msg: RespValue) -> anyhow::Result<()> {
-            socket
-                .write_all(&msg.as_bytes())
-                .await
-                .context("Failed to write to socket")?;
-            Ok(())
-        }
+        let (socket, _addr) = listener.accept().await?;
 
         tokio::spawn(async move {
-            let mut buf = [0; 1024];
+            if let Err(error) = handle_connection(socket).await {
+                tracing::warn!(?error, "Connection closed with error");
+            }
+        });
+    }
+}
 
-            loop {
-                let n = socket
-                    .read(&mut buf)
-                    .await
-                    .expect("Failed to read from socket");
+async fn handle_connection(mut socket: impl AsyncRead + AsyncWrite + Unpin) -> anyhow::Result<()> {
+    let mut decoder = Decoder::new();
+    let mut session = handler::Session::new();
+    let mut buf = [0; 4096];
 
-                if n == 0 {
-                    return;
-                }
+    loop {
+        let n = socket
+            .read(&mut buf)
+            .await
+            .context("Failed to read from socket")?;
 
-                match Command::from_bytes(&buf[..n]) {
-                    Ok(Command::ConfigGet(key)) => {
-                        tracing::info!(?key, "Received CONFIG GET");
-                        match key.as_str() {
-                            "dir" => {
-                                let reply = RespValue::Array(vec![
-                                    RespValue::SimpleString("dir".to_string()),
-                                    RespValue::BulkString(config::get_dir().to_string()),
-                                ]);
-                                send(&mut socket, reply)
-                                    .await
-                                    .expect("Failed to send CONFIG GET");
-                            }
-                            "dbfilename" => {
-                                let reply = RespValue::Array(vec![
-                                    RespValue::SimpleString("dbfilename".to_string()),
-                                    RespValue::BulkString(config::get_dbfilename().to_string()),
-                                ]);
-                                send(&mut socket, reply)
-                                    .await
-                                    .expect("Failed to send CONFIG GET");
-                            }
-                            _ => {}
-                        }
-                    }
-                    Ok(Command::Ping) => {
-                        tracing::info!("Received PING");
-                        let reply = RespValue::SimpleString("PONG".to_string());
-                        send(&mut socket, reply).await.expect("Failed to send PONG");
-                    }
-                    Ok(Command::Echo(arg)) => {
-                        tracing::info!(?arg, "Received ECHO");
-                        let reply = RespValue::BulkString(arg);
-                        send(&mut socket, reply).await.expect("Failed to send ECHO");
-                    }
-                    Ok(Command::Set(key, value, expiry)) => {
-                        tracing::info!(?key, ?value, ?expiry, "Received SET");
-                        kv::set(&key, value, expiry).await;
-                        let reply = RespValue::SimpleString("OK".to_string());
-                        send(&mut socket, reply).await.expect("Failed to send OK");
-                    }
-                    Ok(Command::Get(key)) => {
-                        tracing::info!(?key, "Received GET");
-                        let value = kv::get(&key).await;
-                        match value {
-                            Some(value) => {
-                                let reply = RespValue::BulkString(value.to_string());
-                                send(&mut socket, reply).await.expect("Failed to send GET");
-                            }
-                            None => {
-                                let reply = RespValue::NullBulkString;
-                                send(&mut socket, reply).await.expect("Failed to send GET");
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        tracing::warn!(?error, "Error");
-                        let reply = RespValue::Error("unknown command".to_string());
-                        send(&mut socket, reply)
+        if n == 0 {
+            return Ok(());
+        }
+
+        decoder.extend(&buf[..n]);
+
+        // Answer every complete frame in the buffer with a single write so
+        // pipelined commands cost one round-trip.
+        let mut replies = Vec::new();
+        loop {
+            match decoder.next_frame() {
+                Ok(Some(frame)) => {
+                    // Keep reading while a command blocks so a client that
+                    // hangs up stops waiting, instead of being handed elements
+                    // nobody will read.
+                    let execute = handler::execute(frame, &mut session);
+                    tokio::pin!(execute);
+                    // Replies to the frames before a command that blocks go
+                    // out before it starts waiting.
+                    let ready = std::future::poll_fn(|cx| match execute.as_mut().poll(cx) {
+                        Poll::Ready(reply) => Poll::Ready(Some(reply)),
+                        Poll::Pending => Poll::Ready(None),
+                    })
+                    .await;
+                    if ready.is_none() && !replies.is_empty() {
+                        socket
+                            .write_all(&replies)
                             .await
-                            .expect("Failed to send ERROR");
+                            .context("Failed to write to socket")?;
+                        replies.clear();
                     }
+                    let reply = match ready {
+                        Some(reply) => reply,
+                        None => loop {
+                            tokio::select! {
+                                biased;
+                                reply = &mut execute => break reply,
+                                read = socket.read(&mut buf) => {
+                                    let n = read.context("Failed to read from socket")?;
+                                    if n == 0 {
+                                        return Ok(());
+                                    }
+                                    decoder.extend(&buf[..n]);
+                                }
+                            }
+                        },
+                    };
+                    replies.extend(reply.as_bytes());
+                }
+                Ok(None) => break,
+                Err(error) => {
+                    tracing::warn!(?error, "Protocol error");
+                    let reply = RespValue::Error(format!("ERR Protocol error: {}", error));
+                    replies.extend(reply.as_bytes());
+                    socket
+                        .write_all(&replies)
+                        .await
+                        .context("Failed to write to socket")?;
+                    return Ok(());
                 }
             }
-        });
+        }
+
+        if !replies.is_empty() {
+            socket
+                .write_all(&replies)
+                .await
+                .context("Failed to write to socket")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    async fn request(client: &mut (impl AsyncRead + AsyncWrite + Unpin), frame: &[u8]) -> Vec<u8> {
+        client.write_all(frame).await.unwrap();
+        let mut buf = [0; 64];
+        let n = client.read(&mut buf).await.unwrap();
+        buf[..n].to_vec()
+    }
+
+    #[tokio::test]
+    async fn closing_a_blocked_connection_stops_it_waiting() {
+        kv::init_for_tests();
+
+        let (mut blocked, server) = tokio::io::duplex(4096);
+        let connection = tokio::spawn(handle_connection(server));
+        blocked
+            .write_all(b"*3\r\n$5\r\nBLPOP\r\n$11\r\nclosed:jobs\r\n$1\r\n0\r\n")
+            .await
+            .unwrap();
+        drop(blocked);
+        let closed = tokio::time::timeout(Duration::from_secs(1), connection).await;
+        closed.expect("connection should end").unwrap().unwrap();
+
+        let (mut client, server) = tokio::io::duplex(4096);
+        tokio::spawn(handle_connection(server));
+        let push = b"*3\r\n$5\r\nRPUSH\r\n$11\r\nclosed:jobs\r\n$4\r\njob1\r\n";
+        assert_eq!(request(&mut client, push).await, b":1\r\n");
+        let len = b"*2\r\n$4\r\nLLEN\r\n$11\r\nclosed:jobs\r\n";
+        assert_eq!(request(&mut client, len).await, b":1\r\n");
+    }
+
+    #[tokio::test]
+    async fn replies_before_a_blocking_command_are_not_held_back() {
+        kv::init_for_tests();
+
+        let (mut blocked, server) = tokio::io::duplex(4096);
+        tokio::spawn(handle_connection(server));
+        let frames = b"*3\r\n$5\r\nRPUSH\r\n$13\r\npipelined:one\r\n$1\r\nx\r\n\
+            *3\r\n$5\r\nBLPOP\r\n$13\r\npipelined:two\r\n$1\r\n0\r\n";
+        let pushed = tokio::time::timeout(Duration::from_secs(1), request(&mut blocked, frames));
+        assert_eq!(pushed.await.expect("RPUSH should reply"), b":1\r\n");
+
+        let (mut client, server) = tokio::io::duplex(4096);
+        tokio::spawn(handle_connection(server));
+        let push = b"*3\r\n$5\r\nRPUSH\r\n$13\r\npipelined:two\r\n$1\r\ny\r\n";
+        assert_eq!(request(&mut client, push).await, b":1\r\n");
+        let mut buf = [0; 64];
+        let n = blocked.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"*2\r\n$13\r\npipelined:two\r\n$1\r\ny\r\n");
     }
 }
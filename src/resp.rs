@@ -1,11 +1,21 @@
+use bytes::Bytes;
+
 use crate::{cursor::Cursor, cursor::Error};
 
+/// Version of the protocol a client speaks, chosen with HELLO.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
 #[derive(Debug)]
 pub enum RespValue {
     Array(Vec<RespValue>),
     BigNumber(String),
     BulkError(String),
-    BulkString(String),
+    BulkString(Bytes),
     Double(f64),
     Error(String),
     False,
@@ -14,6 +24,7 @@ pub enum RespValue {
     NaN,
     NegativeInfinity,
     Null,
+    NullArray,
     NullBulkString,
     PositiveInfinity,
     Set(Vec<RespValue>),
@@ -53,19 +64,19 @@ impl RespValue {
                 array.extend_from_slice(b"\r\n");
                 array
             }
-            RespValue::BulkString(string) => {
+            RespValue::BulkString(data) => {
                 let mut array = Vec::new();
                 array.push(b'$');
-                array.extend_from_slice(string.len().to_string().as_bytes());
+                array.extend_from_slice(data.len().to_string().as_bytes());
                 array.extend_from_slice(b"\r\n");
-                array.extend_from_slice(string.as_bytes());
+                array.extend_from_slice(data);
                 array.extend_from_slice(b"\r\n");
                 array
             }
             RespValue::Double(value) => {
                 let mut array = Vec::new();
                 array.push(b',');
-                array.extend_from_slice(value.to_string().as_bytes());
+                array.extend_from_slice(format_double(*value).as_bytes());
                 array.extend_from_slice(b"\r\n");
                 array
             }
@@ -162,17 +173,220 @@ impl RespValue {
                 array.extend_from_slice(b"$-1\r\n");
                 array
             }
+            RespValue::NullArray => {
+                let mut array = Vec::new();
+                array.extend_from_slice(b"*-1\r\n");
+                array
+            }
+        }
+    }
+}
+
+impl RespValue {
+    /// Converts a reply built with RESP3 types into what a client speaking
+    /// `protocol` understands, down to nested elements. RESP2 has no maps,
+    /// sets, doubles, booleans or plain nulls, so those fall back to the types
+    /// Redis uses in their place, while RESP3 has a single null.
+    pub fn for_protocol(self, protocol: Protocol) -> RespValue {
+        let convert = |values: Vec<RespValue>| -> Vec<RespValue> {
+            values
+                .into_iter()
+                .map(|value| value.for_protocol(protocol))
+                .collect()
+        };
+        match (protocol, self) {
+            (Protocol::Resp3, RespValue::NullArray | RespValue::NullBulkString) => RespValue::Null,
+            (Protocol::Resp3, RespValue::Array(values)) => RespValue::Array(convert(values)),
+            (Protocol::Resp3, RespValue::Set(values)) => RespValue::Set(convert(values)),
+            (Protocol::Resp3, RespValue::Map(entries)) => RespValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.for_protocol(protocol), value.for_protocol(protocol)))
+                    .collect(),
+            ),
+            (Protocol::Resp3, value) => value,
+            (Protocol::Resp2, RespValue::Array(values) | RespValue::Set(values)) => {
+                RespValue::Array(convert(values))
+            }
+            (Protocol::Resp2, RespValue::Map(entries)) => RespValue::Array(convert(
+                entries
+                    .into_iter()
+                    .flat_map(|(key, value)| [key, value])
+                    .collect(),
+            )),
+            (Protocol::Resp2, RespValue::Double(value)) => {
+                RespValue::BulkString(format_double(value).into())
+            }
+            (Protocol::Resp2, RespValue::PositiveInfinity) => RespValue::BulkString("inf".into()),
+            (Protocol::Resp2, RespValue::NegativeInfinity) => RespValue::BulkString("-inf".into()),
+            (Protocol::Resp2, RespValue::NaN) => RespValue::BulkString("nan".into()),
+            (Protocol::Resp2, RespValue::BigNumber(number)) => RespValue::BulkString(number.into()),
+            (Protocol::Resp2, RespValue::VerbatimString(_, string)) => {
+                RespValue::BulkString(string.into())
+            }
+            (Protocol::Resp2, RespValue::BulkError(error)) => RespValue::Error(error),
+            (Protocol::Resp2, RespValue::True) => RespValue::Integer(1),
+            (Protocol::Resp2, RespValue::False) => RespValue::Integer(0),
+            (Protocol::Resp2, RespValue::Null) => RespValue::NullBulkString,
+            (Protocol::Resp2, value) => value,
         }
     }
 }
 
+/// Formats a double the way Redis replies with one: the shortest digits that
+/// read back as the same value, switching to an exponent like `%.17g` does for
+/// very large or very small magnitudes.
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    let exponent = if value == 0.0 {
+        0
+    } else {
+        value.abs().log10().floor() as i32
+    };
+    if (-4..17).contains(&exponent) {
+        return value.to_string();
+    }
+
+    let formatted = format!("{:e}", value);
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("exponent notation has an exponent");
+    let exponent = exponent.parse::<i32>().expect("exponent is an integer");
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+}
+
+/// Largest bulk payload accepted from a client, mirroring Redis' `proto-max-bulk-len`.
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+
+/// Deepest aggregates may nest. Requests are flat arrays, so this only stops
+/// input nested deep enough to exhaust the stack.
+const MAX_DEPTH: usize = 32;
+
+#[cfg(test)]
 pub fn parse(input: &[u8]) -> Result<RespValue, Error> {
+    parse_frame(input).map(|(value, _)| value)
+}
+
+/// Parses the frame at the start of `input` and returns it together with the
+/// number of bytes it occupied. `Error::UnexpectedEOF` means the frame is not
+/// complete yet and more input is needed.
+pub fn parse_frame(input: &[u8]) -> Result<(RespValue, usize), Error> {
     let mut cursor = Cursor::new(input);
-    parse_value(&mut cursor)
+    let value = parse_value(&mut cursor, 0)?;
+    Ok((value, cursor.position()))
+}
+
+/// Progress checking whether a frame has fully arrived, kept between reads so
+/// each one only looks at the bytes it added instead of the whole frame again.
+#[derive(Debug, Default)]
+pub struct FrameScan {
+    /// Bytes of values at the front of the frame known to be complete.
+    len: usize,
+    /// Elements each aggregate the scan is inside of still expects, innermost
+    /// last.
+    open: Vec<u64>,
+}
+
+impl FrameScan {
+    /// Continues the scan of the frame at the start of `input`, returning its
+    /// length once it is complete. The frame is only checked for where it
+    /// ends, `parse_frame` reports anything else wrong with it.
+    pub fn advance(&mut self, input: &[u8]) -> Result<Option<usize>, Error> {
+        loop {
+            let mut cursor = Cursor::new(&input[self.len..]);
+            let elements = match skip_value(&mut cursor) {
+                Ok(elements) => elements,
+                Err(Error::UnexpectedEOF) => return Ok(None),
+                Err(error) => return Err(error),
+            };
+            self.len += cursor.position();
+
+            if elements > 0 {
+                if self.open.len() >= MAX_DEPTH {
+                    return Err(Error::InvalidInput(
+                        "too many nested aggregates".to_string(),
+                    ));
+                }
+                self.open.push(elements);
+                continue;
+            }
+            // The value is complete, and so is every aggregate it was the
+            // last element of.
+            loop {
+                match self.open.last_mut() {
+                    None => return Ok(Some(self.len)),
+                    Some(left) if *left > 1 => {
+                        *left -= 1;
+                        break;
+                    }
+                    Some(_) => {
+                        self.open.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Steps over the value at `cursor`, or only over its header if it is an
+/// aggregate, returning how many elements the aggregate holds.
+fn skip_value(cursor: &mut Cursor) -> Result<u64, Error> {
+    match cursor.read_byte()? {
+        b'*' | b'~' => Ok(cursor.read_integer()?.max(0) as u64),
+        b'%' => Ok((cursor.read_integer()?.max(0) as u64).saturating_mul(2)),
+        b'$' | b'!' | b'=' => {
+            let len = cursor.read_integer()?;
+            if len > MAX_BULK_LEN {
+                return Err(Error::InvalidInput(format!("invalid bulk length: {}", len)));
+            }
+            if len >= 0 {
+                cursor.read(len as usize + 2)?;
+            }
+            Ok(0)
+        }
+        b'+' | b'-' | b':' | b'_' | b'#' | b',' | b'(' => {
+            cursor.read_line()?;
+            Ok(0)
+        }
+        // Anything else is left for `parse_value` to reject.
+        _ => Ok(0),
+    }
+}
+
+fn read_length(cursor: &mut Cursor) -> Result<usize, Error> {
+    let len = cursor.read_integer()?;
+    if !(0..=MAX_BULK_LEN).contains(&len) {
+        return Err(Error::InvalidInput(format!("invalid length: {}", len)));
+    }
+    Ok(len as usize)
+}
+
+fn read_terminator(cursor: &mut Cursor) -> Result<(), Error> {
+    let terminator = cursor.read(2)?;
+    if terminator != b"\r\n" {
+        return Err(Error::InvalidInput(format!(
+            "unexpected bytes after value: {:?}",
+            terminator
+        )));
+    }
+    Ok(())
 }
 
-fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
+/// Parses the value at `cursor`, nested in `depth` aggregates.
+fn parse_value(cursor: &mut Cursor, depth: usize) -> Result<RespValue, Error> {
     let first_byte = cursor.read_byte()? as char;
+    if matches!(first_byte, '*' | '%' | '~') && depth >= MAX_DEPTH {
+        return Err(Error::InvalidInput(
+            "too many nested aggregates".to_string(),
+        ));
+    }
     match first_byte {
         '+' => {
             let string = cursor.read_string()?;
@@ -187,17 +401,17 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
             Ok(RespValue::Integer(integer))
         }
         '$' => {
-            // TODO: Handle the case where the length is too large
             let len = cursor.read_integer()?;
 
             if len == -1 {
                 return Ok(RespValue::NullBulkString);
             }
 
+            if !(0..=MAX_BULK_LEN).contains(&len) {
+                return Err(Error::InvalidInput(format!("invalid bulk length: {}", len)));
+            }
+
             let data = cursor.read(len as usize)?;
-            let string = std::str::from_utf8(data).map_err(|_| {
-                Error::InvalidInput(format!("'{:?}' is not a valid UTF-8 sequence", data))
-            })?;
 
             let terminator = cursor.read(2)?;
             if terminator != b"\r\n" {
@@ -207,7 +421,7 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
                 )));
             }
 
-            Ok(RespValue::BulkString(string.to_string()))
+            Ok(RespValue::BulkString(Bytes::copy_from_slice(data)))
         }
         '*' => {
             let len = cursor.read_integer()?;
@@ -216,8 +430,15 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
                 return Ok(RespValue::Null);
             }
 
+            if len < 0 {
+                return Err(Error::InvalidInput(format!(
+                    "invalid multibulk length: {}",
+                    len
+                )));
+            }
+
             let items = (0..len)
-                .map(|_| parse_value(cursor))
+                .map(|_| parse_value(cursor, depth + 1))
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(RespValue::Array(items))
@@ -234,6 +455,7 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
         }
         '#' => {
             let value = cursor.read_byte()?;
+            read_terminator(cursor)?;
             match value {
                 b't' => Ok(RespValue::True),
                 b'f' => Ok(RespValue::False),
@@ -264,7 +486,7 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
         '(' => {
             let value = cursor.read_string()?;
 
-            if !value.starts_with(|c: char| c == '+' || c == '-') {
+            if !value.starts_with(['+', '-']) {
                 return Err(Error::InvalidInput(format!(
                     "invalid big number: {:?}",
                     value
@@ -272,7 +494,7 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
             }
 
             for c in value.chars().skip(1) {
-                if !c.is_digit(10) {
+                if !c.is_ascii_digit() {
                     return Err(Error::InvalidInput(format!(
                         "invalid big number: {:?}",
                         value
@@ -283,18 +505,20 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
             Ok(RespValue::BigNumber(value))
         }
         '!' => {
-            let len = cursor.read_integer()?;
-            let data = cursor.read(len as usize)?;
+            let len = read_length(cursor)?;
+            let data = cursor.read(len)?;
             let string = std::str::from_utf8(data).map_err(|_| {
                 Error::InvalidInput(format!("'{:?}' is not a valid UTF-8 sequence", data))
             })?;
+            read_terminator(cursor)?;
             Ok(RespValue::BulkError(string.to_string()))
         }
         '=' => {
-            let len = cursor.read_integer()?;
-            let data = cursor.read(len as usize)?;
+            let len = read_length(cursor)?;
+            let data = cursor.read(len)?;
+            read_terminator(cursor)?;
 
-            if data[3] != b':' {
+            if data.len() < 4 || data[3] != b':' {
                 return Err(Error::InvalidInput(format!(
                     "invalid verbatim string: {:?}",
                     data
@@ -314,23 +538,23 @@ fn parse_value(cursor: &mut Cursor) -> Result<RespValue, Error> {
             ))
         }
         '%' => {
-            let len = cursor.read_integer()?;
+            let len = read_length(cursor)?;
             let mut entries = Vec::new();
 
             for _ in 0..len {
-                let key = parse_value(cursor)?;
-                let value = parse_value(cursor)?;
+                let key = parse_value(cursor, depth + 1)?;
+                let value = parse_value(cursor, depth + 1)?;
                 entries.push((key, value));
             }
 
             Ok(RespValue::Map(entries))
         }
         '~' => {
-            let len = cursor.read_integer()?;
+            let len = read_length(cursor)?;
             let mut entries = Vec::new();
 
             for _ in 0..len {
-                let value = parse_value(cursor)?;
+                let value = parse_value(cursor, depth + 1)?;
                 entries.push(value);
             }
 
@@ -466,12 +690,64 @@ mod tests {
         assert!(matches!(result, RespValue::BulkString(s) if s == "foo\r\nbar"));
     }
 
+    #[test]
+    fn parse_binary_bulk_string() {
+        let input = b"$4\r\n\xff\x00\xfe\x01\r\n";
+        let result = parse(input).unwrap();
+        assert!(matches!(result, RespValue::BulkString(s) if s == [0xff, 0x00, 0xfe, 0x01][..]));
+    }
+
     #[test]
     fn parse_bulk_string_length_mismatch() {
         let input = b"$10\r\nhello\r\n";
         assert!(matches!(parse(input), Err(Error::UnexpectedEOF)));
     }
 
+    #[test]
+    fn parse_bulk_string_negative_length() {
+        let input = b"$-2\r\n";
+        match parse(input) {
+            Err(Error::InvalidInput(msg)) => {
+                assert!(msg.contains("invalid bulk length"));
+            }
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn parse_bulk_string_too_large() {
+        let input = b"$1000000000000\r\n";
+        match parse(input) {
+            Err(Error::InvalidInput(msg)) => {
+                assert!(msg.contains("invalid bulk length"));
+            }
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn parse_frame_reports_consumed_length() {
+        let input = b"+OK\r\n:1\r\n";
+        let (value, len) = parse_frame(input).unwrap();
+        assert!(matches!(value, RespValue::SimpleString(s) if s == "OK"));
+        assert_eq!(len, 5);
+
+        let (value, len) = parse_frame(&input[len..]).unwrap();
+        assert!(matches!(value, RespValue::Integer(1)));
+        assert_eq!(len, 4);
+    }
+
+    #[test]
+    fn parse_frame_consumes_bulk_error_terminator() {
+        let input = b"!3\r\nerr\r\n#t\r\n";
+        let (value, len) = parse_frame(input).unwrap();
+        assert!(matches!(value, RespValue::BulkError(s) if s == "err"));
+        assert_eq!(len, 9);
+
+        let (value, _) = parse_frame(&input[len..]).unwrap();
+        assert!(matches!(value, RespValue::True));
+    }
+
     #[test]
     fn parse_empty_array() {
         let input = b"*0\r\n";
@@ -512,6 +788,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_deeply_nested_array() {
+        let input = "*1\r\n".repeat(MAX_DEPTH) + ":1\r\n";
+        assert!(parse(input.as_bytes()).is_ok());
+        let input = "*1\r\n".repeat(MAX_DEPTH + 1) + ":1\r\n";
+        match parse(input.as_bytes()) {
+            Err(Error::InvalidInput(msg)) => assert!(msg.contains("nested")),
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
+    #[test]
+    fn scan_frame_across_reads() {
+        let input = b"*3\r\n:1\r\n%1\r\n+key\r\n*0\r\n$5\r\nworld\r\n+next\r\n";
+        let frame_len = input.len() - b"+next\r\n".len();
+        let mut scan = FrameScan::default();
+        for end in 0..frame_len {
+            assert_eq!(scan.advance(&input[..end]).unwrap(), None);
+        }
+        assert_eq!(scan.advance(input).unwrap(), Some(frame_len));
+
+        let mut scan = FrameScan::default();
+        assert_eq!(scan.advance(b"*-1\r\n").unwrap(), Some(5));
+        let mut scan = FrameScan::default();
+        let input = "*1\r\n".repeat(MAX_DEPTH + 1);
+        assert!(matches!(
+            scan.advance(input.as_bytes()),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
     #[test]
     fn parse_null_array() {
         let input = b"*-1\r\n";
@@ -519,6 +826,17 @@ mod tests {
         assert!(matches!(result, RespValue::Null));
     }
 
+    #[test]
+    fn parse_negative_array_length() {
+        let input = b"*-2\r\n";
+        match parse(input) {
+            Err(Error::InvalidInput(msg)) => {
+                assert!(msg.contains("invalid multibulk length"));
+            }
+            _ => panic!("Expected InvalidInput error"),
+        }
+    }
+
     #[test]
     fn parse_incomplete_array() {
         let input = b"*2\r\n:1\r\n";
@@ -804,25 +1122,32 @@ mod tests {
 
     #[test]
     fn bulk_string_as_bytes() {
-        let input = RespValue::BulkString("hello".to_string());
+        let input = RespValue::BulkString(Bytes::from("hello"));
         let result = input.as_bytes();
         assert_eq!(result, b"$5\r\nhello\r\n");
     }
 
     #[test]
     fn empty_bulk_string_as_bytes() {
-        let input = RespValue::BulkString("".to_string());
+        let input = RespValue::BulkString(Bytes::from(""));
         let result = input.as_bytes();
         assert_eq!(result, b"$0\r\n\r\n");
     }
 
     #[test]
     fn bulk_string_with_special_chars_as_bytes() {
-        let input = RespValue::BulkString("foo\r\nbar".to_string());
+        let input = RespValue::BulkString(Bytes::from("foo\r\nbar"));
         let result = input.as_bytes();
         assert_eq!(result, b"$8\r\nfoo\r\nbar\r\n");
     }
 
+    #[test]
+    fn binary_bulk_string_as_bytes() {
+        let input = RespValue::BulkString(Bytes::from_static(b"\xff\x00"));
+        let result = input.as_bytes();
+        assert_eq!(result, b"$2\r\n\xff\x00\r\n");
+    }
+
     #[test]
     fn double_as_bytes_valid() {
         let input = RespValue::Double(123.45);
@@ -889,8 +1214,8 @@ mod tests {
     #[test]
     fn map_as_bytes_single_entry() {
         let map = RespValue::Map(vec![(
-            RespValue::BulkString("key".to_string()),
-            RespValue::BulkString("value".to_string()),
+            RespValue::BulkString(Bytes::from("key")),
+            RespValue::BulkString(Bytes::from("value")),
         )]);
         let result = map.as_bytes();
         assert_eq!(result, b"%1\r\n$3\r\nkey\r\n$5\r\nvalue\r\n");
@@ -900,8 +1225,8 @@ mod tests {
     fn map_as_bytes_multiple_entries() {
         let map = RespValue::Map(vec![
             (
-                RespValue::BulkString("key1".to_string()),
-                RespValue::BulkString("value1".to_string()),
+                RespValue::BulkString(Bytes::from("key1")),
+                RespValue::BulkString(Bytes::from("value1")),
             ),
             (
                 RespValue::SimpleString("key2".to_string()),
@@ -987,4 +1312,47 @@ mod tests {
         let result = input.as_bytes();
         assert_eq!(result, b"=4\r\ntxt:\r\n");
     }
+
+    #[test]
+    fn map_and_set_for_resp2() {
+        let map = RespValue::Map(vec![(
+            RespValue::BulkString("field".into()),
+            RespValue::Set(vec![RespValue::Double(1.5), RespValue::Null]),
+        )]);
+        let result = map.for_protocol(Protocol::Resp2).as_bytes();
+        assert_eq!(result, b"*2\r\n$5\r\nfield\r\n*2\r\n$3\r\n1.5\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn nulls_for_resp3() {
+        let result = RespValue::NullArray
+            .for_protocol(Protocol::Resp3)
+            .as_bytes();
+        assert_eq!(result, b"_\r\n");
+        let result = RespValue::NullArray
+            .for_protocol(Protocol::Resp2)
+            .as_bytes();
+        assert_eq!(result, b"*-1\r\n");
+
+        let nested = RespValue::Map(vec![(
+            RespValue::BulkString("field".into()),
+            RespValue::Array(vec![
+                RespValue::NullBulkString,
+                RespValue::Set(vec![RespValue::NullArray]),
+            ]),
+        )]);
+        let result = nested.for_protocol(Protocol::Resp3).as_bytes();
+        assert_eq!(result, b"%1\r\n$5\r\nfield\r\n*2\r\n_\r\n~1\r\n_\r\n");
+    }
+
+    #[test]
+    fn format_doubles() {
+        assert_eq!(format_double(3.0), "3");
+        assert_eq!(format_double(10.5 + 0.1), "10.6");
+        assert_eq!(format_double(-0.25), "-0.25");
+        assert_eq!(format_double(1e20), "1e+20");
+        assert_eq!(format_double(1.5e-5), "1.5e-05");
+        assert_eq!(format_double(f64::INFINITY), "inf");
+        assert_eq!(format_double(f64::NEG_INFINITY), "-inf");
+    }
 }